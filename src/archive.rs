@@ -0,0 +1,150 @@
+//! Continuous recording of the program output to disk.
+//!
+//! Unlike the recording of fallback events, everything on air is archived,
+//! live or slate, with `splitmuxsink` starting a new file once the current
+//! one reaches the maximum duration or size. Files are named after a
+//! pattern, and old files are pruned whenever a new one is started.
+
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+use gst::prelude::*;
+
+use crate::hints;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ArchiveFormat {
+    Mp4,
+    Ts,
+}
+
+impl ArchiveFormat {
+    fn extension(self) -> &'static str {
+        match self {
+            ArchiveFormat::Mp4 => "mp4",
+            ArchiveFormat::Ts => "ts",
+        }
+    }
+}
+
+impl std::str::FromStr for ArchiveFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "mp4" => Ok(ArchiveFormat::Mp4),
+            "ts" => Ok(ArchiveFormat::Ts),
+            _ => Err(anyhow::anyhow!("Unknown archive format {}", s)),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ArchiveOptions {
+    pub dir: PathBuf,
+    pub format: ArchiveFormat,
+    /// File name without extension, with `{timestamp}` and `{index}`
+    /// replaced by the start time and the number of the file
+    pub pattern: String,
+    pub max_duration: Option<Duration>,
+    /// In bytes
+    pub max_size: Option<u64>,
+    /// Files older than this are deleted
+    pub max_age: Option<Duration>,
+    /// Only the most recent files are kept, including the current one
+    pub max_files: Option<usize>,
+}
+
+impl ArchiveOptions {
+    fn path(&self, index: u32) -> PathBuf {
+        /* Colons are not allowed in file names everywhere */
+        let timestamp = humantime::format_rfc3339_seconds(SystemTime::now())
+            .to_string()
+            .replace(':', "-");
+        let name = self
+            .pattern
+            .replace("{timestamp}", &timestamp)
+            .replace("{index}", &format!("{:05}", index));
+
+        self.dir
+            .join(format!("{}.{}", name, self.format.extension()))
+    }
+
+    /// Archived files, oldest first
+    fn files(&self) -> Result<Vec<(PathBuf, SystemTime)>, anyhow::Error> {
+        let mut files = vec![];
+
+        for entry in std::fs::read_dir(&self.dir)? {
+            let path = entry?.path();
+
+            if path
+                .extension()
+                .map_or(false, |ext| ext == self.format.extension())
+            {
+                files.push((path.clone(), path.metadata()?.modified()?));
+            }
+        }
+
+        files.sort_by_key(|(_, modified)| *modified);
+
+        Ok(files)
+    }
+
+    /// Delete files past the retention before `next` is started
+    fn prune(&self, next: &Path) -> Result<(), anyhow::Error> {
+        let files = self.files()?;
+        let files = files.iter().filter(|(path, _)| path != next);
+        let count = files.clone().count();
+
+        for (index, (path, modified)) in files.enumerate() {
+            let too_old = self.max_age.map_or(false, |max_age| {
+                modified.elapsed().map_or(false, |age| age > max_age)
+            });
+            let too_many = self
+                .max_files
+                .map_or(false, |max_files| count - index >= max_files);
+
+            if too_old || too_many {
+                info!("Pruning archived {}", path.display());
+                std::fs::remove_file(path)?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// `splitmuxsink` writing files as per `options`
+pub fn build_sink(options: &ArchiveOptions) -> Result<gst::Element, anyhow::Error> {
+    std::fs::create_dir_all(&options.dir)?;
+
+    let sink = hints::make_element("splitmuxsink", Some("archive"))?;
+
+    if options.format == ArchiveFormat::Ts {
+        sink.set_property("muxer", &hints::make_element("mpegtsmux", None)?)?;
+    }
+
+    if let Some(max_duration) = options.max_duration {
+        sink.set_property("max-size-time", &(max_duration.as_nanos() as u64))?;
+        sink.set_property("send-keyframe-requests", &true)?;
+    }
+
+    if let Some(max_size) = options.max_size {
+        sink.set_property("max-size-bytes", &max_size)?;
+    }
+
+    let options = options.clone();
+    sink.connect("format-location", false, move |values| {
+        let index = values[1].get_some::<u32>().unwrap();
+        let path = options.path(index);
+
+        if let Err(err) = options.prune(&path) {
+            warn!("Failed to prune archive: {}", err);
+        }
+
+        info!("Archiving to {}", path.display());
+        Some(path.to_string_lossy().to_value())
+    })?;
+
+    Ok(sink)
+}
@@ -0,0 +1,171 @@
+//! Audio path of the output, mixing the live audio with the slate audio.
+//!
+//! Only one of them is audible at a time. By default audio follows the
+//! video on air: the slate audio while the output shows the slate, the live
+//! audio otherwise. With the independent policy, the live audio stays on air
+//! for as long as it keeps arriving, even under the slate, e.g. when only
+//! the live video froze. The slate audio is silence, or looping slate media.
+//! Silent live audio is replaced by the slate audio either way. The mix can
+//! be normalized to a target loudness, so that switching between the live
+//! audio and the slate music does not jump in loudness.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use gst::prelude::*;
+
+use crate::bridge::Bridge;
+use crate::hints;
+use crate::silence::SilenceDetector;
+use crate::status::Status;
+use crate::stream_sync::StreamSync;
+
+/// How often the arrival and silence of the live audio are checked
+const INTERVAL_MS: u32 = 100;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FallbackPolicy {
+    /// Audio follows the video on air
+    Linked,
+    /// Audio and video fall back on their own
+    Independent,
+}
+
+impl std::str::FromStr for FallbackPolicy {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "linked" => Ok(FallbackPolicy::Linked),
+            "independent" => Ok(FallbackPolicy::Independent),
+            _ => Err(anyhow::anyhow!("Unknown fallback policy {}", s)),
+        }
+    }
+}
+
+/// Time of the last live audio buffer flowing through `pad`
+fn track_arrivals(pad: &gst::Pad) -> Arc<Mutex<Option<Instant>>> {
+    let last = Arc::new(Mutex::new(None));
+    let last_clone = last.clone();
+
+    pad.add_probe(gst::PadProbeType::BUFFER, move |_, _| {
+        *last_clone.lock().unwrap() = Some(Instant::now());
+        gst::PadProbeReturn::Ok
+    });
+
+    last
+}
+
+/// When the live audio is on air
+pub struct LiveAudioPolicy {
+    pub policy: FallbackPolicy,
+    /// With the independent policy, the live audio is dropped once none
+    /// arrived for this long
+    pub down_after: Option<Duration>,
+    pub silence: Option<Arc<SilenceDetector>>,
+}
+
+/// Link `source` to `mixer` in `pipe`, returns the mixer pad
+fn add_branch(
+    pipe: &gst::Pipeline,
+    source: &gst::Element,
+    mixer: &gst::Element,
+) -> Result<gst::Pad, anyhow::Error> {
+    let branch = hints::parse_bin("queue ! audioconvert ! audioresample")?;
+
+    pipe.add_many(&[source, branch.upcast_ref()])?;
+    gst::Element::link_many(&[source, branch.upcast_ref(), mixer])?;
+
+    Ok(branch.get_static_pad("src").unwrap().get_peer().unwrap())
+}
+
+/// EBU R128 loudness normalization to `target` LUFS, with true peaks
+/// limited to `max_true_peak` dBTP. `audioloudnorm` only processes 192kHz
+/// samples, and adds 3s of latency for its lookahead.
+pub fn normalizer(target: f64, max_true_peak: f64) -> Result<gst::Element, anyhow::Error> {
+    let bin = hints::parse_bin(&format!(
+        "audioconvert ! audioresample ! audioloudnorm loudness-target={} max-true-peak={} ! \
+         audioconvert ! audioresample",
+        target, max_true_peak
+    ))?;
+
+    Ok(bin.upcast())
+}
+
+/// Mix the live audio from `live_src` with the slate audio into `sink` in
+/// `pipe`, looping the audio of the slate player if `slate_player`, silence
+/// otherwise
+pub fn build(
+    pipe: &gst::Pipeline,
+    sink: &gst::Element,
+    live_src: &gst::Element,
+    slate_player: bool,
+    status: &Arc<Status>,
+    live_policy: LiveAudioPolicy,
+) -> Result<(), anyhow::Error> {
+    let LiveAudioPolicy {
+        policy,
+        down_after,
+        silence,
+    } = live_policy;
+
+    let mixer = hints::make_element("audiomixer", Some("audio_mixer"))?;
+    pipe.add_many(&[&mixer, sink])?;
+    mixer.link(sink)?;
+
+    let live_pad = add_branch(pipe, live_src, &mixer)?;
+
+    let slate_pad = if slate_player {
+        let source = Bridge::Interpipe.source(crate::slate_player::AUDIO, StreamSync::RestartTs)?;
+        add_branch(pipe, &source, &mixer)?
+    } else {
+        let silence = hints::parse_bin("audiotestsrc is-live=true wave=silence ! queue")?;
+        pipe.add(&silence)?;
+        silence.link(&mixer)?;
+        silence.get_static_pad("src").unwrap().get_peer().unwrap()
+    };
+
+    live_pad.set_property("mute", &true)?;
+
+    let last_arrival = track_arrivals(&live_pad);
+    /* The output starts on the slate */
+    let video_live = Arc::new(AtomicBool::new(false));
+    let live = AtomicBool::new(false);
+    let video_live_clone = video_live.clone();
+    let update = Arc::new(move || {
+        let arriving = match (*last_arrival.lock().unwrap(), down_after) {
+            (None, _) => false,
+            (Some(_), None) => true,
+            (Some(last), Some(down_after)) => last.elapsed() < down_after,
+        };
+        let silent = silence
+            .as_ref()
+            .map_or(false, |silence| silence.is_silent());
+        let on_air = !silent
+            && match policy {
+                FallbackPolicy::Linked => video_live_clone.load(Ordering::SeqCst),
+                FallbackPolicy::Independent => arriving,
+            };
+
+        if live.swap(on_air, Ordering::SeqCst) != on_air {
+            live_pad.set_property("mute", &!on_air).unwrap();
+            slate_pad.set_property("mute", &on_air).unwrap();
+        }
+    });
+
+    if policy == FallbackPolicy::Linked {
+        let update = update.clone();
+        status.connect_live_changed(move |shows_live| {
+            video_live.store(shows_live, Ordering::SeqCst);
+            update();
+        });
+    }
+
+    glib::timeout_add(INTERVAL_MS, move || {
+        update();
+        glib::Continue(true)
+    });
+
+    Ok(())
+}
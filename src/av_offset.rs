@@ -0,0 +1,74 @@
+//! Lip-sync correction of the live input.
+//!
+//! Some encoders timestamp audio and video a constant offset apart. The
+//! live audio is delayed by positive offsets and the live video by negative
+//! ones, through pad offsets on the live branches, as delaying is all that
+//! can be done to live streams. The offset can be changed at runtime from
+//! the control API.
+
+use std::sync::atomic::{AtomicI64, Ordering};
+use std::sync::{Arc, Mutex};
+
+use gst::prelude::*;
+
+/// The queues of the live branches hold one second
+pub const MAX_OFFSET_MS: i64 = 1000;
+
+pub struct AvOffset {
+    offset_ms: AtomicI64,
+    /// Sink pads of the live audio and video branches
+    pads: Mutex<Option<(gst::Pad, gst::Pad)>>,
+}
+
+/// Check that `offset_ms` can be applied
+pub fn check(offset_ms: i64) -> Result<(), anyhow::Error> {
+    if offset_ms.abs() > MAX_OFFSET_MS {
+        anyhow::bail!(
+            "A/V offset of {}ms is beyond the {}ms the live branches can hold",
+            offset_ms,
+            MAX_OFFSET_MS
+        );
+    }
+
+    Ok(())
+}
+
+impl AvOffset {
+    pub fn new(offset_ms: i64) -> Arc<Self> {
+        Arc::new(Self {
+            offset_ms: AtomicI64::new(offset_ms),
+            pads: Mutex::new(None),
+        })
+    }
+
+    /// Audio offset relative to the video, in milliseconds
+    pub fn get(&self) -> i64 {
+        self.offset_ms.load(Ordering::Relaxed)
+    }
+
+    fn apply(&self) {
+        if let Some((ref audio_pad, ref video_pad)) = *self.pads.lock().unwrap() {
+            let offset = self.get() * 1_000_000;
+            audio_pad.set_offset(offset.max(0));
+            video_pad.set_offset((-offset).max(0));
+        }
+    }
+
+    /// Offset the live audio entering through `audio_pad` against the live
+    /// video entering through `video_pad`
+    pub fn install(&self, audio_pad: &gst::Pad, video_pad: &gst::Pad) {
+        *self.pads.lock().unwrap() = Some((audio_pad.clone(), video_pad.clone()));
+        self.apply();
+    }
+
+    /// Change the offset to `offset_ms`
+    pub fn set(&self, offset_ms: i64) -> Result<(), anyhow::Error> {
+        check(offset_ms)?;
+
+        info!("Offsetting live audio by {}ms", offset_ms);
+        self.offset_ms.store(offset_ms, Ordering::Relaxed);
+        self.apply();
+
+        Ok(())
+    }
+}
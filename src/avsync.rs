@@ -0,0 +1,113 @@
+//! Audio / video drift detection.
+//!
+//! The running time of the last buffer of the program audio and video is
+//! tracked continuously, after the compositor and the audio mixer so that
+//! what is measured is what goes on air. A broken upstream encoder shows up
+//! as the two drifting apart.
+
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use gst::prelude::*;
+
+#[derive(Default)]
+struct Track {
+    segment: Option<gst::FormattedSegment<gst::ClockTime>>,
+    running_time: gst::ClockTime,
+}
+
+impl Track {
+    fn handle_probe(&mut self, info: &gst::PadProbeInfo) {
+        match info.data {
+            Some(gst::PadProbeData::Buffer(ref buffer)) => {
+                if let Some(ref segment) = self.segment {
+                    let running_time = segment.to_running_time(buffer.get_pts());
+                    if running_time.is_some() {
+                        self.running_time = running_time;
+                    }
+                }
+            }
+            Some(gst::PadProbeData::Event(ref event)) => {
+                if let gst::EventView::Segment(segment) = event.view() {
+                    self.segment = segment.get_segment().downcast_ref::<gst::ClockTime>().cloned();
+                    self.running_time = gst::CLOCK_TIME_NONE;
+                }
+            }
+            _ => (),
+        }
+    }
+}
+
+pub struct AvSyncMonitor {
+    audio: Mutex<Track>,
+    video: Mutex<Track>,
+}
+
+impl AvSyncMonitor {
+    pub fn new() -> Arc<Self> {
+        Arc::new(Self {
+            audio: Mutex::new(Track::default()),
+            video: Mutex::new(Track::default()),
+        })
+    }
+
+    fn install_track(self: &Arc<Self>, pad: &gst::Pad, audio: bool) {
+        let monitor = self.clone();
+        pad.add_probe(
+            gst::PadProbeType::BUFFER | gst::PadProbeType::EVENT_DOWNSTREAM,
+            move |_, info| {
+                let track = if audio { &monitor.audio } else { &monitor.video };
+                track.lock().unwrap().handle_probe(info);
+                gst::PadProbeReturn::Ok
+            },
+        );
+    }
+
+    pub fn install(self: &Arc<Self>, audio_pad: &gst::Pad, video_pad: &gst::Pad) {
+        self.install_track(audio_pad, true);
+        self.install_track(video_pad, false);
+    }
+
+    /// Video running time minus audio running time, in nanoseconds
+    pub fn drift(&self) -> Option<i64> {
+        let audio = self.audio.lock().unwrap().running_time;
+        let video = self.video.lock().unwrap().running_time;
+
+        match (audio.nseconds(), video.nseconds()) {
+            (Some(audio), Some(video)) => Some(video as i64 - audio as i64),
+            _ => None,
+        }
+    }
+
+    /// Check the drift every second, `on_drift` is called once every time
+    /// the drift goes beyond `max_drift`
+    pub fn start<F>(self: &Arc<Self>, max_drift: Duration, on_drift: F)
+    where
+        F: Fn() + Send + 'static,
+    {
+        let monitor = self.clone();
+        let mut drifting = false;
+        glib::timeout_add_seconds(1, move || {
+            let drift = monitor.drift();
+
+            match drift {
+                Some(drift) if drift.abs() as u128 > max_drift.as_nanos() => {
+                    if !drifting {
+                        warn!("A/V drift of {}ms exceeds the limit", drift / 1_000_000);
+                        drifting = true;
+                        on_drift();
+                    }
+                }
+                Some(_) => {
+                    if drifting {
+                        info!("A/V drift back within limits");
+                    }
+                    drifting = false;
+                }
+                None => drifting = false,
+            }
+
+            glib::Continue(true)
+        });
+    }
+}
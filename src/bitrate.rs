@@ -0,0 +1,85 @@
+//! Incoming bitrate measurement over a sliding window.
+//!
+//! An encoder starved of bandwidth keeps the connection up while sending a
+//! few dozen kbps of mush, which viewers find worse than a clean slate.
+
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use gst::prelude::*;
+
+use crate::gate::LiveGate;
+
+pub struct BitrateMonitor {
+    samples: Mutex<VecDeque<(Instant, usize)>>,
+    window: Duration,
+}
+
+impl BitrateMonitor {
+    pub fn new(window: Duration) -> Arc<Self> {
+        Arc::new(Self {
+            samples: Mutex::new(VecDeque::new()),
+            window,
+        })
+    }
+
+    fn add(&self, size: usize) {
+        let now = Instant::now();
+        let mut samples = self.samples.lock().unwrap();
+
+        samples.push_back((now, size));
+        while samples
+            .front()
+            .map_or(false, |(time, _)| now.duration_since(*time) > self.window)
+        {
+            samples.pop_front();
+        }
+    }
+
+    /// Average bitrate over the window, in bits per second
+    pub fn bitrate(&self) -> u64 {
+        let samples = self.samples.lock().unwrap();
+        let now = Instant::now();
+        let bytes: usize = samples
+            .iter()
+            .filter(|(time, _)| now.duration_since(*time) <= self.window)
+            .map(|(_, size)| size)
+            .sum();
+
+        (bytes as f64 * 8.0 / self.window.as_secs_f64()) as u64
+    }
+
+    /// Measure the data flowing through `pad`, usually the source pad of the
+    /// network source, before any demuxing
+    pub fn install(self: &Arc<Self>, pad: &gst::Pad) {
+        let monitor = self.clone();
+        pad.add_probe(
+            gst::PadProbeType::BUFFER | gst::PadProbeType::BUFFER_LIST,
+            move |_, info| {
+                match info.data {
+                    Some(gst::PadProbeData::Buffer(ref buffer)) => monitor.add(buffer.get_size()),
+                    Some(gst::PadProbeData::BufferList(ref list)) => {
+                        monitor.add(list.iter().map(|buffer| buffer.get_size()).sum())
+                    }
+                    _ => (),
+                }
+                gst::PadProbeReturn::Ok
+            },
+        );
+    }
+
+    /// Hold the live gate closed while the bitrate is below `min_bitrate`
+    pub fn start_fallback(self: &Arc<Self>, gate: &Arc<LiveGate>, min_bitrate: u64) {
+        let monitor = self.clone();
+        let gate = gate.clone();
+        glib::timeout_add_seconds(1, move || {
+            if monitor.bitrate() < min_bitrate {
+                gate.close("input bitrate collapsed");
+            } else {
+                gate.open("input bitrate collapsed");
+            }
+            glib::Continue(true)
+        });
+    }
+}
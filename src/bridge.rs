@@ -0,0 +1,198 @@
+//! Transport of the live video and audio from the RTMP pipeline to the
+//! output pipeline.
+//!
+//! interpipe is the default, but is an out-of-tree plugin that is hard to
+//! come by on some distributions. `proxysink`/`proxysrc` from
+//! gst-plugins-bad pass buffers and events through as they are, so both
+//! pipelines are made to share the clock and base time. With
+//! `appsink`/`appsrc`, buffers are pushed across with their timestamps
+//! reset, and `appsrc` timestamps them on arrival like `stream-sync`
+//! `restart-ts` does. Backup inputs and slate media always use interpipe.
+
+use gst::prelude::*;
+
+use crate::hints;
+use crate::stream_sync::StreamSync;
+use crate::Args;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Bridge {
+    Interpipe,
+    Proxy,
+    App,
+}
+
+impl std::str::FromStr for Bridge {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "interpipe" => Ok(Bridge::Interpipe),
+            "proxy" => Ok(Bridge::Proxy),
+            "app" => Ok(Bridge::App),
+            _ => Err(anyhow::anyhow!("Unknown bridge {}", s)),
+        }
+    }
+}
+
+/// Name of the source end receiving from the sink end `name`
+fn source_name(name: &str) -> String {
+    format!("{}_src", name)
+}
+
+impl Bridge {
+    /// Fail on the options the bridge does not support
+    pub fn check(self, args: &Args) -> Result<(), anyhow::Error> {
+        if self == Bridge::Interpipe {
+            return Ok(());
+        }
+
+        if args.live_rtmp_uri.len() > 1 {
+            anyhow::bail!("Backup inputs require --bridge interpipe");
+        }
+
+        if args.audio_meters {
+            anyhow::bail!("--audio-meters requires --bridge interpipe");
+        }
+
+        if self == Bridge::Proxy && args.jitter_buffer.is_some() {
+            anyhow::bail!("--jitter-buffer requires --bridge interpipe or app");
+        }
+
+        Ok(())
+    }
+
+    /// Sink end named `name`, as a gst-launch description
+    pub fn sink(self, name: &str) -> String {
+        match self {
+            Bridge::Interpipe => format!("interpipesink drop=false sync=true name={}", name),
+            Bridge::Proxy => format!("proxysink name={}", name),
+            Bridge::App => format!("appsink sync=true emit-signals=true name={}", name),
+        }
+    }
+
+    /// Source end receiving from the sink end `name`, the timestamps are
+    /// handled as per `stream_sync` with interpipe
+    pub fn source(
+        self,
+        name: &str,
+        stream_sync: StreamSync,
+    ) -> Result<gst::Element, anyhow::Error> {
+        let source_name = source_name(name);
+
+        match self {
+            Bridge::Interpipe => {
+                let source = hints::make_element("interpipesrc", Some(&source_name))?;
+                source.set_property("listen-to", &name)?;
+                source.set_property("format", &gst::Format::Time)?;
+                source.set_property("is-live", &true)?;
+                source.set_property_from_str("stream-sync", stream_sync.nick());
+                Ok(source)
+            }
+            Bridge::Proxy => {
+                let source = hints::make_element("proxysrc", Some(&source_name))?;
+
+                /* The source restarting must not end the output */
+                source.get_static_pad("src").unwrap().add_probe(
+                    gst::PadProbeType::EVENT_DOWNSTREAM,
+                    |_, info| match info.data {
+                        Some(gst::PadProbeData::Event(ref event))
+                            if event.get_type() == gst::EventType::Eos =>
+                        {
+                            gst::PadProbeReturn::Drop
+                        }
+                        _ => gst::PadProbeReturn::Ok,
+                    },
+                );
+
+                Ok(source)
+            }
+            Bridge::App => {
+                let source = hints::make_element("appsrc", Some(&source_name))?;
+                source.set_property("format", &gst::Format::Time)?;
+                source.set_property("is-live", &true)?;
+                source.set_property("do-timestamp", &true)?;
+                Ok(source)
+            }
+        }
+    }
+
+    /// Connect the source ends in `dest` to the sink ends named `names` in
+    /// `src`, once both pipelines are built
+    pub fn connect(
+        self,
+        src: &gst::Pipeline,
+        dest: &gst::Pipeline,
+        names: &[&str],
+    ) -> Result<(), anyhow::Error> {
+        if self == Bridge::Interpipe {
+            return Ok(());
+        }
+
+        if self == Bridge::Proxy {
+            /* Timestamps are passed through, so running times must match */
+            let clock = gst::SystemClock::obtain();
+            for pipe in &[src, dest] {
+                pipe.use_clock(Some(&clock));
+                pipe.set_start_time(gst::CLOCK_TIME_NONE);
+                pipe.set_base_time(gst::ClockTime::from_seconds(0));
+            }
+        }
+
+        for name in names {
+            let sink = src
+                .get_by_name(name)
+                .ok_or_else(|| anyhow::anyhow!("No bridge sink {}", name))?;
+            let source = dest
+                .get_by_name(&source_name(name))
+                .ok_or_else(|| anyhow::anyhow!("No bridge source {}", name))?;
+
+            match self {
+                Bridge::Interpipe => unreachable!(),
+                Bridge::Proxy => source.set_property("proxysink", &sink)?,
+                Bridge::App => {
+                    sink.connect("new-sample", false, move |values| {
+                        let sink = values[0].get::<gst::Element>().unwrap().unwrap();
+                        push_sample(&sink, &source);
+                        Some(gst::FlowReturn::Ok.to_value())
+                    })?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Pull a sample from `sink` and push it to `source` without timestamps
+fn push_sample(sink: &gst::Element, source: &gst::Element) {
+    let sample = match sink
+        .emit("pull-sample", &[])
+        .ok()
+        .flatten()
+        .and_then(|value| value.get::<gst::Sample>().ok().flatten())
+    {
+        Some(sample) => sample,
+        None => return,
+    };
+
+    if let Some(caps) = sample.get_caps() {
+        let current = source
+            .get_property("caps")
+            .ok()
+            .and_then(|caps| caps.get::<gst::Caps>().ok().flatten());
+        if current.map_or(true, |current| *current != *caps) {
+            let _ = source.set_property("caps", &caps.to_owned());
+        }
+    }
+
+    if let Some(buffer) = sample.get_buffer() {
+        let mut buffer = buffer.copy();
+        {
+            let buffer = buffer.get_mut().unwrap();
+            buffer.set_pts(gst::CLOCK_TIME_NONE);
+            buffer.set_dts(gst::CLOCK_TIME_NONE);
+        }
+        let _ = source.emit("push-buffer", &[&buffer]);
+    }
+}
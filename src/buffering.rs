@@ -0,0 +1,112 @@
+//! Handling of the buffering messages of the RTMP pipeline.
+//!
+//! Pausing while buffering is what playbin expects, but with a live source
+//! every pause adds to the latency for good. The policy decides what is
+//! done instead, and the watermarks add hysteresis: buffering starts below
+//! the low watermark and only ends once the high watermark is reached.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+use gst::prelude::*;
+
+use crate::gate::LiveGate;
+
+/// Check that the watermarks leave room for hysteresis, equal ones only
+/// make sense when buffering ends with a full buffer
+pub fn check(low_watermark: i32, high_watermark: i32) -> Result<(), anyhow::Error> {
+    if low_watermark > high_watermark || (low_watermark == high_watermark && high_watermark < 100) {
+        anyhow::bail!(
+            "--buffering-low-watermark {} must be below --buffering-high-watermark {}",
+            low_watermark,
+            high_watermark
+        );
+    }
+
+    Ok(())
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BufferingPolicy {
+    /// Pause the pipeline until buffering is done
+    Pause,
+    /// Keep playing
+    Ignore,
+    /// Keep playing, but show the slate until buffering is done
+    Slate,
+}
+
+impl std::str::FromStr for BufferingPolicy {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "pause" => Ok(BufferingPolicy::Pause),
+            "ignore" => Ok(BufferingPolicy::Ignore),
+            "slate" => Ok(BufferingPolicy::Slate),
+            _ => Err(anyhow::anyhow!("Unknown buffering policy {}", s)),
+        }
+    }
+}
+
+pub struct Buffering {
+    policy: BufferingPolicy,
+    low_watermark: i32,
+    high_watermark: i32,
+    /// Closed by the slate policy, unset for backup inputs as they share
+    /// the gate with the primary input
+    gate: Option<Arc<LiveGate>>,
+    buffering: AtomicBool,
+}
+
+impl Buffering {
+    pub fn new(
+        policy: BufferingPolicy,
+        low_watermark: i32,
+        high_watermark: i32,
+        gate: Option<Arc<LiveGate>>,
+    ) -> Self {
+        Self {
+            policy,
+            low_watermark,
+            high_watermark,
+            gate,
+            buffering: AtomicBool::new(false),
+        }
+    }
+
+    /// Apply the policy to the buffering `percent` reported by `source`
+    pub fn handle(&self, source: &gst::Element, percent: i32) {
+        let buffering = self.buffering.load(Ordering::SeqCst);
+
+        if !buffering && percent < self.low_watermark {
+            self.buffering.store(true, Ordering::SeqCst);
+
+            match self.policy {
+                BufferingPolicy::Pause => {
+                    let _ = source.set_state(gst::State::Paused);
+                }
+                BufferingPolicy::Slate => {
+                    if let Some(ref gate) = self.gate {
+                        gate.close("source buffering");
+                    }
+                }
+                BufferingPolicy::Ignore => (),
+            }
+        } else if buffering && percent >= self.high_watermark {
+            self.buffering.store(false, Ordering::SeqCst);
+
+            match self.policy {
+                BufferingPolicy::Pause => {
+                    let _ = source.set_state(gst::State::Playing);
+                }
+                BufferingPolicy::Slate => {
+                    if let Some(ref gate) = self.gate {
+                        gate.open("source buffering");
+                    }
+                }
+                BufferingPolicy::Ignore => (),
+            }
+        }
+    }
+}
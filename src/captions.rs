@@ -0,0 +1,152 @@
+//! Closed captions on the output.
+//!
+//! The compositor drops the caption metadata of the live video, so
+//! `ccextractor` takes it off the live video before it goes in and
+//! `cccombiner` puts it back onto the output, where encoders such as
+//! `x264enc` carry it. While the slate shows, the captions are switched
+//! over to the slate caption, converted by `tttocea608` and repeated every
+//! few seconds for viewers tuning in.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use gst::prelude::*;
+
+use crate::hints;
+use crate::status::Status;
+
+/// How often the slate caption is sent again
+const REPEAT: Duration = Duration::from_secs(5);
+
+/// What the captions of both sources are converted to
+const CAPTION_CAPS: &str = "closedcaption/x-cea-708,format=cc_data";
+
+pub struct Captions {
+    selector: gst::Element,
+    live_pad: Option<gst::Pad>,
+    slate: Option<(gst::Element, gst::Pad, String)>,
+}
+
+impl Captions {
+    /// Insert the captions between `upstream` and `downstream` in `pipe`,
+    /// those of the live video from the `ccextractor` `extractor` if any,
+    /// and `slate_caption` while the slate shows if any
+    pub fn build(
+        pipe: &gst::Pipeline,
+        upstream: &gst::Element,
+        downstream: &gst::Element,
+        extractor: Option<&gst::Element>,
+        slate_caption: Option<&str>,
+    ) -> Result<Self, anyhow::Error> {
+        let combiner = hints::make_element("cccombiner", None)?;
+        let selector = hints::make_element("input-selector", None)?;
+        /* Captions of the source off air are dropped rather than held back */
+        selector.set_property("sync-streams", &false)?;
+
+        pipe.add_many(&[&combiner, &selector])?;
+        upstream.link_pads(Some("src"), &combiner, Some("sink"))?;
+        combiner.link_pads(Some("src"), downstream, None)?;
+        selector.link_pads(Some("src"), &combiner, Some("caption"))?;
+
+        let live_pad = match extractor {
+            Some(extractor) => {
+                let branch = hints::parse_bin(&format!(
+                    "queue ! ccconverter ! capsfilter caps=\"{}\"",
+                    CAPTION_CAPS
+                ))?;
+                pipe.add(&branch)?;
+                let pad = selector.get_request_pad("sink_%u").unwrap();
+                branch.get_static_pad("src").unwrap().link(&pad)?;
+
+                /* The caption pad only appears once captions show up */
+                let sink = branch.get_static_pad("sink").unwrap();
+                extractor.connect_pad_added(move |_, pad| {
+                    if pad.get_name() == "caption" {
+                        if let Err(err) = pad.link(&sink) {
+                            warn!("Failed to link live captions: {:?}", err);
+                        }
+                    }
+                });
+
+                Some(pad)
+            }
+            None => None,
+        };
+
+        let slate = match slate_caption {
+            Some(text) => {
+                let branch = hints::parse_bin(&format!(
+                    "appsrc name=slate_caption is-live=true format=time do-timestamp=true \
+                     caps=\"text/x-raw,format=utf8\" ! tttocea608 ! ccconverter ! \
+                     capsfilter caps=\"{}\"",
+                    CAPTION_CAPS
+                ))?;
+                pipe.add(&branch)?;
+                let pad = selector.get_request_pad("sink_%u").unwrap();
+                branch.get_static_pad("src").unwrap().link(&pad)?;
+
+                Some((
+                    branch.get_by_name("slate_caption").unwrap(),
+                    pad,
+                    text.to_string(),
+                ))
+            }
+            None => None,
+        };
+
+        Ok(Self {
+            selector,
+            live_pad,
+            slate,
+        })
+    }
+
+    /// Switch the captions along with what `status` shows on air
+    pub fn start(self, status: &Arc<Status>) {
+        let captions = Arc::new(self);
+        /* The output starts on the slate */
+        let live = Arc::new(AtomicBool::new(false));
+        captions.select(false);
+
+        let captions_clone = captions.clone();
+        let live_clone = live.clone();
+        status.connect_live_changed(move |shows_live| {
+            live_clone.store(shows_live, Ordering::SeqCst);
+            captions_clone.select(shows_live);
+
+            if !shows_live {
+                captions_clone.send_slate_caption();
+            }
+        });
+
+        if captions.slate.is_some() {
+            glib::timeout_add(REPEAT.as_millis() as u32, move || {
+                if !live.load(Ordering::SeqCst) {
+                    captions.send_slate_caption();
+                }
+                glib::Continue(true)
+            });
+        }
+    }
+
+    /// Switch to the captions of the live video or of the slate
+    fn select(&self, live: bool) {
+        /* With a single source, it is always the active one */
+        if let (Some(live_pad), Some((_, slate_pad, _))) = (&self.live_pad, &self.slate) {
+            let pad = if live { live_pad } else { slate_pad };
+            self.selector.set_property("active-pad", pad).unwrap();
+        }
+    }
+
+    fn send_slate_caption(&self) {
+        if let Some((ref source, _, ref text)) = self.slate {
+            let mut buffer = gst::Buffer::from_slice(text.clone().into_bytes());
+            buffer
+                .get_mut()
+                .unwrap()
+                .set_duration(gst::ClockTime::from_nseconds(REPEAT.as_nanos() as u64));
+            let _ = source.emit("push-buffer", &[&buffer]);
+        }
+    }
+}
@@ -0,0 +1,187 @@
+//! Fault injection for soak testing the fallback behaviour.
+//!
+//! Once a second, each kind of fault is injected with its own probability:
+//! disconnects error out the RTMP pipeline like a dropped connection would,
+//! stalls drop the decoded video for a while, bitrate drops discard most of
+//! the data coming from the network for a while, and clock jumps shift the
+//! timestamps of the decoded video forward for good. With a seed, the same
+//! faults are injected on every run.
+
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use gst::prelude::*;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+use crate::logging::{self, Level};
+
+/// Share of the network data still let through during bitrate drops
+const KEPT_RATIO: f64 = 0.1;
+
+#[derive(Debug, Clone)]
+pub struct ChaosOptions {
+    pub seed: Option<u64>,
+    /// Probabilities are per second
+    pub disconnect_probability: f64,
+    pub stall_probability: f64,
+    pub stall_duration: Duration,
+    pub bitrate_drop_probability: f64,
+    pub bitrate_drop_duration: Duration,
+    pub clock_jump_probability: f64,
+    pub clock_jump: Duration,
+}
+
+impl ChaosOptions {
+    pub fn is_enabled(&self) -> bool {
+        self.disconnect_probability > 0.0
+            || self.stall_probability > 0.0
+            || self.bitrate_drop_probability > 0.0
+            || self.clock_jump_probability > 0.0
+    }
+}
+
+struct State {
+    rng: StdRng,
+    disconnect_pending: bool,
+    stalled_until: Option<Instant>,
+    starved_until: Option<Instant>,
+    /// Added to the timestamps of the decoded video
+    offset: gst::ClockTime,
+}
+
+pub struct Chaos {
+    options: ChaosOptions,
+    state: Mutex<State>,
+}
+
+fn inject(fault: &str, fields: serde_json::Value) {
+    let mut fields = fields;
+    fields["fault"] = fault.into();
+    logging::event(
+        Level::Warn,
+        "chaos",
+        &format!("Injecting {}", fault),
+        fields,
+    );
+}
+
+fn is_until(until: Option<Instant>) -> bool {
+    until.map_or(false, |until| Instant::now() < until)
+}
+
+impl Chaos {
+    pub fn new(options: ChaosOptions) -> Arc<Self> {
+        let rng = match options.seed {
+            Some(seed) => StdRng::seed_from_u64(seed),
+            None => StdRng::from_entropy(),
+        };
+
+        Arc::new(Self {
+            options,
+            state: Mutex::new(State {
+                rng,
+                disconnect_pending: false,
+                stalled_until: None,
+                starved_until: None,
+                offset: gst::ClockTime::from_nseconds(0),
+            }),
+        })
+    }
+
+    /// Inject disconnects, stalls and clock jumps into the decoded video
+    /// flowing through `pad`
+    pub fn install(self: &Arc<Self>, pad: &gst::Pad) {
+        let chaos = self.clone();
+        pad.add_probe(gst::PadProbeType::BUFFER, move |pad, info| {
+            let mut state = chaos.state.lock().unwrap();
+
+            if state.disconnect_pending {
+                state.disconnect_pending = false;
+                let element = pad.get_parent_element().unwrap();
+                gst::gst_element_error!(
+                    element,
+                    gst::ResourceError::Read,
+                    ["Simulated disconnect"]
+                );
+            }
+
+            if is_until(state.stalled_until) {
+                return gst::PadProbeReturn::Drop;
+            }
+
+            if state.offset.nseconds() != Some(0) {
+                if let Some(gst::PadProbeData::Buffer(ref mut buffer)) = info.data {
+                    let buffer = buffer.make_mut();
+                    buffer.set_pts(buffer.get_pts() + state.offset);
+                    buffer.set_dts(buffer.get_dts() + state.offset);
+                }
+            }
+
+            gst::PadProbeReturn::Ok
+        });
+    }
+
+    /// Inject bitrate drops into the network data flowing through `pad`
+    pub fn install_network(self: &Arc<Self>, pad: &gst::Pad) {
+        let chaos = self.clone();
+        pad.add_probe(gst::PadProbeType::BUFFER, move |_, _| {
+            let mut state = chaos.state.lock().unwrap();
+
+            if is_until(state.starved_until) && !state.rng.gen_bool(KEPT_RATIO) {
+                gst::PadProbeReturn::Drop
+            } else {
+                gst::PadProbeReturn::Ok
+            }
+        });
+    }
+
+    /// Roll the dice every second
+    pub fn start(self: &Arc<Self>) {
+        let chaos = self.clone();
+        glib::timeout_add_seconds(1, move || {
+            let options = &chaos.options;
+            let mut state = chaos.state.lock().unwrap();
+            let mut roll = |probability: f64| state.rng.gen_bool(probability.clamp(0.0, 1.0));
+
+            let disconnect = roll(options.disconnect_probability);
+            let stall = roll(options.stall_probability);
+            let bitrate_drop = roll(options.bitrate_drop_probability);
+            let clock_jump = roll(options.clock_jump_probability);
+
+            if disconnect {
+                inject("disconnect", serde_json::json!({}));
+                state.disconnect_pending = true;
+            }
+
+            if stall && !is_until(state.stalled_until) {
+                inject(
+                    "stall",
+                    serde_json::json!({ "duration_ms": options.stall_duration.as_millis() as u64 }),
+                );
+                state.stalled_until = Some(Instant::now() + options.stall_duration);
+            }
+
+            if bitrate_drop && !is_until(state.starved_until) {
+                inject(
+                    "bitrate-drop",
+                    serde_json::json!({
+                        "duration_ms": options.bitrate_drop_duration.as_millis() as u64,
+                    }),
+                );
+                state.starved_until = Some(Instant::now() + options.bitrate_drop_duration);
+            }
+
+            if clock_jump {
+                inject(
+                    "clock-jump",
+                    serde_json::json!({ "jump_ms": options.clock_jump.as_millis() as u64 }),
+                );
+                state.offset = state.offset
+                    + gst::ClockTime::from_nseconds(options.clock_jump.as_nanos() as u64);
+            }
+
+            glib::Continue(true)
+        });
+    }
+}
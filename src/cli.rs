@@ -0,0 +1,1893 @@
+//! Command line parsing.
+
+use std::net::IpAddr;
+use std::path::PathBuf;
+use std::time::{Duration, SystemTime};
+
+use clap::{CommandFactory, Parser};
+
+use crate::archive::{ArchiveFormat, ArchiveOptions};
+use crate::audio::FallbackPolicy;
+use crate::bridge::Bridge;
+use crate::buffering::BufferingPolicy;
+use crate::chaos::ChaosOptions;
+use crate::cpu;
+use crate::deinterlace::Deinterlace;
+use crate::encoder::Encoder;
+use crate::gap::HoldMode;
+use crate::hwaccel::HwDecoder;
+use crate::launch::PipelineFormat;
+use crate::layout::{Layout, LayoutOptions, Rect};
+use crate::logging::{Level, LogFormat};
+use crate::logo::LogoPosition;
+use crate::netclock::PipelineClock;
+use crate::output::{Encoding, HlsOptions, TsOptions};
+use crate::ranks::RankOverride;
+use crate::reconnect::GiveUpAction;
+use crate::rtsp::{RtspOptions, RtspProtocol};
+use crate::slate::SlateAssets;
+use crate::srt::{SrtMode, SrtOptions};
+use crate::stream_sync::StreamSync;
+use crate::switch::SwitchMode;
+use crate::tally::{TallyOutput, TslVersion};
+use crate::thumbnails::{ThumbnailOptions, ThumbnailTarget};
+use crate::tracks::AudioTrack;
+use crate::PreviewSink;
+
+/// Parse a duration such as "5s" or "250ms", bare numbers are seconds
+pub fn parse_duration(s: &str) -> Result<Duration, String> {
+    if let Ok(seconds) = s.parse::<f64>() {
+        if !seconds.is_finite() {
+            return Err("durations must be finite".to_string());
+        }
+        if seconds < 0.0 {
+            return Err("durations can not be negative".to_string());
+        }
+        /* Duration::from_secs_f64() panics beyond its range */
+        if seconds >= u64::MAX as f64 {
+            return Err("duration is too long".to_string());
+        }
+        return Ok(Duration::from_secs_f64(seconds));
+    }
+
+    humantime::parse_duration(s).map_err(|err| err.to_string())
+}
+
+/// Parse a framerate such as "30", "29.97" or "30000/1001"
+pub fn parse_framerate(s: &str) -> Result<(i32, i32), String> {
+    let (numer, denom) = match s.find('/') {
+        Some(slash) => (
+            s[..slash].parse::<i32>().map_err(|err| err.to_string())?,
+            s[slash + 1..].parse::<i32>().map_err(|err| err.to_string())?,
+        ),
+        None => {
+            let fps = s.parse::<f64>().map_err(|err| err.to_string())?;
+            ((fps * 1000.0).round() as i32, 1000)
+        }
+    };
+
+    if numer <= 0 || denom <= 0 {
+        return Err("framerates must be positive".to_string());
+    }
+
+    Ok((numer, denom))
+}
+
+/// Parse a fraction from 0 to 1
+pub fn parse_fraction(s: &str) -> Result<f64, String> {
+    match s.parse::<f64>() {
+        Ok(fraction) if (0.0..=1.0).contains(&fraction) => Ok(fraction),
+        _ => Err(format!("{} is not a number from 0 to 1", s)),
+    }
+}
+
+/// Parse a percentage from 0 to 100
+pub fn parse_percent(s: &str) -> Result<i32, String> {
+    match s.parse::<i32>() {
+        Ok(percent) if (0..=100).contains(&percent) => Ok(percent),
+        _ => Err(format!("{} is not a percentage from 0 to 100", s)),
+    }
+}
+
+/// Parse a width or height in pixels
+pub fn parse_dimension(s: &str) -> Result<i32, String> {
+    match s.parse::<i32>() {
+        Ok(pixels) if pixels > 0 => Ok(pixels),
+        _ => Err(format!("{} is not a positive number of pixels", s)),
+    }
+}
+
+/// Parse a size in MB, returned in bytes
+pub fn parse_megabytes(s: &str) -> Result<u64, String> {
+    s.parse::<u64>()
+        .map_err(|err| err.to_string())?
+        .checked_mul(1_000_000)
+        .ok_or_else(|| format!("{}MB is too large", s))
+}
+
+/// Check that `s` is an absolute URI, as GStreamer expects them
+pub fn parse_uri(s: &str) -> Result<String, String> {
+    let scheme = s
+        .find(':')
+        .map(|end| &s[..end])
+        .ok_or_else(|| format!("{} is not a URI", s))?;
+
+    let valid_scheme = scheme.starts_with(|c: char| c.is_ascii_alphabetic())
+        && scheme
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '+' || c == '-' || c == '.');
+
+    if !valid_scheme || s.len() == scheme.len() + 1 {
+        return Err(format!("{} is not a URI", s));
+    }
+
+    Ok(s.to_string())
+}
+
+/// Parse an RFC 3339 timestamp such as "2026-10-16T18:00:00Z"
+pub fn parse_timestamp(s: &str) -> Result<SystemTime, String> {
+    humantime::parse_rfc3339_weak(s).map_err(|err| err.to_string())
+}
+
+#[derive(Debug, Parser)]
+#[clap(name = "rtmp-slate-fallback probe", about = "Print the streams of a source URI and exit")]
+pub struct ProbeArgs {
+    #[clap(parse(try_from_str = parse_uri))]
+    pub uri: String,
+    #[clap(
+        long,
+        default_value = "10s",
+        parse(try_from_str = parse_duration),
+        help = "Give up after this long"
+    )]
+    pub timeout: Duration,
+}
+
+#[derive(Debug, Parser)]
+#[clap(
+    name = "rtmp-slate-fallback setup",
+    about = "Interactively check the environment and write a config file"
+)]
+pub struct SetupArgs {
+    #[clap(
+        long,
+        default_value = "rtmp-slate-fallback.toml",
+        parse(from_os_str),
+        help = "Where to write the config file, as TOML or YAML depending on its extension"
+    )]
+    pub output: PathBuf,
+}
+
+#[derive(Debug, Parser)]
+#[clap(
+    name = "rtmp-slate-fallback supervise",
+    about = "Run and supervise one process per channel"
+)]
+pub struct SuperviseArgs {
+    #[clap(
+        parse(from_os_str),
+        help = "File with one channel per line, its name followed by its arguments"
+    )]
+    pub channels: PathBuf,
+    #[clap(
+        long,
+        default_value = "1s",
+        parse(try_from_str = parse_duration),
+        help = "How long to wait before restarting a channel that exited"
+    )]
+    pub restart_delay: Duration,
+}
+
+#[derive(Debug, Parser)]
+#[clap(
+    name = "rtmp-slate-fallback completions",
+    about = "Print a shell completion script and exit"
+)]
+pub struct CompletionsArgs {
+    #[clap(arg_enum)]
+    pub shell: clap_complete::Shell,
+}
+
+#[derive(Debug, Parser)]
+#[clap(
+    name = "rtmp-slate-fallback",
+    version,
+    about = "Relay a live stream, falling back to a slate when it fails",
+    args_override_self = true,
+    after_help = "Run `rtmp-slate-fallback setup` for an interactive first-run setup, \
+                  `rtmp-slate-fallback probe --help` to inspect a source instead, \
+                  `rtmp-slate-fallback supervise --help` to run several channels, or \
+                  `rtmp-slate-fallback completions <SHELL>` to generate shell completions."
+)]
+pub struct Args {
+    #[clap(
+        long,
+        required = true,
+        multiple_occurrences = true,
+        parse(try_from_str = parse_uri),
+        help_heading = "INPUT",
+        help = "Live input URI, e.g. rtmp://, srt:// or rtsp://, repeat for backup inputs in order of priority"
+    )]
+    pub live_rtmp_uri: Vec<String>,
+    #[clap(
+        long,
+        default_value = "1s",
+        parse(try_from_str = parse_duration),
+        help_heading = "INPUT",
+        help = "Fail over to the next backup input once the current input delivered no video for this long"
+    )]
+    pub failover_after: Duration,
+    #[clap(
+        long,
+        default_value = "10s",
+        parse(try_from_str = parse_duration),
+        help_heading = "INPUT",
+        help = "Return to a higher priority input once it delivered video continuously for this long"
+    )]
+    pub failover_return_after: Duration,
+    #[clap(
+        long,
+        help_heading = "MONITORING",
+        help = "Show the first two inputs side by side with their offset and difference, to check that redundant feeds are in sync"
+    )]
+    pub compare_inputs: bool,
+    #[clap(
+        long,
+        possible_values = &["caller", "listener", "rendezvous"],
+        help_heading = "INPUT",
+        help = "SRT connection mode, overriding the mode in srt:// URIs"
+    )]
+    pub srt_mode: Option<SrtMode>,
+    #[clap(
+        long,
+        help_heading = "INPUT",
+        help = "Passphrase to decrypt SRT inputs with"
+    )]
+    pub srt_passphrase: Option<String>,
+    #[clap(
+        long,
+        possible_values = &["128", "192", "256"],
+        requires = "srt_passphrase",
+        help_heading = "INPUT",
+        help = "AES key length of SRT inputs in bits"
+    )]
+    pub srt_key_length: Option<u32>,
+    #[clap(
+        long,
+        parse(try_from_str = parse_duration),
+        help_heading = "INPUT",
+        help = "SRT receiver latency, for retransmissions, e.g. 500ms"
+    )]
+    pub srt_latency: Option<Duration>,
+    #[clap(
+        long,
+        use_value_delimiter = true,
+        possible_values = &["tcp", "udp", "udp-mcast"],
+        help_heading = "INPUT",
+        help = "Lower transports to use for RTSP inputs, e.g. tcp or udp,tcp"
+    )]
+    pub rtsp_protocols: Vec<RtspProtocol>,
+    #[clap(
+        long,
+        parse(try_from_str = parse_duration),
+        help_heading = "INPUT",
+        help = "Time without data over UDP before RTSP inputs retry over TCP"
+    )]
+    pub rtsp_timeout: Option<Duration>,
+    #[clap(
+        long,
+        parse(try_from_str = parse_duration),
+        help_heading = "INPUT",
+        help = "Timeout of RTSP connections and requests over TCP"
+    )]
+    pub rtsp_tcp_timeout: Option<Duration>,
+    #[clap(
+        long,
+        parse(try_from_str = parse_duration),
+        help_heading = "INPUT",
+        help = "RTSP jitterbuffer latency, e.g. 200ms"
+    )]
+    pub rtsp_latency: Option<Duration>,
+    #[clap(
+        long,
+        default_value = "none",
+        possible_values = &["none", "nvv4l2", "v4l2"],
+        help_heading = "INPUT",
+        help = "Prefer hardware decoders for the live stream"
+    )]
+    pub hw_decoder: HwDecoder,
+    #[clap(
+        long,
+        help_heading = "INPUT",
+        help = "Number of CPUs to size codec thread pools and encoder presets for, detected from cgroup limits by default"
+    )]
+    pub cpu_budget: Option<f64>,
+    #[clap(
+        long,
+        help_heading = "INPUT",
+        help = "Decoder element factory to use for the live stream, e.g. avdec_h264"
+    )]
+    pub decoder: Option<String>,
+    #[clap(
+        long,
+        help_heading = "INPUT",
+        help = "Parser element factory to use for the live stream, e.g. h264parse"
+    )]
+    pub parser: Option<String>,
+    #[clap(
+        long,
+        help_heading = "INPUT",
+        help = "Video converter element factory to use in place of videoconvert for the live video and --encoder-element, e.g. v4l2convert"
+    )]
+    pub converter: Option<String>,
+    #[clap(
+        long = "rank",
+        value_name = "FACTORY=RANK",
+        multiple_occurrences = true,
+        help_heading = "INPUT",
+        help = "Override the autoplugging rank of an element factory, none, marginal, secondary, primary or a number"
+    )]
+    pub ranks: Vec<RankOverride>,
+    #[clap(
+        long,
+        parse(try_from_str = parse_duration),
+        help_heading = "INPUT",
+        help = "Buffer the live input for this long to absorb network jitter, instead of pausing on buffering"
+    )]
+    pub jitter_buffer: Option<Duration>,
+    #[clap(
+        long,
+        requires = "jitter_buffer",
+        help_heading = "INPUT",
+        help = "Drop live buffers arriving later than the jitter buffer allows, instead of rendering them late"
+    )]
+    pub drop_late: bool,
+    #[clap(
+        long,
+        default_value = "restart-ts",
+        possible_values = &["restart-ts", "compensate-ts", "passthrough-ts"],
+        help_heading = "INPUT",
+        help = "How interpipesrc timestamps the live video and audio in the output pipeline"
+    )]
+    pub stream_sync: StreamSync,
+    #[clap(
+        long,
+        default_value = "interpipe",
+        possible_values = &["interpipe", "proxy", "app"],
+        help_heading = "INPUT",
+        help = "Elements carrying the live input to the output pipeline, interpipe, proxysink/proxysrc or appsink/appsrc"
+    )]
+    pub bridge: Bridge,
+    #[clap(
+        long,
+        help_heading = "INPUT",
+        help = "Decode the live input within the output pipeline with uridecodebin3, instead of a playbin3 pipeline of its own"
+    )]
+    pub single_pipeline: bool,
+    #[clap(
+        long,
+        default_value = "0",
+        allow_hyphen_values = true,
+        help_heading = "INPUT",
+        help = "Delay the live audio by this many milliseconds to fix lip-sync, or the live video if negative"
+    )]
+    pub av_offset_ms: i64,
+    #[clap(
+        long,
+        help_heading = "INPUT",
+        help = "Play this audio track of inputs with several, by index from 0 or by language code, e.g. 1 or deu"
+    )]
+    pub audio_track: Option<AudioTrack>,
+    #[clap(
+        long,
+        possible_values = &["pause", "ignore", "slate"],
+        help_heading = "INPUT",
+        help = "While the source buffers, pause it, keep playing, or keep playing and show the slate [default: pause, ignore with --jitter-buffer]"
+    )]
+    pub buffering_policy: Option<BufferingPolicy>,
+    #[clap(
+        long,
+        default_value = "100",
+        parse(try_from_str = parse_percent),
+        help_heading = "INPUT",
+        help = "Start buffering when the source reports less than N percent"
+    )]
+    pub buffering_low_watermark: i32,
+    #[clap(
+        long,
+        default_value = "100",
+        parse(try_from_str = parse_percent),
+        help_heading = "INPUT",
+        help = "Stop buffering once the source reports N percent"
+    )]
+    pub buffering_high_watermark: i32,
+    #[clap(
+        long,
+        default_value = "1s",
+        parse(try_from_str = parse_duration),
+        help_heading = "INPUT",
+        help = "Delay before reconnecting to the source after it failed, doubling with every failed attempt"
+    )]
+    pub reconnect_delay: Duration,
+    #[clap(
+        long,
+        default_value = "30s",
+        parse(try_from_str = parse_duration),
+        help_heading = "INPUT",
+        help = "Maximum delay between reconnection attempts"
+    )]
+    pub reconnect_max_delay: Duration,
+    #[clap(
+        long,
+        default_value = "0.2",
+        parse(try_from_str = parse_fraction),
+        help_heading = "INPUT",
+        help = "Fraction of the reconnection delay it is randomised by, from 0 to 1"
+    )]
+    pub reconnect_jitter: f64,
+    #[clap(
+        long,
+        alias = "max-reconnects",
+        help_heading = "INPUT",
+        help = "Give up on the source after this many failed reconnection attempts"
+    )]
+    pub reconnect_max_retries: Option<u32>,
+    #[clap(
+        long,
+        default_value = "stay-on-slate",
+        possible_values = &["exit", "stay-on-slate", "run-command"],
+        help_heading = "INPUT",
+        help = "Once the source is given up on, finalize the output and exit with code 5, stay on the slate, or run --on-giveup-command"
+    )]
+    pub on_giveup: GiveUpAction,
+    #[clap(
+        long,
+        required_if_eq("on_giveup", "run-command"),
+        help_heading = "INPUT",
+        help = "Shell command run with --on-giveup run-command"
+    )]
+    pub on_giveup_command: Option<String>,
+    #[clap(
+        long,
+        parse(try_from_str = parse_duration),
+        help_heading = "INPUT",
+        help = "Reconnect, and log a startup-timeout event, when the source is not live this long after startup"
+    )]
+    pub startup_timeout: Option<Duration>,
+
+    #[clap(
+        long,
+        parse(try_from_str = parse_duration),
+        help_heading = "FALLBACK",
+        help = "Make compositor discard RTMP buffers after this long"
+    )]
+    pub discard_after: Option<Duration>,
+    #[clap(
+        long,
+        default_value = "repeat",
+        possible_values = &["repeat", "gap"],
+        help_heading = "FALLBACK",
+        help = "While the source is down, repeat the last live buffer up to --discard-after, or send GAP events and show the slate right away"
+    )]
+    pub hold: HoldMode,
+    #[clap(
+        long,
+        parse(try_from_str = parse_duration),
+        help_heading = "FALLBACK",
+        help = "Only switch back from the slate once the live video flowed without interruption for this long"
+    )]
+    pub return_delay: Option<Duration>,
+    #[clap(
+        long,
+        parse(try_from_str = parse_duration),
+        help_heading = "FALLBACK",
+        help = "Start on the slate, and only switch to the live video once it flowed without interruption for this long, --return-delay by default"
+    )]
+    pub startup_delay: Option<Duration>,
+    #[clap(
+        long,
+        parse(try_from_str = parse_duration),
+        help_heading = "FALLBACK",
+        help = "Crossfade between live and slate over this long instead of cutting"
+    )]
+    pub transition_duration: Option<Duration>,
+    #[clap(
+        long,
+        default_value = "compositor",
+        possible_values = &["compositor", "fallbackswitch"],
+        help_heading = "FALLBACK",
+        help = "Switch to the slate in the compositor, or with fallbackswitch from gst-plugins-rs"
+    )]
+    pub switch_mode: SwitchMode,
+    #[clap(
+        long,
+        default_value = "switch",
+        possible_values = &["switch", "pip", "side-by-side"],
+        help_heading = "FALLBACK",
+        help = "Replace the live video with the slate, or show the slate in a corner of or next to the last live frame"
+    )]
+    pub layout: Layout,
+    #[clap(
+        long,
+        default_value = "bottom-right",
+        possible_values = &["top-left", "top-right", "bottom-left", "bottom-right"],
+        help_heading = "FALLBACK",
+        help = "Corner of the output the slate is placed in with --layout pip"
+    )]
+    pub pip_position: LogoPosition,
+    #[clap(
+        long,
+        help_heading = "FALLBACK",
+        help = "Position and size of the last live frame while the slate shows, as X,Y,WIDTHxHEIGHT"
+    )]
+    pub live_rect: Option<Rect>,
+    #[clap(
+        long,
+        help_heading = "FALLBACK",
+        help = "Position and size of the slate while it shows, as X,Y,WIDTHxHEIGHT"
+    )]
+    pub slate_rect: Option<Rect>,
+    #[clap(
+        long,
+        default_value = "linked",
+        possible_values = &["linked", "independent"],
+        help_heading = "FALLBACK",
+        help = "Switch the audio along with the video, or keep the live audio on air for as long as it arrives"
+    )]
+    pub fallback_policy: FallbackPolicy,
+    #[clap(
+        long,
+        parse(try_from_str = parse_uri),
+        help_heading = "FALLBACK",
+        help = "Image or video to show as the slate, looping videos, a test pattern by default"
+    )]
+    pub slate_uri: Option<String>,
+    #[clap(
+        long,
+        parse(try_from_str = parse_uri),
+        help_heading = "FALLBACK",
+        help = "Image or video to show as the slate until the source is first live, instead of --slate-uri"
+    )]
+    pub slate_starting_uri: Option<String>,
+    #[clap(
+        long,
+        parse(try_from_str = parse_uri),
+        help_heading = "FALLBACK",
+        help = "Image or video to show as the slate when the source failed, instead of --slate-uri"
+    )]
+    pub slate_error_uri: Option<String>,
+    #[clap(
+        long,
+        parse(try_from_str = parse_uri),
+        help_heading = "FALLBACK",
+        help = "Image or video to show as the slate when the source ended, instead of --slate-uri"
+    )]
+    pub slate_eos_uri: Option<String>,
+    #[clap(
+        long,
+        parse(from_os_str),
+        help_heading = "FALLBACK",
+        help = "Directory keeping the slate asset uploaded through the control API, played instead of --slate-uri"
+    )]
+    pub slate_upload_dir: Option<PathBuf>,
+    #[clap(
+        long,
+        parse(try_from_str = parse_uri),
+        help_heading = "FALLBACK",
+        help = "Media to loop as the slate audio, silence by default"
+    )]
+    pub slate_audio: Option<String>,
+    #[clap(
+        long,
+        help_heading = "FALLBACK",
+        help = "Message on the slate for any reason without its own message, {downtime}, {time} and {uri} are replaced, e.g. \"Stream down for {downtime}\""
+    )]
+    pub slate_text: Option<String>,
+    #[clap(
+        long,
+        help_heading = "FALLBACK",
+        help = "Closed caption sent while on slate, e.g. \"We are experiencing technical difficulties\""
+    )]
+    pub slate_caption: Option<String>,
+    #[clap(
+        long,
+        help_heading = "FALLBACK",
+        help = "Message on the slate until the source is first live, e.g. \"Starting soon\""
+    )]
+    pub slate_starting_text: Option<String>,
+    #[clap(
+        long,
+        help_heading = "FALLBACK",
+        help = "Message on the slate when the source failed, e.g. \"Technical difficulties\""
+    )]
+    pub slate_error_text: Option<String>,
+    #[clap(
+        long,
+        help_heading = "FALLBACK",
+        help = "Message on the slate when the source ended, e.g. \"Stream over\""
+    )]
+    pub slate_eos_text: Option<String>,
+    #[clap(
+        long,
+        parse(from_os_str),
+        help_heading = "FALLBACK",
+        help = "Rhai script hooking into fallback decisions and switches"
+    )]
+    pub script: Option<PathBuf>,
+    #[clap(
+        long,
+        parse(try_from_str = parse_duration),
+        help_heading = "FALLBACK",
+        help = "Delay the live input by this long, playing out the buffered input after a drop"
+    )]
+    pub time_shift: Option<Duration>,
+    #[clap(
+        long,
+        requires = "time_shift",
+        help_heading = "FALLBACK",
+        help = "After a drop, resume the live input from just before it instead of jumping over the outage"
+    )]
+    pub catch_up: bool,
+
+    #[clap(
+        long,
+        default_value = "1280",
+        parse(try_from_str = parse_dimension),
+        help_heading = "OUTPUT",
+        help = "Width of the output, the live video and the slate are scaled to it"
+    )]
+    pub output_width: i32,
+    #[clap(
+        long,
+        default_value = "720",
+        parse(try_from_str = parse_dimension),
+        help_heading = "OUTPUT",
+        help = "Height of the output, the live video and the slate are scaled to it"
+    )]
+    pub output_height: i32,
+    #[clap(
+        long,
+        parse(try_from_str = parse_framerate),
+        help_heading = "OUTPUT",
+        help = "Framerate of the output, e.g. 25 or 30000/1001, higher live framerates are decimated"
+    )]
+    pub output_framerate: Option<(i32, i32)>,
+    #[clap(
+        long,
+        default_value = "auto",
+        possible_values = &["auto", "on", "off"],
+        help_heading = "OUTPUT",
+        help = "Deinterlace the live video when flagged as interlaced, always, or never"
+    )]
+    pub deinterlace: Deinterlace,
+    #[clap(
+        long,
+        help_heading = "OUTPUT",
+        help = "Raw video format of the output, e.g. I420 or NV12"
+    )]
+    pub output_format: Option<String>,
+    #[clap(
+        long,
+        help_heading = "OUTPUT",
+        help = "Request a keyframe from downstream encoders on every switch between live and slate"
+    )]
+    pub keyframe_on_switch: bool,
+    #[clap(
+        long,
+        help_heading = "OUTPUT",
+        help = "Render live diagnostics onto the output: state, timestamps, restarts and queue levels"
+    )]
+    pub debug_overlay: bool,
+    #[clap(
+        long,
+        parse(from_os_str),
+        help_heading = "OUTPUT",
+        help = "Overlay this image, e.g. a PNG with alpha, on the live video and the slate"
+    )]
+    pub logo_file: Option<PathBuf>,
+    #[clap(
+        long,
+        default_value = "top-right",
+        possible_values = &["top-left", "top-right", "bottom-left", "bottom-right"],
+        help_heading = "OUTPUT",
+        help = "Corner of the output the logo is placed in"
+    )]
+    pub logo_position: LogoPosition,
+    #[clap(
+        long,
+        default_value = "1.0",
+        parse(try_from_str = parse_fraction),
+        help_heading = "OUTPUT",
+        help = "Opacity of the logo, from 0 to 1"
+    )]
+    pub logo_opacity: f64,
+    #[clap(
+        long,
+        default_value = "20",
+        help_heading = "OUTPUT",
+        help = "Distance of the logo from the edges of the output in pixels"
+    )]
+    pub logo_margin: i32,
+    #[clap(
+        long,
+        help_heading = "OUTPUT",
+        help = "Render the live audio as a waveform onto the output, for monitoring"
+    )]
+    pub audio_meters: bool,
+    #[clap(
+        long,
+        help_heading = "OUTPUT",
+        help = "Render the closed captions of the live video onto it"
+    )]
+    pub burn_in_captions: bool,
+    #[clap(
+        long,
+        help_heading = "OUTPUT",
+        help = "Carry the CEA-608/708 closed captions of the live video over to the outputs"
+    )]
+    pub caption_passthrough: bool,
+    #[clap(
+        long,
+        parse(from_os_str),
+        help_heading = "OUTPUT",
+        help = "Render the subtitles from this SRT or WebVTT file onto the live video"
+    )]
+    pub subtitle_file: Option<PathBuf>,
+    #[clap(
+        long,
+        parse(from_os_str),
+        help_heading = "OUTPUT",
+        help = "Record the output to this directory whenever the live video goes missing"
+    )]
+    pub record_dir: Option<PathBuf>,
+    #[clap(
+        long,
+        default_value = "30s",
+        parse(try_from_str = parse_duration),
+        help_heading = "OUTPUT",
+        help = "How much output from before the live video went missing to include in recordings"
+    )]
+    pub record_pre_roll: Duration,
+    #[clap(
+        long,
+        default_value = "10s",
+        parse(try_from_str = parse_duration),
+        help_heading = "OUTPUT",
+        help = "How long to keep recording once the live video is back"
+    )]
+    pub record_post_roll: Duration,
+    #[clap(
+        long,
+        default_value = "system",
+        help_heading = "OUTPUT",
+        help = "Pipeline clock, system, ntp:HOST[:PORT] or ptp[:DOMAIN] to align outputs across instances"
+    )]
+    pub clock: PipelineClock,
+    #[clap(
+        long,
+        parse(try_from_str = parse_duration),
+        help_heading = "OUTPUT",
+        help = "Fixed latency for both pipelines, instead of the one computed from latency messages"
+    )]
+    pub latency: Option<Duration>,
+    #[clap(
+        long,
+        parse(try_from_str = parse_duration),
+        help_heading = "OUTPUT",
+        help = "Fixed latency for the RTMP pipeline, overriding --latency"
+    )]
+    pub source_latency: Option<Duration>,
+    #[clap(
+        long,
+        parse(try_from_str = parse_duration),
+        help_heading = "OUTPUT",
+        help = "How long the compositor waits for late live buffers on top of the upstream latency"
+    )]
+    pub mixer_latency: Option<Duration>,
+    #[clap(
+        long,
+        help_heading = "OUTPUT",
+        help = "Run without a preview window, implied when no display server is available"
+    )]
+    pub headless: bool,
+    #[clap(
+        long,
+        default_value = "auto",
+        possible_values = &["auto", "xv", "wayland", "none"],
+        help_heading = "OUTPUT",
+        help = "Preview sink, auto picks one for the running display server"
+    )]
+    pub preview_sink: PreviewSink,
+    #[clap(
+        long,
+        help_heading = "OUTPUT",
+        help = "Video sink element factory of the preview windows, in place of the one --preview-sink picks, e.g. glimagesink"
+    )]
+    pub video_sink: Option<String>,
+    #[clap(
+        long,
+        help_heading = "OUTPUT",
+        help = "Audio sink element factory of the preview, in place of autoaudiosink, e.g. pulsesink"
+    )]
+    pub audio_sink: Option<String>,
+    #[clap(
+        long,
+        multiple_occurrences = true,
+        parse(try_from_str = parse_uri),
+        help_heading = "OUTPUT",
+        help = "Encode the output and push it to this rtmp://, srt://, udp:// or rtp:// URI instead of previewing it, can be repeated"
+    )]
+    pub output_uri: Vec<String>,
+    #[clap(
+        long,
+        parse(try_from_str = parse_uri),
+        help_heading = "OUTPUT",
+        help = "Push the live H.264 as is to this rtmp://, srt://, udp:// or rtp:// URI, spliced at IDR frames with the program encoded while the slate shows"
+    )]
+    pub passthrough_uri: Option<String>,
+    #[clap(
+        long,
+        parse(from_os_str),
+        help_heading = "OUTPUT",
+        help = "Encode the output and write it as HLS segments and playlist.m3u8 to this directory instead of previewing it"
+    )]
+    pub hls_dir: Option<PathBuf>,
+    #[clap(
+        long,
+        default_value = "6s",
+        parse(try_from_str = parse_duration),
+        help_heading = "OUTPUT",
+        help = "Target duration of HLS segments, in whole seconds"
+    )]
+    pub hls_segment_duration: Duration,
+    #[clap(
+        long,
+        default_value = "5",
+        help_heading = "OUTPUT",
+        help = "Number of segments listed in the HLS playlist, 0 for all of them"
+    )]
+    pub hls_playlist_length: u32,
+    #[clap(
+        long,
+        parse(from_os_str),
+        help_heading = "OUTPUT",
+        help = "Encode the output and archive it continuously to files in this directory"
+    )]
+    pub archive_dir: Option<PathBuf>,
+    #[clap(
+        long,
+        default_value = "mp4",
+        possible_values = &["mp4", "ts"],
+        help_heading = "OUTPUT",
+        help = "Container of the archived files"
+    )]
+    pub archive_format: ArchiveFormat,
+    #[clap(
+        long,
+        default_value = "{timestamp}-{index}",
+        help_heading = "OUTPUT",
+        help = "Name of the archived files without extension, {timestamp} and {index} are replaced by their start time and number"
+    )]
+    pub archive_pattern: String,
+    #[clap(
+        long,
+        default_value = "10min",
+        parse(try_from_str = parse_duration),
+        help_heading = "OUTPUT",
+        help = "Start a new archived file after this long"
+    )]
+    pub archive_segment_duration: Duration,
+    #[clap(
+        long,
+        parse(try_from_str = parse_megabytes),
+        help_heading = "OUTPUT",
+        help = "Start a new archived file once it reaches this size in MB"
+    )]
+    pub archive_segment_size: Option<u64>,
+    #[clap(
+        long,
+        parse(try_from_str = parse_duration),
+        help_heading = "OUTPUT",
+        help = "Delete archived files older than this, e.g. 7days"
+    )]
+    pub archive_max_age: Option<Duration>,
+    #[clap(
+        long,
+        help_heading = "OUTPUT",
+        help = "Only keep this many archived files, deleting the oldest ones"
+    )]
+    pub archive_max_files: Option<usize>,
+    #[clap(
+        long,
+        parse(from_os_str),
+        conflicts_with = "thumbnail_url",
+        help_heading = "OUTPUT",
+        help = "Write a JPEG thumbnail of the output to this directory every --thumbnail-interval, along with latest.jpg"
+    )]
+    pub thumbnail_dir: Option<PathBuf>,
+    #[clap(
+        long,
+        parse(try_from_str = parse_uri),
+        help_heading = "OUTPUT",
+        help = "PUT a JPEG thumbnail of the output to this URL every --thumbnail-interval, {timestamp} is replaced, e.g. https://bucket.s3.example.com/channel/{timestamp}.jpg"
+    )]
+    pub thumbnail_url: Option<String>,
+    #[clap(
+        long,
+        default_value = "10s",
+        parse(try_from_str = parse_duration),
+        help_heading = "OUTPUT",
+        help = "How often thumbnails are taken"
+    )]
+    pub thumbnail_interval: Duration,
+    #[clap(
+        long,
+        parse(try_from_str = parse_duration),
+        help_heading = "OUTPUT",
+        help = "Delete thumbnails older than this from --thumbnail-dir, e.g. 1day"
+    )]
+    pub thumbnail_max_age: Option<Duration>,
+    #[clap(
+        long,
+        help_heading = "OUTPUT",
+        help = "Only keep this many thumbnails in --thumbnail-dir, deleting the oldest ones"
+    )]
+    pub thumbnail_max_files: Option<usize>,
+    #[clap(
+        long,
+        help_heading = "OUTPUT",
+        help = "Publish the output to browsers with webrtcsink, for monitoring"
+    )]
+    pub webrtc_preview: bool,
+    #[clap(
+        long,
+        parse(try_from_str = parse_uri),
+        requires = "webrtc_preview",
+        help_heading = "OUTPUT",
+        help = "Signalling server of the WebRTC preview, e.g. ws://signalling:8443 [default: built-in server on port 8443]"
+    )]
+    pub webrtc_signaller_uri: Option<String>,
+    #[clap(
+        long,
+        default_value = "x264",
+        possible_values = &["x264", "vaapih264", "nvh264enc", "nvv4l2h264enc", "v4l2h264enc"],
+        help_heading = "OUTPUT",
+        help = "H.264 encoder of the outputs and recordings, with its matching upload and conversion"
+    )]
+    pub encoder: Encoder,
+    #[clap(
+        long,
+        help_heading = "OUTPUT",
+        help = "H.264 encoder element factory to use in place of the one of --encoder, with its default settings"
+    )]
+    pub encoder_element: Option<String>,
+    #[clap(
+        long,
+        default_value = "4000",
+        help_heading = "OUTPUT",
+        help = "Video bitrate of the encoded outputs and recordings in kbit/s"
+    )]
+    pub output_video_bitrate: u32,
+    #[clap(
+        long,
+        default_value = "128",
+        help_heading = "OUTPUT",
+        help = "Audio bitrate of the encoded outputs in kbit/s"
+    )]
+    pub output_audio_bitrate: u32,
+    #[clap(
+        long,
+        allow_hyphen_values = true,
+        help_heading = "OUTPUT",
+        help = "Normalize the program audio to this EBU R128 loudness in LUFS, e.g. -23"
+    )]
+    pub loudness_target: Option<f64>,
+    #[clap(
+        long,
+        default_value = "-2.0",
+        allow_hyphen_values = true,
+        help_heading = "OUTPUT",
+        help = "Maximum true peak of the program audio in dBTP with --loudness-target"
+    )]
+    pub loudness_max_true_peak: f64,
+    #[clap(
+        long,
+        default_value = "1",
+        help_heading = "OUTPUT",
+        help = "MPEG-TS program number of the --output-uri stream"
+    )]
+    pub output_ts_program: u16,
+    #[clap(
+        long,
+        default_value = "4096",
+        help_heading = "OUTPUT",
+        help = "MPEG-TS PID of the program map table of the --output-uri stream"
+    )]
+    pub output_ts_pmt_pid: u16,
+    #[clap(
+        long,
+        default_value = "256",
+        help_heading = "OUTPUT",
+        help = "MPEG-TS PID of the video of the --output-uri stream"
+    )]
+    pub output_ts_video_pid: u16,
+    #[clap(
+        long,
+        default_value = "257",
+        help_heading = "OUTPUT",
+        help = "MPEG-TS PID of the audio of the --output-uri stream"
+    )]
+    pub output_ts_audio_pid: u16,
+    #[clap(
+        long,
+        help_heading = "OUTPUT",
+        help = "Constant MPEG-TS mux rate of the --output-uri stream in kbit/s, padded with null packets"
+    )]
+    pub output_ts_bitrate: Option<u32>,
+    #[clap(
+        long,
+        help_heading = "OUTPUT",
+        help = "MPEG-TS PID of SCTE-35 splice sections marking the slate periods of the --output-uri stream"
+    )]
+    pub output_ts_scte35_pid: Option<u16>,
+
+    #[clap(
+        long,
+        parse(from_os_str),
+        help_heading = "PROCESS",
+        help = "Read options from this TOML or YAML file, command line flags override them"
+    )]
+    pub config: Option<PathBuf>,
+    #[clap(
+        long,
+        help_heading = "PROCESS",
+        help = "Serve the HTTP control API on this port"
+    )]
+    pub control_port: Option<u16>,
+    #[clap(
+        long,
+        default_value = "127.0.0.1",
+        help_heading = "PROCESS",
+        help = "Address to serve the HTTP control API on, e.g. 0.0.0.0 for all interfaces"
+    )]
+    pub control_bind: IpAddr,
+    #[clap(
+        long,
+        help_heading = "PROCESS",
+        help = "Only accept control API requests carrying this token as Authorization: Bearer <TOKEN>"
+    )]
+    pub control_token: Option<String>,
+    #[clap(
+        long,
+        help_heading = "PROCESS",
+        help = "Read control commands from the standard input, e.g. uri rtmp://host/live/stream"
+    )]
+    pub stdin_commands: bool,
+    #[clap(
+        long,
+        help_heading = "PROCESS",
+        help = "Do not display the status line, for service use"
+    )]
+    pub quiet: bool,
+    #[clap(long, help_heading = "PROCESS", help = "Do not color the status line")]
+    pub no_color: bool,
+    #[clap(
+        long,
+        parse(try_from_str = parse_duration),
+        conflicts_with = "until",
+        help_heading = "PROCESS",
+        help = "Finalize the output and exit after running for this long"
+    )]
+    pub duration: Option<Duration>,
+    #[clap(
+        long,
+        parse(try_from_str = parse_timestamp),
+        help_heading = "PROCESS",
+        help = "Finalize the output and exit at this time, e.g. 2026-10-16T18:00:00Z"
+    )]
+    pub until: Option<SystemTime>,
+    #[clap(
+        long,
+        default_value = "5s",
+        parse(try_from_str = parse_duration),
+        help_heading = "PROCESS",
+        help = "How long to wait for the output to finalize when stopping"
+    )]
+    pub drain_timeout: Duration,
+    #[clap(
+        long,
+        default_value = "text",
+        possible_values = &["text", "json"],
+        help_heading = "PROCESS",
+        help = "Log format, json logs everything to stdout"
+    )]
+    pub log_format: LogFormat,
+    #[clap(
+        long,
+        default_value = "info",
+        possible_values = &["debug", "info", "warn", "error"],
+        help_heading = "PROCESS",
+        help = "Discard log messages below this level"
+    )]
+    pub log_level: Level,
+    #[clap(
+        long,
+        help_heading = "PROCESS",
+        help = "Detach from the terminal and run in the background"
+    )]
+    pub daemon: bool,
+    #[clap(
+        long,
+        parse(from_os_str),
+        help_heading = "PROCESS",
+        help = "Write the process ID to this file"
+    )]
+    pub pid_file: Option<PathBuf>,
+    #[clap(
+        long,
+        parse(from_os_str),
+        help_heading = "PROCESS",
+        help = "Save runtime state to this file and restore it on startup"
+    )]
+    pub state_file: Option<PathBuf>,
+    #[clap(
+        long,
+        parse(from_os_str),
+        help_heading = "PROCESS",
+        help = "Log to this file instead of stdout"
+    )]
+    pub log_file: Option<PathBuf>,
+    #[clap(
+        long,
+        default_value = "10485760",
+        help_heading = "PROCESS",
+        help = "Rotate the log file once it exceeds N bytes"
+    )]
+    pub log_file_size: u64,
+    #[clap(
+        long,
+        default_value = "5",
+        help_heading = "PROCESS",
+        help = "Number of rotated log files to keep"
+    )]
+    pub log_file_count: u32,
+
+    #[clap(
+        long,
+        help_heading = "FALLBACK",
+        help = "Fall back to the slate when the live video quality collapses"
+    )]
+    pub quality_fallback: bool,
+    #[clap(
+        long,
+        default_value = "2.5",
+        help_heading = "FALLBACK",
+        help = "Blockiness above which live video quality is considered collapsed"
+    )]
+    pub max_blockiness: f64,
+    #[clap(
+        long,
+        default_value = "1.0",
+        help_heading = "FALLBACK",
+        help = "Sharpness below which live video quality is considered collapsed"
+    )]
+    pub min_sharpness: f64,
+    #[clap(
+        long,
+        help_heading = "FALLBACK",
+        help = "Fall back to the slate while the input bitrate is below N bits per second"
+    )]
+    pub min_bitrate: Option<u64>,
+    #[clap(
+        long,
+        default_value = "5s",
+        parse(try_from_str = parse_duration),
+        help_heading = "FALLBACK",
+        help = "Window over which the input bitrate and buffer gaps are measured"
+    )]
+    pub bitrate_window: Duration,
+    #[clap(
+        long,
+        parse(try_from_str = parse_duration),
+        help_heading = "FALLBACK",
+        help = "Restart the RTMP pipeline when timestamps stop advancing for this long, e.g. 5s"
+    )]
+    pub stale_timeout: Option<Duration>,
+    #[clap(
+        long,
+        parse(try_from_str = parse_duration),
+        help_heading = "FALLBACK",
+        help = "Restart the RTMP pipeline when the source delivers no video for this long, e.g. 10s"
+    )]
+    pub stall_timeout: Option<Duration>,
+    #[clap(
+        long,
+        help_heading = "FALLBACK",
+        help = "Restart the RTMP pipeline when more than N decode errors occur within the window"
+    )]
+    pub max_decode_errors: Option<usize>,
+    #[clap(
+        long,
+        default_value = "10s",
+        parse(try_from_str = parse_duration),
+        help_heading = "FALLBACK",
+        help = "Window over which decode errors are counted"
+    )]
+    pub decode_error_window: Duration,
+    #[clap(
+        long,
+        default_value = "0.5",
+        parse(try_from_str = parse_fraction),
+        help_heading = "FALLBACK",
+        help = "Flag the input when its framerate drops below this ratio of the advertised one"
+    )]
+    pub fps_drop_ratio: f64,
+    #[clap(
+        long,
+        default_value = "5s",
+        parse(try_from_str = parse_duration),
+        help_heading = "FALLBACK",
+        help = "How long the framerate must stay low before being flagged"
+    )]
+    pub fps_drop_timeout: Duration,
+    #[clap(
+        long,
+        help_heading = "FALLBACK",
+        help = "Fall back to the slate while the input framerate is flagged"
+    )]
+    pub fps_drop_fallback: bool,
+    #[clap(
+        long,
+        help_heading = "FALLBACK",
+        help = "Fall back to the slate after N consecutive identical live frames"
+    )]
+    pub freeze_frames: Option<u32>,
+    #[clap(
+        long,
+        help_heading = "FALLBACK",
+        help = "Fall back to the slate after N consecutive black live frames"
+    )]
+    pub black_frames: Option<u32>,
+    #[clap(
+        long,
+        parse(try_from_str = parse_duration),
+        help_heading = "FALLBACK",
+        help = "Switch to the slate audio when the live audio stays silent for this long, e.g. 10s"
+    )]
+    pub silence_timeout: Option<Duration>,
+    #[clap(
+        long,
+        default_value = "-60.0",
+        allow_hyphen_values = true,
+        help_heading = "FALLBACK",
+        help = "Level in dBFS below which the live audio is silent"
+    )]
+    pub silence_threshold: f64,
+    #[clap(
+        long,
+        default_value = "2s",
+        parse(try_from_str = parse_duration),
+        help_heading = "FALLBACK",
+        help = "How long the live audio must be back before it replaces the slate audio again"
+    )]
+    pub silence_recovery: Duration,
+    #[clap(
+        long,
+        help_heading = "FALLBACK",
+        help = "Fall back to the video slate as well while the live audio is silent"
+    )]
+    pub silence_fallback_video: bool,
+    #[clap(
+        long,
+        help_heading = "FALLBACK",
+        help = "Fall back to the slate while this shell command exits unsuccessfully"
+    )]
+    pub health_command: Option<String>,
+    #[clap(
+        long,
+        parse(try_from_str = parse_uri),
+        help_heading = "FALLBACK",
+        help = "Fall back to the slate while this URL does not answer with a 2xx status"
+    )]
+    pub health_url: Option<String>,
+    #[clap(
+        long,
+        default_value = "5s",
+        parse(try_from_str = parse_duration),
+        help_heading = "FALLBACK",
+        help = "Interval between external health checks"
+    )]
+    pub health_check_interval: Duration,
+    #[clap(
+        long,
+        default_value = "2s",
+        parse(try_from_str = parse_duration),
+        help_heading = "FALLBACK",
+        help = "Timeout after which an external health check fails"
+    )]
+    pub health_check_timeout: Duration,
+
+    #[clap(
+        long,
+        parse(try_from_str = parse_duration),
+        help_heading = "MONITORING",
+        help = "Alert when audio and video drift apart by more than this, e.g. 200ms"
+    )]
+    pub max_av_drift: Option<Duration>,
+    #[clap(
+        long,
+        help_heading = "MONITORING",
+        help = "Restart the RTMP pipeline when the A/V drift is exceeded"
+    )]
+    pub av_drift_restart: bool,
+    #[clap(
+        long,
+        default_value = "-1.0",
+        allow_hyphen_values = true,
+        help_heading = "MONITORING",
+        help = "Alert when the audio true peak exceeds N dBTP"
+    )]
+    pub max_audio_peak: f64,
+    #[clap(
+        long,
+        default_value = "10.0",
+        help_heading = "MONITORING",
+        help = "Alert when the audio level jumps by more than N dB"
+    )]
+    pub max_loudness_jump: f64,
+    #[clap(
+        long,
+        help_heading = "MONITORING",
+        help = "Limit audio peaks to --max-audio-peak"
+    )]
+    pub audio_limiter: bool,
+    #[clap(
+        long,
+        default_value = "10s",
+        parse(try_from_str = parse_duration),
+        help_heading = "MONITORING",
+        help = "Alert when the live keyframe interval exceeds this"
+    )]
+    pub max_gop: Duration,
+    #[clap(
+        long,
+        parse(try_from_str = parse_duration),
+        help_heading = "MONITORING",
+        help = "Log the input bitrate, framerate and buffer gaps this often, e.g. 30s"
+    )]
+    pub input_stats_interval: Option<Duration>,
+    #[clap(
+        long,
+        default_value = "10s",
+        parse(try_from_str = parse_duration),
+        help_heading = "MONITORING",
+        help = "Log the live video quality indicators this often"
+    )]
+    pub quality_interval: Duration,
+    #[clap(
+        long,
+        parse(try_from_str = parse_duration),
+        help_heading = "MONITORING",
+        help = "Alert when the end-to-end latency exceeds this, e.g. 3s"
+    )]
+    pub max_latency: Option<Duration>,
+    #[clap(
+        long,
+        requires = "max_latency",
+        help_heading = "MONITORING",
+        help = "Restart the RTMP pipeline when the latency is exceeded"
+    )]
+    pub max_latency_restart: bool,
+
+    #[clap(
+        long,
+        help_heading = "HEALTH CHECKS",
+        help = "Serve /ready and /live health checks on this port"
+    )]
+    pub health_port: Option<u16>,
+    #[clap(
+        long,
+        help_heading = "MONITORING",
+        help = "Serve Prometheus metrics on /metrics on this port"
+    )]
+    pub metrics_port: Option<u16>,
+    #[clap(
+        long = "webhook-url",
+        multiple_occurrences = true,
+        parse(try_from_str = parse_uri),
+        help_heading = "MONITORING",
+        help = "POST a JSON event to this URL when switching to the slate or back, or giving up on the source, can be repeated"
+    )]
+    pub webhook_urls: Vec<String>,
+    #[clap(
+        long,
+        default_value = "5s",
+        parse(try_from_str = parse_duration),
+        help_heading = "HEALTH CHECKS",
+        help = "Report not ready when no output was produced for this long"
+    )]
+    pub readiness_grace: Duration,
+    #[clap(
+        long,
+        default_value = "30s",
+        parse(try_from_str = parse_duration),
+        help_heading = "HEALTH CHECKS",
+        help = "Report not live when the main loop or output stalled for this long"
+    )]
+    pub liveness_grace: Duration,
+
+    #[clap(
+        long,
+        parse(try_from_str = parse_uri),
+        help_heading = "TALLY",
+        help = "POST the tally state as JSON to this URL on changes"
+    )]
+    pub tally_url: Option<String>,
+    #[clap(
+        long,
+        value_name = "HOST:PORT",
+        help_heading = "TALLY",
+        help = "Send the tally state as TSL UMD messages over UDP to this address"
+    )]
+    pub tally_tsl: Option<String>,
+    #[clap(
+        long,
+        default_value = "5.0",
+        possible_values = &["3.1", "5.0"],
+        help_heading = "TALLY",
+        help = "TSL UMD protocol version"
+    )]
+    pub tally_tsl_version: TslVersion,
+    #[clap(
+        long,
+        default_value = "0",
+        help_heading = "TALLY",
+        help = "TSL UMD display address"
+    )]
+    pub tally_tsl_index: u16,
+    #[clap(
+        long,
+        help_heading = "TALLY",
+        help = "Drive this sysfs GPIO pin high while live"
+    )]
+    pub tally_gpio: Option<u32>,
+    #[clap(
+        long,
+        value_name = "HOST:PORT",
+        help_heading = "TALLY",
+        help = "Send commands to a production switcher at this address over TCP, e.g. RossTalk on port 7788"
+    )]
+    pub switcher: Option<String>,
+    #[clap(
+        long,
+        requires = "switcher",
+        help_heading = "TALLY",
+        help = "Command sent to the switcher when the live input fails, {uri} is replaced by the live URI"
+    )]
+    pub switcher_on_slate: Option<String>,
+    #[clap(
+        long,
+        requires = "switcher",
+        help_heading = "TALLY",
+        help = "Command sent to the switcher when the live input is back, {uri} is replaced by the live URI"
+    )]
+    pub switcher_on_live: Option<String>,
+
+    #[clap(
+        long,
+        parse(try_from_str = parse_duration),
+        help_heading = "TESTING",
+        help = "Run for this long, report the negotiated caps and latency, and exit"
+    )]
+    pub dry_run: Option<Duration>,
+    #[clap(
+        long,
+        possible_values = &["launch", "dot"],
+        help_heading = "TESTING",
+        help = "Log the pipelines as gst-launch-1.0 descriptions or dot graphs at debug level once playing"
+    )]
+    pub print_pipelines: Option<PipelineFormat>,
+    #[clap(
+        long,
+        parse(from_os_str),
+        help_heading = "TESTING",
+        help = "Dump pipeline graphs on state changes and errors here, defaults to GST_DEBUG_DUMP_DOT_DIR"
+    )]
+    pub dot_dir: Option<PathBuf>,
+    #[clap(
+        long,
+        default_value = "100",
+        help_heading = "TESTING",
+        help = "Number of pipeline graph dumps to keep"
+    )]
+    pub dot_retention: usize,
+    #[clap(
+        long,
+        help_heading = "TESTING",
+        help = "Make RTMP pipeline EOS after N buffers"
+    )]
+    pub eos_after: Option<i32>,
+    #[clap(
+        long,
+        help_heading = "TESTING",
+        help = "Make RTMP pipeline error after N buffers"
+    )]
+    pub error_after: Option<i32>,
+    #[clap(
+        long,
+        help_heading = "TESTING",
+        help = "Seed for the fault injection, for the same faults on every run"
+    )]
+    pub chaos_seed: Option<u64>,
+    #[clap(
+        long,
+        default_value = "0",
+        parse(try_from_str = parse_fraction),
+        help_heading = "TESTING",
+        help = "Probability per second of erroring out the RTMP pipeline like a dropped connection"
+    )]
+    pub chaos_disconnect_probability: f64,
+    #[clap(
+        long,
+        default_value = "0",
+        parse(try_from_str = parse_fraction),
+        help_heading = "TESTING",
+        help = "Probability per second of dropping the live video for --chaos-stall-duration"
+    )]
+    pub chaos_stall_probability: f64,
+    #[clap(
+        long,
+        default_value = "5s",
+        parse(try_from_str = parse_duration),
+        help_heading = "TESTING",
+        help = "How long injected stalls last"
+    )]
+    pub chaos_stall_duration: Duration,
+    #[clap(
+        long,
+        default_value = "0",
+        parse(try_from_str = parse_fraction),
+        help_heading = "TESTING",
+        help = "Probability per second of dropping most of the network data for --chaos-bitrate-drop-duration"
+    )]
+    pub chaos_bitrate_drop_probability: f64,
+    #[clap(
+        long,
+        default_value = "10s",
+        parse(try_from_str = parse_duration),
+        help_heading = "TESTING",
+        help = "How long injected bitrate drops last"
+    )]
+    pub chaos_bitrate_drop_duration: Duration,
+    #[clap(
+        long,
+        default_value = "0",
+        parse(try_from_str = parse_fraction),
+        help_heading = "TESTING",
+        help = "Probability per second of the live timestamps jumping forward by --chaos-clock-jump"
+    )]
+    pub chaos_clock_jump_probability: f64,
+    #[clap(
+        long,
+        default_value = "10s",
+        parse(try_from_str = parse_duration),
+        help_heading = "TESTING",
+        help = "How far injected clock jumps go"
+    )]
+    pub chaos_clock_jump: Duration,
+
+    /// Element producing the slate video, instead of a test pattern
+    #[clap(skip)]
+    pub slate_source: Option<gst::Element>,
+    /// Element consuming the output video, instead of a preview sink
+    #[clap(skip)]
+    pub output_sink: Option<gst::Element>,
+    /// Element consuming the output audio, instead of an audio preview sink
+    #[clap(skip)]
+    pub audio_output_sink: Option<gst::Element>,
+}
+
+impl Args {
+    /// How long to run for, from now, if bounded
+    pub fn run_duration(&self) -> Option<Duration> {
+        self.duration.or_else(|| {
+            self.until.map(|until| {
+                until
+                    .duration_since(SystemTime::now())
+                    .unwrap_or(Duration::from_secs(0))
+            })
+        })
+    }
+
+    /// Tally outputs to drive
+    pub fn tally_outputs(&self) -> Vec<TallyOutput> {
+        let mut outputs = vec![];
+        outputs.extend(self.tally_url.clone().map(TallyOutput::Http));
+        outputs.extend(self.tally_tsl.clone().map(|addr| TallyOutput::Tsl {
+            addr,
+            version: self.tally_tsl_version,
+            index: self.tally_tsl_index,
+        }));
+        outputs.extend(self.tally_gpio.map(TallyOutput::Gpio));
+        outputs.extend(self.switcher.clone().map(|addr| TallyOutput::Switcher {
+            addr,
+            on_live: self.switcher_on_live.clone(),
+            on_slate: self.switcher_on_slate.clone(),
+            uri: self.live_rtmp_uri[0].clone(),
+        }));
+        outputs
+    }
+
+    /// Number of CPUs we can use, --cpu-budget or detected
+    pub fn available_cpus(&self) -> f64 {
+        self.cpu_budget.unwrap_or_else(cpu::detect)
+    }
+
+    /// Number of outputs encoding the program, event recordings included
+    pub fn encoded_outputs(&self) -> usize {
+        if self.dry_run.is_some() {
+            return 1;
+        }
+
+        self.output_uri.len()
+            + self.passthrough_uri.iter().count()
+            + self.hls_dir.iter().count()
+            + self.archive_dir.iter().count()
+            + self.record_dir.iter().count()
+    }
+
+    /// Video converter element factory
+    pub fn converter(&self) -> String {
+        self.converter.as_deref().unwrap_or("videoconvert").to_string()
+    }
+
+    /// Encoding of the outputs
+    pub fn encoding(&self) -> Encoding {
+        Encoding {
+            encoder: self.encoder,
+            element: self.encoder_element.clone(),
+            converter: self.converter(),
+            video_bitrate: self.output_video_bitrate,
+            audio_bitrate: self.output_audio_bitrate,
+            threads: cpu::encoder_threads(self.available_cpus(), self.encoded_outputs()),
+        }
+    }
+
+    /// MPEG-TS muxing of the --output-uri stream
+    pub fn ts_options(&self) -> TsOptions {
+        TsOptions {
+            program_number: self.output_ts_program,
+            pmt_pid: self.output_ts_pmt_pid,
+            video_pid: self.output_ts_video_pid,
+            audio_pid: self.output_ts_audio_pid,
+            mux_bitrate: self.output_ts_bitrate,
+            scte35_pid: self.output_ts_scte35_pid,
+        }
+    }
+
+    /// Caps of the program output
+    pub fn output_caps(&self) -> gst::Caps {
+        let mut caps = gst::Caps::new_simple(
+            "video/x-raw",
+            &[("width", &self.output_width), ("height", &self.output_height)],
+        );
+        let structure = caps.get_mut().unwrap().get_mut_structure(0).unwrap();
+
+        if let Some((numer, denom)) = self.output_framerate {
+            structure.set("framerate", &gst::Fraction::new(numer, denom));
+        }
+
+        if let Some(ref format) = self.output_format {
+            structure.set("format", format);
+        }
+
+        caps
+    }
+
+    /// Options for the archive, if enabled
+    pub fn archive_options(&self) -> Option<ArchiveOptions> {
+        self.archive_dir.as_ref().map(|dir| ArchiveOptions {
+            dir: dir.clone(),
+            format: self.archive_format,
+            pattern: self.archive_pattern.clone(),
+            max_duration: Some(self.archive_segment_duration).filter(|d| !d.is_zero()),
+            max_size: self.archive_segment_size,
+            max_age: self.archive_max_age,
+            max_files: self.archive_max_files,
+        })
+    }
+
+    /// Options for the thumbnails, if enabled
+    pub fn thumbnail_options(&self) -> Option<ThumbnailOptions> {
+        let target = match (&self.thumbnail_dir, &self.thumbnail_url) {
+            (Some(dir), _) => ThumbnailTarget::Dir(dir.clone()),
+            (None, Some(url)) => ThumbnailTarget::Url(url.clone()),
+            (None, None) => return None,
+        };
+
+        Some(ThumbnailOptions {
+            target,
+            interval: self.thumbnail_interval,
+            max_age: self.thumbnail_max_age,
+            max_files: self.thumbnail_max_files,
+        })
+    }
+
+    /// Slate media of the reasons that have their own
+    pub fn slate_assets(&self) -> SlateAssets {
+        SlateAssets {
+            starting: self.slate_starting_uri.clone(),
+            error: self.slate_error_uri.clone(),
+            eos: self.slate_eos_uri.clone(),
+        }
+    }
+
+    /// Layout of the compositor, unless the defaults apply
+    pub fn layout_options(&self) -> Option<LayoutOptions> {
+        if self.layout == Layout::Switch && self.live_rect.is_none() && self.slate_rect.is_none() {
+            return None;
+        }
+
+        Some(LayoutOptions {
+            layout: self.layout,
+            pip_position: self.pip_position,
+            live_rect: self.live_rect,
+            slate_rect: self.slate_rect,
+            width: self.output_width,
+            height: self.output_height,
+        })
+    }
+
+    /// Options for the HLS output, if enabled
+    pub fn hls_options(&self) -> Option<HlsOptions> {
+        self.hls_dir.as_ref().map(|dir| HlsOptions {
+            dir: dir.clone(),
+            segment_duration: self.hls_segment_duration,
+            playlist_length: self.hls_playlist_length,
+        })
+    }
+
+    /// Fault injection options, if any fault is enabled
+    pub fn chaos_options(&self) -> Option<ChaosOptions> {
+        Some(ChaosOptions {
+            seed: self.chaos_seed,
+            disconnect_probability: self.chaos_disconnect_probability,
+            stall_probability: self.chaos_stall_probability,
+            stall_duration: self.chaos_stall_duration,
+            bitrate_drop_probability: self.chaos_bitrate_drop_probability,
+            bitrate_drop_duration: self.chaos_bitrate_drop_duration,
+            clock_jump_probability: self.chaos_clock_jump_probability,
+            clock_jump: self.chaos_clock_jump,
+        })
+        .filter(ChaosOptions::is_enabled)
+    }
+
+    /// Options for SRT inputs
+    pub fn srt_options(&self) -> SrtOptions {
+        SrtOptions {
+            mode: self.srt_mode,
+            passphrase: self.srt_passphrase.clone(),
+            key_length: self.srt_key_length,
+            latency: self.srt_latency,
+        }
+    }
+
+    /// Options for RTSP inputs
+    pub fn rtsp_options(&self) -> RtspOptions {
+        RtspOptions {
+            protocols: self.rtsp_protocols.clone(),
+            timeout: self.rtsp_timeout,
+            tcp_timeout: self.rtsp_tcp_timeout,
+            latency: self.rtsp_latency,
+        }
+    }
+
+    /// Rank overrides to apply, pinning the decoder and parser if given
+    pub fn rank_overrides(&self) -> Vec<RankOverride> {
+        let mut overrides = self.ranks.clone();
+        overrides.extend(self.decoder.as_deref().map(RankOverride::pin));
+        overrides.extend(self.parser.as_deref().map(RankOverride::pin));
+        overrides
+    }
+}
+
+/// Print the completion script for `shell` to stdout
+pub fn print_completions(shell: clap_complete::Shell) {
+    let mut command = Args::command()
+        .subcommand(ProbeArgs::command().name("probe"))
+        .subcommand(SetupArgs::command().name("setup"))
+        .subcommand(SuperviseArgs::command().name("supervise"))
+        .subcommand(CompletionsArgs::command().name("completions"));
+
+    clap_complete::generate(
+        shell,
+        &mut command,
+        "rtmp-slate-fallback",
+        &mut std::io::stdout(),
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn durations() {
+        assert_eq!(parse_duration("5"), Ok(Duration::from_secs(5)));
+        assert_eq!(parse_duration("1.5"), Ok(Duration::from_millis(1500)));
+        assert_eq!(parse_duration("250ms"), Ok(Duration::from_millis(250)));
+        assert_eq!(parse_duration("2m"), Ok(Duration::from_secs(120)));
+        assert!(parse_duration("-1").is_err());
+        assert!(parse_duration("inf").is_err());
+        assert!(parse_duration("NaN").is_err());
+        assert!(parse_duration("1e30").is_err());
+        assert!(parse_duration("soon").is_err());
+    }
+
+    #[test]
+    fn uris() {
+        assert!(parse_uri("rtmp://localhost/live/stream").is_ok());
+        assert!(parse_uri("file:///srv/slate.png").is_ok());
+        assert!(parse_uri("srt+rist://host:9000").is_ok());
+        assert!(parse_uri("/srv/slate.png").is_err());
+        assert!(parse_uri("rtmp:").is_err());
+        assert!(parse_uri("1rtmp://host").is_err());
+        assert!(parse_uri("rt mp://host").is_err());
+    }
+
+    #[test]
+    fn fractions() {
+        assert_eq!(parse_fraction("0"), Ok(0.0));
+        assert_eq!(parse_fraction("0.5"), Ok(0.5));
+        assert_eq!(parse_fraction("1"), Ok(1.0));
+        assert!(parse_fraction("1.1").is_err());
+        assert!(parse_fraction("-0.1").is_err());
+        assert!(parse_fraction("NaN").is_err());
+    }
+
+    #[test]
+    fn percents() {
+        assert_eq!(parse_percent("0"), Ok(0));
+        assert_eq!(parse_percent("100"), Ok(100));
+        assert!(parse_percent("101").is_err());
+        assert!(parse_percent("-1").is_err());
+    }
+
+    #[test]
+    fn dimensions() {
+        assert_eq!(parse_dimension("1280"), Ok(1280));
+        assert!(parse_dimension("0").is_err());
+        assert!(parse_dimension("-720").is_err());
+    }
+
+    #[test]
+    fn megabytes() {
+        assert_eq!(parse_megabytes("0"), Ok(0));
+        assert_eq!(parse_megabytes("500"), Ok(500_000_000));
+        assert!(parse_megabytes(&u64::MAX.to_string()).is_err());
+        assert!(parse_megabytes("-1").is_err());
+    }
+
+    #[test]
+    fn validated_args() {
+        let parse = |args: &[&str]| {
+            Args::try_parse_from(
+                [
+                    "rtmp-slate-fallback",
+                    "--live-rtmp-uri",
+                    "rtmp://localhost/live/stream",
+                ]
+                .iter()
+                .chain(args)
+                .copied(),
+            )
+        };
+
+        assert!(parse(&[]).is_ok());
+        assert!(parse(&["--output-width", "0"]).is_err());
+        assert!(parse(&["--fps-drop-ratio", "2"]).is_err());
+        assert!(parse(&["--chaos-stall-probability", "1.5"]).is_err());
+        assert!(parse(&["--buffering-high-watermark", "120"]).is_err());
+        assert!(parse(&["--archive-segment-size", "18446744073709551615"]).is_err());
+    }
+}
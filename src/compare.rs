@@ -0,0 +1,200 @@
+//! Side by side comparison of two redundant inputs.
+//!
+//! Seamless failover between redundant feeds only works when they are in
+//! sync. The comparison pipeline listens to the first two inputs on their
+//! interpipes, shows them side by side, and keeps the last two seconds of
+//! each as tiny grayscale thumbnails. Every second, the latest frame of each
+//! input is matched against the recent frames of the other: the arrival
+//! offset of the best match tells how far apart the feeds are, and its
+//! difference how alike they are at all.
+
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use gst::prelude::*;
+
+use crate::failover;
+use crate::hints;
+use crate::logging::{self, Level};
+
+/// Size of each input in the side by side output
+const WIDTH: i32 = 640;
+const HEIGHT: i32 = 360;
+
+/// Size of the thumbnails frames are matched on
+const THUMB_WIDTH: i32 = 32;
+const THUMB_HEIGHT: i32 = 18;
+
+/// How long thumbnails are kept for matching
+const HISTORY: Duration = Duration::from_secs(2);
+
+type Thumbnails = Mutex<VecDeque<(Instant, Vec<u8>)>>;
+
+/// Offset and difference of the best matching frames
+#[derive(Debug, Clone, Copy)]
+struct Match {
+    /// How much later the frame of the second input arrived, in ms
+    offset_ms: i64,
+    /// Mean absolute difference of the frames, from 0 to 1
+    difference: f64,
+}
+
+fn difference(a: &[u8], b: &[u8]) -> f64 {
+    let sum = a
+        .iter()
+        .zip(b.iter())
+        .map(|(a, b)| (*a as i32 - *b as i32).abs() as u64)
+        .sum::<u64>();
+
+    sum as f64 / (a.len().max(1) as f64 * 255.0)
+}
+
+/// Best match of the latest frame of `latest` among the frames of `history`,
+/// with the offset of the frame of `history` relative to the latest one
+fn best_match(
+    latest: &VecDeque<(Instant, Vec<u8>)>,
+    history: &VecDeque<(Instant, Vec<u8>)>,
+) -> Option<(Duration, bool, f64)> {
+    let (time, frame) = latest.back()?;
+
+    history
+        .iter()
+        .map(|(other_time, other)| {
+            let (offset, later) = if other_time >= time {
+                (*other_time - *time, true)
+            } else {
+                (*time - *other_time, false)
+            };
+            (offset, later, difference(frame, other))
+        })
+        .min_by(|a, b| a.2.partial_cmp(&b.2).unwrap())
+}
+
+struct Comparison {
+    thumbnails: [Thumbnails; 2],
+}
+
+impl Comparison {
+    fn add(&self, index: usize, buffer: &gst::BufferRef) {
+        let map = match buffer.map_readable() {
+            Ok(map) => map,
+            Err(_) => return,
+        };
+        let now = Instant::now();
+        let mut thumbnails = self.thumbnails[index].lock().unwrap();
+
+        thumbnails.push_back((now, map.as_slice().to_vec()));
+        while thumbnails
+            .front()
+            .map_or(false, |(time, _)| now.duration_since(*time) > HISTORY)
+        {
+            thumbnails.pop_front();
+        }
+    }
+
+    fn compare(&self) -> Option<Match> {
+        let first = self.thumbnails[0].lock().unwrap();
+        let second = self.thumbnails[1].lock().unwrap();
+
+        let behind = best_match(&first, &second).map(|(offset, later, difference)| Match {
+            offset_ms: if later { 1 } else { -1 } * offset.as_millis() as i64,
+            difference,
+        });
+        let ahead = best_match(&second, &first).map(|(offset, later, difference)| Match {
+            offset_ms: if later { -1 } else { 1 } * offset.as_millis() as i64,
+            difference,
+        });
+
+        match (behind, ahead) {
+            (Some(behind), Some(ahead)) if ahead.difference < behind.difference => Some(ahead),
+            (Some(behind), _) => Some(behind),
+            (None, ahead) => ahead,
+        }
+    }
+}
+
+/// Launch description of the branch of input `index` into the compositor
+/// `mix`, taking thumbnails of it
+fn branch(index: usize) -> String {
+    format!(
+        "interpipesrc listen-to={listen_to} format=time is-live=true stream-sync=restart-ts \
+         ! queue leaky=downstream ! videoconvert ! videoscale \
+         ! video/x-raw,width={width},height={height},pixel-aspect-ratio=1/1 ! tee name=t{index} \
+         t{index}. ! queue ! mix.sink_{index} \
+         t{index}. ! queue leaky=downstream ! videoscale ! videoconvert \
+         ! video/x-raw,format=GRAY8,width={thumb_width},height={thumb_height} \
+         ! fakesink name=thumbs_{index} sync=false",
+        listen_to = failover::video_sink_name(index),
+        width = WIDTH,
+        height = HEIGHT,
+        index = index,
+        thumb_width = THUMB_WIDTH,
+        thumb_height = THUMB_HEIGHT,
+    )
+}
+
+/// Pipeline showing the first two inputs side by side on `sink`, with the
+/// offset and difference of their frames overlaid and logged every second
+pub fn build(sink: &gst::Element) -> Result<gst::Pipeline, anyhow::Error> {
+    let bin = hints::parse_bin(&format!(
+        "compositor name=mix sink_1::xpos={} ! textoverlay name=text valignment=top \
+         halignment=center font-desc=\"Sans 16\" ! videoconvert ! queue name=out {} {}",
+        WIDTH,
+        branch(0),
+        branch(1),
+    ))?;
+    let pipe = gst::Pipeline::new(Some("compare"));
+    pipe.add_many(&[bin.upcast_ref(), sink])?;
+    bin.link(sink)?;
+
+    let comparison = Arc::new(Comparison {
+        thumbnails: [Mutex::new(VecDeque::new()), Mutex::new(VecDeque::new())],
+    });
+
+    for index in 0..2 {
+        let comparison = comparison.clone();
+        let thumbs = bin.get_by_name(&format!("thumbs_{}", index)).unwrap();
+        thumbs.get_static_pad("sink").unwrap().add_probe(
+            gst::PadProbeType::BUFFER,
+            move |_, info| {
+                if let Some(gst::PadProbeData::Buffer(ref buffer)) = info.data {
+                    comparison.add(index, buffer);
+                }
+                gst::PadProbeReturn::Ok
+            },
+        );
+    }
+
+    let text = bin.get_by_name("text").unwrap();
+    glib::timeout_add_seconds(1, move || {
+        let label = match comparison.compare() {
+            Some(m) => {
+                logging::event(
+                    Level::Debug,
+                    "input-comparison",
+                    &format!(
+                        "Inputs {}ms apart, {:.1}% different",
+                        m.offset_ms,
+                        m.difference * 100.0
+                    ),
+                    serde_json::json!({
+                        "offset_ms": m.offset_ms,
+                        "difference": m.difference,
+                    }),
+                );
+                format!(
+                    "Δ {} ms, difference {:.1}%",
+                    m.offset_ms,
+                    m.difference * 100.0
+                )
+            }
+            None => "Waiting for both inputs".to_string(),
+        };
+        let _ = text.set_property("text", &label);
+
+        glib::Continue(true)
+    });
+
+    Ok(pipe)
+}
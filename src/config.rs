@@ -0,0 +1,152 @@
+//! Configuration files.
+//!
+//! A configuration file holds the long command line options by name, e.g.
+//! `discard-after = "2s"` in TOML or `discard-after: 2s` in YAML, picked by
+//! the file extension. Its options are turned into arguments preceding the
+//! ones of the command line, leaving out those also given on the command
+//! line, so that command line flags override them, repeated options
+//! included.
+
+use std::ffi::OsString;
+use std::path::{Path, PathBuf};
+
+use anyhow::Context as _;
+use clap::CommandFactory;
+use serde_json::Value;
+
+use crate::cli::Args;
+
+/// Path of the `--config` option in `args`, if any
+fn find_config(args: &[OsString]) -> Option<PathBuf> {
+    let mut iter = args.iter().skip(1);
+
+    while let Some(arg) = iter.next() {
+        let arg = arg.to_string_lossy();
+
+        if arg == "--" {
+            break;
+        } else if arg == "--config" {
+            return iter.next().map(PathBuf::from);
+        } else if let Some(path) = arg.strip_prefix("--config=") {
+            return Some(PathBuf::from(path));
+        }
+    }
+
+    None
+}
+
+/// Long name of the option `name` of `command`, resolving its aliases
+fn canonical(command: &clap::Command, name: &str) -> String {
+    command
+        .get_arguments()
+        .find(|arg| {
+            arg.get_long() == Some(name)
+                || arg
+                    .get_all_aliases()
+                    .map_or(false, |aliases| aliases.contains(&name))
+        })
+        .and_then(|arg| arg.get_long())
+        .unwrap_or(name)
+        .to_string()
+}
+
+/// Long names of the options given in `args`
+fn given_options(command: &clap::Command, args: &[OsString]) -> Vec<String> {
+    args.iter()
+        .skip(1)
+        .map(|arg| arg.to_string_lossy())
+        .take_while(|arg| arg != "--")
+        .filter_map(|arg| {
+            let name = arg.strip_prefix("--")?;
+            let name = name.split('=').next().unwrap_or(name);
+            Some(canonical(command, name))
+        })
+        .collect()
+}
+
+fn load(path: &Path) -> Result<serde_json::Map<String, Value>, anyhow::Error> {
+    let contents = std::fs::read_to_string(path)
+        .with_context(|| format!("Failed to read config file {}", path.display()))?;
+
+    let value: Value = match path.extension().and_then(|ext| ext.to_str()) {
+        Some("toml") => toml::from_str(&contents)?,
+        Some("yaml") | Some("yml") => serde_yaml::from_str(&contents)?,
+        _ => anyhow::bail!(
+            "Unknown config file format {}, expected .toml, .yaml or .yml",
+            path.display()
+        ),
+    };
+
+    match value {
+        Value::Object(options) => Ok(options),
+        Value::Null => Ok(Default::default()),
+        _ => anyhow::bail!("Config file {} is not a table of options", path.display()),
+    }
+}
+
+fn scalar(name: &str, value: &Value) -> Result<String, anyhow::Error> {
+    match value {
+        Value::String(s) => Ok(s.clone()),
+        Value::Number(n) => Ok(n.to_string()),
+        _ => anyhow::bail!("Invalid value for {} in config file: {}", name, value),
+    }
+}
+
+/// Arguments for `options`, booleans being flags and arrays repeated
+/// options, leaving out the options named in `skip`
+fn to_args(
+    command: &clap::Command,
+    options: &serde_json::Map<String, Value>,
+    skip: &[String],
+) -> Result<Vec<OsString>, anyhow::Error> {
+    let mut args = vec![];
+
+    for (name, value) in options {
+        let long = name.replace('_', "-");
+        let flag = format!("--{}", long);
+
+        if flag == "--config" {
+            anyhow::bail!("Config files can not include other config files");
+        }
+
+        if skip.contains(&canonical(command, &long)) {
+            continue;
+        }
+
+        match value {
+            Value::Bool(true) => args.push(flag.into()),
+            Value::Bool(false) | Value::Null => (),
+            Value::Array(values) => {
+                for value in values {
+                    args.push(flag.clone().into());
+                    args.push(scalar(name, value)?.into());
+                }
+            }
+            value => {
+                args.push(flag.into());
+                args.push(scalar(name, value)?.into());
+            }
+        }
+    }
+
+    Ok(args)
+}
+
+/// `args` with the options of the config file passed with `--config`
+/// inserted after the program name
+pub fn expand_args(mut args: Vec<OsString>) -> Result<Vec<OsString>, anyhow::Error> {
+    let path = match find_config(&args) {
+        Some(path) => path,
+        None => return Ok(args),
+    };
+
+    let command = Args::command();
+    let given = given_options(&command, &args);
+    let options = to_args(&command, &load(&path)?, &given)
+        .with_context(|| format!("Invalid config file {}", path.display()))?;
+
+    let at = args.len().min(1);
+    args.splice(at..at, options);
+
+    Ok(args)
+}
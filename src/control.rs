@@ -0,0 +1,427 @@
+//! HTTP control API for operating a running instance.
+//!
+//! * `GET /state`: what is on air, the state of the source, uptime, restarts,
+//!   the live source URI, the input statistics and the end-to-end latency
+//! * `POST /slate`: hold the slate on air until `DELETE /slate`
+//! * `POST /live`: ignore the fallback triggers until `DELETE /live`
+//! * `POST /uri`: switch to the live source URI in the request body
+//! * `POST /av-offset`: offset the live audio by the milliseconds in the
+//!   request body, negative to delay the video instead
+//! * `POST /dot`: dump the pipeline graphs, if dumping is enabled
+//! * `GET /snapshot`: the frame currently on air as JPEG, or as PNG with
+//!   `?format=png`
+//! * `POST /slate/asset?name=<FILE>`: replace the slate video with the image
+//!   or video in the request body, the extension of `FILE` telling its format
+//!
+//! The live source URI can also be switched with a `uri <URI>` line on the
+//! standard input, and the A/V offset changed with `av-offset <MS>`, for
+//! when an HTTP server is not wanted. SIGUSR1 and
+//! SIGUSR2 toggle the slate and live overrides respectively.
+//!
+//! The API is only served on localhost by default, and requires a bearer
+//! token with `--control-token`. Switching the URI restarts the source
+//! through its reconnector, which cancels any reconnection scheduled for the
+//! previous URI.
+
+use std::io::{BufRead, Read};
+use std::net::SocketAddr;
+use std::sync::Arc;
+
+use gst::prelude::*;
+use serde_json::json;
+
+use crate::av_offset::AvOffset;
+use crate::gate::LiveGate;
+use crate::health::Health;
+use crate::input_stats::InputStats;
+use crate::latency::LatencyMonitor;
+use crate::machine::StateMachine;
+use crate::reconnect::Reconnector;
+use crate::slate::{Slate, SlateReason};
+use crate::slate_upload::SlateUploads;
+use crate::snapshot::{ImageFormat, Snapshot};
+use crate::status::Status;
+
+pub const FORCED: &str = "forced by operator";
+
+/// Largest slate asset accepted
+const MAX_ASSET_BYTES: u64 = 256 * 1024 * 1024;
+
+pub struct Control {
+    pub gate: Arc<LiveGate>,
+    pub health: Arc<Health>,
+    pub status: Arc<Status>,
+    pub slate: Arc<Slate>,
+    pub machine: Arc<StateMachine>,
+    /// The RTMP source, playbin3 or uridecodebin3 with --single-pipeline
+    pub source: gst::Element,
+    /// Restarts `source`
+    pub reconnector: Arc<Reconnector>,
+    /// Token requests must carry as `Authorization: Bearer <TOKEN>`, if any
+    pub token: Option<String>,
+    /// All pipelines, for graph dumps
+    pub pipes: Vec<gst::Pipeline>,
+    /// Tap on the output, only set when serving the API or taking
+    /// thumbnails
+    pub snapshot: Option<Arc<Snapshot>>,
+    pub input_stats: Arc<InputStats>,
+    pub latency: Arc<LatencyMonitor>,
+    pub av_offset: Arc<AvOffset>,
+    /// Where slate uploads are stored, only set with --slate-upload-dir
+    pub slate_uploads: Option<Arc<SlateUploads>>,
+}
+
+type Response = tiny_http::Response<std::io::Cursor<Vec<u8>>>;
+
+/// Hold the slate on air, replacing any live override
+pub fn force_slate(gate: &LiveGate, forced: bool) {
+    if forced {
+        gate.force_open(false);
+        gate.force_close(FORCED);
+    } else {
+        gate.open(FORCED);
+    }
+}
+
+/// Keep the live video on air, replacing any slate override
+pub fn force_live(gate: &LiveGate, forced: bool) {
+    if forced {
+        gate.open(FORCED);
+    }
+    gate.force_open(forced);
+}
+
+/// Toggle the slate override on SIGUSR1 and the live override on SIGUSR2
+pub fn handle_signals(gate: &Arc<LiveGate>) {
+    let gate_clone = gate.clone();
+    glib::unix_signal_add(libc::SIGUSR1, move || {
+        force_slate(&gate_clone, !gate_clone.is_closed_by(FORCED));
+        glib::Continue(true)
+    });
+
+    let gate = gate.clone();
+    glib::unix_signal_add(libc::SIGUSR2, move || {
+        force_live(&gate, !gate.is_forced_open());
+        glib::Continue(true)
+    });
+}
+
+/// Compare `a` and `b` in a time independent of where they differ, so the
+/// token cannot be guessed byte by byte from response times
+fn constant_time_eq(a: &str, b: &str) -> bool {
+    if a.len() != b.len() {
+        return false;
+    }
+
+    a.bytes()
+        .zip(b.bytes())
+        .fold(0, |acc, (a, b)| acc | (a ^ b))
+        == 0
+}
+
+/// The percent-decoded value of `key` in the query string `query`
+fn query_value(query: &str, key: &str) -> Option<String> {
+    query
+        .split('&')
+        .filter_map(|pair| pair.split_once('='))
+        .find(|(name, _)| *name == key)
+        .and_then(|(_, value)| glib::uri_unescape_string(&value.replace('+', "%20"), None))
+        .map(|value| value.to_string())
+}
+
+fn text(status: u16, body: &str) -> Response {
+    tiny_http::Response::from_string(body).with_status_code(status)
+}
+
+impl Control {
+    fn state(&self) -> Response {
+        let body = json!({
+            "on_air": self.status.on_air().label(),
+            "live": self.status.shows_live(),
+            "state": self.machine.state().name(),
+            "slate_forced": self.gate.is_closed_by(FORCED),
+            "live_forced": self.gate.is_forced_open(),
+            "uptime": self.health.uptime().as_secs(),
+            "restarts": crate::restarts(),
+            "uri": self.status.uri(),
+            "input": self.input_stats.to_json(),
+            "latency_ms": self.latency.latency().map(|latency| latency.as_millis() as u64),
+            "av_offset_ms": self.av_offset.get(),
+        });
+
+        tiny_http::Response::from_string(body.to_string()).with_header(
+            "Content-Type: application/json"
+                .parse::<tiny_http::Header>()
+                .unwrap(),
+        )
+    }
+
+    /// Restart the RTMP source on `uri`, the output pipeline keeps running
+    /// and shows the slate until the new source is live
+    fn restart_on(&self, uri: &str) -> Result<(), anyhow::Error> {
+        info!("Switching live source to {}", uri);
+
+        self.slate.set_reason(SlateReason::Starting);
+        self.status.set_uri(uri);
+
+        self.source.set_property("uri", &uri)?;
+        self.reconnector.restart(&self.source)
+    }
+
+    fn swap_uri(&self, uri: &str) -> Response {
+        let uri = match crate::cli::parse_uri(uri.trim()) {
+            Ok(uri) => uri,
+            Err(err) => return text(400, &err),
+        };
+
+        match self.restart_on(&uri) {
+            Ok(_) => text(200, "OK"),
+            Err(err) => text(500, &err.to_string()),
+        }
+    }
+
+    fn set_av_offset(&self, offset_ms: &str) -> Response {
+        let offset_ms = match offset_ms.trim().parse::<i64>() {
+            Ok(offset_ms) => offset_ms,
+            Err(err) => return text(400, &err.to_string()),
+        };
+
+        match self.av_offset.set(offset_ms) {
+            Ok(_) => text(200, "OK"),
+            Err(err) => text(400, &err.to_string()),
+        }
+    }
+
+    fn run_command(&self, line: &str) {
+        let mut words = line.split_whitespace();
+
+        match (words.next(), words.next(), words.next()) {
+            (None, ..) => (),
+            (Some("uri"), Some(uri), None) => match crate::cli::parse_uri(uri) {
+                Ok(uri) => {
+                    if let Err(err) = self.restart_on(&uri) {
+                        warn!("Failed to switch live source: {}", err);
+                    }
+                }
+                Err(err) => warn!("{}", err),
+            },
+            (Some("av-offset"), Some(offset_ms), None) => match offset_ms.parse::<i64>() {
+                Ok(offset_ms) => {
+                    if let Err(err) = self.av_offset.set(offset_ms) {
+                        warn!("Failed to change A/V offset: {}", err);
+                    }
+                }
+                Err(err) => warn!("Invalid A/V offset {}: {}", offset_ms, err),
+            },
+            _ => warn!(
+                "Unknown command {:?}, expected uri <URI> or av-offset <MS>",
+                line
+            ),
+        }
+    }
+
+    fn upload_slate(&self, query: &str, request: &mut tiny_http::Request) -> Response {
+        let uploads = match self.slate_uploads {
+            Some(ref uploads) => uploads,
+            None => return text(409, "Slate uploads are disabled, set --slate-upload-dir"),
+        };
+        let extension = match query_value(query, "name")
+            .as_deref()
+            .and_then(|name| std::path::Path::new(name).extension())
+        {
+            Some(extension) => extension.to_string_lossy().to_string(),
+            None => return text(400, "Missing ?name=<FILE> with an extension"),
+        };
+
+        let mut data = vec![];
+        if let Err(err) = request
+            .as_reader()
+            .take(MAX_ASSET_BYTES + 1)
+            .read_to_end(&mut data)
+        {
+            return text(400, &err.to_string());
+        }
+        if data.len() as u64 > MAX_ASSET_BYTES {
+            return text(413, "Payload Too Large");
+        }
+
+        match uploads.store(&data, &extension, |uri| self.slate.set_default_asset(uri)) {
+            Ok(_) => text(200, "OK"),
+            Err(err) => text(422, &format!("{:#}", err)),
+        }
+    }
+
+    fn dump(&self) -> Response {
+        if !crate::dotdump::is_enabled() {
+            return text(409, "Graph dumps are disabled, set --dot-dir");
+        }
+
+        for pipe in &self.pipes {
+            crate::dotdump::dump(pipe, "CONTROL");
+        }
+
+        text(200, "OK")
+    }
+
+    fn snapshot(&self, query: &str) -> Response {
+        let snapshot = match self.snapshot {
+            Some(ref snapshot) => snapshot,
+            None => return text(404, "Not Found"),
+        };
+        let format = match query_value(query, "format") {
+            Some(format) => match format.parse::<ImageFormat>() {
+                Ok(format) => format,
+                Err(err) => return text(400, &err.to_string()),
+            },
+            None => ImageFormat::Jpeg,
+        };
+
+        match snapshot.take(format) {
+            Ok(Some(image)) => tiny_http::Response::from_data(image).with_header(
+                format!("Content-Type: {}", format.mime_type())
+                    .parse::<tiny_http::Header>()
+                    .unwrap(),
+            ),
+            Ok(None) => text(503, "No frame output yet"),
+            Err(err) => text(500, &err.to_string()),
+        }
+    }
+
+    /// Whether `request` carries the token, if one is required
+    fn authorized(&self, request: &tiny_http::Request) -> bool {
+        let token = match self.token {
+            Some(ref token) => token,
+            None => return true,
+        };
+
+        request.headers().iter().any(|header| {
+            header.field.equiv("Authorization")
+                && header
+                    .value
+                    .as_str()
+                    .strip_prefix("Bearer ")
+                    .map_or(false, |bearer| constant_time_eq(bearer, token))
+        })
+    }
+
+    fn handle(&self, request: &mut tiny_http::Request) -> Response {
+        if !self.authorized(request) {
+            return text(401, "Unauthorized");
+        }
+
+        let method = request.method().clone();
+        let url = request.url().to_string();
+        let (path, query) = url.split_once('?').unwrap_or((&url, ""));
+
+        match (method, path) {
+            (tiny_http::Method::Get, "/state") => self.state(),
+            (tiny_http::Method::Post, "/slate") => {
+                force_slate(&self.gate, true);
+                text(200, "OK")
+            }
+            (tiny_http::Method::Delete, "/slate") => {
+                force_slate(&self.gate, false);
+                text(200, "OK")
+            }
+            (tiny_http::Method::Post, "/live") => {
+                force_live(&self.gate, true);
+                text(200, "OK")
+            }
+            (tiny_http::Method::Delete, "/live") => {
+                force_live(&self.gate, false);
+                text(200, "OK")
+            }
+            (tiny_http::Method::Post, "/uri") => {
+                let mut uri = String::new();
+                match request.as_reader().read_to_string(&mut uri) {
+                    Ok(_) => self.swap_uri(&uri),
+                    Err(err) => text(400, &err.to_string()),
+                }
+            }
+            (tiny_http::Method::Post, "/av-offset") => {
+                let mut offset_ms = String::new();
+                match request.as_reader().read_to_string(&mut offset_ms) {
+                    Ok(_) => self.set_av_offset(&offset_ms),
+                    Err(err) => text(400, &err.to_string()),
+                }
+            }
+            (tiny_http::Method::Post, "/slate/asset") => self.upload_slate(query, request),
+            (tiny_http::Method::Post, "/dot") => self.dump(),
+            (tiny_http::Method::Get, "/snapshot") => self.snapshot(query),
+            (_, "/state")
+            | (_, "/slate")
+            | (_, "/live")
+            | (_, "/uri")
+            | (_, "/av-offset")
+            | (_, "/slate/asset")
+            | (_, "/dot")
+            | (_, "/snapshot") => text(405, "Method Not Allowed"),
+            _ => text(404, "Not Found"),
+        }
+    }
+
+    fn respond(&self, mut request: tiny_http::Request) {
+        let response = self.handle(&mut request);
+
+        if let Err(err) = request.respond(response) {
+            warn!("Failed to respond to control request: {}", err);
+        }
+    }
+
+    /// Serve the API on `address` from a dedicated thread
+    pub fn serve(self: &Arc<Self>, address: SocketAddr) -> Result<(), anyhow::Error> {
+        let server = tiny_http::Server::http(address)
+            .map_err(|err| anyhow::anyhow!("Failed to listen on {}: {}", address, err))?;
+
+        info!("Serving control API on {}", address);
+
+        let control = self.clone();
+        std::thread::spawn(move || {
+            for request in server.incoming_requests() {
+                /* Uploads preroll for up to ten seconds, during which the
+                 * slate must remain controllable */
+                if request.url().starts_with("/slate/asset") {
+                    let control = control.clone();
+                    std::thread::spawn(move || control.respond(request));
+                } else {
+                    control.respond(request);
+                }
+            }
+        });
+
+        Ok(())
+    }
+
+    /// Run the commands read from the standard input, one per line, from a
+    /// dedicated thread
+    pub fn read_commands(self: &Arc<Self>) {
+        let control = self.clone();
+        std::thread::spawn(move || {
+            for line in std::io::stdin().lock().lines() {
+                match line {
+                    Ok(line) => control.run_command(&line),
+                    Err(err) => {
+                        warn!("Failed to read commands: {}", err);
+                        break;
+                    }
+                }
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn query_values() {
+        assert_eq!(query_value("name=a.png", "name").as_deref(), Some("a.png"));
+        assert_eq!(
+            query_value("x=1&name=my%20slate+v2.mp4", "name").as_deref(),
+            Some("my slate v2.mp4")
+        );
+        assert_eq!(query_value("format=png", "name"), None);
+        assert_eq!(query_value("", "format"), None);
+    }
+}
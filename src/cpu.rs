@@ -0,0 +1,112 @@
+//! CPU budget detection.
+//!
+//! In containers the CPU quota is usually much lower than the number of
+//! CPUs visible on the host, yet codec libraries size their thread pools
+//! after the latter. Oversubscribing a quota gets the whole process
+//! throttled, which shows up as stuttering output. The software encoders of
+//! the outputs share what is left after decoding and compositing, with a
+//! faster preset the less they get.
+
+/// Path of our cgroup when the unified cgroup v2 hierarchy is mounted
+fn cgroup_v2_path() -> Option<std::path::PathBuf> {
+    if !std::path::Path::new("/sys/fs/cgroup/cgroup.controllers").exists() {
+        return None;
+    }
+
+    let cgroups = std::fs::read_to_string("/proc/self/cgroup").ok()?;
+    let path = cgroups.lines().find_map(|line| line.strip_prefix("0::"))?;
+
+    Some(std::path::Path::new("/sys/fs/cgroup").join(path.trim_start_matches('/')))
+}
+
+/// Parse a cgroup v2 `cpu.max`, `None` when unlimited
+fn parse_cpu_max(max: &str) -> Option<f64> {
+    /* "<quota> <period>" or "max <period>" */
+    let mut fields = max.split_whitespace();
+    let quota = fields.next()?.parse::<f64>().ok()?;
+    let period = fields.next()?.parse::<f64>().ok()?;
+
+    Some(quota / period)
+}
+
+/// The CPU quota of our cgroup, in CPUs
+fn cgroup_quota() -> Option<f64> {
+    /* cgroup v2, the quotas of all ancestors apply as well */
+    if let Some(path) = cgroup_v2_path() {
+        return path
+            .ancestors()
+            .take_while(|dir| dir.starts_with("/sys/fs/cgroup"))
+            .filter_map(|dir| std::fs::read_to_string(dir.join("cpu.max")).ok())
+            .filter_map(|max| parse_cpu_max(&max))
+            .fold(None, |min: Option<f64>, quota| {
+                Some(min.map_or(quota, |min| min.min(quota)))
+            });
+    }
+
+    /* cgroup v1, the quota is -1 when unlimited */
+    let quota = std::fs::read_to_string("/sys/fs/cgroup/cpu/cpu.cfs_quota_us").ok()?;
+    let period = std::fs::read_to_string("/sys/fs/cgroup/cpu/cpu.cfs_period_us").ok()?;
+    let quota = quota.trim().parse::<f64>().ok().filter(|quota| *quota > 0.0)?;
+    let period = period.trim().parse::<f64>().ok()?;
+
+    Some(quota / period)
+}
+
+/// Number of CPUs we can actually use, fractional when limited by a quota
+pub fn detect() -> f64 {
+    let cpus = std::thread::available_parallelism()
+        .map(|n| n.get() as f64)
+        .unwrap_or(1.0);
+
+    match cgroup_quota() {
+        Some(quota) if quota < cpus => quota,
+        _ => cpus,
+    }
+}
+
+/// CPUs reserved for decoding the live stream and compositing the program
+const MIXING_CPUS: f64 = 1.0;
+
+/// CPUs a software H.264 encoder needs for 720p at its fastest preset
+const MIN_ENCODER_CPUS: f64 = 0.5;
+
+/// Number of worker threads to give a codec within `budget`
+pub fn threads(budget: f64) -> u32 {
+    (budget.ceil() as u32).max(1)
+}
+
+/// Number of worker threads of each of `encoders` software encoders, sharing
+/// what is left of `budget` after decoding and compositing
+pub fn encoder_threads(budget: f64, encoders: usize) -> u32 {
+    threads((budget - MIXING_CPUS).max(0.0) / encoders.max(1) as f64)
+}
+
+/// x264 speed preset fitting an encoder with `threads` worker threads,
+/// trading compression for speed as the budget shrinks
+pub fn x264_preset(threads: u32) -> &'static str {
+    match threads {
+        0..=1 => "ultrafast",
+        2..=3 => "superfast",
+        _ => "veryfast",
+    }
+}
+
+/// Warn when the budget is below what decoding and compositing a 720p
+/// stream, and encoding `encoders` outputs in software, typically needs.
+pub fn check(budget: f64, encoders: usize) {
+    info!("CPU budget: {:.2} CPUs", budget);
+
+    let needed = MIXING_CPUS + encoders as f64 * MIN_ENCODER_CPUS;
+    if budget < MIXING_CPUS {
+        warn!(
+            "CPU budget of {:.2} CPUs is likely too low to decode and composite in real time",
+            budget
+        );
+    } else if budget < needed {
+        warn!(
+            "CPU budget of {:.2} CPUs is likely too low to also encode {} outputs in software, \
+             {:.2} CPUs needed, consider a hardware --encoder or fewer outputs",
+            budget, encoders, needed
+        );
+    }
+}
@@ -0,0 +1,57 @@
+//! Classic double-fork daemonization with a PID file, for deployments
+//! without a service manager.
+
+use std::os::unix::io::AsRawFd;
+use std::path::{Path, PathBuf};
+
+/// Resolve `path` against the current directory, as daemonizing changes it
+pub fn absolute(path: &Path) -> Result<PathBuf, anyhow::Error> {
+    Ok(std::env::current_dir()?.join(path))
+}
+
+/// Detach from the terminal, this must run before any thread is spawned,
+/// in particular before gst::init().
+pub fn daemonize(pid_file: Option<&Path>) -> Result<(), anyhow::Error> {
+    unsafe {
+        match libc::fork() {
+            -1 => return Err(std::io::Error::last_os_error().into()),
+            0 => (),
+            _ => libc::_exit(0),
+        }
+
+        if libc::setsid() < 0 {
+            return Err(std::io::Error::last_os_error().into());
+        }
+
+        /* Make sure we can never reacquire a controlling terminal */
+        match libc::fork() {
+            -1 => return Err(std::io::Error::last_os_error().into()),
+            0 => (),
+            _ => libc::_exit(0),
+        }
+    }
+
+    std::env::set_current_dir("/")?;
+
+    let null = std::fs::OpenOptions::new()
+        .read(true)
+        .write(true)
+        .open("/dev/null")?;
+    for fd in 0..3 {
+        if unsafe { libc::dup2(null.as_raw_fd(), fd) } < 0 {
+            return Err(std::io::Error::last_os_error().into());
+        }
+    }
+
+    if let Some(pid_file) = pid_file {
+        std::fs::write(pid_file, format!("{}\n", std::process::id()))?;
+    }
+
+    Ok(())
+}
+
+pub fn remove_pid_file(pid_file: &Path) {
+    if let Err(err) = std::fs::remove_file(pid_file) {
+        warn!("Failed to remove PID file {}: {}", pid_file.display(), err);
+    }
+}
@@ -0,0 +1,103 @@
+//! Live diagnostics burned into the output, for debugging switches in the
+//! lab.
+
+use std::sync::{Arc, Mutex};
+
+use gst::prelude::*;
+
+use crate::status::Status;
+
+/// How often the overlay is refreshed
+const INTERVAL_MS: u32 = 200;
+
+struct State {
+    live_pts: gst::ClockTime,
+    output_pts: gst::ClockTime,
+}
+
+pub struct DebugOverlay {
+    overlay: gst::Element,
+    state: Mutex<State>,
+}
+
+fn track_pts(overlay: &Arc<DebugOverlay>, pad: &gst::Pad, live: bool) {
+    let overlay = overlay.clone();
+    pad.add_probe(gst::PadProbeType::BUFFER, move |_, info| {
+        if let Some(gst::PadProbeData::Buffer(ref buffer)) = info.data {
+            let mut state = overlay.state.lock().unwrap();
+            if live {
+                state.live_pts = buffer.get_pts();
+            } else {
+                state.output_pts = buffer.get_pts();
+            }
+        }
+        gst::PadProbeReturn::Ok
+    });
+}
+
+impl DebugOverlay {
+    pub fn new() -> Result<Arc<Self>, anyhow::Error> {
+        let overlay = crate::hints::make_element("textoverlay", Some("debug_overlay"))?;
+        overlay.set_property_from_str("valignment", "top");
+        overlay.set_property_from_str("halignment", "left");
+        overlay.set_property("font-desc", &"Monospace 14")?;
+        overlay.set_property("shaded-background", &true)?;
+
+        Ok(Arc::new(Self {
+            overlay,
+            state: Mutex::new(State {
+                live_pts: gst::CLOCK_TIME_NONE,
+                output_pts: gst::CLOCK_TIME_NONE,
+            }),
+        }))
+    }
+
+    /// Element rendering the diagnostics, to link after the compositor
+    pub fn element(&self) -> &gst::Element {
+        &self.overlay
+    }
+
+    /// Track the timestamps of the live input on `live_pad` and of the
+    /// output on `output_pad`
+    pub fn install(self: &Arc<Self>, live_pad: &gst::Pad, output_pad: &gst::Pad) {
+        track_pts(self, live_pad, true);
+        track_pts(self, output_pad, false);
+    }
+
+    fn text(&self, status: &Status, queue: &gst::Element) -> String {
+        let state = self.state.lock().unwrap();
+        let level_buffers = queue
+            .get_property("current-level-buffers")
+            .ok()
+            .and_then(|value| value.get_some::<u32>().ok())
+            .unwrap_or(0);
+        let level_time = queue
+            .get_property("current-level-time")
+            .ok()
+            .and_then(|value| value.get_some::<u64>().ok())
+            .unwrap_or(0);
+
+        format!(
+            "state     {}\nlive pts  {}\nout pts   {}\nrestarts  {}\nqueue     {} buffers / {}",
+            status.on_air().label(),
+            state.live_pts,
+            state.output_pts,
+            crate::restarts(),
+            level_buffers,
+            gst::ClockTime::from_nseconds(level_time),
+        )
+    }
+
+    /// Refresh the diagnostics periodically, `queue` being the live queue
+    /// in front of the compositor
+    pub fn start(self: &Arc<Self>, status: &Arc<Status>, queue: &gst::Element) {
+        let overlay = self.clone();
+        let status = status.clone();
+        let queue = queue.clone();
+        glib::timeout_add(INTERVAL_MS, move || {
+            let text = overlay.text(&status, &queue);
+            overlay.overlay.set_property("text", &text).unwrap();
+            glib::Continue(true)
+        });
+    }
+}
@@ -0,0 +1,84 @@
+//! Decoder error rate tracking.
+//!
+//! Decoders report recoverable errors as warnings, or flag the output
+//! buffers as corrupted, and keep producing macroblock soup. When errors
+//! pile up we would rather restart the source and resync on a keyframe.
+
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use gst::prelude::*;
+
+pub struct DecodeErrorMonitor {
+    errors: Mutex<VecDeque<Instant>>,
+    max_errors: usize,
+    window: Duration,
+}
+
+impl DecodeErrorMonitor {
+    pub fn new(max_errors: usize, window: Duration) -> Arc<Self> {
+        Arc::new(Self {
+            errors: Mutex::new(VecDeque::new()),
+            max_errors,
+            window,
+        })
+    }
+
+    /// Record an error, returns whether the rate is now exceeded, in which
+    /// case the count starts over
+    pub fn record(&self) -> bool {
+        let now = Instant::now();
+        let mut errors = self.errors.lock().unwrap();
+
+        errors.push_back(now);
+        while errors
+            .front()
+            .map_or(false, |time| now.duration_since(*time) > self.window)
+        {
+            errors.pop_front();
+        }
+
+        if errors.len() > self.max_errors {
+            errors.clear();
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Whether `msg` is a warning posted by a decoder
+    pub fn is_decoder_warning(msg: &gst::Message) -> bool {
+        if let gst::MessageView::Warning(_) = msg.view() {
+            msg.get_src()
+                .and_then(|src| src.downcast::<gst::Element>().ok())
+                .and_then(|element| element.get_factory())
+                .and_then(|factory| {
+                    factory
+                        .get_metadata(&gst::ELEMENT_METADATA_KLASS)
+                        .map(|klass| klass.contains("Decoder"))
+                })
+                .unwrap_or(false)
+        } else {
+            false
+        }
+    }
+
+    /// Count decoded buffers flagged as corrupted flowing through `pad`
+    pub fn install(self: &Arc<Self>, pad: &gst::Pad) {
+        let monitor = self.clone();
+        pad.add_probe(gst::PadProbeType::BUFFER, move |pad, info| {
+            if let Some(gst::PadProbeData::Buffer(ref buffer)) = info.data {
+                if buffer.get_flags().contains(gst::BufferFlags::CORRUPTED) && monitor.record() {
+                    let element = pad.get_parent_element().unwrap();
+                    gst::gst_element_error!(
+                        element,
+                        gst::StreamError::Decode,
+                        ["Too many corrupted frames"]
+                    );
+                }
+            }
+            gst::PadProbeReturn::Ok
+        });
+    }
+}
@@ -0,0 +1,40 @@
+//! Deinterlacing of the live video.
+//!
+//! Interlaced contributions would otherwise reach the compositor and the
+//! encoders as is, which progressive-only encoders and players downstream
+//! render with combing artifacts. Every field becomes a frame, and
+//! `videorate` in the live branch decimates the doubled framerate to the
+//! output framerate if one is set.
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Deinterlace {
+    /// Only interlaced frames, as flagged in the caps and buffers
+    Auto,
+    /// All frames, for sources flagging interlaced video as progressive
+    On,
+    Off,
+}
+
+impl std::str::FromStr for Deinterlace {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "auto" => Ok(Deinterlace::Auto),
+            "on" => Ok(Deinterlace::On),
+            "off" => Ok(Deinterlace::Off),
+            _ => Err(anyhow::anyhow!("Unknown deinterlace mode {}", s)),
+        }
+    }
+}
+
+impl Deinterlace {
+    /// Elements to prepend to the live branch, as a gst-launch description
+    pub fn prefix(self) -> &'static str {
+        match self {
+            Deinterlace::Auto => "deinterlace mode=auto ! ",
+            Deinterlace::On => "deinterlace mode=interlaced ! ",
+            Deinterlace::Off => "",
+        }
+    }
+}
@@ -0,0 +1,101 @@
+//! Pipeline graph dumps on every state transition and error.
+//!
+//! Files are named `rtmp-slate-fallback-<pid>-<timestamp>-<pipeline>-<label>.dot`
+//! so that they sort chronologically, and the oldest ones are removed once
+//! the retention cap is reached, so long runs don't fill the disk. Only the
+//! dumps of this process are ever removed, the directory may be shared with
+//! other processes or channels.
+
+use std::path::{Path, PathBuf};
+
+use gst::prelude::*;
+use once_cell::sync::OnceCell;
+
+struct DotDumper {
+    dir: PathBuf,
+    /// Prefix of the files of this process
+    prefix: String,
+    retention: usize,
+}
+
+static DUMPER: OnceCell<DotDumper> = OnceCell::new();
+
+/// Dump to `dir`, or to GST_DEBUG_DUMP_DOT_DIR when not set. Dumping is
+/// disabled when neither is set.
+pub fn init(dir: Option<&Path>, retention: usize) -> Result<(), anyhow::Error> {
+    let dir = match dir
+        .map(Path::to_path_buf)
+        .or_else(|| std::env::var_os("GST_DEBUG_DUMP_DOT_DIR").map(PathBuf::from))
+    {
+        Some(dir) => dir,
+        None => return Ok(()),
+    };
+
+    std::fs::create_dir_all(&dir)?;
+
+    let _ = DUMPER.set(DotDumper {
+        dir,
+        prefix: format!("rtmp-slate-fallback-{}-", std::process::id()),
+        retention,
+    });
+
+    Ok(())
+}
+
+impl DotDumper {
+    fn prune(&self) -> Result<(), anyhow::Error> {
+        let mut dumps = std::fs::read_dir(&self.dir)?
+            .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+            .filter(|path| path.extension().map_or(false, |ext| ext == "dot"))
+            .filter(|path| {
+                path.file_name()
+                    .map_or(false, |name| name.to_string_lossy().starts_with(&self.prefix))
+            })
+            .collect::<Vec<_>>();
+
+        if dumps.len() <= self.retention {
+            return Ok(());
+        }
+
+        dumps.sort();
+        for path in &dumps[..dumps.len() - self.retention] {
+            std::fs::remove_file(path)?;
+        }
+
+        Ok(())
+    }
+
+    fn dump(&self, pipe: &gst::Pipeline, label: &str) -> Result<(), anyhow::Error> {
+        let timestamp = humantime::format_rfc3339_millis(std::time::SystemTime::now());
+        let path = self
+            .dir
+            .join(format!(
+                "{}{}-{}-{}.dot",
+                self.prefix,
+                timestamp,
+                pipe.get_name(),
+                label
+            ));
+
+        std::fs::write(
+            &path,
+            gst::debug_bin_to_dot_data(pipe, gst::DebugGraphDetails::all()).as_str(),
+        )?;
+
+        self.prune()
+    }
+}
+
+/// Whether graphs are dumped at all
+pub fn is_enabled() -> bool {
+    DUMPER.get().is_some()
+}
+
+/// Dump the graph of `pipe`, `label` describes the occasion
+pub fn dump(pipe: &gst::Pipeline, label: &str) {
+    if let Some(dumper) = DUMPER.get() {
+        if let Err(err) = dumper.dump(pipe, label) {
+            warn!("Failed to dump {} graph: {}", pipe.get_name(), err);
+        }
+    }
+}
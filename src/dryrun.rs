@@ -0,0 +1,91 @@
+//! Dry run reporting the negotiated caps and latency of the pipelines.
+//!
+//! Live pipelines do not preroll in PAUSED, caps are only negotiated once
+//! data flows in PLAYING. The dry run thus brings the pipelines to PAUSED,
+//! then to PLAYING for a short while, and reports what got negotiated.
+
+use gst::prelude::*;
+
+fn report_latency(pipe: &gst::Pipeline) {
+    let mut query = gst::query::Latency::new();
+
+    if pipe.query(&mut query) {
+        let (live, min, max) = query.get_result();
+        println!("  latency: live {}, min {}, max {}", live, min, max);
+    } else {
+        println!("  latency: query failed");
+    }
+}
+
+/// Print the caps negotiated on every linked pad of `pipe`, returns whether
+/// all linked pads negotiated
+pub fn report(pipe: &gst::Pipeline) -> bool {
+    let mut lines = vec![];
+    let mut negotiated = true;
+
+    for element in pipe.iterate_recurse().into_iter().flatten() {
+        for pad in element.get_pads() {
+            if !pad.is_linked() {
+                continue;
+            }
+
+            let path = format!("{}.{}", element.get_name(), pad.get_name());
+            match pad.get_current_caps() {
+                Some(caps) => lines.push(format!("  {}: {}", path, caps)),
+                None => {
+                    lines.push(format!("  {}: NOT NEGOTIATED", path));
+                    negotiated = false;
+                }
+            }
+        }
+    }
+
+    lines.sort();
+
+    println!("{}:", pipe.get_name());
+    for line in lines {
+        println!("{}", line);
+    }
+    report_latency(pipe);
+
+    negotiated
+}
+
+/// Bring `pipes` to PAUSED then PLAYING, report after `timeout` and quit
+/// `main_loop`. `on_done` receives whether every pipeline negotiated.
+pub fn start<F>(
+    pipes: Vec<gst::Pipeline>,
+    main_loop: &glib::MainLoop,
+    timeout: std::time::Duration,
+    on_done: F,
+) -> Result<(), anyhow::Error>
+where
+    F: FnOnce(bool) + Send + 'static,
+{
+    for pipe in &pipes {
+        let ret = pipe.set_state(gst::State::Paused)?;
+        println!("{} to PAUSED: {:?}", pipe.get_name(), ret);
+    }
+
+    for pipe in &pipes {
+        crate::hints::set_state(pipe, gst::State::Playing)?;
+    }
+
+    let main_loop = main_loop.clone();
+    let mut on_done = Some(on_done);
+    glib::timeout_add(timeout.as_millis() as u32, move || {
+        let mut negotiated = true;
+        for pipe in &pipes {
+            negotiated &= report(pipe);
+        }
+
+        if let Some(on_done) = on_done.take() {
+            on_done(negotiated);
+        }
+
+        main_loop.quit();
+        glib::Continue(false)
+    });
+
+    Ok(())
+}
@@ -0,0 +1,72 @@
+//! H.264 encoder selection for the outputs.
+//!
+//! Software conversion and encoding of 1080p60 is more than small edge
+//! boxes can do, so the hardware encoders come with their own upload and
+//! conversion elements, keeping the frames in device memory from the
+//! conversion to the encoder.
+
+use crate::cpu;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Encoder {
+    X264,
+    /// Intel and AMD GPUs through VA-API
+    Vaapi,
+    /// NVIDIA GPUs through NVENC
+    Nvenc,
+    /// NVIDIA Jetson encoders, taking NVMM memory
+    Nvv4l2,
+    /// V4L2 stateful encoders, e.g. on Raspberry Pi
+    V4l2,
+}
+
+impl std::str::FromStr for Encoder {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "x264" => Ok(Encoder::X264),
+            "vaapih264" => Ok(Encoder::Vaapi),
+            "nvh264enc" => Ok(Encoder::Nvenc),
+            "nvv4l2h264enc" => Ok(Encoder::Nvv4l2),
+            "v4l2h264enc" => Ok(Encoder::V4l2),
+            _ => Err(anyhow::anyhow!("Unknown encoder {}", s)),
+        }
+    }
+}
+
+impl Encoder {
+    /// Conversion and encoding of raw video to H.264 at `bitrate` kbit/s
+    /// with a keyframe every 60 frames, as a gst-launch description. Software
+    /// encoding is limited to `threads` worker threads.
+    pub fn video_chain(self, bitrate: u32, threads: u32) -> String {
+        match self {
+            Encoder::X264 => format!(
+                "videoconvert n-threads={threads} ! x264enc tune=zerolatency \
+                 speed-preset={preset} threads={threads} key-int-max=60 bitrate={bitrate}",
+                threads = threads,
+                preset = cpu::x264_preset(threads),
+                bitrate = bitrate
+            ),
+            Encoder::Vaapi => format!(
+                "vaapipostproc ! vaapih264enc rate-control=cbr keyframe-period=60 bitrate={}",
+                bitrate
+            ),
+            Encoder::Nvenc => format!(
+                "cudaupload ! cudaconvert ! nvh264enc preset=low-latency-hq rc-mode=cbr \
+                 gop-size=60 bitrate={}",
+                bitrate
+            ),
+            Encoder::Nvv4l2 => format!(
+                "nvvidconv ! video/x-raw(memory:NVMM),format=I420 ! nvv4l2h264enc \
+                 control-rate=1 iframeinterval=60 insert-sps-pps=true bitrate={}",
+                bitrate * 1000
+            ),
+            Encoder::V4l2 => format!(
+                "v4l2convert ! v4l2h264enc \
+                 extra-controls=\"controls,h264_i_frame_period=60,video_bitrate={}\"",
+                bitrate * 1000
+            ),
+        }
+    }
+}
@@ -0,0 +1,50 @@
+//! Process exit codes, distinct per failure class so that supervisors can
+//! decide whether restarting the process is useful. Keep the table in the
+//! README in sync.
+
+use crate::hints::MissingElement;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ExitCode {
+    /// Clean shutdown, the run duration elapsed or the output finished
+    Success,
+    /// Invalid command line or configuration
+    Config,
+    /// A required GStreamer plugin is not installed
+    MissingPlugin,
+    /// The output pipeline failed
+    OutputFailed,
+    /// The source failed for good, e.g. it could not be probed
+    SourceFailed,
+    /// Any other initialization failure, e.g. caps negotiation failing
+    Setup,
+    /// Terminated by a signal, exits with 128 + the signal number as shells do
+    Signal(i32),
+}
+
+impl ExitCode {
+    pub fn code(self) -> i32 {
+        match self {
+            ExitCode::Success => 0,
+            ExitCode::Config => 2,
+            ExitCode::MissingPlugin => 3,
+            ExitCode::OutputFailed => 4,
+            ExitCode::SourceFailed => 5,
+            ExitCode::Setup => 6,
+            ExitCode::Signal(signum) => 128 + signum,
+        }
+    }
+
+    /// Classify an initialization error
+    pub fn for_error(err: &anyhow::Error) -> Self {
+        if err.chain().any(|cause| cause.is::<MissingElement>()) {
+            ExitCode::MissingPlugin
+        } else {
+            ExitCode::Setup
+        }
+    }
+
+    pub fn exit(self) -> ! {
+        std::process::exit(self.code())
+    }
+}
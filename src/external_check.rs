@@ -0,0 +1,79 @@
+//! User-defined health checks, feeding into the fallback decision.
+//!
+//! Either a shell command, healthy when it exits successfully, or an HTTP
+//! URL, healthy when it answers with a 2xx status. This allows querying e.g.
+//! the contribution encoder's own API. Checks run on a dedicated thread so
+//! that slow checks never block the main loop.
+
+use std::process::Command;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use crate::gate::LiveGate;
+
+#[derive(Debug, Clone)]
+pub enum ExternalCheck {
+    Command(String),
+    Http(String),
+}
+
+impl ExternalCheck {
+    fn run_command(command: &str, timeout: Duration) -> Result<(), anyhow::Error> {
+        let mut child = Command::new("sh").arg("-c").arg(command).spawn()?;
+        let start = Instant::now();
+
+        loop {
+            if let Some(status) = child.try_wait()? {
+                if status.success() {
+                    return Ok(());
+                }
+                anyhow::bail!("Command exited with {}", status);
+            }
+
+            if start.elapsed() > timeout {
+                let _ = child.kill();
+                let _ = child.wait();
+                anyhow::bail!("Command timed out after {:?}", timeout);
+            }
+
+            std::thread::sleep(Duration::from_millis(50));
+        }
+    }
+
+    fn run_http(url: &str, timeout: Duration) -> Result<(), anyhow::Error> {
+        ureq::get(url).timeout(timeout).call()?;
+        Ok(())
+    }
+
+    /// Reason given to the live gate, distinct per kind of check so that one
+    /// succeeding does not mask the other failing
+    fn reason(&self) -> &'static str {
+        match self {
+            ExternalCheck::Command(_) => "external health command failed",
+            ExternalCheck::Http(_) => "external health URL failed",
+        }
+    }
+
+    pub fn run(&self, timeout: Duration) -> Result<(), anyhow::Error> {
+        match self {
+            ExternalCheck::Command(command) => Self::run_command(command, timeout),
+            ExternalCheck::Http(url) => Self::run_http(url, timeout),
+        }
+    }
+
+    /// Run the check every `interval`, holding the live gate closed while
+    /// it fails. A check exceeding `timeout` counts as failed.
+    pub fn start(self, interval: Duration, timeout: Duration, gate: Arc<LiveGate>) {
+        std::thread::spawn(move || loop {
+            match self.run(timeout) {
+                Ok(()) => gate.open(self.reason()),
+                Err(err) => {
+                    warn!("External health check {:?} failed: {}", self, err);
+                    gate.close(self.reason());
+                }
+            }
+
+            std::thread::sleep(interval);
+        });
+    }
+}
@@ -0,0 +1,168 @@
+//! Failover between prioritized live inputs.
+//!
+//! Every input has its own RTMP pipeline feeding its own interpipes, and the
+//! mixer listens to the input with the highest priority that currently
+//! delivers video. Once an input delivered no video for the failover
+//! timeout, the mixer switches to the next input that does, and only falls
+//! back to the slate when none does. A higher priority input is switched
+//! back to once it delivered video continuously for the stabilization
+//! period.
+
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use gst::prelude::*;
+
+use crate::status::Status;
+
+/// How often the inputs are checked
+const INTERVAL_MS: u32 = 100;
+
+/// Name of the video interpipesink of input `index`
+pub fn video_sink_name(index: usize) -> String {
+    match index {
+        0 => "rtmp".to_string(),
+        _ => format!("rtmp_{}", index),
+    }
+}
+
+/// Name of the audio interpipesink of input `index`
+pub fn audio_sink_name(index: usize) -> String {
+    match index {
+        0 => "rtmp_audio".to_string(),
+        _ => format!("rtmp_audio_{}", index),
+    }
+}
+
+#[derive(Default)]
+struct Input {
+    last_buffer: Option<Instant>,
+    /// Start of the current streak of video without interruption
+    healthy_since: Option<Instant>,
+}
+
+pub struct Failover {
+    uris: Vec<String>,
+    timeout: Duration,
+    stabilization: Duration,
+    inputs: Vec<Mutex<Input>>,
+    active: Mutex<usize>,
+}
+
+impl Failover {
+    pub fn new(uris: &[String], timeout: Duration, stabilization: Duration) -> Arc<Self> {
+        Arc::new(Self {
+            uris: uris.to_vec(),
+            timeout,
+            stabilization,
+            inputs: uris.iter().map(|_| Mutex::new(Input::default())).collect(),
+            active: Mutex::new(0),
+        })
+    }
+
+    /// Index of the input listened to
+    pub fn active(&self) -> usize {
+        *self.active.lock().unwrap()
+    }
+
+    /// Listen to input `index` from the start, e.g. as saved before a
+    /// restart, the input is only switched once `start()` is called
+    pub fn restore(&self, index: usize) {
+        if index < self.uris.len() {
+            *self.active.lock().unwrap() = index;
+        }
+    }
+
+    /// Track the video of input `index` flowing through `pad`
+    pub fn watch(self: &Arc<Self>, index: usize, pad: &gst::Pad) {
+        let failover = self.clone();
+        let timeout = self.timeout;
+        pad.add_probe(gst::PadProbeType::BUFFER, move |_, _| {
+            let now = Instant::now();
+            let mut input = failover.inputs[index].lock().unwrap();
+
+            if input
+                .last_buffer
+                .map_or(true, |last_buffer| now - last_buffer > timeout)
+            {
+                input.healthy_since = Some(now);
+            }
+            input.last_buffer = Some(now);
+
+            gst::PadProbeReturn::Ok
+        });
+    }
+
+    fn is_healthy(&self, index: usize) -> bool {
+        self.inputs[index]
+            .lock()
+            .unwrap()
+            .last_buffer
+            .map_or(false, |last_buffer| last_buffer.elapsed() <= self.timeout)
+    }
+
+    /// Input other than `index` currently delivering video, if any
+    pub fn healthy_other(&self, index: usize) -> Option<usize> {
+        (0..self.uris.len()).find(|&other| other != index && self.is_healthy(other))
+    }
+
+    fn is_stable(&self, index: usize) -> bool {
+        self.is_healthy(index)
+            && self.inputs[index]
+                .lock()
+                .unwrap()
+                .healthy_since
+                .map_or(false, |since| since.elapsed() >= self.stabilization)
+    }
+
+    /// Input to listen to, given the currently `active` one
+    fn choose(&self, active: usize) -> usize {
+        if self.is_healthy(active) {
+            (0..active)
+                .find(|&index| self.is_stable(index))
+                .unwrap_or(active)
+        } else {
+            (0..self.uris.len())
+                .find(|&index| self.is_healthy(index))
+                .unwrap_or(active)
+        }
+    }
+
+    /// Switch the `video` and `audio` interpipesrcs of the mixer between
+    /// the inputs from the default main context
+    pub fn start(self: &Arc<Self>, video: &gst::Element, audio: &gst::Element, status: &Arc<Status>) {
+        let failover = self.clone();
+        let video = video.clone();
+        let audio = audio.clone();
+        let status = status.clone();
+
+        let active = self.active();
+        if active != 0 {
+            video.set_property("listen-to", &video_sink_name(active)).unwrap();
+            audio.set_property("listen-to", &audio_sink_name(active)).unwrap();
+            status.set_uri(&self.uris[active]);
+        }
+
+        glib::timeout_add(INTERVAL_MS, move || {
+            let mut active = failover.active.lock().unwrap();
+            let chosen = failover.choose(*active);
+
+            if chosen != *active {
+                warn!(
+                    "Failing over from input {} to input {}: {}",
+                    *active, chosen, failover.uris[chosen]
+                );
+                *active = chosen;
+                video
+                    .set_property("listen-to", &video_sink_name(chosen))
+                    .unwrap();
+                audio
+                    .set_property("listen-to", &audio_sink_name(chosen))
+                    .unwrap();
+                status.set_uri(&failover.uris[chosen]);
+            }
+
+            glib::Continue(true)
+        });
+    }
+}
@@ -0,0 +1,95 @@
+//! Alarms on input format changes.
+//!
+//! An unexpected change of resolution, framerate, codec or audio layout on
+//! a contribution feed usually means someone reconfigured the encoder, we
+//! report each change as a structured event.
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+use gst::prelude::*;
+
+use crate::logging::{self, Level};
+
+pub struct FormatWatcher {
+    formats: Mutex<HashMap<&'static str, String>>,
+}
+
+fn describe_video(s: &gst::StructureRef) -> Option<String> {
+    let width = s.get_some::<i32>("width").ok()?;
+    let height = s.get_some::<i32>("height").ok()?;
+    let framerate = s
+        .get_some::<gst::Fraction>("framerate")
+        .map(|f| format!("{}/{}", f.numer(), f.denom()))
+        .unwrap_or_else(|_| "?".to_string());
+
+    Some(format!("{}x{}@{}", width, height, framerate))
+}
+
+fn describe_audio(s: &gst::StructureRef) -> Option<String> {
+    let channels = s.get_some::<i32>("channels").ok()?;
+    let rate = s.get_some::<i32>("rate").ok()?;
+
+    Some(format!("{} channels @ {}Hz", channels, rate))
+}
+
+impl FormatWatcher {
+    pub fn new() -> Arc<Self> {
+        Arc::new(Self {
+            formats: Mutex::new(HashMap::new()),
+        })
+    }
+
+    fn update(&self, kind: &'static str, format: String) {
+        let previous = self.formats.lock().unwrap().insert(kind, format.clone());
+
+        match previous {
+            None => info!("Input {}: {}", kind, format),
+            Some(previous) if previous != format => logging::event(
+                Level::Warn,
+                "format-changed",
+                &format!("Input {} changed", kind),
+                serde_json::json!({ "kind": kind, "from": previous, "to": format }),
+            ),
+            Some(_) => (),
+        }
+    }
+
+    /// Watch the raw caps flowing through `pad`, `audio` selects whether
+    /// the layout or the resolution and framerate are tracked
+    pub fn install(self: &Arc<Self>, pad: &gst::Pad, audio: bool) {
+        let watcher = self.clone();
+        pad.add_probe(gst::PadProbeType::EVENT_DOWNSTREAM, move |_, info| {
+            if let Some(gst::PadProbeData::Event(ref event)) = info.data {
+                if let gst::EventView::Caps(caps) = event.view() {
+                    if let Some(s) = caps.get_caps().get_structure(0) {
+                        if audio {
+                            if let Some(format) = describe_audio(s) {
+                                watcher.update("audio layout", format);
+                            }
+                        } else if let Some(format) = describe_video(s) {
+                            watcher.update("video format", format);
+                        }
+                    }
+                }
+            }
+            gst::PadProbeReturn::Ok
+        });
+    }
+
+    /// Track the codecs of the streams announced by playbin3
+    pub fn handle_stream_collection(&self, collection: &gst::StreamCollection) {
+        let mut codecs = collection
+            .iter()
+            .filter_map(|stream| stream.get_caps())
+            .filter_map(|caps| caps.get_structure(0).map(|s| s.get_name().to_string()))
+            .collect::<Vec<_>>();
+
+        if codecs.is_empty() {
+            return;
+        }
+
+        codecs.sort();
+        self.update("codecs", codecs.join(", "));
+    }
+}
@@ -0,0 +1,113 @@
+//! Input framerate monitoring.
+//!
+//! A feed persistently delivering far fewer frames than its caps advertise
+//! is usually on its way to a full outage.
+
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+
+use gst::prelude::*;
+
+use crate::gate::LiveGate;
+
+pub struct FpsMonitor {
+    frames: AtomicU64,
+    advertised: Mutex<Option<f64>>,
+    measured: Mutex<Option<f64>>,
+}
+
+impl FpsMonitor {
+    pub fn new() -> Arc<Self> {
+        Arc::new(Self {
+            frames: AtomicU64::new(0),
+            advertised: Mutex::new(None),
+            measured: Mutex::new(None),
+        })
+    }
+
+    /// Framerate measured over the last second
+    pub fn measured(&self) -> Option<f64> {
+        *self.measured.lock().unwrap()
+    }
+
+    /// Framerate advertised in the caps, None for variable framerates
+    pub fn advertised(&self) -> Option<f64> {
+        *self.advertised.lock().unwrap()
+    }
+
+    fn handle_caps(&self, caps: &gst::CapsRef) {
+        let framerate = caps
+            .get_structure(0)
+            .and_then(|s| s.get_some::<gst::Fraction>("framerate").ok())
+            .filter(|fraction| *fraction.numer() > 0 && *fraction.denom() > 0)
+            .map(|fraction| *fraction.numer() as f64 / *fraction.denom() as f64);
+
+        *self.advertised.lock().unwrap() = framerate;
+    }
+
+    /// Count the frames flowing through `pad`
+    pub fn install(self: &Arc<Self>, pad: &gst::Pad) {
+        let monitor = self.clone();
+        pad.add_probe(
+            gst::PadProbeType::BUFFER | gst::PadProbeType::EVENT_DOWNSTREAM,
+            move |_, info| {
+                match info.data {
+                    Some(gst::PadProbeData::Buffer(_)) => {
+                        monitor.frames.fetch_add(1, Ordering::Relaxed);
+                    }
+                    Some(gst::PadProbeData::Event(ref event)) => {
+                        if let gst::EventView::Caps(caps) = event.view() {
+                            monitor.handle_caps(caps.get_caps());
+                        }
+                    }
+                    _ => (),
+                }
+                gst::PadProbeReturn::Ok
+            },
+        );
+    }
+
+    /// Measure the framerate every second, flagging it once it stayed below
+    /// `ratio` times the advertised framerate for `timeout_ms` milliseconds.
+    /// When `gate` is given, live video is dropped for as long as that lasts.
+    pub fn start(self: &Arc<Self>, ratio: f64, timeout_ms: u32, gate: Option<Arc<LiveGate>>) {
+        let monitor = self.clone();
+        let mut low_ms = 0u32;
+        let mut dropped = false;
+        glib::timeout_add_seconds(1, move || {
+            let frames = monitor.frames.swap(0, Ordering::Relaxed) as f64;
+            *monitor.measured.lock().unwrap() = Some(frames);
+
+            let advertised = match monitor.advertised() {
+                Some(advertised) => advertised,
+                None => return glib::Continue(true),
+            };
+
+            /* No frames at all is an outage, which is handled elsewhere */
+            if frames > 0.0 && frames < advertised * ratio {
+                low_ms = low_ms.saturating_add(1000);
+                if !dropped && low_ms >= timeout_ms {
+                    dropped = true;
+                    warn!(
+                        "Input framerate dropped to {} fps, {:.2} fps advertised",
+                        frames, advertised
+                    );
+                    if let Some(ref gate) = gate {
+                        gate.close("input framerate dropped");
+                    }
+                }
+            } else {
+                if dropped {
+                    info!("Input framerate recovered to {} fps", frames);
+                    if let Some(ref gate) = gate {
+                        gate.open("input framerate dropped");
+                    }
+                }
+                low_ms = 0;
+                dropped = false;
+            }
+
+            glib::Continue(true)
+        });
+    }
+}
@@ -0,0 +1,149 @@
+//! Frozen and black frame detection on the live video.
+//!
+//! Encoders crashing upstream of the RTMP server often keep sending the last
+//! frame, or plain black, forever. Buffers keep flowing in that case, so
+//! neither the stall watchdog nor the compositor timeout notice anything.
+//! The luma plane of every frame is sampled on a sparse grid and compared to
+//! the previous frame's samples.
+
+use std::sync::{Arc, Mutex};
+
+use gst::prelude::*;
+
+use crate::gate::LiveGate;
+use crate::quality;
+
+/// Mean absolute difference of the samples below which two frames are
+/// considered identical, allowing for dithering in the decoder
+const FREEZE_TOLERANCE: f64 = 0.5;
+/// Luma below which a sample is black, limited range black being 16
+const BLACK_LUMA: u8 = 32;
+/// Proportion of black samples for the frame to be considered black
+const BLACK_RATIO: f64 = 0.99;
+
+#[derive(Default)]
+struct State {
+    previous: Vec<u8>,
+    frozen: u32,
+    black: u32,
+}
+
+pub struct FreezeDetector {
+    state: Mutex<State>,
+    /// Consecutive identical frames before the video is considered frozen
+    freeze_frames: Option<u32>,
+    /// Consecutive black frames before the video is considered black
+    black_frames: Option<u32>,
+    gate: Arc<LiveGate>,
+}
+
+/// Luma samples every 4 pixels on every 4th row
+fn sample(luma: &[u8], stride: usize, width: usize, height: usize) -> Vec<u8> {
+    (0..height)
+        .step_by(4)
+        .flat_map(|y| luma[y * stride..y * stride + width].iter().step_by(4))
+        .copied()
+        .collect()
+}
+
+fn is_identical(samples: &[u8], previous: &[u8]) -> bool {
+    if samples.is_empty() || samples.len() != previous.len() {
+        return false;
+    }
+
+    let diff: u64 = samples
+        .iter()
+        .zip(previous)
+        .map(|(a, b)| (*a as i32 - *b as i32).abs() as u64)
+        .sum();
+
+    (diff as f64 / samples.len() as f64) < FREEZE_TOLERANCE
+}
+
+fn is_black(samples: &[u8]) -> bool {
+    let black = samples.iter().filter(|value| **value < BLACK_LUMA).count();
+
+    !samples.is_empty() && black as f64 / samples.len() as f64 >= BLACK_RATIO
+}
+
+impl FreezeDetector {
+    pub fn new(
+        freeze_frames: Option<u32>,
+        black_frames: Option<u32>,
+        gate: Arc<LiveGate>,
+    ) -> Arc<Self> {
+        Arc::new(Self {
+            state: Mutex::new(State::default()),
+            freeze_frames,
+            black_frames,
+            gate,
+        })
+    }
+
+    /// Close or open the gate for `reason` after `count` consecutive
+    /// matching frames
+    fn update(&self, reason: &'static str, count: u32, limit: Option<u32>) {
+        match limit {
+            Some(limit) if count >= limit => self.gate.close(reason),
+            Some(_) if count == 0 => self.gate.open(reason),
+            _ => (),
+        }
+    }
+
+    fn handle_frame(&self, pad: &gst::Pad, buffer: &gst::BufferRef) {
+        let info = match pad
+            .get_current_caps()
+            .and_then(|caps| gst_video::VideoInfo::from_caps(&caps).ok())
+        {
+            Some(info) if quality::has_luma_plane(info.format()) => info,
+            _ => return,
+        };
+
+        let frame = match gst_video::VideoFrameRef::from_buffer_ref_readable(buffer, &info) {
+            Ok(frame) => frame,
+            Err(_) => return,
+        };
+
+        let luma = match frame.plane_data(0) {
+            Ok(luma) => luma,
+            Err(_) => return,
+        };
+
+        let samples = sample(
+            luma,
+            frame.plane_stride()[0] as usize,
+            frame.width() as usize,
+            frame.height() as usize,
+        );
+
+        let mut state = self.state.lock().unwrap();
+
+        if is_identical(&samples, &state.previous) {
+            state.frozen += 1;
+        } else {
+            state.frozen = 0;
+        }
+
+        if is_black(&samples) {
+            state.black += 1;
+        } else {
+            state.black = 0;
+        }
+
+        state.previous = samples;
+
+        self.update("live video frozen", state.frozen, self.freeze_frames);
+        self.update("live video black", state.black, self.black_frames);
+    }
+
+    /// Analyse the video flowing through `pad`
+    pub fn install(self: &Arc<Self>, pad: &gst::Pad) {
+        let detector = self.clone();
+        pad.add_probe(gst::PadProbeType::BUFFER, move |pad, info| {
+            if let Some(gst::PadProbeData::Buffer(ref buffer)) = info.data {
+                detector.handle_frame(pad, buffer);
+            }
+            gst::PadProbeReturn::Ok
+        });
+    }
+}
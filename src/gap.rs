@@ -0,0 +1,140 @@
+//! GAP events in place of repeated live buffers.
+//!
+//! By default the compositor repeats the last live buffer while the source is
+//! down. In gap mode it does not, and GAP events covering the outage are
+//! pushed into the live branch instead, so that the compositor knows there is
+//! no live data for that time right away and never outputs stale content.
+//!
+//! The events are sent into the queue in front of the compositor like any
+//! upstream data, under the stream lock of its sink pad, so that it is the
+//! queue's streaming thread that pushes them on, in order with the live
+//! buffers.
+
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use gst::prelude::*;
+
+/// How often gaps are filled in
+const INTERVAL: Duration = Duration::from_millis(40);
+
+/// Live input missing for longer than this is considered an outage
+pub const STALL_AFTER: Duration = Duration::from_millis(200);
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum HoldMode {
+    Repeat,
+    Gap,
+}
+
+impl std::str::FromStr for HoldMode {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "repeat" => Ok(HoldMode::Repeat),
+            "gap" => Ok(HoldMode::Gap),
+            _ => Err(anyhow::anyhow!("Unknown hold mode {}", s)),
+        }
+    }
+}
+
+struct State {
+    /// End of the data covered so far, in segment time
+    position: gst::ClockTime,
+    last_buffer: Instant,
+}
+
+pub struct GapFiller {
+    state: Mutex<State>,
+}
+
+impl GapFiller {
+    pub fn new() -> Arc<Self> {
+        Arc::new(Self {
+            state: Mutex::new(State {
+                position: gst::CLOCK_TIME_NONE,
+                last_buffer: Instant::now(),
+            }),
+        })
+    }
+
+    fn handle_buffer(&self, buffer: &gst::BufferRef) {
+        let mut state = self.state.lock().unwrap();
+        state.last_buffer = Instant::now();
+
+        let end = buffer.get_pts() + buffer.get_duration();
+        if end.is_some() {
+            state.position = end;
+        }
+    }
+
+    /// Current running time of `pad`'s element, mapped to its segment
+    fn current_position(pad: &gst::Pad) -> Option<gst::ClockTime> {
+        let element = pad.get_parent_element()?;
+        let running_time = element.get_clock()?.get_time() - element.get_base_time();
+
+        let event = pad.get_sticky_event(gst::EventType::Segment, 0)?;
+        match event.view() {
+            gst::EventView::Segment(segment) => segment
+                .get_segment()
+                .downcast_ref::<gst::format::Time>()
+                .map(|segment| segment.position_from_running_time(running_time)),
+            _ => None,
+        }
+    }
+
+    /// Send a GAP event for the time since the last data into `pad`, once
+    /// the live input is considered down
+    fn fill(&self, pad: &gst::Pad) {
+        let mut state = self.state.lock().unwrap();
+
+        if state.position.is_none() || state.last_buffer.elapsed() < STALL_AFTER {
+            return;
+        }
+
+        let position = match Self::current_position(pad) {
+            Some(position) if position.is_some() && position > state.position => position,
+            _ => return,
+        };
+
+        let gap = gst::event::Gap::new(state.position, position - state.position);
+        state.position = position;
+        drop(state);
+
+        /* Serialized events sent to a sink pad take its stream lock, so this
+         * can not interleave with a live buffer being queued */
+        pad.send_event(gap);
+    }
+
+    /// Fill in the outages of the live data flowing into the sink pad `pad`
+    /// of a queue
+    pub fn install(self: &Arc<Self>, pad: &gst::Pad) {
+        let filler = self.clone();
+        pad.add_probe(
+            gst::PadProbeType::BUFFER | gst::PadProbeType::EVENT_DOWNSTREAM,
+            move |_, info| {
+                match info.data {
+                    Some(gst::PadProbeData::Buffer(ref buffer)) => filler.handle_buffer(buffer),
+                    Some(gst::PadProbeData::Event(ref event))
+                        if event.get_type() == gst::EventType::Segment =>
+                    {
+                        filler.state.lock().unwrap().position = gst::CLOCK_TIME_NONE;
+                    }
+                    _ => (),
+                }
+                gst::PadProbeReturn::Ok
+            },
+        );
+
+        let filler = self.clone();
+        let pad = pad.downgrade();
+        glib::timeout_add(INTERVAL.as_millis() as u32, move || match pad.upgrade() {
+            Some(pad) => {
+                filler.fill(&pad);
+                glib::Continue(true)
+            }
+            None => glib::Continue(false),
+        });
+    }
+}
@@ -0,0 +1,155 @@
+//! Gate on the live video, closed by the fallback triggers.
+//!
+//! While any trigger holds the gate closed, live buffers are dropped before
+//! reaching the interpipe, and the compositor falls back to the slate after
+//! `--discard-after` exactly as when the source stops producing. Operators
+//! can force the gate open, overriding the triggers until released.
+//!
+//! A veto, e.g. a script hook, may keep the gate open when a trigger fires.
+//! Triggers keep closing the gate for every frame from streaming threads,
+//! so the veto is asked once per firing, from the main loop, and its answer
+//! holds until the trigger lifts.
+
+use std::collections::HashSet;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+
+use gst::prelude::*;
+use once_cell::sync::OnceCell;
+
+type Veto = Box<dyn Fn(&'static str) -> bool + Send + Sync>;
+
+#[derive(Default)]
+struct Vetoes {
+    /// Reasons the veto is yet to be asked about
+    pending: HashSet<&'static str>,
+    /// Reasons the veto kept the gate open for, until they are lifted
+    vetoed: HashSet<&'static str>,
+}
+
+pub struct LiveGate {
+    closed_by: Mutex<HashSet<&'static str>>,
+    veto: OnceCell<Veto>,
+    vetoes: Mutex<Vetoes>,
+    forced_open: AtomicBool,
+    dropped: AtomicU64,
+}
+
+impl LiveGate {
+    pub fn new() -> Arc<Self> {
+        Arc::new(Self {
+            closed_by: Mutex::new(HashSet::new()),
+            veto: OnceCell::new(),
+            vetoes: Mutex::new(Vetoes::default()),
+            forced_open: AtomicBool::new(false),
+            dropped: AtomicU64::new(0),
+        })
+    }
+
+    /// Let `veto` keep the gate open, it is passed the reason for closing
+    /// and returns whether to close
+    pub fn set_veto(&self, veto: Veto) {
+        let _ = self.veto.set(veto);
+    }
+
+    pub fn close(self: &Arc<Self>, reason: &'static str) {
+        if self.closed_by.lock().unwrap().contains(reason) {
+            return;
+        }
+
+        if self.veto.get().is_none() {
+            self.force_close(reason);
+            return;
+        }
+
+        {
+            let mut vetoes = self.vetoes.lock().unwrap();
+            if vetoes.vetoed.contains(reason) || !vetoes.pending.insert(reason) {
+                return;
+            }
+        }
+
+        let gate = self.clone();
+        glib::idle_add(move || {
+            gate.ask_veto(reason);
+            glib::Continue(false)
+        });
+    }
+
+    /// Close the gate for `reason` unless the veto says otherwise, if it
+    /// was not lifted meanwhile
+    fn ask_veto(&self, reason: &'static str) {
+        if !self.vetoes.lock().unwrap().pending.remove(reason) {
+            return;
+        }
+
+        if self.veto.get().map_or(true, |veto| veto(reason)) {
+            self.force_close(reason);
+        } else {
+            info!("Keeping live video despite {}: vetoed", reason);
+            self.vetoes.lock().unwrap().vetoed.insert(reason);
+        }
+    }
+
+    /// Close the gate, bypassing any veto
+    pub fn force_close(&self, reason: &'static str) {
+        if self.closed_by.lock().unwrap().insert(reason) {
+            warn!("Dropping live video: {}", reason);
+        }
+    }
+
+    pub fn open(&self, reason: &'static str) {
+        {
+            let mut vetoes = self.vetoes.lock().unwrap();
+            vetoes.pending.remove(reason);
+            vetoes.vetoed.remove(reason);
+        }
+
+        if self.closed_by.lock().unwrap().remove(reason) {
+            info!("No longer dropping live video: {}", reason);
+        }
+    }
+
+    /// Keep the gate open whatever the triggers say, until called with
+    /// `false`. The triggers keep track of their state meanwhile.
+    pub fn force_open(&self, forced: bool) {
+        if self.forced_open.swap(forced, Ordering::SeqCst) != forced {
+            if forced {
+                warn!("Passing live video whatever the fallback triggers say");
+            } else {
+                info!("Fallback triggers no longer overridden");
+            }
+        }
+    }
+
+    pub fn is_forced_open(&self) -> bool {
+        self.forced_open.load(Ordering::SeqCst)
+    }
+
+    /// Whether the gate is held closed for `reason`
+    pub fn is_closed_by(&self, reason: &'static str) -> bool {
+        self.closed_by.lock().unwrap().contains(reason)
+    }
+
+    pub fn is_open(&self) -> bool {
+        self.is_forced_open() || self.closed_by.lock().unwrap().is_empty()
+    }
+
+    /// Number of live buffers dropped so far
+    pub fn dropped(&self) -> u64 {
+        self.dropped.load(Ordering::Relaxed)
+    }
+
+    /// Drop buffers flowing through `pad` while the gate is closed
+    pub fn install(self: &Arc<Self>, pad: &gst::Pad) {
+        let gate = self.clone();
+        pad.add_probe(gst::PadProbeType::BUFFER, move |_, _| {
+            if gate.is_open() {
+                gst::PadProbeReturn::Ok
+            } else {
+                gate.dropped.fetch_add(1, Ordering::Relaxed);
+                gst::PadProbeReturn::Drop
+            }
+        });
+    }
+}
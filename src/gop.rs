@@ -0,0 +1,102 @@
+//! Keyframe interval monitoring on the encoded live video.
+//!
+//! Absurdly long GOPs, or streams without any IDR at all, break downstream
+//! segmentation and make recovery after a restart slow, as decoding can
+//! only resume from a keyframe.
+
+use std::sync::{Arc, Mutex};
+
+use gst::prelude::*;
+
+use crate::logging::{self, Level};
+
+#[derive(Default)]
+struct State {
+    first_pts: gst::ClockTime,
+    last_keyframe: gst::ClockTime,
+    missing_flagged: bool,
+}
+
+pub struct GopMonitor {
+    max_gop: gst::ClockTime,
+    state: Mutex<State>,
+}
+
+impl GopMonitor {
+    pub fn new(max_gop: gst::ClockTime) -> Arc<Self> {
+        Arc::new(Self {
+            max_gop,
+            state: Mutex::new(State::default()),
+        })
+    }
+
+    fn handle_buffer(&self, buffer: &gst::BufferRef) {
+        let pts = buffer.get_pts();
+        if pts.is_none() {
+            return;
+        }
+
+        let mut state = self.state.lock().unwrap();
+
+        if state.first_pts.is_none() {
+            state.first_pts = pts;
+        }
+
+        if !buffer.get_flags().contains(gst::BufferFlags::DELTA_UNIT) {
+            if state.last_keyframe.is_some() && pts > state.last_keyframe {
+                let gop = pts - state.last_keyframe;
+                if gop > self.max_gop {
+                    logging::event(
+                        Level::Warn,
+                        "long-gop",
+                        "Keyframe interval above limit",
+                        serde_json::json!({
+                            "gop_ms": gop.mseconds(),
+                            "limit_ms": self.max_gop.mseconds(),
+                        }),
+                    );
+                }
+            }
+            state.last_keyframe = pts;
+            state.missing_flagged = false;
+            return;
+        }
+
+        let since = if state.last_keyframe.is_some() {
+            state.last_keyframe
+        } else {
+            state.first_pts
+        };
+
+        if !state.missing_flagged && pts > since && pts - since > self.max_gop {
+            logging::event(
+                Level::Warn,
+                "missing-keyframe",
+                "No keyframe received within the keyframe interval limit",
+                serde_json::json!({ "limit_ms": self.max_gop.mseconds() }),
+            );
+            state.missing_flagged = true;
+        }
+    }
+
+    /// Watch the encoded buffers flowing through `pad`, usually the sink pad
+    /// of the video decoder
+    pub fn install(self: &Arc<Self>, pad: &gst::Pad) {
+        let monitor = self.clone();
+        pad.add_probe(
+            gst::PadProbeType::BUFFER | gst::PadProbeType::EVENT_DOWNSTREAM,
+            move |_, info| {
+                match info.data {
+                    Some(gst::PadProbeData::Buffer(ref buffer)) => monitor.handle_buffer(buffer),
+                    Some(gst::PadProbeData::Event(ref event))
+                        if event.get_type() == gst::EventType::Segment =>
+                    {
+                        *monitor.state.lock().unwrap() = State::default();
+                    }
+                    _ => (),
+                }
+                gst::PadProbeReturn::Ok
+            },
+        );
+    }
+}
@@ -0,0 +1,108 @@
+//! Health reporting for orchestrators such as Kubernetes.
+//!
+//! Readiness means program output is being produced, live or slate alike: a
+//! failing source is precisely the situation this tool exists for and must
+//! not take the instance out of rotation. Liveness means the main loop still
+//! dispatches and the output pipeline still pushes buffers, which are the
+//! only failures a restart can fix.
+
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use gst::prelude::*;
+
+pub struct Health {
+    started: Instant,
+    last_heartbeat: Mutex<Instant>,
+    last_output: Mutex<Option<Instant>>,
+    readiness_grace: Duration,
+    liveness_grace: Duration,
+}
+
+impl Health {
+    pub fn new(readiness_grace: Duration, liveness_grace: Duration) -> Arc<Self> {
+        let now = Instant::now();
+
+        Arc::new(Self {
+            started: now,
+            last_heartbeat: Mutex::new(now),
+            last_output: Mutex::new(None),
+            readiness_grace,
+            liveness_grace,
+        })
+    }
+
+    /// Track buffers leaving `pad`, the output of the program
+    pub fn watch_output(self: &Arc<Self>, pad: &gst::Pad) {
+        let health = self.clone();
+        pad.add_probe(gst::PadProbeType::BUFFER, move |_, _| {
+            *health.last_output.lock().unwrap() = Some(Instant::now());
+            gst::PadProbeReturn::Ok
+        });
+    }
+
+    /// Update the heartbeat from the default main context every second
+    pub fn start_heartbeat(self: &Arc<Self>) {
+        let health = self.clone();
+        glib::timeout_add_seconds(1, move || {
+            *health.last_heartbeat.lock().unwrap() = Instant::now();
+            glib::Continue(true)
+        });
+    }
+
+    /// Time since the process started up
+    pub fn uptime(&self) -> Duration {
+        self.started.elapsed()
+    }
+
+    pub fn is_ready(&self) -> bool {
+        self.last_output
+            .lock()
+            .unwrap()
+            .map_or(false, |last| last.elapsed() <= self.readiness_grace)
+    }
+
+    pub fn is_live(&self) -> bool {
+        if self.last_heartbeat.lock().unwrap().elapsed() > self.liveness_grace {
+            return false;
+        }
+
+        /* Give the output pipeline the grace period to start up */
+        let last_output = self.last_output.lock().unwrap().unwrap_or(self.started);
+
+        last_output.elapsed() <= self.liveness_grace
+    }
+
+    /// Serve `/ready` and `/live` on `port` from a dedicated thread
+    pub fn serve(self: &Arc<Self>, port: u16) -> Result<(), anyhow::Error> {
+        let server = tiny_http::Server::http(("0.0.0.0", port))
+            .map_err(|err| anyhow::anyhow!("Failed to listen on port {}: {}", port, err))?;
+        let health = self.clone();
+
+        info!("Serving health checks on port {}", port);
+
+        std::thread::spawn(move || {
+            for request in server.incoming_requests() {
+                let healthy = match request.url() {
+                    "/ready" => Some(health.is_ready()),
+                    "/live" => Some(health.is_live()),
+                    _ => None,
+                };
+
+                let response = match healthy {
+                    Some(true) => tiny_http::Response::from_string("OK"),
+                    Some(false) => {
+                        tiny_http::Response::from_string("UNHEALTHY").with_status_code(503)
+                    }
+                    None => tiny_http::Response::from_string("Not Found").with_status_code(404),
+                };
+
+                if let Err(err) = request.respond(response) {
+                    warn!("Failed to respond to health check: {}", err);
+                }
+            }
+        });
+
+        Ok(())
+    }
+}
@@ -0,0 +1,174 @@
+//! Remediation hints for the usual failure modes, so that errors say what is
+//! likely wrong and how to fix it rather than only what failed.
+
+use gst::prelude::*;
+
+/// Where to find the plugin providing `factory`
+fn plugin_hint(factory: &str) -> String {
+    let package = match factory {
+        "interpipesrc" | "interpipesink" => {
+            "gst-interpipe, see https://github.com/RidgeRun/gst-interpipe"
+        }
+        "playbin3" | "compositor" | "videotestsrc" | "xvimagesink" | "audioconvert"
+        | "audioresample" | "audiotestsrc" | "udpsink" | "appsrc" | "appsink"
+        | "uridecodebin3" => "gst-plugins-base",
+        "level" | "audiodynamic" | "deinterlace" | "rtspsrc" | "hlssink2" | "rtpmp2tpay"
+        | "splitmuxsink" | "mp4mux" | "gdkpixbufoverlay" => "gst-plugins-good",
+        "waylandsink" | "rtmp2sink" | "srtsink" | "srtsrc" | "mpegtsmux" | "watchdog"
+        | "proxysrc" | "proxysink" | "cccombiner" | "ccextractor" | "ccconverter"
+        | "h264parse" => {
+            "gst-plugins-bad"
+        }
+        "cudaupload" | "cudaconvert" | "nvh264enc" => "gst-plugins-bad (nvcodec)",
+        "v4l2convert" | "v4l2h264enc" => "gst-plugins-good",
+        "vaapipostproc" | "vaapih264enc" => "gstreamer-vaapi",
+        "x264enc" => "gst-plugins-ugly",
+        "avenc_aac" => "gst-libav",
+        "flvmux" => "gst-plugins-good",
+        "fallbackswitch" => "gst-plugins-rs (gst-plugin-fallbackswitch)",
+        "tttocea608" => "gst-plugins-rs (gst-plugin-closedcaption)",
+        "webrtcsink" => "gst-plugins-rs (gst-plugin-webrtc)",
+        "audioloudnorm" => "gst-plugins-rs (gst-plugin-audiofx)",
+        "nvvidconv" | "nvv4l2decoder" | "nvv4l2h264enc" => "the NVIDIA L4T GStreamer packages",
+        "identity" | "queue" | "fakesink" | "capsfilter" | "input-selector" => "GStreamer core",
+        _ => return format!("install the plugin providing {}", factory),
+    };
+
+    format!(
+        "{} is provided by {}, check that it is installed and that \
+         GST_PLUGIN_PATH includes it (`gst-inspect-1.0 {}` must succeed)",
+        factory, package, factory
+    )
+}
+
+/// An element could not be created as its plugin is not installed
+#[derive(Debug)]
+pub struct MissingElement {
+    factory: String,
+}
+
+impl std::fmt::Display for MissingElement {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(
+            f,
+            "Missing element {}: {}",
+            self.factory,
+            plugin_hint(&self.factory)
+        )
+    }
+}
+
+impl std::error::Error for MissingElement {}
+
+/// gst::ElementFactory::make() with a hint on failure
+pub fn make_element(factory: &str, name: Option<&str>) -> Result<gst::Element, anyhow::Error> {
+    gst::ElementFactory::make(factory, name).map_err(|err| {
+        anyhow::Error::from(err).context(MissingElement {
+            factory: factory.to_string(),
+        })
+    })
+}
+
+/// gst::parse_bin_from_description() with a hint on missing elements
+pub fn parse_bin(description: &str) -> Result<gst::Bin, anyhow::Error> {
+    gst::parse_bin_from_description(description, true).map_err(|err| {
+        let missing = match err.kind::<gst::ParseError>() {
+            Some(gst::ParseError::NoSuchElement) => {
+                err.to_string().split('"').nth(1).map(str::to_string)
+            }
+            _ => None,
+        };
+
+        match missing {
+            Some(factory) => anyhow::Error::from(err).context(MissingElement { factory }),
+            None => anyhow::Error::from(err).context(format!("Failed to parse {}", description)),
+        }
+    })
+}
+
+fn source_factory(msg: &gst::message::Error) -> Option<String> {
+    msg.get_src()
+        .and_then(|src| src.downcast::<gst::Element>().ok())
+        .and_then(|element| element.get_factory())
+        .map(|factory| factory.get_name().to_string())
+}
+
+/// A hint for an error posted on the bus, if we have one
+pub fn for_error(msg: &gst::message::Error) -> Option<String> {
+    let err = msg.get_error();
+    let factory = source_factory(msg).unwrap_or_default();
+
+    if let Some(gst::CoreError::MissingPlugin) = err.kind::<gst::CoreError>() {
+        return Some(
+            "a plugin needed to handle the stream is missing, run `rtmp-slate-fallback probe` \
+             on the URI and install the plugin for the reported codecs (usually gst-libav)"
+                .to_string(),
+        );
+    }
+
+    if let Some(gst::StreamError::CodecNotFound) = err.kind::<gst::StreamError>() {
+        return Some("no decoder for the stream codec, install gst-libav".to_string());
+    }
+
+    if factory == "xvimagesink" {
+        return Some(
+            "the X server offers no usable Xv adaptor, use --preview-sink wayland, \
+             or --headless if no preview is needed"
+                .to_string(),
+        );
+    }
+
+    if factory.starts_with("interpipe") {
+        return Some(
+            "interpipesrc stream-sync restart-ts requires interpipesink to be synchronised, \
+             check that the gst-interpipe version supports it and that the listen-to name \
+             matches the interpipesink name"
+                .to_string(),
+        );
+    }
+
+    match err.kind::<gst::ResourceError>() {
+        Some(gst::ResourceError::OpenRead)
+        | Some(gst::ResourceError::NotFound)
+        | Some(gst::ResourceError::Read) => Some(
+            "could not connect to the source, check that the server is reachable \
+             and that the application name and stream key in the URI are correct"
+                .to_string(),
+        ),
+        _ => None,
+    }
+}
+
+/// Log an error posted on the bus along with its hint
+pub fn log_error(what: &str, msg: &gst::message::Error) {
+    error!("{}: {:?}", what, msg);
+
+    if let Some(hint) = for_error(msg) {
+        error!("  hint: {}", hint);
+    }
+}
+
+/// Set `pipe` to `state`, on failure include the error posted on its bus
+/// and its hint in the returned error
+pub fn set_state(pipe: &gst::Pipeline, state: gst::State) -> Result<(), anyhow::Error> {
+    let err = match pipe.set_state(state) {
+        Ok(_) => return Ok(()),
+        Err(err) => anyhow::Error::from(err),
+    };
+
+    let msg = pipe
+        .get_bus()
+        .and_then(|bus| bus.pop_filtered(&[gst::MessageType::Error]));
+
+    if let Some(msg) = msg {
+        if let gst::MessageView::Error(msg) = msg.view() {
+            let err = err.context(format!("{:?}", msg));
+            return Err(match for_error(&msg) {
+                Some(hint) => err.context(format!("hint: {}", hint)),
+                None => err,
+            });
+        }
+    }
+
+    Err(err.context(format!("Failed to set {} to {:?}", pipe.get_name(), state)))
+}
@@ -0,0 +1,74 @@
+//! Hardware decoding support for edge devices.
+//!
+//! playbin3 picks decoders by rank, so selecting a hardware decoder boils
+//! down to promoting the matching factories above the software decoders.
+
+use glib::translate::{from_glib, ToGlib};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum HwDecoder {
+    None,
+    /// NVIDIA Jetson decoders, outputting NVMM memory
+    Nvv4l2,
+    /// Generic V4L2 stateful decoders, e.g. on Raspberry Pi or i.MX
+    V4l2,
+}
+
+impl std::str::FromStr for HwDecoder {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "none" => Ok(HwDecoder::None),
+            "nvv4l2" => Ok(HwDecoder::Nvv4l2),
+            "v4l2" => Ok(HwDecoder::V4l2),
+            _ => Err(anyhow::anyhow!("Unknown hardware decoder {}", s)),
+        }
+    }
+}
+
+impl HwDecoder {
+    fn factories(self) -> &'static [&'static str] {
+        match self {
+            HwDecoder::None => &[],
+            HwDecoder::Nvv4l2 => &["nvv4l2decoder"],
+            HwDecoder::V4l2 => &[
+                "v4l2h264dec",
+                "v4l2h265dec",
+                "v4l2vp8dec",
+                "v4l2vp9dec",
+                "v4l2mpeg2dec",
+            ],
+        }
+    }
+
+    /// Promote the decoders above the software ones, must be called after
+    /// gst::init()
+    pub fn install(self) -> Result<(), anyhow::Error> {
+        let registry = gst::Registry::get();
+        let mut found = false;
+
+        for name in self.factories() {
+            if let Some(feature) = registry.lookup_feature(name) {
+                feature.set_rank(from_glib(gst::Rank::Primary.to_glib() + 1));
+                found = true;
+            }
+        }
+
+        if self != HwDecoder::None && !found {
+            anyhow::bail!("No {:?} hardware decoder is available", self);
+        }
+
+        Ok(())
+    }
+
+    /// Elements to prepend to the video sink, the Jetson decoders output
+    /// NVMM memory which needs to be brought back to system memory before it
+    /// can cross the interpipe to the compositor.
+    pub fn sink_prefix(self) -> &'static str {
+        match self {
+            HwDecoder::Nvv4l2 => "nvvidconv ! video/x-raw ! ",
+            _ => "",
+        }
+    }
+}
@@ -0,0 +1,191 @@
+//! Health statistics of the live input.
+//!
+//! Bitrate and framerate come from their monitors, and the gaps between
+//! consecutive live video buffers are timed as they arrive. Growing gap
+//! percentiles show a feed stuttering well before it stops altogether, so
+//! the statistics are logged periodically and exposed on the metrics and
+//! control APIs.
+
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use gst::prelude::*;
+use serde_json::json;
+
+use crate::bitrate::BitrateMonitor;
+use crate::fps::FpsMonitor;
+use crate::logging::{self, Level};
+
+/// Gap quantiles reported, with their labels
+pub const QUANTILES: [(f64, &str); 3] = [(0.5, "p50"), (0.95, "p95"), (0.99, "p99")];
+
+pub struct InputStats {
+    bitrate: Arc<BitrateMonitor>,
+    fps: Arc<FpsMonitor>,
+    /// Arrival time of the last buffer
+    last: Mutex<Option<Instant>>,
+    /// Arrival time of each buffer and the gap since the previous one
+    gaps: Mutex<VecDeque<(Instant, Duration)>>,
+    /// Sum and number of all gaps since startup
+    totals: Mutex<(Duration, u64)>,
+    window: Duration,
+}
+
+/// Gap statistics over the window
+#[derive(Debug, Clone, Copy)]
+pub struct Gaps {
+    /// Gaps at each of `QUANTILES`
+    pub quantiles: [Duration; 3],
+    pub max: Duration,
+}
+
+impl InputStats {
+    pub fn new(
+        bitrate: &Arc<BitrateMonitor>,
+        fps: &Arc<FpsMonitor>,
+        window: Duration,
+    ) -> Arc<Self> {
+        Arc::new(Self {
+            bitrate: bitrate.clone(),
+            fps: fps.clone(),
+            last: Mutex::new(None),
+            gaps: Mutex::new(VecDeque::new()),
+            totals: Mutex::new((Duration::from_secs(0), 0)),
+            window,
+        })
+    }
+
+    fn add(&self) {
+        let now = Instant::now();
+        let last = self.last.lock().unwrap().replace(now);
+        let mut gaps = self.gaps.lock().unwrap();
+
+        if let Some(last) = last {
+            let gap = now.duration_since(last);
+            gaps.push_back((now, gap));
+
+            let mut totals = self.totals.lock().unwrap();
+            totals.0 += gap;
+            totals.1 += 1;
+        }
+        while gaps
+            .front()
+            .map_or(false, |(time, _)| now.duration_since(*time) > self.window)
+        {
+            gaps.pop_front();
+        }
+    }
+
+    /// Gaps between the buffers of the window, None without any
+    pub fn gaps(&self) -> Option<Gaps> {
+        let now = Instant::now();
+        let mut gaps = self
+            .gaps
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|(time, _)| now.duration_since(*time) <= self.window)
+            .map(|(_, gap)| *gap)
+            .collect::<Vec<_>>();
+
+        /* A stalled input has no recent gaps, but the one it is stuck in */
+        if let Some(last) = *self.last.lock().unwrap() {
+            if !gaps.is_empty() || now.duration_since(last) <= self.window {
+                gaps.push(now.duration_since(last));
+            }
+        }
+
+        if gaps.is_empty() {
+            return None;
+        }
+
+        gaps.sort();
+        let quantile = |q: f64| gaps[((gaps.len() - 1) as f64 * q).round() as usize];
+
+        Some(Gaps {
+            quantiles: [
+                quantile(QUANTILES[0].0),
+                quantile(QUANTILES[1].0),
+                quantile(QUANTILES[2].0),
+            ],
+            max: *gaps.last().unwrap(),
+        })
+    }
+
+    /// Sum and number of all gaps since startup, unlike `gaps()` not limited
+    /// to the window
+    pub fn gap_totals(&self) -> (Duration, u64) {
+        *self.totals.lock().unwrap()
+    }
+
+    /// Bitrate in bits per second
+    pub fn bitrate(&self) -> u64 {
+        self.bitrate.bitrate()
+    }
+
+    /// Framerate measured over the last second
+    pub fn fps(&self) -> Option<f64> {
+        self.fps.measured()
+    }
+
+    /// All statistics, with the gaps in milliseconds
+    pub fn to_json(&self) -> serde_json::Value {
+        let gaps = self.gaps().map(|gaps| {
+            let mut fields = serde_json::Map::new();
+
+            for ((_, label), gap) in QUANTILES.iter().zip(gaps.quantiles.iter()) {
+                fields.insert(label.to_string(), json!(gap.as_secs_f64() * 1000.0));
+            }
+            fields.insert("max".to_string(), json!(gaps.max.as_secs_f64() * 1000.0));
+
+            serde_json::Value::Object(fields)
+        });
+
+        json!({
+            "bitrate_bps": self.bitrate(),
+            "fps": self.fps(),
+            "advertised_fps": self.fps.advertised(),
+            "gap_ms": gaps,
+        })
+    }
+
+    /// Time the video buffers flowing through `pad`
+    pub fn install(self: &Arc<Self>, pad: &gst::Pad) {
+        let stats = self.clone();
+        pad.add_probe(gst::PadProbeType::BUFFER, move |_, _| {
+            stats.add();
+            gst::PadProbeReturn::Ok
+        });
+    }
+
+    /// Log the statistics every `interval`
+    pub fn start(self: &Arc<Self>, interval: Duration) {
+        let stats = self.clone();
+        glib::timeout_add(interval.as_millis() as u32, move || {
+            let gaps = stats.gaps();
+            logging::event(
+                Level::Info,
+                "input-stats",
+                &format!(
+                    "Input at {} kbps, {} fps, gaps p95 {} max {}",
+                    stats.bitrate() / 1000,
+                    stats
+                        .fps()
+                        .map_or_else(|| "?".to_string(), |fps| format!("{:.1}", fps)),
+                    gaps.map_or_else(
+                        || "-".to_string(),
+                        |gaps| format!("{}ms", gaps.quantiles[1].as_millis())
+                    ),
+                    gaps.map_or_else(
+                        || "-".to_string(),
+                        |gaps| format!("{}ms", gaps.max.as_millis())
+                    ),
+                ),
+                stats.to_json(),
+            );
+
+            glib::Continue(true)
+        });
+    }
+}
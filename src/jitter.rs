@@ -0,0 +1,42 @@
+//! Explicit jitter buffer on the live input.
+//!
+//! Each branch of the live input goes through a queue, and its sink renders
+//! buffers `size` after their timestamp, so that the queue absorbs delivery
+//! jitter up to that size at a fixed latency, whatever playbin's own
+//! buffering does.
+
+use std::time::Duration;
+
+use gst::prelude::*;
+
+pub struct JitterBuffer {
+    size: Duration,
+    drop_late: bool,
+}
+
+impl JitterBuffer {
+    pub fn new(size: Duration, drop_late: bool) -> Self {
+        Self { size, drop_late }
+    }
+
+    /// Launch description of the queue to link in front of a sink
+    pub fn queue(&self) -> String {
+        format!(
+            "queue max-size-buffers=0 max-size-bytes=0 max-size-time={}{} ! ",
+            2 * self.size.as_nanos(),
+            if self.drop_late { " leaky=downstream" } else { "" }
+        )
+    }
+
+    /// Delay rendering on `sink` by the size of the buffer, dropping
+    /// buffers that arrive later than that if so configured
+    pub fn configure_sink(&self, sink: &gst::Element) -> Result<(), anyhow::Error> {
+        sink.set_property("ts-offset", &(self.size.as_nanos() as i64))?;
+
+        if self.drop_late {
+            sink.set_property("max-lateness", &0i64)?;
+        }
+
+        Ok(())
+    }
+}
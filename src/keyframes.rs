@@ -0,0 +1,47 @@
+//! Keyframe-aligned switching.
+//!
+//! The compositor output is raw video, so switches always happen on frame
+//! boundaries. For encoded outputs, a force-key-unit event is sent
+//! downstream with the first frame after every switch between live and
+//! slate, so that decoders and packagers downstream never see a switch in
+//! the middle of a GOP and can start a new segment right at it.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+use gst::prelude::*;
+
+use crate::status::Status;
+
+/// Request a keyframe on switches, on the output of the compositor `pad`
+pub fn install(pad: &gst::Pad, status: &Arc<Status>) {
+    let status = status.clone();
+    let showed_live = AtomicBool::new(false);
+
+    pad.add_probe(gst::PadProbeType::BUFFER, move |pad, info| {
+        let shows_live = status.shows_live();
+
+        if showed_live.swap(shows_live, Ordering::SeqCst) != shows_live {
+            let running_time = match info.data {
+                Some(gst::PadProbeData::Buffer(ref buffer)) => buffer.get_pts(),
+                _ => gst::CLOCK_TIME_NONE,
+            };
+
+            info!(
+                "Switching to {}, requesting keyframe",
+                if shows_live { "live" } else { "slate" }
+            );
+
+            pad.push_event(
+                gst_video::DownstreamForceKeyUnitEvent::builder()
+                    .timestamp(running_time)
+                    .stream_time(running_time)
+                    .running_time(running_time)
+                    .all_headers(true)
+                    .build(),
+            );
+        }
+
+        gst::PadProbeReturn::Ok
+    });
+}
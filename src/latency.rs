@@ -0,0 +1,154 @@
+//! End-to-end latency measurement.
+//!
+//! The program leaves the compositor pipeline later than the running time
+//! it carries by the latency of that pipeline, plus whatever delay builds
+//! up when elements fall behind, which is measured against the clock on
+//! the output tee. On top of that comes the latency of the RTMP pipeline,
+//! as answered to a latency query, when it is a separate pipeline. A latency
+//! creeping up is an early sign of a pipeline that cannot keep up.
+
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use gst::prelude::*;
+
+use crate::logging::{self, Level};
+
+pub struct LatencyMonitor {
+    segment: Mutex<Option<gst::FormattedSegment<gst::ClockTime>>>,
+    /// Delay of the last buffer reaching the output
+    output_delay: Mutex<Option<Duration>>,
+    /// Latest end-to-end latency
+    latency: Mutex<Option<Duration>>,
+}
+
+fn query_latency(pipe: &gst::Pipeline) -> Option<Duration> {
+    let mut query = gst::query::Latency::new();
+
+    if pipe.query(&mut query) {
+        let (_, min, _) = query.get_result();
+        min.nseconds().map(Duration::from_nanos)
+    } else {
+        None
+    }
+}
+
+impl LatencyMonitor {
+    pub fn new() -> Arc<Self> {
+        Arc::new(Self {
+            segment: Mutex::new(None),
+            output_delay: Mutex::new(None),
+            latency: Mutex::new(None),
+        })
+    }
+
+    /// Latest end-to-end latency, None before the output started
+    pub fn latency(&self) -> Option<Duration> {
+        *self.latency.lock().unwrap()
+    }
+
+    fn handle_buffer(&self, pad: &gst::Pad, buffer: &gst::BufferRef) {
+        let running_time = match *self.segment.lock().unwrap() {
+            Some(ref segment) => segment.to_running_time(buffer.get_pts()),
+            None => return,
+        };
+        let element = match pad.get_parent_element() {
+            Some(element) => element,
+            None => return,
+        };
+        let now = match element.get_clock() {
+            Some(clock) => clock.get_time(),
+            None => return,
+        };
+
+        if let (Some(now), Some(sync_time)) = (
+            now.nseconds(),
+            (element.get_base_time() + running_time).nseconds(),
+        ) {
+            *self.output_delay.lock().unwrap() =
+                Some(Duration::from_nanos(now.saturating_sub(sync_time)));
+        }
+    }
+
+    /// Measure the delay of the buffers reaching `pad` of the output
+    pub fn install(self: &Arc<Self>, pad: &gst::Pad) {
+        let monitor = self.clone();
+        pad.add_probe(
+            gst::PadProbeType::BUFFER | gst::PadProbeType::EVENT_DOWNSTREAM,
+            move |pad, info| {
+                match info.data {
+                    Some(gst::PadProbeData::Buffer(ref buffer)) => {
+                        monitor.handle_buffer(pad, buffer);
+                    }
+                    Some(gst::PadProbeData::Event(ref event)) => {
+                        if let gst::EventView::Segment(segment) = event.view() {
+                            *monitor.segment.lock().unwrap() = segment
+                                .get_segment()
+                                .downcast_ref::<gst::ClockTime>()
+                                .cloned();
+                        }
+                    }
+                    _ => (),
+                }
+                gst::PadProbeReturn::Ok
+            },
+        );
+    }
+
+    /// Compute the latency every second, adding that of `source_pipe` if
+    /// any. Once it exceeds `max_latency`, a `latency-exceeded` event is
+    /// logged and `on_exceeded` called, once until it is back within limits.
+    pub fn start<F>(
+        self: &Arc<Self>,
+        source_pipe: Option<gst::Pipeline>,
+        max_latency: Option<Duration>,
+        on_exceeded: F,
+    ) where
+        F: Fn() + 'static,
+    {
+        let monitor = self.clone();
+        let mut exceeded = false;
+        glib::timeout_add_seconds(1, move || {
+            let output_delay = match *monitor.output_delay.lock().unwrap() {
+                Some(output_delay) => output_delay,
+                None => return glib::Continue(true),
+            };
+            let source_latency = source_pipe
+                .as_ref()
+                .and_then(query_latency)
+                .unwrap_or_default();
+            let latency = output_delay + source_latency;
+            *monitor.latency.lock().unwrap() = Some(latency);
+
+            let max_latency = match max_latency {
+                Some(max_latency) => max_latency,
+                None => return glib::Continue(true),
+            };
+
+            if latency > max_latency && !exceeded {
+                exceeded = true;
+                logging::event(
+                    Level::Warn,
+                    "latency-exceeded",
+                    &format!(
+                        "Latency of {}ms exceeds the limit of {}ms",
+                        latency.as_millis(),
+                        max_latency.as_millis()
+                    ),
+                    serde_json::json!({
+                        "latency_ms": latency.as_millis() as u64,
+                        "source_latency_ms": source_latency.as_millis() as u64,
+                        "output_delay_ms": output_delay.as_millis() as u64,
+                        "max_latency_ms": max_latency.as_millis() as u64,
+                    }),
+                );
+                on_exceeded();
+            } else if latency <= max_latency && exceeded {
+                exceeded = false;
+                info!("Latency back within limits at {}ms", latency.as_millis());
+            }
+
+            glib::Continue(true)
+        });
+    }
+}
@@ -0,0 +1,232 @@
+//! Print pipelines as gst-launch-1.0 descriptions or dot graphs, so they can
+//! be reproduced and tweaked outside of this tool.
+//!
+//! The descriptions list every element with its non-default properties, and
+//! then every link between them as `a.src ! b.sink`. Bins are nested as
+//! `bin.( ... )`, and links through their ghost pads are made between the
+//! elements inside, which gst-launch-1.0 ghosts again. Elements implemented
+//! as bins, such as playbin, are described as a single element, with their
+//! sinks as nested descriptions.
+//!
+//! Each pipeline is logged at debug level once it first reaches PLAYING.
+
+use std::cmp::Ordering;
+use std::collections::HashSet;
+use std::sync::Mutex;
+
+use gst::prelude::*;
+use once_cell::sync::Lazy;
+
+/// Names of the pipelines printed already
+static PRINTED: Lazy<Mutex<HashSet<String>>> = Lazy::new(|| Mutex::new(HashSet::new()));
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PipelineFormat {
+    Launch,
+    Dot,
+}
+
+impl std::str::FromStr for PipelineFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "launch" => Ok(PipelineFormat::Launch),
+            "dot" => Ok(PipelineFormat::Dot),
+            _ => Err(anyhow::anyhow!("Unknown pipeline format {}", s)),
+        }
+    }
+}
+
+fn quote(value: &str) -> String {
+    if value
+        .chars()
+        .any(|c| c.is_whitespace() || c == '!' || c == ',' || c == '"')
+    {
+        format!("\"{}\"", value.replace('\\', "\\\\").replace('"', "\\\""))
+    } else {
+        value.to_string()
+    }
+}
+
+/// `name=value` for every property of `object` differing from its default
+fn properties<O: IsA<glib::Object>>(object: &O, prefix: &str) -> Vec<String> {
+    let mut properties = vec![];
+
+    for pspec in object.list_properties() {
+        let owner = pspec.get_owner_type();
+        if owner == gst::Object::static_type() || owner == gst::Pad::static_type() {
+            continue;
+        }
+
+        let flags = pspec.get_flags();
+        if !flags.contains(glib::ParamFlags::READABLE | glib::ParamFlags::WRITABLE) {
+            continue;
+        }
+
+        let name = pspec.get_name();
+        let value = match object.get_property(name) {
+            Ok(value) => value,
+            Err(_) => continue,
+        };
+
+        if pspec
+            .get_default_value()
+            .map_or(false, |default| value.compare(default) == Some(Ordering::Equal))
+        {
+            continue;
+        }
+
+        let serialized = match value.get::<gst::Element>() {
+            Ok(Some(element)) => describe(&element),
+            _ => match value.serialize() {
+                Some(serialized) => serialized.to_string(),
+                None => continue,
+            },
+        };
+
+        properties.push(format!("{}{}={}", prefix, name, quote(&serialized)));
+    }
+
+    properties
+}
+
+fn factory_name(element: &gst::Element) -> Option<String> {
+    element
+        .get_factory()
+        .map(|factory| factory.get_name().to_string())
+}
+
+fn describe_element(element: &gst::Element) -> String {
+    let mut parts = vec![
+        factory_name(element).unwrap_or_else(|| "bin".to_string()),
+        format!("name={}", element.get_name()),
+    ];
+    parts.extend(properties(element, ""));
+
+    for pad in element.get_sink_pads() {
+        parts.extend(properties(&pad, &format!("{}::", pad.get_name())));
+    }
+
+    parts.join(" ")
+}
+
+/// Whether `element` is a bin only grouping its children, rather than an
+/// element implemented as a bin such as playbin, whose children it creates
+/// itself
+fn is_plain_bin(element: &gst::Element) -> Option<&gst::Bin> {
+    match factory_name(element).as_deref() {
+        None | Some("bin") | Some("pipeline") => element.downcast_ref::<gst::Bin>(),
+        _ => None,
+    }
+}
+
+/// Pad at the other end of the link of `pad`, looking through ghost pads
+fn link_peer(pad: &gst::Pad) -> Option<gst::Pad> {
+    let mut peer = pad.get_peer()?;
+
+    loop {
+        /* Into a bin, through its sink ghost pad */
+        if let Some(ghost) = peer.downcast_ref::<gst::GhostPad>() {
+            peer = ghost.get_target()?;
+            continue;
+        }
+
+        /* Out of a bin, from the internal pad of its source ghost pad */
+        if let Some(ghost) = peer
+            .get_parent()
+            .and_then(|parent| parent.downcast::<gst::GhostPad>().ok())
+        {
+            peer = ghost.get_peer()?;
+            continue;
+        }
+
+        return Some(peer);
+    }
+}
+
+/// Describe the children of `bin`, nesting plain bins as `bin.( ... )`, and
+/// collect the elements described into `elements`
+fn describe_children(bin: &gst::Bin, elements: &mut Vec<gst::Element>) -> Vec<String> {
+    let mut children = bin.get_children();
+    children.reverse();
+
+    children
+        .iter()
+        .map(|child| match is_plain_bin(child) {
+            Some(child_bin) => {
+                let mut parts = vec![format!("name={}", child.get_name())];
+                parts.extend(properties(child, ""));
+                parts.extend(describe_children(child_bin, elements));
+                format!("bin.( {} )", parts.join("  "))
+            }
+            None => {
+                elements.push(child.clone());
+                describe_element(child)
+            }
+        })
+        .collect()
+}
+
+/// Describe `element`, listing the children of bins and then the links
+/// between them as `a.src ! b.sink`, all by name so that links across
+/// nested bins resolve as well
+pub fn describe(element: &gst::Element) -> String {
+    let bin = match is_plain_bin(element) {
+        Some(bin) => bin,
+        None => return describe_element(element),
+    };
+
+    let mut elements = vec![];
+    let mut parts = describe_children(bin, &mut elements);
+
+    for element in &elements {
+        for pad in element.get_src_pads() {
+            let peer = match link_peer(&pad) {
+                Some(peer) => peer,
+                None => continue,
+            };
+
+            if let Some(peer_element) = peer.get_parent_element() {
+                if elements.contains(&peer_element) {
+                    parts.push(format!(
+                        "{}.{} ! {}.{}",
+                        element.get_name(),
+                        pad.get_name(),
+                        peer_element.get_name(),
+                        peer.get_name()
+                    ));
+                }
+            }
+        }
+    }
+
+    parts.join("  ")
+}
+
+/// Log the description of `pipe` at debug level, once per pipeline so that
+/// restarts do not repeat it
+pub fn print(pipe: &gst::Pipeline, format: PipelineFormat) {
+    if !PRINTED.lock().unwrap().insert(pipe.get_name().to_string()) {
+        return;
+    }
+
+    match format {
+        PipelineFormat::Launch => {
+            /* Quoted as a whole, bins and links being shell syntax */
+            let description = describe(pipe.upcast_ref::<gst::Element>());
+            debug!(
+                "Pipeline {}: gst-launch-1.0 '{}'",
+                pipe.get_name(),
+                description.replace('\'', "'\\''")
+            );
+        }
+        PipelineFormat::Dot => {
+            debug!(
+                "Pipeline {}:\n{}",
+                pipe.get_name(),
+                gst::debug_bin_to_dot_data(pipe, gst::DebugGraphDetails::all())
+            );
+        }
+    }
+}
@@ -0,0 +1,220 @@
+//! Layout of the live video and the slate in the compositor.
+//!
+//! By default the slate replaces the live video once it was discarded. The
+//! other layouts keep repeating the last live frame instead, and rearrange
+//! the compositor pads while the slate shows: the slate as a "signal lost"
+//! badge in a corner over the frozen frame, or both side by side. Once the
+//! live video is back it takes the whole output again.
+
+use std::sync::Arc;
+
+use gst::prelude::*;
+
+use crate::cli::Args;
+use crate::gap::HoldMode;
+use crate::logo::LogoPosition;
+use crate::status::Status;
+use crate::switch::SwitchMode;
+
+/// Distance of the picture-in-picture slate from the edges, in pixels
+const PIP_MARGIN: i32 = 20;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Layout {
+    Switch,
+    Pip,
+    SideBySide,
+}
+
+impl std::str::FromStr for Layout {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "switch" => Ok(Layout::Switch),
+            "pip" => Ok(Layout::Pip),
+            "side-by-side" => Ok(Layout::SideBySide),
+            _ => Err(anyhow::anyhow!("Unknown layout {}", s)),
+        }
+    }
+}
+
+/// Position and size of a compositor pad, in pixels
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Rect {
+    pub x: i32,
+    pub y: i32,
+    pub width: i32,
+    pub height: i32,
+}
+
+/// Parse `X,Y,WIDTHxHEIGHT`
+impl std::str::FromStr for Rect {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let parse = || -> Option<Rect> {
+            let mut parts = s.splitn(3, ',');
+            let x = parts.next()?.trim().parse().ok()?;
+            let y = parts.next()?.trim().parse().ok()?;
+            let mut size = parts.next()?.splitn(2, 'x');
+            let width = size.next()?.trim().parse().ok()?;
+            let height = size.next()?.trim().parse().ok()?;
+
+            Some(Rect {
+                x,
+                y,
+                width,
+                height,
+            })
+        };
+
+        parse()
+            .filter(|rect| rect.width > 0 && rect.height > 0)
+            .ok_or_else(|| anyhow::anyhow!("{} is not X,Y,WIDTHxHEIGHT", s))
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct LayoutOptions {
+    pub layout: Layout,
+    /// Corner of the picture-in-picture slate
+    pub pip_position: LogoPosition,
+    /// Overrides of the geometry of the live video and the slate while the
+    /// slate shows
+    pub live_rect: Option<Rect>,
+    pub slate_rect: Option<Rect>,
+    pub width: i32,
+    pub height: i32,
+}
+
+impl LayoutOptions {
+    fn full(&self) -> Rect {
+        Rect {
+            x: 0,
+            y: 0,
+            width: self.width,
+            height: self.height,
+        }
+    }
+
+    /// Geometry of the live video and the slate while the slate shows
+    fn on_slate(&self) -> (Rect, Rect) {
+        let (live, slate) = match self.layout {
+            Layout::Switch => (self.full(), self.full()),
+            Layout::Pip => {
+                let width = self.width / 3;
+                let height = self.height / 3;
+                let left = PIP_MARGIN;
+                let right = self.width - width - PIP_MARGIN;
+                let top = PIP_MARGIN;
+                let bottom = self.height - height - PIP_MARGIN;
+                let (x, y) = match self.pip_position {
+                    LogoPosition::TopLeft => (left, top),
+                    LogoPosition::TopRight => (right, top),
+                    LogoPosition::BottomLeft => (left, bottom),
+                    LogoPosition::BottomRight => (right, bottom),
+                };
+
+                (
+                    self.full(),
+                    Rect {
+                        x,
+                        y,
+                        width,
+                        height,
+                    },
+                )
+            }
+            Layout::SideBySide => {
+                /* Letterboxed halves, keeping the aspect ratio of the output */
+                let width = self.width / 2;
+                let height = self.height / 2;
+                let y = (self.height - height) / 2;
+
+                (
+                    Rect {
+                        x: 0,
+                        y,
+                        width,
+                        height,
+                    },
+                    Rect {
+                        x: width,
+                        y,
+                        width,
+                        height,
+                    },
+                )
+            }
+        };
+
+        (
+            self.live_rect.unwrap_or(live),
+            self.slate_rect.unwrap_or(slate),
+        )
+    }
+}
+
+/// Check that the layout can be used with the other arguments
+pub fn check(args: &Args) -> Result<(), anyhow::Error> {
+    let custom = args.live_rect.is_some() || args.slate_rect.is_some();
+
+    if (custom || args.layout != Layout::Switch) && args.switch_mode != SwitchMode::Compositor {
+        anyhow::bail!(
+            "--layout, --live-rect and --slate-rect require --switch-mode compositor"
+        );
+    }
+
+    if args.layout == Layout::Switch {
+        return Ok(());
+    }
+
+    if args.discard_after.is_none() {
+        anyhow::bail!("--layout requires --discard-after to know when to show the slate");
+    }
+
+    if args.hold != HoldMode::Repeat {
+        anyhow::bail!("--layout is only supported with --hold repeat");
+    }
+
+    if args.transition_duration.is_some() {
+        anyhow::bail!("--layout is not supported with --transition-duration");
+    }
+
+    Ok(())
+}
+
+fn place(pad: &gst::Pad, rect: Rect, zorder: u32) {
+    pad.set_property("xpos", &rect.x).unwrap();
+    pad.set_property("ypos", &rect.y).unwrap();
+    pad.set_property("width", &rect.width).unwrap();
+    pad.set_property("height", &rect.height).unwrap();
+    pad.set_property("zorder", &zorder).unwrap();
+}
+
+/// Lay out the compositor pads `live_pad` and `slate_pad` along with what
+/// `status` shows on air
+pub fn start(
+    options: LayoutOptions,
+    live_pad: gst::Pad,
+    slate_pad: gst::Pad,
+    status: &Arc<Status>,
+) {
+    let (live_rect, slate_rect) = options.on_slate();
+
+    /* There is no last frame to show the slate over before the source
+     * first went live, the output starting on the slate */
+    place(&live_pad, options.full(), 1);
+    place(&slate_pad, options.full(), 0);
+
+    status.connect_live_changed(move |live| {
+        if live {
+            place(&live_pad, options.full(), 1);
+            place(&slate_pad, options.full(), 0);
+        } else {
+            place(&live_pad, live_rect, 1);
+            place(&slate_pad, slate_rect, 2);
+        }
+    });
+}
@@ -0,0 +1,1923 @@
+//! Relay a live stream, falling back to a slate when it fails.
+//!
+//! [`FallbackPipeline`] embeds the whole behaviour of the
+//! `rtmp-slate-fallback` binary, which is a thin command line wrapper around
+//! it:
+//!
+//! ```no_run
+//! use std::time::Duration;
+//!
+//! use rtmp_slate_fallback::FallbackPipeline;
+//!
+//! gst::init()?;
+//!
+//! let exit_code = FallbackPipeline::builder("rtmp://ingest/live/stream")?
+//!     .discard_after(Duration::from_secs(2))
+//!     .output_sink(gst::ElementFactory::make("autovideosink", None)?)
+//!     .build()
+//!     .run()?;
+//! # Ok::<(), anyhow::Error>(())
+//! ```
+
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use clap::Parser;
+use gst::prelude::*;
+
+#[macro_use]
+pub mod logging;
+mod archive;
+mod audio;
+mod av_offset;
+mod avsync;
+mod bitrate;
+mod bridge;
+mod buffering;
+mod captions;
+mod chaos;
+pub mod cli;
+mod compare;
+pub mod config;
+mod control;
+mod cpu;
+pub mod daemon;
+mod debug_overlay;
+mod decode_errors;
+mod deinterlace;
+mod dotdump;
+mod dryrun;
+mod encoder;
+pub mod exit_code;
+mod external_check;
+mod failover;
+mod format_watch;
+mod fps;
+mod freeze;
+mod gap;
+mod gate;
+mod gop;
+mod health;
+mod hints;
+mod hwaccel;
+mod input_stats;
+mod jitter;
+mod keyframes;
+mod latency;
+mod launch;
+mod layout;
+mod logo;
+mod loudness;
+mod machine;
+mod meters;
+mod metrics;
+mod netclock;
+mod output;
+mod persist;
+pub mod probe;
+mod quality;
+mod ranks;
+mod reconnect;
+mod recorder;
+mod return_delay;
+mod rtsp;
+mod script;
+mod scte35;
+pub mod setup;
+mod silence;
+mod single;
+mod slate;
+mod slate_player;
+mod slate_upload;
+mod snapshot;
+mod splice;
+mod srt;
+mod stale;
+mod status;
+mod stream_sync;
+pub mod supervisor;
+mod switch;
+mod systemd;
+mod tally;
+mod thumbnails;
+mod timeshift;
+mod tracks;
+mod transition;
+mod webhook;
+
+use audio::LiveAudioPolicy;
+use av_offset::AvOffset;
+use avsync::AvSyncMonitor;
+use bitrate::BitrateMonitor;
+use buffering::Buffering;
+use captions::Captions;
+use chaos::Chaos;
+use debug_overlay::DebugOverlay;
+use decode_errors::DecodeErrorMonitor;
+use external_check::ExternalCheck;
+use failover::Failover;
+use format_watch::FormatWatcher;
+use fps::FpsMonitor;
+use freeze::FreezeDetector;
+use gap::GapFiller;
+use gate::LiveGate;
+use gop::GopMonitor;
+use health::Health;
+use input_stats::InputStats;
+use jitter::JitterBuffer;
+use latency::LatencyMonitor;
+use layout::Layout;
+use loudness::LoudnessMonitor;
+use machine::{Event, SourceState, StateMachine};
+use metrics::Metrics;
+use output::Output;
+use persist::Persisted;
+use quality::{QualityMonitor, QualityThresholds};
+use reconnect::{GiveUpAction, ReconnectPolicy, Reconnector};
+use recorder::EventRecorder;
+use return_delay::ReturnDelay;
+use script::ScriptHooks;
+use silence::SilenceDetector;
+use single::SingleSource;
+use slate::{Slate, SlateReason};
+use slate_player::SlateVideo;
+use slate_upload::SlateUploads;
+use snapshot::Snapshot;
+use splice::Splicer;
+use stale::StaleTimestampDetector;
+use status::Status;
+use timeshift::TimeShift;
+use transition::Transition;
+use webhook::Webhook;
+
+pub use cli::Args;
+pub use archive::ArchiveFormat;
+pub use audio::FallbackPolicy;
+pub use bridge::Bridge;
+pub use buffering::BufferingPolicy;
+pub use encoder::Encoder;
+pub use exit_code::ExitCode;
+pub use gap::HoldMode;
+pub use hwaccel::HwDecoder;
+pub use launch::PipelineFormat;
+pub use logging::{Level as LogLevel, LogFormat};
+pub use logo::LogoPosition;
+pub use netclock::PipelineClock;
+pub use ranks::RankOverride;
+pub use rtsp::RtspProtocol;
+pub use srt::SrtMode;
+pub use stream_sync::StreamSync;
+pub use switch::SwitchMode;
+pub use tally::{TallyOutput, TslVersion};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PreviewSink {
+    Auto,
+    Xv,
+    Wayland,
+    None,
+}
+
+impl std::str::FromStr for PreviewSink {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "auto" => Ok(PreviewSink::Auto),
+            "xv" => Ok(PreviewSink::Xv),
+            "wayland" => Ok(PreviewSink::Wayland),
+            "none" => Ok(PreviewSink::None),
+            _ => Err(anyhow::anyhow!("Unknown preview sink {}", s)),
+        }
+    }
+}
+
+fn env_is_set(name: &str) -> bool {
+    std::env::var_os(name).map_or(false, |d| !d.is_empty())
+}
+
+/// Pick a preview sink for the display server we are running under, if any
+fn resolve_preview_sink(args: &Args) -> PreviewSink {
+    if args.headless {
+        return PreviewSink::None;
+    }
+
+    match args.preview_sink {
+        PreviewSink::Auto if env_is_set("WAYLAND_DISPLAY") => PreviewSink::Wayland,
+        PreviewSink::Auto if env_is_set("DISPLAY") => PreviewSink::Xv,
+        PreviewSink::Auto => {
+            info!("No display server found, running headless");
+            PreviewSink::None
+        }
+        preview_sink => preview_sink,
+    }
+}
+
+fn build_preview_sink(args: &Args) -> Result<gst::Element, anyhow::Error> {
+    if let Some(ref sink) = args.output_sink {
+        return Ok(sink.clone());
+    }
+
+    let factory = match (resolve_preview_sink(args), &args.video_sink) {
+        (PreviewSink::None, _) => {
+            let sink = hints::make_element("fakesink", Some("preview"))?;
+            sink.set_property("sync", &true)?;
+            return Ok(sink);
+        }
+        (_, Some(factory)) => factory.as_str(),
+        (PreviewSink::Xv, None) => "xvimagesink",
+        (_, None) => "waylandsink",
+    };
+
+    let sink = hints::make_element(factory, Some("preview"))?;
+
+    if args.stream_sync.breaks_qos() {
+        sink.set_property("qos", &false)?;
+    }
+
+    Ok(sink)
+}
+
+/// Window showing the comparison of the inputs, or a `fakesink` when
+/// running headless
+fn build_compare_sink(args: &Args) -> Result<gst::Element, anyhow::Error> {
+    let factory = match (resolve_preview_sink(args), &args.video_sink) {
+        (PreviewSink::None, _) => "fakesink",
+        (_, Some(factory)) => factory.as_str(),
+        (PreviewSink::Xv, None) => "xvimagesink",
+        (_, None) => "waylandsink",
+    };
+    let sink = hints::make_element(factory, Some("compare_preview"))?;
+    sink.set_property("sync", &true)?;
+
+    Ok(sink)
+}
+
+fn build_audio_sink(args: &Args) -> Result<gst::Element, anyhow::Error> {
+    if let Some(ref sink) = args.audio_output_sink {
+        return Ok(sink.clone());
+    }
+
+    let sink = match resolve_preview_sink(args) {
+        PreviewSink::None => hints::make_element("fakesink", Some("audio_preview"))?,
+        _ => hints::make_element(
+            args.audio_sink.as_deref().unwrap_or("autoaudiosink"),
+            Some("audio_preview"),
+        )?,
+    };
+    sink.set_property("sync", &true)?;
+
+    Ok(sink)
+}
+
+/// Outputs of the program, the preview is left out when re-streaming or
+/// archiving unless a preview sink was asked for explicitly. A dry run only
+/// encodes into a `fakesink`.
+fn build_outputs(
+    args: &Args,
+    splicer: Option<&Arc<Splicer>>,
+) -> Result<Vec<Output>, anyhow::Error> {
+    if args.dry_run.is_some() {
+        return Ok(vec![Output::null(
+            "output_dry_run",
+            &args.ts_options(),
+            &args.encoding(),
+        )?]);
+    }
+
+    let mut outputs = vec![];
+
+    for (index, uri) in args.output_uri.iter().enumerate() {
+        outputs.push(Output::new(
+            &format!("output_{}", index),
+            uri,
+            &args.ts_options(),
+            &args.encoding(),
+        )?);
+    }
+
+    if let (Some(uri), Some(splicer)) = (&args.passthrough_uri, splicer) {
+        outputs.push(Output::passthrough(
+            "output_passthrough",
+            uri,
+            &args.ts_options(),
+            &args.encoding(),
+            splicer,
+        )?);
+    }
+
+    if let Some(hls) = args.hls_options() {
+        outputs.push(Output::hls(
+            "output_hls",
+            &hls,
+            &args.encoding(),
+        )?);
+    }
+
+    if let Some(archive) = args.archive_options() {
+        outputs.push(Output::archive(
+            "output_archive",
+            &archive,
+            &args.encoding(),
+        )?);
+    }
+
+    if args.webrtc_preview {
+        outputs.push(Output::webrtc(
+            "output_webrtc",
+            args.webrtc_signaller_uri.as_deref(),
+        )?);
+    }
+
+    let preview = outputs.is_empty()
+        || args.output_sink.is_some()
+        || args.audio_output_sink.is_some()
+        || (!args.headless
+            && matches!(args.preview_sink, PreviewSink::Xv | PreviewSink::Wayland));
+
+    if preview {
+        outputs.push(Output::raw(
+            "output_preview",
+            &build_preview_sink(args)?,
+            &build_audio_sink(args)?,
+        )?);
+    }
+
+    Ok(outputs)
+}
+
+fn clock_time(duration: Duration) -> gst::ClockTime {
+    gst::ClockTime::from_nseconds(duration.as_nanos() as u64)
+}
+
+fn default_handle_message(
+    pipe: &gst::Pipeline,
+    msg: &gst::Message,
+    print_pipelines: Option<PipelineFormat>,
+) {
+    match msg.view() {
+        gst::MessageView::Latency(..) => {
+            info!("Recalculating latency!");
+            pipe.recalculate_latency().unwrap();
+        }
+        gst::MessageView::StateChanged(state_changed) => {
+            if state_changed.get_src().map(|s| &s == pipe).unwrap_or(false) {
+                dotdump::dump(
+                    pipe,
+                    &format!(
+                        "{:?}_{:?}",
+                        state_changed.get_old(),
+                        state_changed.get_current()
+                    )
+                    .to_uppercase(),
+                );
+
+                if state_changed.get_current() == gst::State::Playing {
+                    if let Some(format) = print_pipelines {
+                        launch::print(pipe, format);
+                    }
+                }
+            }
+        }
+        _ => (),
+    }
+}
+
+/// State shared between the pipelines and the monitoring machinery
+struct Context {
+    gate: Arc<LiveGate>,
+    quality: Arc<QualityMonitor>,
+    bitrate: Arc<BitrateMonitor>,
+    fps: Arc<FpsMonitor>,
+    input_stats: Arc<InputStats>,
+    latency: Arc<LatencyMonitor>,
+    av_offset: Arc<AvOffset>,
+    health: Arc<Health>,
+    status: Arc<Status>,
+    slate: Arc<Slate>,
+    metrics: Arc<Metrics>,
+    failover: Arc<Failover>,
+    webhook: Option<Arc<Webhook>>,
+    silence: Option<Arc<SilenceDetector>>,
+    machine: Arc<StateMachine>,
+    snapshot: Option<Arc<Snapshot>>,
+    avsync: Option<Arc<AvSyncMonitor>>,
+    /// Slate video played by a replaceable player rather than in the mixer
+    slate_video: Option<Arc<SlateVideo>>,
+    slate_uploads: Option<Arc<SlateUploads>>,
+    /// Splices the live H.264 with the encoded program, with
+    /// --passthrough-uri
+    splicer: Option<Arc<Splicer>>,
+}
+
+/// Handling of the buffering messages of an input, `gate` is closed by the
+/// slate policy
+fn buffering_handler(args: &Args, gate: Option<Arc<LiveGate>>) -> Buffering {
+    /* The jitter buffer takes care of late data by default instead */
+    let policy = args.buffering_policy.unwrap_or(if args.jitter_buffer.is_some() {
+        BufferingPolicy::Ignore
+    } else {
+        BufferingPolicy::Pause
+    });
+
+    Buffering::new(
+        policy,
+        args.buffering_low_watermark,
+        args.buffering_high_watermark,
+        gate,
+    )
+}
+
+/// How long the live video is missing before the slate shows, if ever
+fn down_after(args: &Args) -> Option<Duration> {
+    match args.hold {
+        HoldMode::Repeat => args.discard_after,
+        HoldMode::Gap => Some(gap::STALL_AFTER),
+    }
+}
+
+/// Element erroring out the source pipeline when no video arrives for the
+/// stall timeout, as a frozen source that keeps its connection up neither
+/// errors nor reaches EOS on its own
+fn stall_watchdog(args: &Args) -> String {
+    match args.stall_timeout {
+        Some(stall_timeout) => format!("watchdog timeout={} ! ", stall_timeout.as_millis()),
+        None => String::new(),
+    }
+}
+
+/// Hysteresis on the live video of an input, if any
+fn return_delay(args: &Args) -> Option<Arc<ReturnDelay>> {
+    if args.return_delay.is_none() && args.startup_delay.is_none() {
+        return None;
+    }
+
+    Some(ReturnDelay::new(
+        args.return_delay.unwrap_or_default(),
+        args.startup_delay.or(args.return_delay).unwrap_or_default(),
+        down_after(args),
+    ))
+}
+
+fn reconnector(args: &Args, ctx: &Context) -> Arc<Reconnector> {
+    let reconnector = Reconnector::new(ReconnectPolicy {
+        initial_delay: args.reconnect_delay,
+        max_delay: args.reconnect_max_delay,
+        jitter: args.reconnect_jitter,
+        max_retries: args.reconnect_max_retries,
+    });
+
+    if let Some(webhook) = ctx.webhook.clone() {
+        reconnector.connect_give_up(move |source, attempts| {
+            let uri = source
+                .get_property("uri")
+                .ok()
+                .and_then(|uri| uri.get::<String>().ok().flatten());
+            webhook.notify(
+                "reconnect-exhausted",
+                serde_json::json!({ "uri": uri, "attempts": attempts }),
+            );
+        });
+    }
+
+    reconnector
+}
+
+/// End of the live branch named `name` in the source
+fn live_sink(args: &Args, name: &str) -> String {
+    if args.single_pipeline {
+        single::sink(name)
+    } else {
+        args.bridge.sink(name)
+    }
+}
+
+/// Start of the output branch fed by the live branch named `name`
+fn live_source(args: &Args, name: &str) -> Result<gst::Element, anyhow::Error> {
+    if args.single_pipeline {
+        single::source(name)
+    } else {
+        args.bridge.source(name, args.stream_sync)
+    }
+}
+
+/// The RTMP source
+enum Source {
+    /// playbin3, in a pipeline of its own
+    Pipeline(gst::Pipeline),
+    /// uridecodebin3, within the output pipeline
+    Single(Arc<SingleSource>),
+}
+
+impl Source {
+    /// Element restarted on failures and carrying the URI
+    fn element(&self) -> gst::Element {
+        match self {
+            Source::Pipeline(pipe) => pipe.clone().upcast(),
+            Source::Single(single) => single.decodebin().clone(),
+        }
+    }
+
+    fn pipeline(&self) -> Option<&gst::Pipeline> {
+        match self {
+            Source::Pipeline(pipe) => Some(pipe),
+            Source::Single(_) => None,
+        }
+    }
+
+    fn single(&self) -> Option<&Arc<SingleSource>> {
+        match self {
+            Source::Pipeline(_) => None,
+            Source::Single(single) => Some(single),
+        }
+    }
+}
+
+/// Pipeline `element` runs in
+fn pipeline_of(element: &gst::Element) -> gst::Pipeline {
+    let mut object = element.clone().upcast::<gst::Object>();
+
+    loop {
+        object = match object.downcast::<gst::Pipeline>() {
+            Ok(pipe) => return pipe,
+            Err(object) => object.get_parent().expect("Element outside of a pipeline"),
+        };
+    }
+}
+
+/// The RTMP source, decoding into the live video and audio branches and
+/// restarted by `reconnector` on failures
+fn build_source(
+    args: &Args,
+    ctx: &Context,
+    reconnector: &Arc<Reconnector>,
+) -> Result<Source, anyhow::Error> {
+    let jitter_buffer = args
+        .jitter_buffer
+        .map(|size| JitterBuffer::new(size, args.drop_late));
+    let jitter_queue = jitter_buffer
+        .as_ref()
+        .map_or_else(String::new, JitterBuffer::queue);
+    let vsink = hints::parse_bin(&format!(
+        "{}{}{}identity name=id ! {}{}",
+        args.hw_decoder.sink_prefix(),
+        if args.burn_in_captions {
+            "cc708overlay ! "
+        } else {
+            ""
+        },
+        stall_watchdog(args),
+        jitter_queue,
+        live_sink(args, "rtmp")
+    ))?;
+    let asink = hints::parse_bin(&format!(
+        "audioconvert ! level name=level ! {}{}{}{}",
+        if args.audio_meters { "tee name=t ! " } else { "" },
+        jitter_queue,
+        live_sink(args, "rtmp_audio"),
+        if args.audio_meters {
+            format!(" t. ! {}", meters::branch(&jitter_queue))
+        } else {
+            String::new()
+        }
+    ))?;
+
+    if let Some(ref jitter_buffer) = jitter_buffer {
+        jitter_buffer.configure_sink(&vsink.get_by_name("rtmp").unwrap())?;
+        jitter_buffer.configure_sink(&asink.get_by_name("rtmp_audio").unwrap())?;
+
+        if let Some(meters) = asink.get_by_name("meters") {
+            jitter_buffer.configure_sink(&meters)?;
+        }
+    }
+    asink
+        .get_by_name("level")
+        .unwrap()
+        .set_property("interval", &loudness::LEVEL_INTERVAL)?;
+
+    let single = if args.single_pipeline {
+        Some(SingleSource::new(&vsink, &asink)?)
+    } else {
+        None
+    };
+    let source = match single {
+        Some(ref single) => single.decodebin().clone(),
+        None => hints::make_element("playbin3", Some("rtmp_source"))?,
+    };
+
+    let identity = vsink.get_by_name("id").unwrap();
+
+    if let Some(eos_after) = args.eos_after {
+        identity.set_property("eos-after", &eos_after)?;
+    }
+
+    if let Some(error_after) = args.error_after {
+        identity.set_property("error-after", &error_after)?;
+    }
+
+    ctx.quality.install(&identity.get_static_pad("sink").unwrap());
+    ctx.fps.install(&identity.get_static_pad("sink").unwrap());
+    ctx.input_stats.install(&identity.get_static_pad("sink").unwrap());
+
+    let chaos = args.chaos_options().map(Chaos::new);
+    if let Some(ref chaos) = chaos {
+        chaos.install(&identity.get_static_pad("sink").unwrap());
+        chaos.start();
+    }
+
+    if args.freeze_frames.is_some() || args.black_frames.is_some() {
+        FreezeDetector::new(args.freeze_frames, args.black_frames, ctx.gate.clone())
+            .install(&identity.get_static_pad("sink").unwrap());
+    }
+
+    let decode_errors = args.max_decode_errors.map(|max_errors| {
+        DecodeErrorMonitor::new(max_errors, args.decode_error_window)
+    });
+
+    if let Some(ref decode_errors) = decode_errors {
+        decode_errors.install(&identity.get_static_pad("sink").unwrap());
+    }
+
+    if let Some(stale_timeout) = args.stale_timeout {
+        StaleTimestampDetector::new(stale_timeout)
+            .install(&identity.get_static_pad("sink").unwrap());
+    }
+    ctx.gate.install(&identity.get_static_pad("src").unwrap());
+
+    if let Some(return_delay) = return_delay(args) {
+        return_delay.install(&identity.get_static_pad("src").unwrap());
+    }
+
+    ctx.failover.watch(
+        0,
+        &vsink
+            .get_by_name("rtmp")
+            .unwrap()
+            .get_static_pad("sink")
+            .unwrap(),
+    );
+
+    let reconnector = reconnector.clone();
+    reconnector.watch_live(&identity.get_static_pad("src").unwrap());
+
+    if let Some(startup_timeout) = args.startup_timeout {
+        reconnector.watch_startup(
+            &source,
+            &identity.get_static_pad("src").unwrap(),
+            startup_timeout,
+            &ctx.machine,
+        );
+    }
+
+    let threads = cpu::threads(args.available_cpus());
+
+    let gop = GopMonitor::new(clock_time(args.max_gop));
+    let splicer = ctx.splicer.clone();
+
+    let setup_element = move |element: gst::Element| {
+        let factory = match element.get_factory() {
+            Some(factory) => factory,
+            None => return,
+        };
+
+        /* libav sizes its thread pool after the host CPUs, not our quota */
+        if factory.get_name().starts_with("avdec_") && element.find_property("max-threads").is_some()
+        {
+            element.set_property("max-threads", &(threads as i32)).unwrap();
+        }
+
+        let is_video_decoder = factory
+            .get_metadata(&gst::ELEMENT_METADATA_KLASS)
+            .map_or(false, |klass| klass.contains("Decoder") && klass.contains("Video"));
+
+        if is_video_decoder {
+            gop.install(&element.get_static_pad("sink").unwrap());
+
+            if let Some(ref splicer) = splicer {
+                splicer.install_live(&element.get_static_pad("sink").unwrap());
+            }
+        }
+    };
+
+    /* uridecodebin3 has no element-setup, unlike playbin3 */
+    if single.is_some() {
+        source.connect("deep-element-added", false, move |values| {
+            setup_element(values[2].get::<gst::Element>().unwrap().unwrap());
+            None
+        })?;
+    } else {
+        source.connect("element-setup", false, move |values| {
+            setup_element(values[1].get::<gst::Element>().unwrap().unwrap());
+            None
+        })?;
+    }
+
+    let bitrate = ctx.bitrate.clone();
+    let srt_options = args.srt_options();
+    let rtsp_options = args.rtsp_options();
+    source.connect("source-setup", false, move |values| {
+        let source = values[1].get::<gst::Element>().unwrap().unwrap();
+
+        if let Err(err) = srt_options.configure(&source) {
+            warn!("Failed to configure SRT source: {}", err);
+        }
+
+        if let Err(err) = rtsp_options.configure(&source) {
+            warn!("Failed to configure RTSP source: {}", err);
+        }
+
+        if let Some(pad) = source.get_static_pad("src") {
+            bitrate.install(&pad);
+
+            if let Some(ref chaos) = chaos {
+                chaos.install_network(&pad);
+            }
+        }
+
+        None
+    })?;
+
+    source.set_property("uri", &args.live_rtmp_uri[0])?;
+
+    /* playbin renders the subtitles onto the video itself */
+    if let Some(ref subtitle_file) = args.subtitle_file {
+        let uri = glib::filename_to_uri(daemon::absolute(subtitle_file)?, None)?;
+        source.set_property("suburi", &uri.as_str())?;
+    }
+
+    if single.is_none() {
+        source.set_property("video-sink", &vsink)?;
+        source.set_property("audio-sink", &asink)?;
+    }
+
+    ctx.av_offset.install(
+        &asink.get_static_pad("sink").unwrap(),
+        &vsink.get_static_pad("sink").unwrap(),
+    );
+
+    let format_watcher = FormatWatcher::new();
+    format_watcher.install(&asink.get_static_pad("sink").unwrap(), true);
+    format_watcher.install(&vsink.get_static_pad("sink").unwrap(), false);
+
+    let source_clone = source.clone();
+    let status = ctx.status.clone();
+    let metrics = ctx.metrics.clone();
+    let slate = ctx.slate.clone();
+    let silence = ctx.silence.clone();
+    let state_machine = ctx.machine.clone();
+    let print_pipelines = args.print_pipelines;
+    let buffering = buffering_handler(args, Some(ctx.gate.clone()));
+    let audio_track = args.audio_track.clone();
+
+    let handle_message = move |msg: &gst::Message| {
+        let source = &source_clone;
+        let pipe = &pipeline_of(source);
+        let reconnecting = machine::event_for(source, msg)
+            .map(|event| state_machine.handle(event))
+            .map_or(false, |transition| transition.to == SourceState::Reconnecting);
+
+        match msg.view() {
+            gst::MessageView::Error(err) if !reconnecting => {
+                hints::log_error("Error while draining, not restarting pipeline", &err);
+            }
+            gst::MessageView::Error(err) => {
+                hints::log_error("Error, restarting pipeline", &err);
+                slate.set_reason(SlateReason::Error);
+                dotdump::dump(pipe, "ERROR");
+                reconnector.schedule(source);
+            }
+            gst::MessageView::Buffering(buffering_msg) => {
+                let percent = buffering_msg.get_percent();
+                status.set_buffering(percent);
+                buffering.handle(source, percent);
+            }
+            gst::MessageView::Eos(_) if !reconnecting => {
+                info!("Source EOS while draining");
+            }
+            gst::MessageView::Eos(_) => {
+                warn!("We are EOS");
+                slate.set_reason(SlateReason::Eos);
+                reconnector.schedule(source);
+            }
+            gst::MessageView::Element(..) => {
+                if let Some(ref silence) = silence {
+                    silence.handle_message(msg);
+                }
+            }
+            gst::MessageView::Qos(..) => metrics.handle_qos(msg),
+            gst::MessageView::StreamCollection(collection) => {
+                let collection = collection.get_stream_collection();
+                format_watcher.handle_stream_collection(&collection);
+
+                if let Some(ref audio_track) = audio_track {
+                    audio_track.select(source, &collection);
+                }
+            }
+            gst::MessageView::Warning(warning) => {
+                warn!("Warning: {:?}", warning);
+
+                if decode_errors.as_ref().map_or(false, |decode_errors| {
+                    DecodeErrorMonitor::is_decoder_warning(msg) && decode_errors.record()
+                }) && state_machine.handle(Event::Restart).to == SourceState::Reconnecting
+                {
+                    error!("Too many decode errors, restarting pipeline");
+                    reconnector.schedule(source);
+                }
+            }
+            _ => default_handle_message(pipe, msg, print_pipelines),
+        };
+    };
+
+    match single {
+        Some(single) => {
+            single.set_message_handler(handle_message);
+            Ok(Source::Single(single))
+        }
+        None => {
+            let pipe = source.downcast::<gst::Pipeline>().unwrap();
+            pipe.get_bus().unwrap().add_watch(move |_, msg| {
+                handle_message(msg);
+                glib::Continue(true)
+            })?;
+            Ok(Source::Pipeline(pipe))
+        }
+    }
+}
+
+/// Pipeline relaying the backup input `index` to its own interpipes, with
+/// none of the monitoring of the primary input
+fn build_backup_pipeline(
+    args: &Args,
+    ctx: &Context,
+    index: usize,
+    reconnector: &Arc<Reconnector>,
+) -> Result<gst::Pipeline, anyhow::Error> {
+    let playbin = hints::make_element("playbin3", Some(&format!("rtmp_source_{}", index)))?;
+    let jitter_buffer = args
+        .jitter_buffer
+        .map(|size| JitterBuffer::new(size, args.drop_late));
+    let jitter_queue = jitter_buffer
+        .as_ref()
+        .map_or_else(String::new, JitterBuffer::queue);
+    let video_sink_name = failover::video_sink_name(index);
+    let audio_sink_name = failover::audio_sink_name(index);
+    let vsink = hints::parse_bin(&format!(
+        "{}{}identity name=id ! {}interpipesink drop=false sync=true name={}",
+        args.hw_decoder.sink_prefix(),
+        stall_watchdog(args),
+        jitter_queue,
+        video_sink_name
+    ))?;
+    let asink = hints::parse_bin(&format!(
+        "audioconvert ! {}interpipesink drop=false sync=true name={}",
+        jitter_queue, audio_sink_name
+    ))?;
+
+    let video_sink = vsink.get_by_name(&video_sink_name).unwrap();
+    if let Some(ref jitter_buffer) = jitter_buffer {
+        jitter_buffer.configure_sink(&video_sink)?;
+        jitter_buffer.configure_sink(&asink.get_by_name(&audio_sink_name).unwrap())?;
+    }
+
+    let identity = vsink.get_by_name("id").unwrap();
+    ctx.gate.install(&identity.get_static_pad("src").unwrap());
+
+    if let Some(return_delay) = return_delay(args) {
+        return_delay.install(&identity.get_static_pad("src").unwrap());
+    }
+
+    ctx.failover
+        .watch(index, &video_sink.get_static_pad("sink").unwrap());
+
+    let reconnector = reconnector.clone();
+    reconnector.watch_live(&identity.get_static_pad("src").unwrap());
+
+    let srt_options = args.srt_options();
+    let rtsp_options = args.rtsp_options();
+    playbin.connect("source-setup", false, move |values| {
+        let source = values[1].get::<gst::Element>().unwrap().unwrap();
+
+        if let Err(err) = srt_options.configure(&source) {
+            warn!("Failed to configure SRT source: {}", err);
+        }
+
+        if let Err(err) = rtsp_options.configure(&source) {
+            warn!("Failed to configure RTSP source: {}", err);
+        }
+
+        None
+    })?;
+
+    playbin.set_property("uri", &args.live_rtmp_uri[index])?;
+    playbin.set_property("video-sink", &vsink)?;
+    playbin.set_property("audio-sink", &asink)?;
+
+    let pipe = playbin.downcast::<gst::Pipeline>().unwrap();
+
+    let bus = pipe.get_bus().unwrap();
+    let pipe_clone = pipe.clone();
+    let print_pipelines = args.print_pipelines;
+    let buffering = buffering_handler(args, None);
+
+    bus.add_watch(move |_, msg| {
+        let pipe = &pipe_clone;
+        match msg.view() {
+            gst::MessageView::Error(err) => {
+                hints::log_error(&format!("Error on input {}, restarting it", index), &err);
+                dotdump::dump(pipe, "ERROR");
+                reconnector.schedule(pipe.upcast_ref());
+            }
+            gst::MessageView::Eos(_) => {
+                warn!("Input {} is EOS", index);
+                reconnector.schedule(pipe.upcast_ref());
+            }
+            gst::MessageView::Buffering(buffering_msg) => {
+                buffering.handle(pipe.upcast_ref(), buffering_msg.get_percent());
+            }
+            _ => default_handle_message(pipe, msg, print_pipelines),
+        };
+        glib::Continue(true)
+    })?;
+
+    Ok(pipe)
+}
+
+fn build_compositor_pipeline(
+    args: &Args,
+    main_loop: &glib::MainLoop,
+    exit_code: &Arc<Mutex<ExitCode>>,
+    ctx: &Context,
+    single: Option<&Arc<SingleSource>>,
+) -> Result<gst::Pipeline, anyhow::Error> {
+    let pipe = gst::Pipeline::new(Some("video_mixer"));
+
+    let live_src = live_source(args, "rtmp")?;
+    let queue = hints::make_element("queue", None)?;
+    let compositor = hints::make_element("compositor", None)?;
+    /* Blends in parallel since GStreamer 1.20, on as many threads as CPUs */
+    if compositor.find_property("max-threads").is_some() {
+        compositor.set_property("max-threads", &cpu::threads(args.available_cpus()))?;
+    }
+    let switch = match args.switch_mode {
+        SwitchMode::Compositor => None,
+        SwitchMode::FallbackSwitch => Some(switch::build_fallbackswitch(
+            args.hold,
+            args.discard_after,
+        )?),
+    };
+    /* Where the live video and the slate go */
+    let mixer = switch.as_ref().unwrap_or(&compositor);
+    let outputs = build_outputs(args, ctx.splicer.as_ref())?;
+    let video_tee = hints::make_element("tee", Some("output_tee"))?;
+    let audio_tee = hints::make_element("tee", Some("audio_output_tee"))?;
+    /* Outputs that failed get unlinked while the others go on */
+    video_tee.set_property("allow-not-linked", &true)?;
+    audio_tee.set_property("allow-not-linked", &true)?;
+
+    pipe.add_many(&[&live_src, &queue, &compositor, &video_tee])?;
+    if let Some(ref switch) = switch {
+        pipe.add(switch)?;
+        switch.link(&compositor)?;
+    }
+    gst::Element::link_many(&[&live_src, &queue])?;
+
+    /* Progressive square pixels for the compositor to scale to the output
+     * size, and only decimate, repeating frames would fill outages with the
+     * last one */
+    let normalize = hints::parse_bin(&format!(
+        "{}{} ! videoscale ! videorate drop-only=true ! \
+         capsfilter caps=\"video/x-raw,pixel-aspect-ratio=1/1{}\"",
+        args.deinterlace.prefix(),
+        args.converter(),
+        match args.output_framerate {
+            Some((numer, denom)) => format!(",framerate=[0/1,{}/{}]", numer, denom),
+            None => String::new(),
+        }
+    ))?;
+    pipe.add(&normalize)?;
+
+    /* Caption metadata does not make it through the compositor */
+    let extractor = if args.caption_passthrough {
+        let extractor = hints::make_element("ccextractor", None)?;
+        pipe.add(&extractor)?;
+        gst::Element::link_many(&[&queue, &extractor, normalize.upcast_ref(), mixer])?;
+        Some(extractor)
+    } else {
+        gst::Element::link_many(&[&queue, normalize.upcast_ref(), mixer])?;
+        None
+    };
+
+    /* compositor ! capsfilter ! [logo !] [debug overlay !] tee ! outputs */
+    let program_caps = hints::make_element("capsfilter", Some("program_caps"))?;
+    program_caps.set_property("caps", &args.output_caps())?;
+    pipe.add(&program_caps)?;
+    compositor.link(&program_caps)?;
+    let mut output = program_caps;
+
+    if let Some(ref logo_file) = args.logo_file {
+        let logo = logo::build(logo_file, args.logo_position, args.logo_opacity, args.logo_margin)?;
+        pipe.add(&logo)?;
+        output.link(&logo)?;
+        output = logo;
+    }
+
+    if args.debug_overlay {
+        let overlay = DebugOverlay::new()?;
+        pipe.add(overlay.element())?;
+        output.link(overlay.element())?;
+        output = overlay.element().clone();
+
+        overlay.install(
+            &queue.get_static_pad("src").unwrap(),
+            &compositor.get_static_pad("src").unwrap(),
+        );
+        overlay.start(&ctx.status, &queue);
+    }
+
+    if args.caption_passthrough || args.slate_caption.is_some() {
+        Captions::build(
+            &pipe,
+            &output,
+            &video_tee,
+            extractor.as_ref(),
+            args.slate_caption.as_deref(),
+        )?
+        .start(&ctx.status);
+    } else {
+        output.link(&video_tee)?;
+    }
+
+    if let Some(ref snapshot) = ctx.snapshot {
+        snapshot.link(&pipe, &video_tee)?;
+    }
+
+    ctx.latency.install(&video_tee.get_static_pad("sink").unwrap());
+
+    if let Some(ref record_dir) = args.record_dir {
+        EventRecorder::new(
+            &pipe,
+            &video_tee,
+            &args.encoding(),
+            record_dir,
+            args.record_pre_roll,
+            args.record_post_roll,
+        )?
+        .start(&ctx.status);
+    }
+
+    /* Delaying the live input by the time-shift lets the queue play out what
+     * it holds when the source drops, or with catch-up hold it until the
+     * source is back and resume from just before the drop */
+    if let Some(time_shift) = args.time_shift {
+        TimeShift::install(&queue, time_shift, args.catch_up)?;
+    }
+
+    ctx.health.watch_output(&compositor.get_static_pad("src").unwrap());
+    ctx.status.watch_live(&queue.get_static_pad("src").unwrap());
+    ctx.slate.watch_live(&queue.get_static_pad("src").unwrap());
+    ctx.machine.watch_live(&queue.get_static_pad("src").unwrap());
+
+    if args.keyframe_on_switch {
+        keyframes::install(&compositor.get_static_pad("src").unwrap(), &ctx.status);
+    }
+
+    let pad = compositor.get_static_pad("sink_0").unwrap();
+    pad.set_property("zorder", &(1 as u32))?;
+    pad.set_property("width", &args.output_width)?;
+    pad.set_property("height", &args.output_height)?;
+
+    match (args.hold, &switch) {
+        (_, Some(_)) => switch::set_priority(normalize.upcast_ref(), 0)?,
+        (HoldMode::Repeat, None) => {
+            /* Keep repeating the last frame while it fades out, or forever
+             * for the slate to be laid out over it */
+            if let (Some(discard_after), Layout::Switch) = (args.discard_after, args.layout) {
+                let fade_out = args.transition_duration.unwrap_or_default();
+                pad.set_property(
+                    "max-last-buffer-repeat",
+                    &clock_time(discard_after + fade_out),
+                )?;
+            }
+        }
+        (HoldMode::Gap, None) => {
+            pad.set_property("max-last-buffer-repeat", &gst::ClockTime::from_seconds(0))?;
+            GapFiller::new().install(&queue.get_static_pad("sink").unwrap());
+        }
+    }
+
+    if let (Some(duration), None) = (args.transition_duration, &switch) {
+        Transition::new(&pipe, &pad, duration)?.start(&ctx.status);
+    }
+
+    if let Some(mixer_latency) = args.mixer_latency {
+        compositor.set_property("latency", &clock_time(mixer_latency))?;
+    }
+
+    let queue = hints::make_element("queue", None)?;
+    let capsfilter = hints::make_element("capsfilter", None)?;
+    let fallbacksrc = match (&args.slate_source, &ctx.slate_video, &args.slate_uri) {
+        (Some(source), _, _) => source.clone(),
+        (None, Some(slate_video), _) => slate_video.src(),
+        (None, None, Some(uri)) if slate_player::is_image(uri) => {
+            slate_player::build_image_source(uri)?
+        }
+        (None, None, Some(_)) => {
+            let source = hints::make_element("interpipesrc", None)?;
+            source.set_property("listen-to", &slate_player::VIDEO)?;
+            source.set_property("format", &gst::Format::Time)?;
+            source.set_property("is-live", &true)?;
+            source.set_property_from_str("stream-sync", &"restart-ts");
+            source
+        }
+        (None, None, None) => {
+            let source = hints::make_element("videotestsrc", None)?;
+            source.set_property("is-live", &true)?;
+            capsfilter.set_property(
+                "caps",
+                &gst::Caps::new_simple("video/x-raw", &[("width", &800), ("height", &448)]),
+            )?;
+            source
+        }
+    };
+
+    let mut slate_branch = vec![&fallbacksrc, &queue, &capsfilter];
+    if let Some(overlay) = ctx.slate.overlay() {
+        slate_branch.push(overlay);
+    }
+
+    pipe.add_many(&slate_branch)?;
+    slate_branch.push(mixer);
+    gst::Element::link_many(&slate_branch)?;
+
+    if switch.is_some() {
+        switch::set_priority(slate_branch[slate_branch.len() - 2], 1)?;
+    } else {
+        let pad = compositor.get_static_pad("sink_1").unwrap();
+        pad.set_property("zorder", &(0 as u32))?;
+        pad.set_property("width", &args.output_width)?;
+        pad.set_property("height", &args.output_height)?;
+
+        if let Some(options) = args.layout_options() {
+            /* Black rather than a checkerboard around smaller pads */
+            compositor.set_property_from_str("background", "black");
+            layout::start(
+                options,
+                compositor.get_static_pad("sink_0").unwrap(),
+                pad,
+                &ctx.status,
+            );
+        }
+    }
+
+    if args.audio_meters {
+        meters::install(&pipe, &compositor, args.output_width, args.output_height)?;
+    }
+
+    /* audiomixer ! [loudness normalization !] [limiter !] tee ! outputs */
+    let mut program_chain = vec![];
+    if let Some(target) = args.loudness_target {
+        program_chain.push(audio::normalizer(target, args.loudness_max_true_peak)?);
+    }
+    if args.audio_limiter {
+        program_chain.push(loudness::limiter(args.max_audio_peak)?);
+    }
+    program_chain.push(audio_tee.clone());
+    /* audio::build adds the head of the chain */
+    let program_audio = program_chain.remove(0);
+    pipe.add_many(&program_chain.iter().collect::<Vec<_>>())?;
+
+    let live_audio = live_source(args, "rtmp_audio")?;
+    audio::build(
+        &pipe,
+        &program_audio,
+        &live_audio,
+        args.slate_audio.is_some(),
+        &ctx.status,
+        LiveAudioPolicy {
+            policy: args.fallback_policy,
+            down_after: down_after(args),
+            silence: ctx.silence.clone(),
+        },
+    )?;
+
+    program_chain.insert(0, program_audio);
+    gst::Element::link_many(&program_chain.iter().collect::<Vec<_>>())?;
+
+    /* Peaks and loudness are measured on air, the slate audio included */
+    let meter = loudness::meter()?;
+    pipe.add(&meter)?;
+    audio_tee.link(&meter)?;
+    let loudness = LoudnessMonitor::new(args.max_audio_peak, args.max_loudness_jump);
+
+    /* Drift is measured on air, after the compositor and the audio mixer */
+    if let Some(ref avsync) = ctx.avsync {
+        avsync.install(
+            &audio_tee.get_static_pad("sink").unwrap(),
+            &video_tee.get_static_pad("sink").unwrap(),
+        );
+    }
+
+    if let Some(single) = single {
+        single.link(&pipe, &live_src, &live_audio)?;
+    }
+
+    if args.live_rtmp_uri.len() > 1 {
+        ctx.failover.start(&live_src, &live_audio, &ctx.status);
+    }
+
+    for output in &outputs {
+        output.link(&pipe, &video_tee, &audio_tee)?;
+    }
+
+    if let Some(ref splicer) = ctx.splicer {
+        splicer.start(&ctx.status);
+    }
+
+    if let Some(pid) = args.output_ts_scte35_pid {
+        scte35::start(outputs.iter().filter_map(Output::ts_mux).collect(), pid, &ctx.status);
+    }
+
+    let bus = pipe.get_bus().unwrap();
+    let pipe_clone = pipe.clone();
+    let main_loop = main_loop.clone();
+    let exit_code = exit_code.clone();
+    let metrics = ctx.metrics.clone();
+    let print_pipelines = args.print_pipelines;
+    let single = single.cloned();
+    let mut outputs = outputs;
+    bus.add_watch(move |_, msg| {
+        let pipe = &pipe_clone;
+
+        if single.as_ref().map_or(false, |single| single.handle(msg)) {
+            return glib::Continue(true);
+        }
+
+        match msg.view() {
+            gst::MessageView::Error(err) => {
+                match outputs.iter().position(|output| output.posted(msg)) {
+                    Some(index) if outputs.len() > 1 => {
+                        let output = outputs.remove(index);
+                        hints::log_error(&format!("Output {} failed, dropping it", output.name()), &err);
+                        dotdump::dump(pipe, "ERROR");
+                        if let Err(err) = output.remove(pipe) {
+                            warn!("Failed to remove output {}: {}", output.name(), err);
+                        }
+                    }
+                    /* Without output there is nothing left to protect, let the
+                     * supervisor restart us */
+                    _ => {
+                        hints::log_error("Output pipeline error, exiting", &err);
+                        dotdump::dump(pipe, "ERROR");
+                        *exit_code.lock().unwrap() = ExitCode::OutputFailed;
+                        main_loop.quit();
+                    }
+                }
+            }
+            gst::MessageView::Eos(_) => {
+                info!("Output finalized, exiting");
+                main_loop.quit();
+            }
+            gst::MessageView::StateChanged(state_changed) => {
+                /* The slate guarantees output from here on, whatever the source does */
+                if state_changed.get_src().map(|s| &s == pipe).unwrap_or(false)
+                    && state_changed.get_current() == gst::State::Playing
+                {
+                    systemd::notify_ready();
+                }
+                default_handle_message(pipe, msg, print_pipelines);
+            }
+            gst::MessageView::Qos(..) => metrics.handle_qos(msg),
+            gst::MessageView::Element(..) => loudness.handle_message(msg),
+            _ => default_handle_message(pipe, msg, print_pipelines),
+        }
+        glib::Continue(true)
+    })?;
+
+    Ok(pipe)
+}
+
+static RESTARTS: AtomicU64 = AtomicU64::new(0);
+
+/// Number of times the RTMP source was restarted
+fn restarts() -> u64 {
+    RESTARTS.load(Ordering::Relaxed)
+}
+
+fn restart_source(source: &gst::Element) -> Result<(), anyhow::Error> {
+    RESTARTS.fetch_add(1, Ordering::Relaxed);
+    let uri = source.get_property("uri")?;
+    source.set_state(gst::State::Null)?;
+    source.set_property("uri", &uri)?;
+    source.set_state(gst::State::Playing)?;
+
+    Ok(())
+}
+
+/// Call `func` once after `duration` from the default main context, even
+/// when longer than the u32::MAX milliseconds of a single glib timeout
+fn call_after<F: FnOnce() + Send + 'static>(duration: Duration, func: F) {
+    let millis = duration.as_millis().min(u32::MAX as u128) as u32;
+    let remaining = duration - Duration::from_millis(millis as u64);
+    let mut func = Some(func);
+
+    glib::timeout_add(millis, move || {
+        if let Some(func) = func.take() {
+            if remaining > Duration::from_secs(0) {
+                call_after(remaining, func);
+            } else {
+                func();
+            }
+        }
+        glib::Continue(false)
+    });
+}
+
+/// Send EOS through the output pipeline so that its outputs get finalized,
+/// the bus watch quits once it arrives, or after `drain_timeout` with the
+/// output considered failed
+fn drain(
+    pipe: &gst::Pipeline,
+    main_loop: &glib::MainLoop,
+    exit_code: &Arc<Mutex<ExitCode>>,
+    drain_timeout: Duration,
+) {
+    pipe.send_event(gst::event::Eos::new());
+
+    let main_loop = main_loop.clone();
+    let exit_code = exit_code.clone();
+    call_after(drain_timeout, move || {
+        error!("Output did not finalize in time, exiting");
+        *exit_code.lock().unwrap() = ExitCode::OutputFailed;
+        main_loop.quit();
+    });
+}
+
+/// Drain the output pipeline after `duration` and quit, unless it is
+/// draining already
+fn stop_after(
+    pipe: &gst::Pipeline,
+    main_loop: &glib::MainLoop,
+    exit_code: &Arc<Mutex<ExitCode>>,
+    duration: Duration,
+    drain_timeout: Duration,
+    state_machine: &Arc<StateMachine>,
+) {
+    let pipe = pipe.clone();
+    let main_loop = main_loop.clone();
+    let exit_code = exit_code.clone();
+    let state_machine = state_machine.clone();
+
+    call_after(duration, move || {
+        if state_machine.handle(Event::Drain).from != SourceState::Draining {
+            info!("Run duration elapsed, finalizing output");
+            drain(&pipe, &main_loop, &exit_code, drain_timeout);
+        }
+    });
+}
+
+fn run(args: &Args) -> Result<ExitCode, anyhow::Error> {
+    let main_loop = glib::MainLoop::new(None, false);
+    let exit_code = Arc::new(Mutex::new(ExitCode::Success));
+
+    dotdump::init(args.dot_dir.as_deref(), args.dot_retention)?;
+    args.hw_decoder.install()?;
+
+    ranks::apply(&args.rank_overrides())?;
+    let software_encoders = match args.encoder {
+        Encoder::X264 => args.encoded_outputs(),
+        _ => 0,
+    };
+    cpu::check(args.available_cpus(), software_encoders);
+
+    let gate = LiveGate::new();
+    let bitrate = BitrateMonitor::new(args.bitrate_window);
+    let fps = FpsMonitor::new();
+    let input_stats = InputStats::new(&bitrate, &fps, args.bitrate_window);
+    let latency = LatencyMonitor::new();
+    av_offset::check(args.av_offset_ms)?;
+    buffering::check(args.buffering_low_watermark, args.buffering_high_watermark)?;
+    let status = Status::new(&args.live_rtmp_uri[0], down_after(args));
+    let slate_uploads = match (&args.slate_upload_dir, &args.slate_source) {
+        (Some(dir), None) => Some(Arc::new(SlateUploads::new(dir)?)),
+        _ => None,
+    };
+    let slate_assets = args.slate_assets();
+    let default_slate_uri = slate_uploads
+        .as_ref()
+        .and_then(|uploads| uploads.current())
+        .or_else(|| args.slate_uri.clone());
+    let slate_video = if (slate_uploads.is_some() || !slate_assets.is_empty())
+        && args.slate_source.is_none()
+    {
+        Some(SlateVideo::new(
+            slate_assets
+                .starting
+                .as_ref()
+                .or_else(|| default_slate_uri.as_ref())
+                .map(String::as_str),
+        )?)
+    } else {
+        None
+    };
+    let quality = QualityMonitor::new(if args.quality_fallback {
+        Some((
+            QualityThresholds {
+                max_blockiness: args.max_blockiness,
+                min_sharpness: args.min_sharpness,
+            },
+            gate.clone(),
+        ))
+    } else {
+        None
+    });
+    let ctx = Context {
+        quality: quality.clone(),
+        metrics: Metrics::new(&status, &input_stats, &latency, &quality, &gate),
+        failover: Failover::new(
+            &args.live_rtmp_uri,
+            args.failover_after,
+            args.failover_return_after,
+        ),
+        silence: args.silence_timeout.map(|timeout| {
+            SilenceDetector::new(
+                args.silence_threshold,
+                timeout,
+                args.silence_recovery,
+                if args.silence_fallback_video {
+                    Some(gate.clone())
+                } else {
+                    None
+                },
+            )
+        }),
+        gate,
+        bitrate,
+        fps,
+        input_stats,
+        latency,
+        av_offset: AvOffset::new(args.av_offset_ms),
+        health: Health::new(args.readiness_grace, args.liveness_grace),
+        status: status.clone(),
+        slate: Slate::new(
+            args.slate_starting_text.clone(),
+            args.slate_error_text.clone(),
+            args.slate_eos_text.clone(),
+            args.slate_text.clone(),
+            &status,
+        )?,
+        webhook: if args.webhook_urls.is_empty() {
+            None
+        } else {
+            Some(Webhook::new(args.webhook_urls.clone()))
+        },
+        machine: StateMachine::new(),
+        snapshot: if args.control_port.is_some() || args.thumbnail_options().is_some() {
+            Some(Arc::new(Snapshot::new()?))
+        } else {
+            None
+        },
+        avsync: args.max_av_drift.map(|_| AvSyncMonitor::new()),
+        slate_video,
+        slate_uploads,
+        splicer: match args.passthrough_uri {
+            Some(_) => {
+                splice::check(args)?;
+                Some(Splicer::new(&args.encoding())?)
+            }
+            None => None,
+        },
+    };
+
+    if let Some(ref slate_video) = ctx.slate_video {
+        ctx.slate.set_assets(slate_video, slate_assets, default_slate_uri);
+    }
+
+    if args.single_pipeline {
+        single::check(args)?;
+    } else {
+        args.bridge.check(args)?;
+    }
+
+    layout::check(args)?;
+
+    /* One per input, the primary one first */
+    let reconnectors = args
+        .live_rtmp_uri
+        .iter()
+        .map(|_| reconnector(args, &ctx))
+        .collect::<Vec<_>>();
+    let reconnector = reconnectors[0].clone();
+    let persisted = Arc::new(Persisted {
+        gate: ctx.gate.clone(),
+        failover: ctx.failover.clone(),
+        reconnector: reconnector.clone(),
+    });
+
+    /* Before the failover starts switching and anything goes on air */
+    if let Some(ref state_file) = args.state_file {
+        persisted.restore(state_file)?;
+    }
+
+    let source = build_source(args, &ctx, &reconnector)?;
+    let compositor_pipe =
+        build_compositor_pipeline(args, &main_loop, &exit_code, &ctx, source.single())?;
+    let mut pipes = vec![compositor_pipe.clone()];
+
+    if let Some(rtmp_pipe) = source.pipeline() {
+        args.bridge.connect(rtmp_pipe, &compositor_pipe, &["rtmp", "rtmp_audio"])?;
+        pipes.insert(0, rtmp_pipe.clone());
+    }
+
+    for (index, reconnector) in reconnectors.iter().enumerate().skip(1) {
+        pipes.push(build_backup_pipeline(args, &ctx, index, reconnector)?);
+    }
+
+    if args.compare_inputs {
+        if args.live_rtmp_uri.len() < 2 {
+            anyhow::bail!("--compare-inputs requires a backup --live-rtmp-uri");
+        }
+        pipes.push(compare::build(&build_compare_sink(args)?)?);
+    }
+
+    let slate_video = args
+        .slate_uri
+        .as_deref()
+        .filter(|uri| ctx.slate_video.is_none() && !slate_player::is_image(uri));
+
+    if let Some(ref slate_video) = ctx.slate_video {
+        pipes.push(slate_video.pipeline());
+    }
+
+    match (slate_video, args.slate_audio.as_deref()) {
+        (Some(video), Some(audio)) if video == audio => {
+            pipes.push(slate_player::build(video, true, true)?);
+        }
+        (video, audio) => {
+            if let Some(uri) = video {
+                pipes.push(slate_player::build(uri, false, true)?);
+            }
+            if let Some(uri) = audio {
+                pipes.push(slate_player::build(uri, true, false)?);
+            }
+        }
+    }
+
+    args.clock.apply(&pipes.iter().collect::<Vec<_>>())?;
+
+    /* A fixed latency survives restarts of the source, unlike the one
+     * negotiated from latency messages */
+    if let (Some(rtmp_pipe), Some(latency)) =
+        (source.pipeline(), args.source_latency.or(args.latency))
+    {
+        rtmp_pipe.set_latency(clock_time(latency));
+    }
+
+    if let Some(latency) = args.latency {
+        compositor_pipe.set_latency(clock_time(latency));
+    }
+
+    if let Some(port) = args.health_port {
+        ctx.health.serve(port)?;
+    }
+
+    if let Some(port) = args.metrics_port {
+        ctx.metrics.serve(port, pipes.clone())?;
+        ctx.metrics.start();
+    }
+
+    if args.control_port.is_some() || args.stdin_commands {
+        let control = Arc::new(control::Control {
+            gate: ctx.gate.clone(),
+            health: ctx.health.clone(),
+            status: ctx.status.clone(),
+            slate: ctx.slate.clone(),
+            machine: ctx.machine.clone(),
+            source: source.element(),
+            reconnector: reconnector.clone(),
+            token: args.control_token.clone(),
+            pipes: pipes.clone(),
+            snapshot: ctx.snapshot.clone(),
+            input_stats: ctx.input_stats.clone(),
+            latency: ctx.latency.clone(),
+            av_offset: ctx.av_offset.clone(),
+            slate_uploads: ctx.slate_uploads.clone(),
+        });
+
+        if let Some(port) = args.control_port {
+            control.serve((args.control_bind, port).into())?;
+        }
+
+        if args.stdin_commands {
+            control.read_commands();
+        }
+    }
+
+    control::handle_signals(&ctx.gate);
+
+    /* The first signal drains the output, a second one exits right away */
+    for signal in &[libc::SIGINT, libc::SIGTERM] {
+        let signal = *signal;
+        let main_loop = main_loop.clone();
+        let exit_code = exit_code.clone();
+        let compositor_pipe = compositor_pipe.clone();
+        let state_machine = ctx.machine.clone();
+        let drain_timeout = args.drain_timeout;
+        glib::unix_signal_add(signal, move || {
+            *exit_code.lock().unwrap() = ExitCode::Signal(signal);
+
+            if state_machine.handle(Event::Drain).from == SourceState::Draining {
+                info!("Received termination signal again, exiting");
+                main_loop.quit();
+            } else {
+                info!("Received termination signal, finalizing output");
+                drain(&compositor_pipe, &main_loop, &exit_code, drain_timeout);
+            }
+
+            glib::Continue(true)
+        });
+    }
+
+    /* Nothing to act on while failover still serves another input */
+    for (index, reconnector) in reconnectors.iter().enumerate() {
+        let failover = ctx.failover.clone();
+        let still_served = move || match failover.healthy_other(index) {
+            Some(other) => {
+                info!("Gave up on input {}, input {} still delivers video", index, other);
+                true
+            }
+            None => false,
+        };
+
+        match args.on_giveup {
+            GiveUpAction::StayOnSlate => (),
+            GiveUpAction::Exit => {
+                let main_loop = main_loop.clone();
+                let exit_code = exit_code.clone();
+                let compositor_pipe = compositor_pipe.clone();
+                let state_machine = ctx.machine.clone();
+                let drain_timeout = args.drain_timeout;
+                reconnector.connect_give_up(move |_, _| {
+                    if still_served() {
+                        return;
+                    }
+                    error!("Gave up on the source, finalizing output and exiting");
+                    *exit_code.lock().unwrap() = ExitCode::SourceFailed;
+                    state_machine.handle(Event::Drain);
+                    drain(&compositor_pipe, &main_loop, &exit_code, drain_timeout);
+                });
+            }
+            GiveUpAction::RunCommand => {
+                let command = args.on_giveup_command.clone().unwrap();
+                reconnector.connect_give_up(move |source, attempts| {
+                    if still_served() {
+                        return;
+                    }
+                    let uri = source
+                        .get_property("uri")
+                        .ok()
+                        .and_then(|uri| uri.get::<String>().ok().flatten());
+                    reconnect::run_give_up_command(&command, uri, attempts);
+                });
+            }
+        }
+    }
+
+    if let Some(timeout) = args.dry_run {
+        let exit_code = exit_code.clone();
+        dryrun::start(
+            pipes.clone(),
+            &main_loop,
+            timeout,
+            move |negotiated| {
+                if !negotiated {
+                    *exit_code.lock().unwrap() = ExitCode::Setup;
+                }
+            },
+        )?;
+    } else {
+        for pipe in &pipes {
+            hints::set_state(pipe, gst::State::Playing)?;
+        }
+    }
+
+    if let Some(duration) = args.run_duration() {
+        stop_after(
+            &compositor_pipe,
+            &main_loop,
+            &exit_code,
+            duration,
+            args.drain_timeout,
+            &ctx.machine,
+        );
+    }
+
+    systemd::start_watchdog();
+    ctx.health.start_heartbeat();
+    ctx.quality.start_reporting(args.quality_interval);
+
+    ctx.status.log_changes();
+    ctx.machine.start(&ctx.status);
+    ctx.slate.start();
+
+    if !args.quiet {
+        ctx.status.start(&ctx.bitrate, !args.no_color);
+    }
+
+    ctx.fps.start(
+        args.fps_drop_ratio,
+        args.fps_drop_timeout
+            .as_millis()
+            .min(u32::MAX as u128) as u32,
+        if args.fps_drop_fallback {
+            Some(ctx.gate.clone())
+        } else {
+            None
+        },
+    );
+
+    if let Some(interval) = args.input_stats_interval {
+        ctx.input_stats.start(interval);
+    }
+
+    if let (Some(ref avsync), Some(max_av_drift)) = (&ctx.avsync, args.max_av_drift) {
+        let source_element = source.element();
+        let reconnector = reconnector.clone();
+        let status = ctx.status.clone();
+        let state_machine = ctx.machine.clone();
+        let restart = args.av_drift_restart;
+        avsync.start(max_av_drift, move || {
+            /* The source is only to blame while it is on air */
+            if restart
+                && status.shows_live()
+                && state_machine.handle(Event::Restart).to == SourceState::Reconnecting
+            {
+                warn!("Restarting pipeline to resync audio and video");
+                reconnector.schedule(&source_element);
+            }
+        });
+    }
+
+    let source_element = source.element();
+    let latency_reconnector = reconnector.clone();
+    let state_machine = ctx.machine.clone();
+    let restart = args.max_latency_restart;
+    ctx.latency.start(source.pipeline().cloned(), args.max_latency, move || {
+        if restart && state_machine.handle(Event::Restart).to == SourceState::Reconnecting {
+            warn!("Restarting pipeline to bring the latency down");
+            latency_reconnector.schedule(&source_element);
+        }
+    });
+
+    let external_checks = args
+        .health_command
+        .iter()
+        .cloned()
+        .map(ExternalCheck::Command)
+        .chain(args.health_url.iter().cloned().map(ExternalCheck::Http));
+
+    for check in external_checks {
+        check.start(
+            args.health_check_interval,
+            args.health_check_timeout,
+            ctx.gate.clone(),
+        );
+    }
+
+    if let Some(min_bitrate) = args.min_bitrate {
+        ctx.bitrate.start_fallback(&ctx.gate, min_bitrate);
+    }
+
+    /* A dry run does not notify anyone nor persist its state */
+    let on_air = args.dry_run.is_none();
+
+    if let Some(state_file) = args.state_file.as_ref().filter(|_| on_air) {
+        persisted.start(state_file.clone());
+    }
+
+    if let (Some(options), Some(ref snapshot)) = (args.thumbnail_options(), &ctx.snapshot) {
+        thumbnails::start(options, snapshot)?;
+    }
+
+    if on_air {
+        tally::start(args.tally_outputs(), &ctx.status)?;
+
+        if let Some(ref webhook) = ctx.webhook {
+            webhook.start(&ctx.status);
+        }
+    }
+
+    if let Some(ref script) = args.script {
+        ScriptHooks::load(script, &ctx.gate, &ctx.slate)?.start(&ctx.status);
+    }
+
+    main_loop.run();
+
+    systemd::notify_stopping();
+
+    if let Some(state_file) = args.state_file.as_ref().filter(|_| on_air) {
+        if let Err(err) = persisted.save(state_file) {
+            warn!("Failed to save state to {}: {}", state_file.display(), err);
+        }
+    }
+
+    for pipe in &pipes {
+        pipe.set_state(gst::State::Null)?;
+    }
+
+    let exit_code = *exit_code.lock().unwrap();
+
+    Ok(exit_code)
+}
+
+/// The live input relayed to the output, with the slate shown whenever it
+/// fails
+pub struct FallbackPipeline {
+    args: Args,
+}
+
+impl FallbackPipeline {
+    /// Relay `uri`, with the defaults of the binary otherwise
+    pub fn builder(uri: &str) -> Result<FallbackPipelineBuilder, anyhow::Error> {
+        Ok(FallbackPipelineBuilder {
+            args: Args::try_parse_from(&["rtmp-slate-fallback", "--live-rtmp-uri", uri])?,
+        })
+    }
+
+    /// Configure everything as from the command line
+    pub fn from_args(args: Args) -> Self {
+        Self { args }
+    }
+
+    /// Run until the run duration elapsed, the output failed or the process
+    /// was signalled, gst::init() must have been called
+    pub fn run(&self) -> Result<ExitCode, anyhow::Error> {
+        run(&self.args)
+    }
+}
+
+/// Options of a [`FallbackPipeline`], the ones not set explicitly keep the
+/// defaults of the corresponding command line options
+pub struct FallbackPipelineBuilder {
+    args: Args,
+}
+
+impl FallbackPipelineBuilder {
+    /// Element producing the slate video, instead of a test pattern
+    pub fn slate_source(mut self, source: gst::Element) -> Self {
+        self.args.slate_source = Some(source);
+        self
+    }
+
+    /// Element consuming the output video, instead of a preview window
+    pub fn output_sink(mut self, sink: gst::Element) -> Self {
+        self.args.output_sink = Some(sink);
+        self
+    }
+
+    /// Element consuming the output audio, instead of an audio preview
+    pub fn audio_output_sink(mut self, sink: gst::Element) -> Self {
+        self.args.audio_output_sink = Some(sink);
+        self
+    }
+
+    /// Fail over to `uri` when the inputs added before it fail
+    pub fn backup_uri(mut self, uri: &str) -> Self {
+        self.args.live_rtmp_uri.push(uri.to_string());
+        self
+    }
+
+    /// Repeat the last live frame for this long before showing the slate
+    pub fn discard_after(mut self, discard_after: Duration) -> Self {
+        self.args.discard_after = Some(discard_after);
+        self
+    }
+
+    /// Restart the source when its timestamps stop advancing for this long
+    pub fn stale_timeout(mut self, stale_timeout: Duration) -> Self {
+        self.args.stale_timeout = Some(stale_timeout);
+        self
+    }
+
+    /// Restart the source when it delivers no video for this long
+    pub fn stall_timeout(mut self, stall_timeout: Duration) -> Self {
+        self.args.stall_timeout = Some(stall_timeout);
+        self
+    }
+
+    /// Stop after this long
+    pub fn duration(mut self, duration: Duration) -> Self {
+        self.args.duration = Some(duration);
+        self
+    }
+
+    /// Adjust any other option
+    pub fn configure(mut self, f: impl FnOnce(&mut Args)) -> Self {
+        f(&mut self.args);
+        self
+    }
+
+    pub fn build(self) -> FallbackPipeline {
+        FallbackPipeline::from_args(self.args)
+    }
+}
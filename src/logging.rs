@@ -0,0 +1,376 @@
+//! Console logging, either human readable or as one JSON object per line.
+//!
+//! Messages and structured events are emitted through `tracing`, so that an
+//! application embedding the library can collect them with a subscriber of
+//! its own. The binary installs a layer writing them out: in text mode
+//! informational messages go to stdout and warnings / errors to stderr. In
+//! JSON mode everything goes to stdout, which is what log collectors in
+//! container environments expect.
+//!
+//! Alternatively logs can be written to a file, rotated by size.
+//!
+//! Messages below the configured level are discarded, debug messages are
+//! only shown when asked for.
+
+use std::fmt;
+use std::fs::File;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicU8, Ordering};
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use once_cell::sync::Lazy;
+use serde_json::Value;
+use tracing::field::{Field, Visit};
+use tracing::subscriber::Interest;
+use tracing::Metadata;
+use tracing_subscriber::layer::{Context, Layer, SubscriberExt};
+
+#[doc(hidden)]
+pub use tracing;
+
+static JSON: AtomicBool = AtomicBool::new(false);
+static MIN_LEVEL: AtomicU8 = AtomicU8::new(Level::Info as u8);
+static STATUS_LINE: AtomicBool = AtomicBool::new(false);
+static LOG_FILE: Lazy<Mutex<Option<RotatingFile>>> = Lazy::new(|| Mutex::new(None));
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LogFormat {
+    Text,
+    Json,
+}
+
+impl std::str::FromStr for LogFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "text" => Ok(LogFormat::Text),
+            "json" => Ok(LogFormat::Json),
+            _ => Err(anyhow::anyhow!("Unknown log format {}", s)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, PartialOrd)]
+pub enum Level {
+    Debug,
+    Info,
+    Warn,
+    Error,
+}
+
+impl std::str::FromStr for Level {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "debug" => Ok(Level::Debug),
+            "info" => Ok(Level::Info),
+            "warn" => Ok(Level::Warn),
+            "error" => Ok(Level::Error),
+            _ => Err(anyhow::anyhow!("Unknown log level {}", s)),
+        }
+    }
+}
+
+impl From<tracing::Level> for Level {
+    fn from(level: tracing::Level) -> Self {
+        match level {
+            tracing::Level::ERROR => Level::Error,
+            tracing::Level::WARN => Level::Warn,
+            tracing::Level::INFO => Level::Info,
+            _ => Level::Debug,
+        }
+    }
+}
+
+impl Level {
+    fn as_str(self) -> &'static str {
+        match self {
+            Level::Debug => "debug",
+            Level::Info => "info",
+            Level::Warn => "warn",
+            Level::Error => "error",
+        }
+    }
+}
+
+/// A log file renamed to `<path>.1` once it exceeds `max_size` bytes, with
+/// at most `keep` rotated files kept around.
+struct RotatingFile {
+    path: PathBuf,
+    file: File,
+    size: u64,
+    max_size: u64,
+    keep: u32,
+}
+
+impl RotatingFile {
+    fn open(path: &Path, max_size: u64, keep: u32) -> Result<Self, anyhow::Error> {
+        let file = std::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)?;
+        let size = file.metadata()?.len();
+
+        Ok(Self {
+            path: path.to_path_buf(),
+            file,
+            size,
+            max_size,
+            keep,
+        })
+    }
+
+    fn rotated_path(&self, index: u32) -> PathBuf {
+        let mut path = self.path.clone().into_os_string();
+        path.push(format!(".{}", index));
+        path.into()
+    }
+
+    fn rotate(&mut self) -> Result<(), anyhow::Error> {
+        for index in (1..self.keep).rev() {
+            let from = self.rotated_path(index);
+            if from.exists() {
+                std::fs::rename(&from, self.rotated_path(index + 1))?;
+            }
+        }
+
+        if self.keep > 0 {
+            std::fs::rename(&self.path, self.rotated_path(1))?;
+        } else {
+            std::fs::remove_file(&self.path)?;
+        }
+
+        *self = Self::open(&self.path, self.max_size, self.keep)?;
+
+        Ok(())
+    }
+
+    fn write_line(&mut self, line: &str) -> Result<(), anyhow::Error> {
+        if self.size > 0 && self.size + line.len() as u64 + 1 > self.max_size {
+            self.rotate()?;
+        }
+
+        writeln!(self.file, "{}", line)?;
+        self.size += line.len() as u64 + 1;
+
+        Ok(())
+    }
+}
+
+/// Write the `tracing` events of the process out, as set up by `init()`
+/// and `log_to_file()`
+pub fn install() {
+    let _ = tracing::subscriber::set_global_default(
+        tracing_subscriber::registry().with(ConsoleLayer),
+    );
+}
+
+pub fn init(format: LogFormat, level: Level) {
+    JSON.store(format == LogFormat::Json, Ordering::SeqCst);
+    MIN_LEVEL.store(level as u8, Ordering::SeqCst);
+}
+
+fn is_enabled(level: Level) -> bool {
+    level as u8 >= MIN_LEVEL.load(Ordering::SeqCst)
+}
+
+/// Write all logs to `path` instead of stdout / stderr
+pub fn log_to_file(path: &Path, max_size: u64, keep: u32) -> Result<(), anyhow::Error> {
+    *LOG_FILE.lock().unwrap() = Some(RotatingFile::open(path, max_size, keep)?);
+    Ok(())
+}
+
+/// Returns whether the line was written to the log file
+fn write_to_file(line: &str) -> bool {
+    let mut log_file = LOG_FILE.lock().unwrap();
+
+    match *log_file {
+        Some(ref mut file) => {
+            if let Err(err) = file.write_line(line) {
+                eprintln!("Failed to write to log file: {}", err);
+            }
+            true
+        }
+        None => false,
+    }
+}
+
+pub fn is_json() -> bool {
+    JSON.load(Ordering::SeqCst)
+}
+
+/// Fields of a `tracing` event
+#[derive(Default)]
+struct EventFields {
+    message: String,
+    /// Name of a structured event
+    name: Option<String>,
+    fields: serde_json::Map<String, Value>,
+}
+
+impl Visit for EventFields {
+    fn record_str(&mut self, field: &Field, value: &str) {
+        match field.name() {
+            "event" => self.name = Some(value.to_string()),
+            /* The JSON object of the details of a structured event */
+            "fields" => {
+                if let Ok(Value::Object(fields)) = serde_json::from_str(value) {
+                    self.fields.extend(fields);
+                }
+            }
+            name => {
+                self.fields.insert(name.to_string(), Value::from(value));
+            }
+        }
+    }
+
+    fn record_i64(&mut self, field: &Field, value: i64) {
+        self.fields.insert(field.name().to_string(), Value::from(value));
+    }
+
+    fn record_u64(&mut self, field: &Field, value: u64) {
+        self.fields.insert(field.name().to_string(), Value::from(value));
+    }
+
+    fn record_bool(&mut self, field: &Field, value: bool) {
+        self.fields.insert(field.name().to_string(), Value::from(value));
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        if field.name() == "message" {
+            self.message = format!("{:?}", value);
+        } else {
+            self.record_str(field, &format!("{:?}", value));
+        }
+    }
+}
+
+fn write(level: Level, event: EventFields) {
+    if is_json() {
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs_f64())
+            .unwrap_or(0.0);
+        let mut line = serde_json::Map::new();
+
+        line.insert("timestamp".to_string(), Value::from(timestamp));
+        line.insert("level".to_string(), Value::from(level.as_str()));
+        if let Some(name) = event.name {
+            line.insert("event".to_string(), Value::from(name));
+        }
+        line.insert("message".to_string(), Value::from(event.message));
+        line.extend(event.fields);
+
+        let line = Value::Object(line).to_string();
+        if !write_to_file(&line) {
+            println!("{}", line);
+        }
+        return;
+    }
+
+    let message = if event.name.is_some() || !event.fields.is_empty() {
+        format!("{} {}", event.message, Value::Object(event.fields))
+    } else {
+        event.message
+    };
+
+    if !write_to_file(&format!("[{}] {}", level.as_str(), message)) {
+        /* Log above the status line, which is redrawn on its next update */
+        if STATUS_LINE.swap(false, Ordering::SeqCst) {
+            print!("\r\x1b[2K");
+        }
+
+        if level <= Level::Info {
+            println!("{}", message);
+        } else {
+            eprintln!("{}", message);
+        }
+    }
+}
+
+/// Writes the events at or above the configured level out
+struct ConsoleLayer;
+
+impl<S: tracing::Subscriber> Layer<S> for ConsoleLayer {
+    /* The level is only known once the command line is parsed, and events
+     * from before then must not be discarded for good */
+    fn register_callsite(&self, _metadata: &'static Metadata<'static>) -> Interest {
+        Interest::sometimes()
+    }
+
+    fn enabled(&self, metadata: &Metadata<'_>, _ctx: Context<'_, S>) -> bool {
+        is_enabled(Level::from(*metadata.level()))
+    }
+
+    fn on_event(&self, event: &tracing::Event<'_>, _ctx: Context<'_, S>) {
+        let mut fields = EventFields::default();
+        event.record(&mut fields);
+
+        write(Level::from(*event.metadata().level()), fields);
+    }
+}
+
+/// Log a structured event, `fields` must be a JSON object. In JSON mode the
+/// fields are merged into the log line, in text mode they are appended.
+pub fn event(level: Level, name: &str, message: &str, fields: serde_json::Value) {
+    let fields = fields.to_string();
+    let fields = fields.as_str();
+
+    match level {
+        Level::Debug => tracing::debug!(event = name, fields, "{}", message),
+        Level::Info => tracing::info!(event = name, fields, "{}", message),
+        Level::Warn => tracing::warn!(event = name, fields, "{}", message),
+        Level::Error => tracing::error!(event = name, fields, "{}", message),
+    }
+}
+
+/// Whether stdout is an interactive terminal
+pub fn is_terminal() -> bool {
+    unsafe { libc::isatty(1) == 1 }
+}
+
+/// Display a status line, overwritten by the next one. Only shown on
+/// interactive terminals in text mode, where it stays below the log lines.
+pub fn status_line(line: &str) {
+    if is_json() || LOG_FILE.lock().unwrap().is_some() || !is_terminal() {
+        return;
+    }
+
+    STATUS_LINE.store(true, Ordering::SeqCst);
+    print!("\r\x1b[2K{}", line);
+    if let Err(err) = std::io::stdout().flush() {
+        eprintln!("Failed: {}", err);
+    }
+}
+
+#[macro_export]
+macro_rules! debug {
+    ($($arg:tt)*) => {
+        $crate::logging::tracing::debug!($($arg)*)
+    };
+}
+
+#[macro_export]
+macro_rules! info {
+    ($($arg:tt)*) => {
+        $crate::logging::tracing::info!($($arg)*)
+    };
+}
+
+#[macro_export]
+macro_rules! warn {
+    ($($arg:tt)*) => {
+        $crate::logging::tracing::warn!($($arg)*)
+    };
+}
+
+#[macro_export]
+macro_rules! error {
+    ($($arg:tt)*) => {
+        $crate::logging::tracing::error!($($arg)*)
+    };
+}
@@ -0,0 +1,65 @@
+//! Logo or watermark on the program output.
+//!
+//! The image is overlaid after the compositor, so that it shows on top of
+//! both the live video and the slate, and keeps its alpha channel.
+
+use std::path::Path;
+
+use gst::prelude::*;
+
+use crate::hints;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LogoPosition {
+    TopLeft,
+    TopRight,
+    BottomLeft,
+    BottomRight,
+}
+
+impl std::str::FromStr for LogoPosition {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "top-left" => Ok(LogoPosition::TopLeft),
+            "top-right" => Ok(LogoPosition::TopRight),
+            "bottom-left" => Ok(LogoPosition::BottomLeft),
+            "bottom-right" => Ok(LogoPosition::BottomRight),
+            _ => Err(anyhow::anyhow!("Unknown logo position {}", s)),
+        }
+    }
+}
+
+/// Element overlaying the image at `path` at `position`, `margin` pixels
+/// away from the edges
+pub fn build(
+    path: &Path,
+    position: LogoPosition,
+    opacity: f64,
+    margin: i32,
+) -> Result<gst::Element, anyhow::Error> {
+    if !path.is_file() {
+        anyhow::bail!("Logo {} does not exist", path.display());
+    }
+
+    let bin = hints::parse_bin("videoconvert ! gdkpixbufoverlay name=logo")?;
+    let overlay = bin.get_by_name("logo").unwrap();
+
+    /* Negative offsets are from the right and bottom edges, so a margin of
+     * 0 from those is off by a pixel */
+    let far = -margin.max(1);
+    let (x, y) = match position {
+        LogoPosition::TopLeft => (margin, margin),
+        LogoPosition::TopRight => (far, margin),
+        LogoPosition::BottomLeft => (margin, far),
+        LogoPosition::BottomRight => (far, far),
+    };
+
+    overlay.set_property("location", &path.to_string_lossy().as_ref())?;
+    overlay.set_property("offset-x", &x)?;
+    overlay.set_property("offset-y", &y)?;
+    overlay.set_property("alpha", &opacity)?;
+
+    Ok(bin.upcast())
+}
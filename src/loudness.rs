@@ -0,0 +1,155 @@
+//! Audio peak and loudness anomaly detection on the program audio.
+//!
+//! Based on the messages of a `level` element after the audio mixer: true
+//! peaks above the configured limit, and short-term level jumping away from
+//! the long-term average, are reported as events. Transmission chains
+//! downstream tend to react badly to both. Live and slate audio alike are
+//! measured, after the optional limiter.
+//!
+//! The `level` element only sees sample peaks, which miss the peaks between
+//! samples that the reconstructed signal reaches. It is fed audio resampled
+//! to `TRUE_PEAK_RATE`, 4x oversampling of 48kHz as in ITU-R BS.1770, so that
+//! its peaks are true peaks.
+
+use std::sync::Mutex;
+
+use gst::prelude::*;
+
+use crate::hints;
+
+/// Smoothing factors for 100ms level intervals, roughly 1s and 10s
+const SHORT_TERM_ALPHA: f64 = 0.1;
+const LONG_TERM_ALPHA: f64 = 0.01;
+/// Ignore jumps out of or into silence
+const SILENCE_DB: f64 = -60.0;
+
+pub const LEVEL_INTERVAL: u64 = 100_000_000;
+
+/// Sample rate the audio is measured at
+const TRUE_PEAK_RATE: i32 = 192_000;
+
+/// Name of the `level` element measuring the program audio
+const PROGRAM_LEVEL: &str = "program_level";
+
+#[derive(Default)]
+struct State {
+    short_term: Option<f64>,
+    long_term: Option<f64>,
+    peaking: bool,
+    jumping: bool,
+}
+
+pub struct LoudnessMonitor {
+    max_peak_db: f64,
+    max_jump_db: f64,
+    state: Mutex<State>,
+}
+
+pub fn max_of(s: &gst::StructureRef, field: &str) -> Option<f64> {
+    let values = s.get_some::<glib::ValueArray>(field).ok()?;
+
+    values
+        .iter()
+        .filter_map(|value| value.get_some::<f64>().ok())
+        .fold(None, |max: Option<f64>, value| {
+            Some(max.map_or(value, |max| max.max(value)))
+        })
+}
+
+fn smooth(previous: Option<f64>, value: f64, alpha: f64) -> f64 {
+    previous.map_or(value, |previous| previous + alpha * (value - previous))
+}
+
+/// Convert dBFS to a linear amplitude
+fn db_to_linear(db: f64) -> f64 {
+    10f64.powf(db / 20.0)
+}
+
+/// Hard limiter of the program audio at `max_peak_db` dBFS
+pub fn limiter(max_peak_db: f64) -> Result<gst::Element, anyhow::Error> {
+    let bin = hints::parse_bin(&format!(
+        "audioconvert ! audiodynamic mode=compressor characteristics=hard-knee ratio=0 \
+         threshold={}",
+        db_to_linear(max_peak_db)
+    ))?;
+
+    Ok(bin.upcast())
+}
+
+/// Sink measuring the program audio on a 4x oversampled copy
+pub fn meter() -> Result<gst::Element, anyhow::Error> {
+    let bin = hints::parse_bin(&format!(
+        "queue leaky=downstream ! audioconvert ! audioresample ! audio/x-raw,rate={} ! \
+         level name={} ! fakesink sync=false async=false",
+        TRUE_PEAK_RATE, PROGRAM_LEVEL
+    ))?;
+    bin.get_by_name(PROGRAM_LEVEL)
+        .unwrap()
+        .set_property("interval", &LEVEL_INTERVAL)?;
+
+    Ok(bin.upcast())
+}
+
+impl LoudnessMonitor {
+    pub fn new(max_peak_db: f64, max_jump_db: f64) -> Self {
+        Self {
+            max_peak_db,
+            max_jump_db,
+            state: Mutex::new(State::default()),
+        }
+    }
+
+    /// Handle a message posted by the `level` element of `meter()`, other
+    /// messages are ignored
+    pub fn handle_message(&self, msg: &gst::Message) {
+        let posted = msg
+            .get_src()
+            .map_or(false, |src| src.get_name() == PROGRAM_LEVEL);
+
+        let s = match msg.get_structure() {
+            Some(s) if posted && s.get_name() == "level" => s,
+            _ => return,
+        };
+
+        let (peak, rms) = match (max_of(s, "peak"), max_of(s, "rms")) {
+            (Some(peak), Some(rms)) => (peak, rms),
+            _ => return,
+        };
+
+        let mut state = self.state.lock().unwrap();
+
+        if peak > self.max_peak_db {
+            if !state.peaking {
+                crate::logging::event(
+                    crate::logging::Level::Warn,
+                    "audio-peak",
+                    "Audio true peak above limit",
+                    serde_json::json!({ "true_peak_db": peak, "limit_db": self.max_peak_db }),
+                );
+                state.peaking = true;
+            }
+        } else {
+            state.peaking = false;
+        }
+
+        let short_term = smooth(state.short_term, rms, SHORT_TERM_ALPHA);
+        let long_term = smooth(state.long_term, rms, LONG_TERM_ALPHA);
+        state.short_term = Some(short_term);
+        state.long_term = Some(long_term);
+
+        let jump = short_term - long_term;
+        if jump.abs() > self.max_jump_db && short_term > SILENCE_DB && long_term > SILENCE_DB {
+            if !state.jumping {
+                crate::logging::event(
+                    crate::logging::Level::Warn,
+                    "loudness-jump",
+                    "Audio loudness jumped",
+                    serde_json::json!({ "jump_db": jump, "limit_db": self.max_jump_db }),
+                );
+                state.jumping = true;
+            }
+        } else {
+            state.jumping = false;
+        }
+    }
+}
@@ -0,0 +1,268 @@
+//! Explicit state machine of the live source.
+//!
+//! The bus messages of the RTMP pipeline, live buffers reaching the
+//! compositor, the compositor timing out the live video and shutdown
+//! requests are all translated into events, and the state only changes
+//! through `transition`. Both bus watches and the signal handlers go through
+//! the same lock, so that e.g. an error racing with a drain can no longer
+//! schedule a reconnection once the output is being finalized.
+
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+
+use gst::prelude::*;
+
+use crate::logging::{self, Level};
+use crate::status::Status;
+
+/// How often the compositor is checked for having timed out the live video
+const CHECK_INTERVAL_MS: u32 = 100;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SourceState {
+    /// Waiting for the first live buffer since the source was started
+    Connecting,
+    Live,
+    /// The source is buffering, with the given percentage
+    Buffering(i32),
+    /// The source was live, but the compositor timed it out
+    OnSlate,
+    /// The source failed, a restart is scheduled
+    Reconnecting,
+    /// The output is being finalized, nothing changes anymore
+    Draining,
+}
+
+impl SourceState {
+    /// Name in structured log events and the control API
+    pub fn name(self) -> &'static str {
+        match self {
+            SourceState::Connecting => "connecting",
+            SourceState::Live => "live",
+            SourceState::Buffering(_) => "buffering",
+            SourceState::OnSlate => "on-slate",
+            SourceState::Reconnecting => "reconnecting",
+            SourceState::Draining => "draining",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Event {
+    /// The RTMP pipeline was (re)started
+    Started,
+    Buffering(i32),
+    /// A live buffer reached the compositor
+    LiveBuffer,
+    /// The compositor switched to the slate
+    LiveTimeout,
+    Error,
+    Eos,
+    /// The source is restarted without having failed itself, e.g. on decode
+    /// errors, latency or drift
+    Restart,
+    /// Shutdown was requested
+    Drain,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Transition {
+    pub from: SourceState,
+    pub to: SourceState,
+}
+
+/// State after `event` in `state`
+pub fn transition(state: SourceState, event: Event) -> SourceState {
+    use SourceState::*;
+
+    match (state, event) {
+        (Draining, _) | (_, Event::Drain) => Draining,
+        (_, Event::Error) | (_, Event::Eos) | (_, Event::Restart) => Reconnecting,
+        (_, Event::Started) => Connecting,
+        /* Buffers still queued from before the failure */
+        (Reconnecting, _) => Reconnecting,
+        (_, Event::Buffering(percent)) if percent < 100 => Buffering(percent),
+        (Buffering(_), Event::Buffering(_)) => Connecting,
+        (_, Event::LiveBuffer) => Live,
+        (Live, Event::LiveTimeout) => OnSlate,
+        (state, _) => state,
+    }
+}
+
+/// Event for a message posted by the RTMP source `source` or from within
+/// it, if it is relevant to the state
+pub fn event_for(source: &gst::Element, msg: &gst::Message) -> Option<Event> {
+    match msg.view() {
+        gst::MessageView::Error(..) => Some(Event::Error),
+        gst::MessageView::Eos(..) => Some(Event::Eos),
+        gst::MessageView::Buffering(buffering) => Some(Event::Buffering(buffering.get_percent())),
+        gst::MessageView::StateChanged(state_changed)
+            if state_changed.get_src().map_or(false, |src| &src == source)
+                && state_changed.get_old() == gst::State::Null
+                && state_changed.get_current() == gst::State::Ready =>
+        {
+            Some(Event::Started)
+        }
+        _ => None,
+    }
+}
+
+struct State {
+    state: SourceState,
+    since: Instant,
+}
+
+pub struct StateMachine {
+    state: Mutex<State>,
+}
+
+impl StateMachine {
+    pub fn new() -> Arc<Self> {
+        Arc::new(Self {
+            state: Mutex::new(State {
+                state: SourceState::Connecting,
+                since: Instant::now(),
+            }),
+        })
+    }
+
+    pub fn state(&self) -> SourceState {
+        self.state.lock().unwrap().state
+    }
+
+    /// Apply `event`, logging the change of state if any
+    pub fn handle(&self, event: Event) -> Transition {
+        let mut state = self.state.lock().unwrap();
+        let from = state.state;
+        let to = transition(from, event);
+
+        if from.name() != to.name() {
+            logging::event(
+                if to == SourceState::Reconnecting {
+                    Level::Warn
+                } else {
+                    Level::Debug
+                },
+                "state-changed",
+                &format!("Source {} after {:?}", to.name(), event),
+                serde_json::json!({
+                    "from": from.name(),
+                    "to": to.name(),
+                    "event": format!("{:?}", event),
+                    "after_ms": state.since.elapsed().as_millis() as u64,
+                }),
+            );
+            state.since = Instant::now();
+        }
+        state.state = to;
+
+        Transition { from, to }
+    }
+
+    /// Feed the live buffers flowing to the compositor through `pad`
+    pub fn watch_live(self: &Arc<Self>, pad: &gst::Pad) {
+        let machine = self.clone();
+        pad.add_probe(gst::PadProbeType::BUFFER, move |_, _| {
+            if machine.state() != SourceState::Live {
+                machine.handle(Event::LiveBuffer);
+            }
+            gst::PadProbeReturn::Ok
+        });
+    }
+
+    /// Feed the compositor timing out the live video, as tracked by `status`
+    pub fn start(self: &Arc<Self>, status: &Arc<Status>) {
+        let machine = self.clone();
+        let status = status.clone();
+        glib::timeout_add(CHECK_INTERVAL_MS, move || {
+            if machine.state() == SourceState::Live && !status.shows_live() {
+                machine.handle(Event::LiveTimeout);
+            }
+            glib::Continue(true)
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use SourceState::*;
+
+    const STATES: [SourceState; 6] = [
+        Connecting,
+        Live,
+        Buffering(50),
+        OnSlate,
+        Reconnecting,
+        Draining,
+    ];
+
+    const EVENTS: [Event; 9] = [
+        Event::Started,
+        Event::Buffering(50),
+        Event::Buffering(100),
+        Event::LiveBuffer,
+        Event::LiveTimeout,
+        Event::Error,
+        Event::Eos,
+        Event::Restart,
+        Event::Drain,
+    ];
+
+    #[test]
+    fn draining_absorbs_everything() {
+        for &state in &STATES {
+            assert_eq!(transition(state, Event::Drain), Draining, "{:?}", state);
+        }
+
+        for &event in &EVENTS {
+            assert_eq!(transition(Draining, event), Draining, "{:?}", event);
+        }
+    }
+
+    #[test]
+    fn failures_reconnect() {
+        for &state in STATES.iter().filter(|&&state| state != Draining) {
+            for &event in &[Event::Error, Event::Eos, Event::Restart] {
+                assert_eq!(
+                    transition(state, event),
+                    Reconnecting,
+                    "{:?} in {:?}",
+                    event,
+                    state
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn transitions() {
+        let cases = [
+            (Connecting, Event::LiveBuffer, Live),
+            (Connecting, Event::Buffering(30), Buffering(30)),
+            (Connecting, Event::LiveTimeout, Connecting),
+            (Buffering(30), Event::Buffering(60), Buffering(60)),
+            (Buffering(60), Event::Buffering(100), Connecting),
+            (Buffering(60), Event::LiveBuffer, Live),
+            (Live, Event::Buffering(100), Live),
+            (Live, Event::LiveTimeout, OnSlate),
+            (OnSlate, Event::LiveTimeout, OnSlate),
+            (OnSlate, Event::LiveBuffer, Live),
+            (Reconnecting, Event::LiveBuffer, Reconnecting),
+            (Reconnecting, Event::Buffering(50), Reconnecting),
+            (Reconnecting, Event::LiveTimeout, Reconnecting),
+            (Reconnecting, Event::Started, Connecting),
+            (Live, Event::Started, Connecting),
+        ];
+
+        for &(state, event, expected) in &cases {
+            assert_eq!(
+                transition(state, event),
+                expected,
+                "{:?} in {:?}",
+                event,
+                state
+            );
+        }
+    }
+}
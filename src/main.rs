@@ -1,183 +1,128 @@
-use std::io::Write;
-
-use gst::prelude::*;
-use structopt::StructOpt;
-
-#[derive(Debug, StructOpt)]
-struct Args {
-    #[structopt(long)]
-    live_rtmp_uri: String,
-    #[structopt(long, help = "Make RTMP pipeline EOS after N buffers")]
-    eos_after: Option<i32>,
-    #[structopt(long, help = "Make RTMP pipeline error after N buffers")]
-    error_after: Option<i32>,
-    #[structopt(long, help = "Make compositor discard RTMP buffers after N seconds")]
-    discard_after: Option<u64>,
-}
-
-fn default_handle_message(pipe: &gst::Pipeline, msg: &gst::Message) {
-    match msg.view() {
-        gst::MessageView::Latency(..) => {
-            println!("Recalculating latency!");
-            pipe.recalculate_latency().unwrap();
-        }
-        gst::MessageView::StateChanged(state_changed) => {
-            if state_changed.get_src().map(|s| &s == pipe).unwrap_or(false)
-                && state_changed.get_current() == gst::State::Playing
-            {
-                pipe.debug_to_dot_file(
-                    gst::DebugGraphDetails::all(),
-                    format!("PLAYING-{}", pipe.get_name()),
-                );
-            }
+use clap::Parser;
+
+use rtmp_slate_fallback::cli::{self, Args, CompletionsArgs, ProbeArgs, SetupArgs, SuperviseArgs};
+use rtmp_slate_fallback::{
+    config, daemon, error, logging, probe, setup, supervisor, warn, ExitCode, FallbackPipeline,
+};
+
+/// Set up logging, daemonize and write the PID file as requested
+fn setup_process(args: &mut Args) -> Result<(), anyhow::Error> {
+    if args.daemon {
+        args.pid_file = args.pid_file.as_deref().map(daemon::absolute).transpose()?;
+        args.log_file = args.log_file.as_deref().map(daemon::absolute).transpose()?;
+        args.state_file = args.state_file.as_deref().map(daemon::absolute).transpose()?;
+        args.record_dir = args.record_dir.as_deref().map(daemon::absolute).transpose()?;
+        args.dot_dir = args.dot_dir.as_deref().map(daemon::absolute).transpose()?;
+        args.subtitle_file = args.subtitle_file.as_deref().map(daemon::absolute).transpose()?;
+        args.script = args.script.as_deref().map(daemon::absolute).transpose()?;
+
+        if args.log_file.is_none() {
+            warn!("Running as a daemon without --log-file, logs will be discarded");
         }
-        _ => (),
-    }
-}
-
-fn build_rtmp_pipeline(args: &Args) -> Result<gst::Pipeline, anyhow::Error> {
-    let playbin = gst::ElementFactory::make("playbin3", Some("rtmp_source"))?;
-    let vsink = gst::parse_bin_from_description(
-        "identity name=id ! interpipesink drop=false sync=true name=rtmp",
-        true,
-    )?;
-    let asink = gst::ElementFactory::make("fakesink", None)?;
-
-    let identity = vsink.get_by_name("id").unwrap();
 
-    if let Some(eos_after) = args.eos_after {
-        identity.set_property("eos-after", &eos_after)?;
+        daemon::daemonize(args.pid_file.as_deref())?;
+    } else if let Some(ref pid_file) = args.pid_file {
+        std::fs::write(pid_file, format!("{}\n", std::process::id()))?;
     }
 
-    if let Some(error_after) = args.error_after {
-        identity.set_property("error-after", &error_after)?;
+    if let Some(ref log_file) = args.log_file {
+        logging::log_to_file(log_file, args.log_file_size, args.log_file_count)?;
     }
 
-    playbin.set_property("uri", &args.live_rtmp_uri)?;
-    playbin.set_property("video-sink", &vsink)?;
-    playbin.set_property("audio-sink", &asink)?;
-
-    let pipe = playbin.downcast::<gst::Pipeline>().unwrap();
-    let bus = pipe.get_bus().unwrap();
-    let pipe_clone = pipe.clone();
-    let uri = args.live_rtmp_uri.clone();
-
-    bus.add_watch(move |_, msg| {
-        let pipe = &pipe_clone;
-        match msg.view() {
-            gst::MessageView::Error(err) => {
-                /* Naive throttling */
-                std::thread::sleep(std::time::Duration::from_millis(1000));
-                eprintln!("Error: {:?}, restarting pipeline", err);
-                restart_pipeline(uri.clone(), pipe);
-            }
-            gst::MessageView::Buffering(buffering) => {
-                let percent = buffering.get_percent();
-                print!("Buffering ({}%)\r", percent);
-                match std::io::stdout().flush() {
-                    Ok(_) => {}
-                    Err(err) => eprintln!("Failed: {}", err),
-                };
-
-                if percent < 100 {
-                    let _ = pipe.set_state(gst::State::Paused);
-                } else {
-                    let _ = pipe.set_state(gst::State::Playing);
-                }
-            }
-            gst::MessageView::Eos(_) => {
-                eprintln!("We are EOS");
-                restart_pipeline(uri.clone(), pipe);
-            }
-            _ => default_handle_message(pipe, msg),
-        };
-        glib::Continue(true)
-    })?;
-
-    Ok(pipe)
+    Ok(())
 }
 
-fn build_compositor_pipeline(args: &Args) -> Result<gst::Pipeline, anyhow::Error> {
-    let pipe = gst::Pipeline::new(Some("video_mixer"));
+fn parse_args<T: Parser>(iter: impl Iterator<Item = std::ffi::OsString>) -> T {
+    T::try_parse_from(iter).unwrap_or_else(|err| {
+        if err.use_stderr() {
+            let _ = err.print();
+            ExitCode::Config.exit();
+        }
+        err.exit()
+    })
+}
 
-    let interpipesrc = gst::ElementFactory::make("interpipesrc", None)?;
-    let queue = gst::ElementFactory::make("queue", None)?;
-    let compositor = gst::ElementFactory::make("compositor", None)?;
-    let sink = gst::ElementFactory::make("xvimagesink", None)?;
+fn main() {
+    logging::install();
 
-    pipe.add_many(&[&interpipesrc, &queue, &compositor, &sink])?;
+    if std::env::args().nth(1).as_deref() == Some("completions") {
+        let args: CompletionsArgs = parse_args(std::env::args_os().skip(1));
+        cli::print_completions(args.shell);
+        ExitCode::Success.exit();
+    }
 
-    gst::Element::link_many(&[&interpipesrc, &queue, &compositor, &sink])?;
+    if std::env::args().nth(1).as_deref() == Some("setup") {
+        let args: SetupArgs = parse_args(std::env::args_os().skip(1));
 
-    let pad = compositor.get_static_pad("sink_0").unwrap();
-    pad.set_property("zorder", &(1 as u32))?;
-    pad.set_property("width", &1280)?;
-    pad.set_property("height", &720)?;
+        if let Err(err) = gst::init()
+            .map_err(anyhow::Error::from)
+            .and_then(|_| setup::run(&args.output))
+        {
+            error!("{:?}", err);
+            ExitCode::for_error(&err).exit();
+        }
 
-    if let Some(discard_after) = args.discard_after {
-        pad.set_property("max-last-buffer-repeat", &(discard_after * gst::SECOND))?;
+        ExitCode::Success.exit();
     }
 
-    interpipesrc.set_property("listen-to", &"rtmp")?;
-    interpipesrc.set_property("format", &gst::Format::Time)?;
-    interpipesrc.set_property("is-live", &true)?;
-    interpipesrc.set_property_from_str("stream-sync", &"restart-ts");
-
-    // FIXME: interpipesink should translate QoS events when stream-sync = compensate-ts
-    sink.set_property("qos", &false).unwrap();
-
-    let fallbacksrc = gst::ElementFactory::make("videotestsrc", None)?;
-    let queue = gst::ElementFactory::make("queue", None)?;
-    let capsfilter = gst::ElementFactory::make("capsfilter", None)?;
-
-    fallbacksrc.set_property("is-live", &true)?;
-    capsfilter.set_property(
-        "caps",
-        &gst::Caps::new_simple("video/x-raw", &[("width", &800), ("height", &448)]),
-    )?;
-
-    pipe.add_many(&[&fallbacksrc, &queue, &capsfilter])?;
-    gst::Element::link_many(&[&fallbacksrc, &queue, &capsfilter, &compositor])?;
-
-    let pad = compositor.get_static_pad("sink_1").unwrap();
-    pad.set_property("zorder", &(0 as u32))?;
-    pad.set_property("width", &1280)?;
-    pad.set_property("height", &720)?;
-
-    let bus = pipe.get_bus().unwrap();
-    let pipe_clone = pipe.clone();
-    bus.add_watch(move |_, msg| {
-        let pipe = &pipe_clone;
-        default_handle_message(pipe, msg);
-        glib::Continue(true)
-    })?;
-
-    Ok(pipe)
-}
+    if std::env::args().nth(1).as_deref() == Some("supervise") {
+        let args: SuperviseArgs = parse_args(std::env::args_os().skip(1));
 
-fn restart_pipeline(uri: String, pipe: &gst::Pipeline) {
-    pipe.set_state(gst::State::Null).unwrap();
-    pipe.set_property("uri", &uri).unwrap();
-    pipe.set_state(gst::State::Playing).unwrap();
-}
+        match supervisor::run(&args.channels, args.restart_delay) {
+            Ok(exit_code) => exit_code.exit(),
+            Err(err) => {
+                error!("{:?}", err);
+                ExitCode::Config.exit();
+            }
+        }
+    }
 
-fn main() -> Result<(), anyhow::Error> {
-    gst::init()?;
+    if std::env::args().nth(1).as_deref() == Some("probe") {
+        let args: ProbeArgs = parse_args(std::env::args_os().skip(1));
+
+        let exit_code = match gst::init()
+            .map_err(anyhow::Error::from)
+            .and_then(|_| probe::run(&args.uri, args.timeout))
+        {
+            Ok(()) => ExitCode::Success,
+            Err(err) => {
+                error!("{:?}", err);
+                ExitCode::SourceFailed
+            }
+        };
 
-    let args = Args::from_args();
+        exit_code.exit();
+    }
 
-    let rtmp_pipe = build_rtmp_pipeline(&args)?;
-    let compositor_pipe = build_compositor_pipeline(&args)?;
+    let argv = config::expand_args(std::env::args_os().collect()).unwrap_or_else(|err| {
+        error!("{:?}", err);
+        ExitCode::Config.exit();
+    });
+    let mut args: Args = parse_args(argv.into_iter());
 
-    rtmp_pipe.set_state(gst::State::Playing)?;
-    compositor_pipe.set_state(gst::State::Playing)?;
+    logging::init(args.log_format, args.log_level);
 
-    let main_loop = glib::MainLoop::new(None, false);
+    if let Err(err) = setup_process(&mut args) {
+        error!("Failed to set up process: {:?}", err);
+        ExitCode::Setup.exit();
+    }
 
-    main_loop.run();
+    let pid_file = args.pid_file.clone();
 
-    rtmp_pipe.set_state(gst::State::Null)?;
-    compositor_pipe.set_state(gst::State::Null)?;
+    let exit_code = match gst::init()
+        .map_err(anyhow::Error::from)
+        .and_then(|_| FallbackPipeline::from_args(args).run())
+    {
+        Ok(exit_code) => exit_code,
+        Err(err) => {
+            error!("Failed to set up pipelines: {:?}", err);
+            ExitCode::for_error(&err)
+        }
+    };
 
-    Ok(())
+    if let Some(ref pid_file) = pid_file {
+        daemon::remove_pid_file(pid_file);
+    }
+
+    exit_code.exit();
 }
@@ -0,0 +1,53 @@
+//! Audio meters rendered onto the output, so that silent or broken audio is
+//! visible at a glance on monitoring walls.
+//!
+//! The live audio is rendered to video in the RTMP pipeline and crosses over
+//! to the compositor on its own interpipe, where it is overlaid in the bottom
+//! left corner. The meters disappear when the live audio stops.
+
+use gst::prelude::*;
+
+use crate::hints;
+
+const WIDTH: i32 = 320;
+const HEIGHT: i32 = 120;
+const MARGIN: i32 = 20;
+
+/// Launch description of the branch rendering the meters, to link from a
+/// tee on the live audio, `prefix` going in front of the interpipesink
+pub fn branch(prefix: &str) -> String {
+    format!(
+        "queue ! wavescope style=color-lines ! video/x-raw,width={},height={} ! \
+         {}interpipesink drop=false sync=true name=meters",
+        WIDTH, HEIGHT, prefix
+    )
+}
+
+/// Overlay the meters on the output of `compositor`, which is `width`x`height`
+pub fn install(
+    pipe: &gst::Pipeline,
+    compositor: &gst::Element,
+    width: i32,
+    height: i32,
+) -> Result<(), anyhow::Error> {
+    let interpipesrc = hints::make_element("interpipesrc", None)?;
+    let queue = hints::make_element("queue", None)?;
+
+    interpipesrc.set_property("listen-to", &"meters")?;
+    interpipesrc.set_property("format", &gst::Format::Time)?;
+    interpipesrc.set_property("is-live", &true)?;
+    interpipesrc.set_property_from_str("stream-sync", &"restart-ts");
+
+    pipe.add_many(&[&interpipesrc, &queue])?;
+    gst::Element::link_many(&[&interpipesrc, &queue, compositor])?;
+
+    let pad = queue.get_static_pad("src").unwrap().get_peer().unwrap();
+    pad.set_property("zorder", &(2 as u32))?;
+    pad.set_property("xpos", &MARGIN)?;
+    pad.set_property("ypos", &(height - HEIGHT - MARGIN))?;
+    pad.set_property("width", &WIDTH.min(width))?;
+    pad.set_property("height", &HEIGHT)?;
+    pad.set_property("max-last-buffer-repeat", &gst::ClockTime::from_seconds(1))?;
+
+    Ok(())
+}
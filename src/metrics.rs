@@ -0,0 +1,310 @@
+//! Prometheus metrics.
+//!
+//! Served on `/metrics` in the text exposition format, so that operators can
+//! alert on channels stuck on the slate. Time on air is sampled from the
+//! status, dropped frames are counted from QoS messages and from the live
+//! gate, and latencies are queried from the pipelines on every scrape, along
+//! with the end-to-end latency and the live video quality as last measured.
+
+use std::collections::HashMap;
+use std::fmt::Write;
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+
+use gst::prelude::*;
+
+use crate::gate::LiveGate;
+use crate::input_stats::{self, InputStats};
+use crate::latency::LatencyMonitor;
+use crate::quality::QualityMonitor;
+use crate::status::Status;
+
+/// How often the time on air is sampled
+const INTERVAL_MS: u32 = 100;
+
+#[derive(Default)]
+struct OnAirTime {
+    live: f64,
+    slate: f64,
+}
+
+pub struct Metrics {
+    status: Arc<Status>,
+    input_stats: Arc<InputStats>,
+    latency: Arc<LatencyMonitor>,
+    quality: Arc<QualityMonitor>,
+    gate: Arc<LiveGate>,
+    on_air: Mutex<OnAirTime>,
+    /// Frames dropped as reported by the latest QoS message of each element
+    qos_dropped: Mutex<HashMap<String, u64>>,
+}
+
+fn latency(pipe: &gst::Pipeline) -> Option<f64> {
+    let mut query = gst::query::Latency::new();
+
+    if pipe.query(&mut query) {
+        let (_, min, _) = query.get_result();
+        min.nseconds().map(|ns| ns as f64 / 1_000_000_000.0)
+    } else {
+        None
+    }
+}
+
+/// Write metric `slate_fallback_<name>` with one value per label set
+fn write_metric(out: &mut String, name: &str, kind: &str, help: &str, samples: &[(&str, f64)]) {
+    let _ = writeln!(out, "# HELP slate_fallback_{} {}", name, help);
+    let _ = writeln!(out, "# TYPE slate_fallback_{} {}", name, kind);
+
+    write_samples(out, name, samples);
+}
+
+/// Write the samples of `slate_fallback_<name>`, without its header, as for
+/// the `_sum` and `_count` of a summary
+fn write_samples(out: &mut String, name: &str, samples: &[(&str, f64)]) {
+    for (labels, value) in samples {
+        if labels.is_empty() {
+            let _ = writeln!(out, "slate_fallback_{} {}", name, value);
+        } else {
+            let _ = writeln!(out, "slate_fallback_{}{{{}}} {}", name, labels, value);
+        }
+    }
+}
+
+impl Metrics {
+    pub fn new(
+        status: &Arc<Status>,
+        input_stats: &Arc<InputStats>,
+        latency: &Arc<LatencyMonitor>,
+        quality: &Arc<QualityMonitor>,
+        gate: &Arc<LiveGate>,
+    ) -> Arc<Self> {
+        Arc::new(Self {
+            status: status.clone(),
+            input_stats: input_stats.clone(),
+            latency: latency.clone(),
+            quality: quality.clone(),
+            gate: gate.clone(),
+            on_air: Mutex::new(OnAirTime::default()),
+            qos_dropped: Mutex::new(HashMap::new()),
+        })
+    }
+
+    /// Count the frames dropped according to a QoS message
+    pub fn handle_qos(&self, msg: &gst::Message) {
+        if let gst::MessageView::Qos(qos) = msg.view() {
+            let (_, dropped) = qos.get_stats();
+            let source = msg
+                .get_src()
+                .map(|src| src.get_path_string().to_string())
+                .unwrap_or_default();
+
+            if dropped.get_value() >= 0 {
+                self.qos_dropped
+                    .lock()
+                    .unwrap()
+                    .insert(source, dropped.get_value() as u64);
+            }
+        }
+    }
+
+    /// Sample the time on air from the default main context
+    pub fn start(self: &Arc<Self>) {
+        let metrics = self.clone();
+        let mut last = Instant::now();
+        glib::timeout_add(INTERVAL_MS, move || {
+            let elapsed = last.elapsed().as_secs_f64();
+            last = Instant::now();
+
+            let mut on_air = metrics.on_air.lock().unwrap();
+            if metrics.status.shows_live() {
+                on_air.live += elapsed;
+            } else {
+                on_air.slate += elapsed;
+            }
+
+            glib::Continue(true)
+        });
+    }
+
+    fn render(&self, pipes: &[gst::Pipeline]) -> String {
+        let mut out = String::new();
+        let on_air = self.on_air.lock().unwrap();
+        let qos_dropped = self.qos_dropped.lock().unwrap().values().sum::<u64>();
+
+        write_metric(
+            &mut out,
+            "live",
+            "gauge",
+            "Whether the live video is on air",
+            &[("", self.status.shows_live() as u8 as f64)],
+        );
+        write_metric(
+            &mut out,
+            "on_air_seconds_total",
+            "counter",
+            "Time spent on air",
+            &[
+                ("source=\"live\"", on_air.live),
+                ("source=\"slate\"", on_air.slate),
+            ],
+        );
+        write_metric(
+            &mut out,
+            "buffering_percent",
+            "gauge",
+            "Buffering level of the source",
+            &[("", self.status.buffering().unwrap_or(100) as f64)],
+        );
+        write_metric(
+            &mut out,
+            "reconnects_total",
+            "counter",
+            "Restarts of the source pipeline",
+            &[("", crate::restarts() as f64)],
+        );
+        write_metric(
+            &mut out,
+            "input_bitrate_bps",
+            "gauge",
+            "Bitrate of the source",
+            &[("", self.input_stats.bitrate() as f64)],
+        );
+        write_metric(
+            &mut out,
+            "input_fps",
+            "gauge",
+            "Framerate of the source measured over the last second",
+            &[("", self.input_stats.fps().unwrap_or(0.0))],
+        );
+
+        let gaps = self.input_stats.gaps().map(|gaps| {
+            input_stats::QUANTILES
+                .iter()
+                .zip(gaps.quantiles.iter())
+                .map(|((quantile, _), gap)| (format!("quantile=\"{}\"", quantile), *gap))
+                .chain(std::iter::once(("quantile=\"1\"".to_string(), gaps.max)))
+                .collect::<Vec<_>>()
+        });
+        let (gap_sum, gap_count) = self.input_stats.gap_totals();
+        write_metric(
+            &mut out,
+            "input_gap_seconds",
+            "summary",
+            "Gaps between the video buffers of the source, quantiles over the bitrate window",
+            &gaps
+                .iter()
+                .flatten()
+                .map(|(labels, gap)| (labels.as_str(), gap.as_secs_f64()))
+                .collect::<Vec<_>>(),
+        );
+        write_samples(&mut out, "input_gap_seconds_sum", &[("", gap_sum.as_secs_f64())]);
+        write_samples(&mut out, "input_gap_seconds_count", &[("", gap_count as f64)]);
+
+        if let Some(quality) = self.quality.last() {
+            write_metric(
+                &mut out,
+                "video_mean_luma",
+                "gauge",
+                "Average brightness of the live video, from 0 to 255",
+                &[("", quality.mean_luma)],
+            );
+            write_metric(
+                &mut out,
+                "video_black_ratio",
+                "gauge",
+                "Proportion of black pixels in the live video",
+                &[("", quality.black_ratio)],
+            );
+            write_metric(
+                &mut out,
+                "video_sharpness",
+                "gauge",
+                "Mean horizontal gradient of the live video",
+                &[("", quality.sharpness)],
+            );
+            write_metric(
+                &mut out,
+                "video_blockiness",
+                "gauge",
+                "Gradient across 8 pixel block boundaries relative to inside blocks",
+                &[("", quality.blockiness)],
+            );
+        }
+
+        write_metric(
+            &mut out,
+            "dropped_frames_total",
+            "counter",
+            "Frames dropped by sinks and by the fallback triggers",
+            &[
+                ("reason=\"qos\"", qos_dropped as f64),
+                ("reason=\"fallback\"", self.gate.dropped() as f64),
+            ],
+        );
+
+        let latencies = pipes
+            .iter()
+            .filter_map(|pipe| {
+                latency(pipe).map(|latency| (format!("pipeline=\"{}\"", pipe.get_name()), latency))
+            })
+            .collect::<Vec<_>>();
+        write_metric(
+            &mut out,
+            "latency_seconds",
+            "gauge",
+            "Minimum latency of each pipeline",
+            &latencies
+                .iter()
+                .map(|(labels, latency)| (labels.as_str(), *latency))
+                .collect::<Vec<_>>(),
+        );
+        write_metric(
+            &mut out,
+            "end_to_end_latency_seconds",
+            "gauge",
+            "Latency from the live input to the output",
+            &self
+                .latency
+                .latency()
+                .iter()
+                .map(|latency| ("", latency.as_secs_f64()))
+                .collect::<Vec<_>>(),
+        );
+
+        out
+    }
+
+    /// Serve `/metrics` on `port` from a dedicated thread, with the
+    /// latencies of `pipes`
+    pub fn serve(
+        self: &Arc<Self>,
+        port: u16,
+        pipes: Vec<gst::Pipeline>,
+    ) -> Result<(), anyhow::Error> {
+        let server = tiny_http::Server::http(("0.0.0.0", port))
+            .map_err(|err| anyhow::anyhow!("Failed to listen on port {}: {}", port, err))?;
+        let metrics = self.clone();
+
+        info!("Serving metrics on port {}", port);
+
+        std::thread::spawn(move || {
+            for request in server.incoming_requests() {
+                let response = match request.url() {
+                    "/metrics" => tiny_http::Response::from_string(metrics.render(&pipes))
+                        .with_header(
+                            "Content-Type: text/plain; version=0.0.4"
+                                .parse::<tiny_http::Header>()
+                                .unwrap(),
+                        ),
+                    _ => tiny_http::Response::from_string("Not Found").with_status_code(404),
+                };
+
+                if let Err(err) = request.respond(response) {
+                    warn!("Failed to respond to metrics scrape: {}", err);
+                }
+            }
+        });
+
+        Ok(())
+    }
+}
@@ -0,0 +1,102 @@
+//! Network-disciplined pipeline clock.
+//!
+//! Instances slaved to the same NTP server or PTP domain share a timebase.
+//! Both pipelines use base time 0 with such a clock, so running times are
+//! the clock's absolute times and outputs line up across instances.
+
+use std::time::Duration;
+
+use anyhow::{anyhow, bail};
+use gst::prelude::*;
+
+/// How long to wait for the clock to synchronize before giving up
+const SYNC_TIMEOUT: Duration = Duration::from_secs(10);
+
+const DEFAULT_NTP_PORT: i32 = 123;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum PipelineClock {
+    System,
+    Ntp { host: String, port: i32 },
+    Ptp { domain: u32 },
+}
+
+impl std::str::FromStr for PipelineClock {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (kind, arg) = match s.find(':') {
+            Some(idx) => (&s[..idx], Some(&s[idx + 1..])),
+            None => (s, None),
+        };
+
+        match (kind, arg) {
+            ("system", None) => Ok(PipelineClock::System),
+            ("ntp", Some(server)) => {
+                let (host, port) = match server.rfind(':') {
+                    Some(idx) => (&server[..idx], server[idx + 1..].parse()?),
+                    None => (server, DEFAULT_NTP_PORT),
+                };
+
+                Ok(PipelineClock::Ntp {
+                    host: host.to_string(),
+                    port,
+                })
+            }
+            ("ptp", domain) => Ok(PipelineClock::Ptp {
+                domain: domain.map_or(Ok(0), str::parse)?,
+            }),
+            _ => Err(anyhow!(
+                "Unknown clock {}, expected system, ntp:HOST[:PORT] or ptp[:DOMAIN]",
+                s
+            )),
+        }
+    }
+}
+
+impl PipelineClock {
+    /// Create the clock and wait for it to synchronize, `None` for the
+    /// system clock
+    fn obtain(&self) -> Result<Option<gst::Clock>, anyhow::Error> {
+        let clock = match self {
+            PipelineClock::System => return Ok(None),
+            PipelineClock::Ntp { host, port } => {
+                info!("Synchronizing to NTP server {}:{}", host, port);
+                gst_net::NtpClock::new(None, host, *port, gst::ClockTime::from_seconds(0))
+                    .upcast::<gst::Clock>()
+            }
+            PipelineClock::Ptp { domain } => {
+                info!("Synchronizing to PTP domain {}", domain);
+                gst_net::PtpClock::init(None, &[])?;
+                gst_net::PtpClock::new(None, *domain).upcast::<gst::Clock>()
+            }
+        };
+
+        if clock
+            .wait_for_sync(crate::clock_time(SYNC_TIMEOUT))
+            .is_err()
+        {
+            bail!("Clock did not synchronize within {:?}", SYNC_TIMEOUT);
+        }
+
+        info!("Clock synchronized");
+
+        Ok(Some(clock))
+    }
+
+    /// Make `pipes` run on this clock
+    pub fn apply(&self, pipes: &[&gst::Pipeline]) -> Result<(), anyhow::Error> {
+        let clock = match self.obtain()? {
+            Some(clock) => clock,
+            None => return Ok(()),
+        };
+
+        for pipe in pipes {
+            pipe.use_clock(Some(&clock));
+            pipe.set_start_time(gst::CLOCK_TIME_NONE);
+            pipe.set_base_time(gst::ClockTime::from_seconds(0));
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,439 @@
+//! Outputs of the program: re-streaming to an RTMP, SRT, UDP or RTP
+//! destination, HLS, or raw sinks such as the preview.
+//!
+//! Video is encoded with x264 and audio with AAC, then muxed to FLV for RTMP
+//! or to MPEG-TS otherwise and pushed to the destination URI. MPEG-TS over
+//! UDP or RTP is sent with `udpsink`, to unicast or multicast addresses
+//! alike. For HLS, `hlssink2` muxes the segments itself and writes them along
+//! with the playlist to a directory, for a web server to serve. Archiving
+//! to files is an output as well, and so is the WebRTC preview. The
+//! passthrough output carries the live H.264 as is instead, spliced with the
+//! encoded program.
+//!
+//! Several outputs can run at once, each in its own bin fed from the output
+//! tees.
+
+use std::path::PathBuf;
+use std::time::Duration;
+
+use gst::prelude::*;
+
+use crate::archive::{self, ArchiveOptions};
+use crate::encoder::Encoder;
+use crate::hints;
+use crate::splice::Splicer;
+
+#[derive(Debug, Clone)]
+pub struct HlsOptions {
+    pub dir: PathBuf,
+    pub segment_duration: Duration,
+    /// Segments listed in the playlist, 0 for all of them
+    pub playlist_length: u32,
+}
+
+/// How the outputs encode the program
+#[derive(Debug, Clone)]
+pub struct Encoding {
+    pub encoder: Encoder,
+    /// Encoder element factory replacing the one of `encoder`
+    pub element: Option<String>,
+    /// Video converter element factory in front of `element`
+    pub converter: String,
+    /// In kbit/s
+    pub video_bitrate: u32,
+    /// In kbit/s
+    pub audio_bitrate: u32,
+    /// Worker threads of a software video encoder
+    pub threads: u32,
+}
+
+impl Encoding {
+    /// Conversion and encoding of raw video to H.264, as a gst-launch
+    /// description
+    pub fn video_chain(&self) -> String {
+        match self.element {
+            Some(ref element) => format!("{} ! {}", self.converter, element),
+            None => self.encoder.video_chain(self.video_bitrate, self.threads),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct TsOptions {
+    pub program_number: u16,
+    pub pmt_pid: u16,
+    pub video_pid: u16,
+    pub audio_pid: u16,
+    /// Constant mux rate in kbit/s, padding with null packets
+    pub mux_bitrate: Option<u32>,
+    /// PID of the SCTE-35 splice sections marking the slate periods
+    pub scte35_pid: Option<u16>,
+}
+
+/// Where the encoded audio and video go, `mux` is unset when the sink muxes
+/// itself
+struct MuxSink {
+    mux: Option<gst::Element>,
+    sink: gst::Element,
+    video_pad: String,
+    audio_pad: String,
+}
+
+/// HLS sink writing to the directory of `options`
+fn build_hls_sink(options: &HlsOptions) -> Result<MuxSink, anyhow::Error> {
+    std::fs::create_dir_all(&options.dir)?;
+
+    let sink = hints::make_element("hlssink2", Some("output"))?;
+    sink.set_property(
+        "location",
+        &options
+            .dir
+            .join("segment%05d.ts")
+            .to_string_lossy()
+            .as_ref(),
+    )?;
+    sink.set_property(
+        "playlist-location",
+        &options.dir.join("playlist.m3u8").to_string_lossy().as_ref(),
+    )?;
+    sink.set_property(
+        "target-duration",
+        &(options.segment_duration.as_secs().max(1) as u32),
+    )?;
+    sink.set_property("playlist-length", &options.playlist_length)?;
+    /* Keep the segments still listed around for slow clients */
+    sink.set_property("max-files", &(options.playlist_length * 2))?;
+
+    Ok(MuxSink {
+        mux: None,
+        sink,
+        video_pad: "video".to_string(),
+        audio_pad: "audio".to_string(),
+    })
+}
+
+/// MPEG-TS muxer, its sink pads are named after the PIDs they get
+fn build_ts_mux(ts: &TsOptions) -> Result<(gst::Element, String, String), anyhow::Error> {
+    let mux = hints::make_element("mpegtsmux", Some("output_mux"))?;
+    /* 7 packets fill a UDP datagram of 1316 bytes */
+    mux.set_property("alignment", &7)?;
+
+    if let Some(mux_bitrate) = ts.mux_bitrate {
+        mux.set_property("bitrate", &(mux_bitrate as u64 * 1000))?;
+    }
+
+    if let Some(scte35_pid) = ts.scte35_pid {
+        mux.set_property("scte-35-pid", &(scte35_pid as u32))?;
+    }
+
+    let video_pad = format!("sink_{}", ts.video_pid);
+    let audio_pad = format!("sink_{}", ts.audio_pid);
+    let prog_map = gst::Structure::builder("program_map")
+        .field(&video_pad, &(ts.program_number as i32))
+        .field(&audio_pad, &(ts.program_number as i32))
+        .field(&format!("PMT_{}", ts.program_number), &(ts.pmt_pid as i32))
+        .build();
+    mux.set_property("prog-map", &prog_map)?;
+
+    Ok((mux, video_pad, audio_pad))
+}
+
+/// Host and port of a udp:// or rtp:// URI
+fn host_port(uri: &str) -> Result<(String, i32), anyhow::Error> {
+    let address = uri.splitn(2, "://").nth(1).unwrap_or_default();
+    let address = address.split(&['/', '?'][..]).next().unwrap_or_default();
+
+    match address.rfind(':') {
+        Some(colon) => Ok((
+            address[..colon].trim_matches(&['[', ']'][..]).to_string(),
+            address[colon + 1..]
+                .parse()
+                .map_err(|_| anyhow::anyhow!("Invalid port in output URI {}", uri))?,
+        )),
+        None => anyhow::bail!("Output URI {} has no port", uri),
+    }
+}
+
+/// Muxer and sink for the scheme of `uri`
+fn build_mux_sink(uri: &str, ts: &TsOptions) -> Result<MuxSink, anyhow::Error> {
+    let scheme = uri.split(':').next().unwrap_or_default();
+
+    if scheme == "rtmp" || scheme == "rtmps" {
+        let mux = hints::make_element("flvmux", Some("output_mux"))?;
+        mux.set_property("streamable", &true)?;
+        let sink = hints::make_element("rtmp2sink", Some("output"))?;
+        sink.set_property("location", &uri)?;
+
+        return Ok(MuxSink {
+            mux: Some(mux),
+            sink,
+            video_pad: "video".to_string(),
+            audio_pad: "audio".to_string(),
+        });
+    }
+
+    let sink = match scheme {
+        "srt" => {
+            let sink = hints::make_element("srtsink", Some("output"))?;
+            sink.set_property("uri", &uri)?;
+            sink
+        }
+        "udp" | "rtp" => {
+            let (host, port) = host_port(uri)?;
+            let bin = hints::parse_bin(&format!(
+                "{}udpsink name=udp",
+                if scheme == "rtp" { "rtpmp2tpay ! " } else { "" }
+            ))?;
+            let udpsink = bin.get_by_name("udp").unwrap();
+            udpsink.set_property("host", &host)?;
+            udpsink.set_property("port", &port)?;
+            bin.set_property("name", &"output")?;
+            bin.upcast()
+        }
+        _ => anyhow::bail!(
+            "Unsupported output URI {}, expected rtmp://, srt://, udp:// or rtp://",
+            uri
+        ),
+    };
+
+    let (mux, video_pad, audio_pad) = build_ts_mux(ts)?;
+
+    Ok(MuxSink {
+        mux: Some(mux),
+        sink,
+        video_pad,
+        audio_pad,
+    })
+}
+
+/// One output of the program, a bin with a `video` and an `audio` sink pad
+/// fed from the output tees, so that it can be dropped on its own when it
+/// fails
+pub struct Output {
+    bin: gst::Bin,
+}
+
+impl Output {
+    /// Encoders, muxer and sink pushing to `uri`, MPEG-TS muxed as per `ts`
+    pub fn new(
+        name: &str,
+        uri: &str,
+        ts: &TsOptions,
+        encoding: &Encoding,
+    ) -> Result<Self, anyhow::Error> {
+        Self::encoded(name, build_mux_sink(uri, ts)?, encoding)
+    }
+
+    /// Muxer and sink pushing the H.264 spliced by `splicer` to `uri`, the
+    /// audio is encoded as for the other outputs
+    pub fn passthrough(
+        name: &str,
+        uri: &str,
+        ts: &TsOptions,
+        encoding: &Encoding,
+        splicer: &Splicer,
+    ) -> Result<Self, anyhow::Error> {
+        Self::muxed(name, build_mux_sink(uri, ts)?, splicer.bin().clone(), encoding)
+    }
+
+    /// Encoders and MPEG-TS muxer as per `ts` into a `fakesink`, so that a
+    /// dry run negotiates the encoded streams without going on air
+    pub fn null(name: &str, ts: &TsOptions, encoding: &Encoding) -> Result<Self, anyhow::Error> {
+        let (mux, video_pad, audio_pad) = build_ts_mux(ts)?;
+        let mux_sink = MuxSink {
+            mux: Some(mux),
+            sink: hints::make_element("fakesink", Some("output"))?,
+            video_pad,
+            audio_pad,
+        };
+
+        Self::encoded(name, mux_sink, encoding)
+    }
+
+    /// Encoders and HLS sink writing segments as per `options`
+    pub fn hls(
+        name: &str,
+        options: &HlsOptions,
+        encoding: &Encoding,
+    ) -> Result<Self, anyhow::Error> {
+        Self::encoded(name, build_hls_sink(options)?, encoding)
+    }
+
+    /// Encoders and `splitmuxsink` archiving to files as per `options`
+    pub fn archive(
+        name: &str,
+        options: &ArchiveOptions,
+        encoding: &Encoding,
+    ) -> Result<Self, anyhow::Error> {
+        let mux_sink = MuxSink {
+            mux: None,
+            sink: archive::build_sink(options)?,
+            video_pad: "video".to_string(),
+            audio_pad: "audio_%u".to_string(),
+        };
+
+        Self::encoded(name, mux_sink, encoding)
+    }
+
+    /// `webrtcsink` publishing to browsers, through the signalling server at
+    /// `signaller_uri` or its built-in one, it encodes on its own
+    pub fn webrtc(name: &str, signaller_uri: Option<&str>) -> Result<Self, anyhow::Error> {
+        let sink = hints::make_element("webrtcsink", Some("output"))?;
+
+        match signaller_uri {
+            Some(uri) => sink
+                .dynamic_cast_ref::<gst::ChildProxy>()
+                .unwrap()
+                .set_child_property("signaller::uri", &uri)?,
+            None => sink.set_property("run-signalling-server", &true)?,
+        }
+
+        /* Leaky, so that browsers that cannot keep up do not hold up the
+         * other outputs */
+        let video = hints::make_element("queue", None)?;
+        let audio = hints::make_element("queue", None)?;
+        video.set_property_from_str("leaky", "downstream");
+        audio.set_property_from_str("leaky", "downstream");
+
+        let bin = gst::Bin::new(Some(name));
+        bin.add_many(&[&video, &audio, &sink])?;
+        video.link_pads(None, &sink, Some("video_%u"))?;
+        audio.link_pads(None, &sink, Some("audio_%u"))?;
+
+        Self::with_bin(bin, &video, &audio)
+    }
+
+    /// Raw video and audio sinks, e.g. for previewing
+    pub fn raw(
+        name: &str,
+        video_sink: &gst::Element,
+        audio_sink: &gst::Element,
+    ) -> Result<Self, anyhow::Error> {
+        let video = hints::make_element("queue", None)?;
+        let audio = hints::make_element("queue", None)?;
+        let bin = gst::Bin::new(Some(name));
+
+        bin.add_many(&[&video, video_sink, &audio, audio_sink])?;
+        video.link(video_sink)?;
+        audio.link(audio_sink)?;
+
+        Self::with_bin(bin, &video, &audio)
+    }
+
+    fn encoded(
+        name: &str,
+        mux_sink: MuxSink,
+        encoding: &Encoding,
+    ) -> Result<Self, anyhow::Error> {
+        /* Leaky, so that an output stalling does not hold up the others */
+        let video = hints::parse_bin(&format!(
+            "queue leaky=downstream ! {} ! h264parse ! queue",
+            encoding.video_chain()
+        ))?;
+
+        Self::muxed(name, mux_sink, video, encoding)
+    }
+
+    /// `video`, taking the raw program video and outputting H.264, and the
+    /// audio encoder into `mux_sink`
+    fn muxed(
+        name: &str,
+        mux_sink: MuxSink,
+        video: gst::Bin,
+        encoding: &Encoding,
+    ) -> Result<Self, anyhow::Error> {
+        let audio = hints::parse_bin(&format!(
+            "queue leaky=downstream ! audioconvert ! audioresample ! avenc_aac bitrate={} \
+             ! aacparse ! queue",
+            encoding.audio_bitrate * 1000
+        ))?;
+
+        let MuxSink {
+            mux,
+            sink,
+            video_pad,
+            audio_pad,
+        } = mux_sink;
+
+        let bin = gst::Bin::new(Some(name));
+        bin.add_many(&[video.upcast_ref(), audio.upcast_ref(), &sink])?;
+
+        let target = match mux {
+            Some(mux) => {
+                bin.add(&mux)?;
+                mux.link(&sink)?;
+                mux
+            }
+            None => sink,
+        };
+
+        video.link_pads(None, &target, Some(&video_pad))?;
+        audio.link_pads(None, &target, Some(&audio_pad))?;
+
+        Self::with_bin(bin, video.upcast_ref(), audio.upcast_ref())
+    }
+
+    fn with_bin(
+        bin: gst::Bin,
+        video: &gst::Element,
+        audio: &gst::Element,
+    ) -> Result<Self, anyhow::Error> {
+        for (name, element) in &[("video", video), ("audio", audio)] {
+            let pad = gst::GhostPad::new(Some(name), &element.get_static_pad("sink").unwrap())?;
+            bin.add_pad(&pad)?;
+        }
+
+        Ok(Self { bin })
+    }
+
+    pub fn name(&self) -> String {
+        self.bin.get_name().to_string()
+    }
+
+    /// MPEG-TS muxer of the output, if it muxes to MPEG-TS
+    pub fn ts_mux(&self) -> Option<gst::Element> {
+        self.bin.get_by_name("output_mux").filter(|mux| {
+            mux.get_factory()
+                .map_or(false, |factory| factory.get_name() == "mpegtsmux")
+        })
+    }
+
+    /// Whether `msg` was posted by an element of this output
+    pub fn posted(&self, msg: &gst::Message) -> bool {
+        msg.get_src()
+            .map_or(false, |src| src.has_as_ancestor(&self.bin))
+    }
+
+    /// Feed the output from `video_tee` and `audio_tee` in `pipe`
+    pub fn link(
+        &self,
+        pipe: &gst::Pipeline,
+        video_tee: &gst::Element,
+        audio_tee: &gst::Element,
+    ) -> Result<(), anyhow::Error> {
+        pipe.add(&self.bin)?;
+        video_tee.link_pads(None, &self.bin, Some("video"))?;
+        audio_tee.link_pads(None, &self.bin, Some("audio"))?;
+
+        Ok(())
+    }
+
+    /// Detach the output from the tees and drop it from `pipe`
+    pub fn remove(&self, pipe: &gst::Pipeline) -> Result<(), anyhow::Error> {
+        for pad in &["video", "audio"] {
+            let pad = self.bin.get_static_pad(pad).unwrap();
+
+            if let Some(tee_pad) = pad.get_peer() {
+                tee_pad.unlink(&pad)?;
+                if let Some(tee) = tee_pad.get_parent_element() {
+                    tee.release_request_pad(&tee_pad);
+                }
+            }
+        }
+
+        self.bin.set_state(gst::State::Null)?;
+        pipe.remove(&self.bin)?;
+
+        Ok(())
+    }
+}
@@ -0,0 +1,104 @@
+//! Runtime state persisted across process restarts.
+//!
+//! The state is saved periodically and on exit, and restored on startup, so
+//! that a supervisor restarting the process does not reset it, and in
+//! particular does not silently undo an override forced by an operator.
+
+use std::path::{Path, PathBuf};
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+
+use anyhow::Context as _;
+
+use crate::control;
+use crate::failover::Failover;
+use crate::gate::LiveGate;
+use crate::reconnect::Reconnector;
+
+/// How often the state is saved
+const SAVE_INTERVAL_SECS: u32 = 5;
+
+/// Parts of the pipeline whose state is persisted
+pub struct Persisted {
+    pub gate: Arc<LiveGate>,
+    pub failover: Arc<Failover>,
+    pub reconnector: Arc<Reconnector>,
+}
+
+impl Persisted {
+    /// Restore the state saved to `path`, if any
+    pub fn restore(&self, path: &Path) -> Result<(), anyhow::Error> {
+        let contents = match std::fs::read_to_string(path) {
+            Ok(contents) => contents,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(()),
+            Err(err) => return Err(err.into()),
+        };
+
+        let state: serde_json::Value = serde_json::from_str(&contents)
+            .with_context(|| format!("Invalid state file {}", path.display()))?;
+
+        if let Some(restarts) = state["restarts"].as_u64() {
+            crate::RESTARTS.store(restarts, Ordering::Relaxed);
+        }
+
+        if state["slate_forced"].as_bool() == Some(true) {
+            warn!("Slate still forced by operator before the restart");
+            control::force_slate(&self.gate, true);
+        } else if state["live_forced"].as_bool() == Some(true) {
+            warn!("Live video still forced by operator before the restart");
+            control::force_live(&self.gate, true);
+        }
+
+        if let Some(input) = state["failover_input"].as_u64() {
+            self.failover.restore(input as usize);
+        }
+
+        if let Some(attempts) = state["reconnect_attempts"].as_u64() {
+            self.reconnector.restore(attempts as u32);
+        }
+
+        info!("Restored state from {}", path.display());
+
+        Ok(())
+    }
+
+    /// Reconnection attempts to carry on from after a restart, none once the
+    /// source was given up on, as the restarted process would otherwise give
+    /// up again on its first error without a single retry.
+    fn reconnect_attempts(&self) -> u32 {
+        if self.reconnector.exhausted() {
+            0
+        } else {
+            self.reconnector.attempts()
+        }
+    }
+
+    /// Save the state to `path`, atomically so that a crash never leaves a
+    /// truncated file behind
+    pub fn save(&self, path: &Path) -> Result<(), anyhow::Error> {
+        let state = serde_json::json!({
+            "restarts": crate::restarts(),
+            "slate_forced": self.gate.is_closed_by(control::FORCED),
+            "live_forced": self.gate.is_forced_open(),
+            "failover_input": self.failover.active(),
+            "reconnect_attempts": self.reconnect_attempts(),
+        });
+
+        let tmp_path = path.with_extension("tmp");
+        std::fs::write(&tmp_path, format!("{}\n", state))?;
+        std::fs::rename(&tmp_path, path)?;
+
+        Ok(())
+    }
+
+    /// Save the state to `path` periodically
+    pub fn start(self: &Arc<Self>, path: PathBuf) {
+        let persisted = self.clone();
+        glib::timeout_add_seconds(SAVE_INTERVAL_SECS, move || {
+            if let Err(err) = persisted.save(&path) {
+                warn!("Failed to save state to {}: {}", path.display(), err);
+            }
+            glib::Continue(true)
+        });
+    }
+}
@@ -0,0 +1,97 @@
+//! The `probe` command, printing what a source URI carries.
+
+use std::time::Duration;
+
+use gst_pbutils::prelude::*;
+
+fn print_tags(indent: &str, tags: Option<gst::TagList>) {
+    if let Some(tags) = tags {
+        for (name, value) in tags.iter() {
+            println!("{}{}: {:?}", indent, name, value);
+        }
+    }
+}
+
+fn print_stream(stream: &gst_pbutils::DiscovererStreamInfo) {
+    let caps = stream.get_caps();
+    let codec = caps
+        .as_ref()
+        .and_then(|caps| gst_pbutils::pb_utils_get_codec_description(caps).ok())
+        .map(|codec| codec.to_string())
+        .unwrap_or_else(|| "unknown".to_string());
+
+    println!("  {} stream: {}", stream.get_stream_type_nick(), codec);
+
+    if let Some(caps) = caps {
+        println!("    caps: {}", caps);
+    }
+
+    if let Some(video) = stream.downcast_ref::<gst_pbutils::DiscovererVideoInfo>() {
+        println!(
+            "    {}x{} @ {}/{} fps{}",
+            video.get_width(),
+            video.get_height(),
+            video.get_framerate().numer(),
+            video.get_framerate().denom(),
+            if video.is_interlaced() {
+                ", interlaced"
+            } else {
+                ""
+            }
+        );
+        println!(
+            "    bitrate: {} bps (max {} bps)",
+            video.get_bitrate(),
+            video.get_max_bitrate()
+        );
+    }
+
+    if let Some(audio) = stream.downcast_ref::<gst_pbutils::DiscovererAudioInfo>() {
+        println!(
+            "    {} channels @ {}Hz, {} bits",
+            audio.get_channels(),
+            audio.get_sample_rate(),
+            audio.get_depth()
+        );
+        println!(
+            "    bitrate: {} bps (max {} bps)",
+            audio.get_bitrate(),
+            audio.get_max_bitrate()
+        );
+        if let Some(language) = audio.get_language() {
+            println!("    language: {}", language);
+        }
+    }
+
+    print_tags("    ", stream.get_tags());
+}
+
+/// Discover the streams of `uri`, failing if it is unreachable or could not
+/// be prerolled within `timeout`
+pub fn run(uri: &str, timeout: Duration) -> Result<(), anyhow::Error> {
+    let discoverer = gst_pbutils::Discoverer::new(crate::clock_time(timeout))?;
+    let info = discoverer.discover_uri(uri)?;
+
+    match info.get_result() {
+        gst_pbutils::DiscovererResult::Ok => (),
+        result => anyhow::bail!("Failed to probe {}: {:?}", uri, result),
+    }
+
+    println!("URI: {}", uri);
+    println!("Live: {}", info.get_live());
+    println!("Seekable: {}", info.get_seekable());
+
+    if let Some(container) = info.get_container_streams().first() {
+        if let Some(caps) = container.get_caps() {
+            println!("Container: {}", caps);
+        }
+    }
+
+    print_tags("  ", info.get_tags());
+
+    for stream in info.get_stream_list() {
+        print_stream(&stream);
+    }
+
+    Ok(())
+}
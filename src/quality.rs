@@ -0,0 +1,201 @@
+//! Lightweight objective quality indicators on the live video.
+//!
+//! Every few frames the luma plane is sampled on a sparse grid to estimate
+//! the average brightness, the proportion of black pixels, the sharpness
+//! (mean horizontal gradient) and the blockiness (gradient across 8 pixel
+//! block boundaries relative to the gradient inside blocks). A starving
+//! encoder produces blurry and blocky pictures well before it stops. The
+//! latest indicators are logged periodically and exported as metrics.
+
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use gst::prelude::*;
+
+use crate::gate::LiveGate;
+
+/// Only analyse one frame out of this many
+const ANALYSIS_INTERVAL: u64 = 15;
+/// Number of consecutive bad analyses before we consider quality collapsed
+const COLLAPSE_COUNT: u32 = 5;
+const BLACK_THRESHOLD: u8 = 32;
+
+#[derive(Debug, Default, Clone, Copy)]
+pub struct FrameQuality {
+    pub mean_luma: f64,
+    pub black_ratio: f64,
+    pub sharpness: f64,
+    pub blockiness: f64,
+}
+
+pub struct QualityThresholds {
+    pub max_blockiness: f64,
+    pub min_sharpness: f64,
+}
+
+#[derive(Default)]
+struct State {
+    frames: u64,
+    bad_count: u32,
+    last: Option<FrameQuality>,
+}
+
+pub struct QualityMonitor {
+    state: Mutex<State>,
+    /// When set, collapsed quality closes the live gate
+    fallback: Option<(QualityThresholds, Arc<LiveGate>)>,
+}
+
+fn analyse(luma: &[u8], stride: usize, width: usize, height: usize) -> FrameQuality {
+    let mut luma_sum = 0u64;
+    let mut black = 0u64;
+    let mut samples = 0u64;
+    let mut edge_gradient = 0u64;
+    let mut edge_samples = 0u64;
+    let mut inner_gradient = 0u64;
+    let mut inner_samples = 0u64;
+
+    for y in (0..height).step_by(4) {
+        let row = &luma[y * stride..y * stride + width];
+
+        for x in 1..width {
+            let gradient = (row[x] as i32 - row[x - 1] as i32).abs() as u64;
+            if x % 8 == 0 {
+                edge_gradient += gradient;
+                edge_samples += 1;
+            } else {
+                inner_gradient += gradient;
+                inner_samples += 1;
+            }
+        }
+
+        for value in row.iter().step_by(4) {
+            luma_sum += *value as u64;
+            if *value < BLACK_THRESHOLD {
+                black += 1;
+            }
+            samples += 1;
+        }
+    }
+
+    let samples = samples.max(1) as f64;
+    let edge = edge_gradient as f64 / edge_samples.max(1) as f64;
+    let inner = inner_gradient as f64 / inner_samples.max(1) as f64;
+
+    FrameQuality {
+        mean_luma: luma_sum as f64 / samples,
+        black_ratio: black as f64 / samples,
+        sharpness: (edge_gradient + inner_gradient) as f64
+            / (edge_samples + inner_samples).max(1) as f64,
+        blockiness: if inner > 0.0 { edge / inner } else { 1.0 },
+    }
+}
+
+/// Formats whose first plane is 8-bit luma without padding between samples
+pub fn has_luma_plane(format: gst_video::VideoFormat) -> bool {
+    use gst_video::VideoFormat::*;
+
+    matches!(format, I420 | Yv12 | Y42b | Y444 | Nv12 | Nv21 | Gray8)
+}
+
+impl QualityMonitor {
+    pub fn new(fallback: Option<(QualityThresholds, Arc<LiveGate>)>) -> Arc<Self> {
+        Arc::new(Self {
+            state: Mutex::new(State::default()),
+            fallback,
+        })
+    }
+
+    pub fn last(&self) -> Option<FrameQuality> {
+        self.state.lock().unwrap().last
+    }
+
+    fn is_collapsed(&self, quality: &FrameQuality) -> bool {
+        match self.fallback {
+            Some((ref thresholds, _)) => {
+                quality.blockiness > thresholds.max_blockiness
+                    || quality.sharpness < thresholds.min_sharpness
+            }
+            None => false,
+        }
+    }
+
+    fn handle_frame(&self, pad: &gst::Pad, buffer: &gst::BufferRef) {
+        let mut state = self.state.lock().unwrap();
+
+        state.frames += 1;
+        if state.frames % ANALYSIS_INTERVAL != 0 {
+            return;
+        }
+
+        let info = match pad
+            .get_current_caps()
+            .and_then(|caps| gst_video::VideoInfo::from_caps(&caps).ok())
+        {
+            Some(info) if has_luma_plane(info.format()) => info,
+            _ => return,
+        };
+
+        let frame = match gst_video::VideoFrameRef::from_buffer_ref_readable(buffer, &info) {
+            Ok(frame) => frame,
+            Err(_) => return,
+        };
+
+        let luma = match frame.plane_data(0) {
+            Ok(luma) => luma,
+            Err(_) => return,
+        };
+
+        let quality = analyse(
+            luma,
+            frame.plane_stride()[0] as usize,
+            frame.width() as usize,
+            frame.height() as usize,
+        );
+
+        state.last = Some(quality);
+
+        if self.is_collapsed(&quality) {
+            state.bad_count += 1;
+        } else {
+            state.bad_count = 0;
+        }
+
+        if let Some((_, ref gate)) = self.fallback {
+            if state.bad_count >= COLLAPSE_COUNT {
+                gate.close("video quality collapsed");
+            } else if state.bad_count == 0 {
+                gate.open("video quality collapsed");
+            }
+        }
+    }
+
+    /// Analyse the video flowing through `pad`
+    pub fn install(self: &Arc<Self>, pad: &gst::Pad) {
+        let monitor = self.clone();
+        pad.add_probe(gst::PadProbeType::BUFFER, move |pad, info| {
+            if let Some(gst::PadProbeData::Buffer(ref buffer)) = info.data {
+                monitor.handle_frame(pad, buffer);
+            }
+            gst::PadProbeReturn::Ok
+        });
+    }
+
+    /// Log the latest indicators every `interval`
+    pub fn start_reporting(self: &Arc<Self>, interval: Duration) {
+        let monitor = self.clone();
+        let interval_ms = interval.as_millis().min(u32::MAX as u128) as u32;
+        glib::timeout_add(interval_ms.max(1), move || {
+            if let Some(quality) = monitor.last() {
+                info!(
+                    "Live video quality: luma {:.1}, black {:.1}%, sharpness {:.2}, blockiness {:.2}",
+                    quality.mean_luma,
+                    quality.black_ratio * 100.0,
+                    quality.sharpness,
+                    quality.blockiness
+                );
+            }
+            glib::Continue(true)
+        });
+    }
+}
@@ -0,0 +1,72 @@
+//! Plugin rank overrides.
+//!
+//! playbin3 autoplugs elements by rank, so avoiding a known-bad element or
+//! pinning a specific one boils down to adjusting ranks in the registry, as
+//! GST_PLUGIN_FEATURE_RANK would, but without touching the environment.
+
+use anyhow::{anyhow, bail};
+use glib::translate::{from_glib, ToGlib};
+
+/// Rank given to pinned factories above primary, beyond anything GStreamer
+/// or the hardware decoder selection assigns
+const PINNED_ABOVE_PRIMARY: i32 = 768;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct RankOverride {
+    factory: String,
+    rank: i32,
+}
+
+impl RankOverride {
+    /// Pin `factory`, making autoplugging prefer it over any other
+    pub fn pin(factory: &str) -> Self {
+        Self {
+            factory: factory.to_string(),
+            rank: gst::Rank::Primary.to_glib() + PINNED_ABOVE_PRIMARY,
+        }
+    }
+}
+
+impl std::str::FromStr for RankOverride {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let idx = s
+            .find('=')
+            .ok_or_else(|| anyhow!("Expected FACTORY=RANK, got {}", s))?;
+        let rank = match &s[idx + 1..] {
+            "none" => gst::Rank::None.to_glib(),
+            "marginal" => gst::Rank::Marginal.to_glib(),
+            "secondary" => gst::Rank::Secondary.to_glib(),
+            "primary" => gst::Rank::Primary.to_glib(),
+            rank => rank
+                .parse()
+                .map_err(|_| anyhow!("Invalid rank {}", rank))?,
+        };
+
+        Ok(Self {
+            factory: s[..idx].to_string(),
+            rank,
+        })
+    }
+}
+
+/// Apply `overrides` to the registry, must be called after gst::init()
+pub fn apply(overrides: &[RankOverride]) -> Result<(), anyhow::Error> {
+    let registry = gst::Registry::get();
+
+    for rank_override in overrides {
+        match registry.lookup_feature(&rank_override.factory) {
+            Some(feature) => {
+                info!(
+                    "Setting rank of {} to {}",
+                    rank_override.factory, rank_override.rank
+                );
+                feature.set_rank(from_glib(rank_override.rank));
+            }
+            None => bail!("No element factory {} to set the rank of", rank_override.factory),
+        }
+    }
+
+    Ok(())
+}
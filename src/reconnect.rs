@@ -0,0 +1,335 @@
+//! Reconnection of the RTMP pipeline with exponential backoff.
+//!
+//! The pipeline is stopped as soon as the source fails, and restarted from a
+//! main loop timeout after a delay doubling with every failed attempt, up to
+//! a maximum and randomised by the jitter so that many instances losing the
+//! same server do not all reconnect at once. The delay is back to its
+//! initial value once live buffers flow again. A source that never goes
+//! live at startup, e.g. stuck connecting, is treated as a failure as well
+//! once the startup timeout expires. When the retries are exhausted, the
+//! process stays on the slate, exits for a supervisor to restart it, or
+//! runs a hook command.
+
+use std::process::Command;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use gst::prelude::*;
+use rand::Rng;
+
+use crate::logging::{self, Level};
+use crate::machine::{Event, SourceState, StateMachine};
+
+#[derive(Debug, Clone, Copy)]
+pub struct ReconnectPolicy {
+    pub initial_delay: Duration,
+    pub max_delay: Duration,
+    /// Fraction of the delay it is randomised by, in both directions
+    pub jitter: f64,
+    /// Attempts after which the source is given up on
+    pub max_retries: Option<u32>,
+}
+
+impl ReconnectPolicy {
+    /// Delay before reconnection attempt `attempt`, counting from 0
+    fn delay(&self, attempt: u32) -> Duration {
+        let factor = 2f64.powi(attempt.min(31) as i32);
+        let delay = self
+            .initial_delay
+            .mul_f64(factor)
+            .min(self.max_delay.max(self.initial_delay));
+
+        if self.jitter > 0.0 {
+            delay.mul_f64(1.0 + rand::thread_rng().gen_range(-self.jitter..=self.jitter))
+        } else {
+            delay
+        }
+    }
+}
+
+/// What to do once the source is given up on
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum GiveUpAction {
+    /// Drain the output and exit with `ExitCode::SourceFailed`
+    Exit,
+    StayOnSlate,
+    RunCommand,
+}
+
+impl std::str::FromStr for GiveUpAction {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "exit" => Ok(GiveUpAction::Exit),
+            "stay-on-slate" => Ok(GiveUpAction::StayOnSlate),
+            "run-command" => Ok(GiveUpAction::RunCommand),
+            _ => Err(anyhow::anyhow!("Unknown give up action {}", s)),
+        }
+    }
+}
+
+/// Run the hook `command` from a dedicated thread, with the URI of the
+/// source given up on and the number of attempts in the environment
+pub fn run_give_up_command(command: &str, uri: Option<String>, attempts: u32) {
+    let mut cmd = Command::new("sh");
+    cmd.arg("-c")
+        .arg(command)
+        .env("SLATE_FALLBACK_ATTEMPTS", attempts.to_string());
+    if let Some(uri) = uri {
+        cmd.env("SLATE_FALLBACK_URI", uri);
+    }
+
+    let command = command.to_string();
+    std::thread::spawn(move || match cmd.status() {
+        Ok(status) if status.success() => (),
+        Ok(status) => warn!("Give up command {:?} exited with {}", command, status),
+        Err(err) => warn!("Failed to run give up command {:?}: {}", command, err),
+    });
+}
+
+#[derive(Default)]
+struct State {
+    /// Failed attempts since the source was last live
+    attempts: u32,
+    /// A reconnection is scheduled
+    pending: bool,
+    /// Timeout of the scheduled reconnection
+    timeout: Option<glib::SourceId>,
+    /// Counts scheduled reconnections, for their timeouts to tell whether
+    /// they are still current
+    generation: u64,
+    /// The retries are exhausted and the source given up on
+    exhausted: bool,
+}
+
+type GiveUpHandler = Arc<dyn Fn(&gst::Element, u32) + Send + Sync>;
+
+pub struct Reconnector {
+    policy: ReconnectPolicy,
+    state: Mutex<State>,
+    give_up_handlers: Mutex<Vec<GiveUpHandler>>,
+}
+
+impl Reconnector {
+    pub fn new(policy: ReconnectPolicy) -> Arc<Self> {
+        Arc::new(Self {
+            policy,
+            state: Mutex::new(State::default()),
+            give_up_handlers: Mutex::new(vec![]),
+        })
+    }
+
+    /// Call `f` with the source and the number of attempts once the
+    /// retries are exhausted
+    pub fn connect_give_up<F: Fn(&gst::Element, u32) + Send + Sync + 'static>(&self, f: F) {
+        self.give_up_handlers.lock().unwrap().push(Arc::new(f));
+    }
+
+    /// Failed attempts since the source was last live
+    pub fn attempts(&self) -> u32 {
+        self.state.lock().unwrap().attempts
+    }
+
+    /// The retries are exhausted, until the next restart
+    pub fn exhausted(&self) -> bool {
+        self.state.lock().unwrap().exhausted
+    }
+
+    /// Carry on the backoff from `attempts`, e.g. as saved before a restart
+    pub fn restore(&self, attempts: u32) {
+        self.state.lock().unwrap().attempts = attempts;
+    }
+
+    /// Reset the backoff on the live buffers flowing through `pad`
+    pub fn watch_live(self: &Arc<Self>, pad: &gst::Pad) {
+        let reconnector = self.clone();
+        pad.add_probe(gst::PadProbeType::BUFFER, move |_, _| {
+            let mut state = reconnector.state.lock().unwrap();
+            if state.attempts > 0 && !state.pending {
+                logging::event(
+                    Level::Info,
+                    "reconnected",
+                    &format!("Source live again after {} reconnection attempts", state.attempts),
+                    serde_json::json!({ "attempts": state.attempts }),
+                );
+                state.attempts = 0;
+            }
+            gst::PadProbeReturn::Ok
+        });
+    }
+
+    /// Reconnect `source` if no live buffer flowed through `pad` within
+    /// `timeout` of startup, unless `machine` is draining
+    pub fn watch_startup(
+        self: &Arc<Self>,
+        source: &gst::Element,
+        pad: &gst::Pad,
+        timeout: Duration,
+        machine: &Arc<StateMachine>,
+    ) {
+        let started = Arc::new(AtomicBool::new(false));
+        let started_clone = started.clone();
+        pad.add_probe(gst::PadProbeType::BUFFER, move |_, _| {
+            started_clone.store(true, Ordering::Relaxed);
+            gst::PadProbeReturn::Remove
+        });
+
+        let reconnector = self.clone();
+        let source = source.clone();
+        let machine = machine.clone();
+        let timeout_ms = timeout.as_millis().min(u32::MAX as u128) as u32;
+        glib::timeout_add(timeout_ms, move || {
+            if !started.load(Ordering::Relaxed)
+                && machine.handle(Event::Restart).to == SourceState::Reconnecting
+            {
+                logging::event(
+                    Level::Error,
+                    "startup-timeout",
+                    &format!(
+                        "Source not live {} after startup",
+                        humantime::format_duration(timeout)
+                    ),
+                    serde_json::json!({ "timeout_ms": timeout_ms }),
+                );
+                reconnector.schedule(&source);
+            }
+            glib::Continue(false)
+        });
+    }
+
+    /// Stop `source` and restart it after the backoff delay, unless
+    /// a restart is already scheduled. Once the retries are exhausted,
+    /// `source` stays stopped and later calls are ignored. Called from the
+    /// main context only, like the reconnections themselves.
+    pub fn schedule(self: &Arc<Self>, source: &gst::Element) {
+        /* Scheduled under the same lock as the check, so that the timeout
+         * and the pending flag always go together */
+        let (attempts, delay) = {
+            let mut state = self.state.lock().unwrap();
+
+            if state.pending || state.exhausted {
+                return;
+            }
+
+            let exhausted = self
+                .policy
+                .max_retries
+                .map_or(false, |max_retries| state.attempts >= max_retries);
+            if exhausted {
+                state.exhausted = true;
+                (state.attempts, None)
+            } else {
+                let delay = self.policy.delay(state.attempts);
+                state.attempts += 1;
+                state.pending = true;
+                state.generation += 1;
+
+                let reconnector = self.clone();
+                let source = source.clone();
+                let generation = state.generation;
+                state.timeout = Some(glib::timeout_add(
+                    delay.as_millis().min(u32::MAX as u128) as u32,
+                    move || {
+                        reconnector.reconnect(&source, generation);
+                        glib::Continue(false)
+                    },
+                ));
+
+                (state.attempts, Some(delay))
+            }
+        };
+
+        if let Err(err) = source.set_state(gst::State::Null) {
+            warn!("Failed to stop the source: {}", err);
+        }
+
+        let delay = match delay {
+            Some(delay) => delay,
+            None => {
+                logging::event(
+                    Level::Error,
+                    "reconnect-exhausted",
+                    &format!("Giving up on the source after {} reconnection attempts", attempts),
+                    serde_json::json!({ "attempts": attempts }),
+                );
+                /* The handlers may well call back into the reconnector */
+                let handlers = self.give_up_handlers.lock().unwrap().clone();
+                for handler in handlers.iter() {
+                    handler(source, attempts);
+                }
+                return;
+            }
+        };
+
+        logging::event(
+            Level::Info,
+            "reconnect-scheduled",
+            &format!(
+                "Reconnecting in {} (attempt {})",
+                humantime::format_duration(Duration::from_millis(delay.as_millis() as u64)),
+                attempts
+            ),
+            serde_json::json!({
+                "attempt": attempts,
+                "delay_ms": delay.as_millis() as u64,
+            }),
+        );
+    }
+
+    /// Restart `source` from the timeout of the reconnection scheduled as
+    /// `generation`, unless it was cancelled since
+    fn reconnect(self: &Arc<Self>, source: &gst::Element, generation: u64) {
+        {
+            let mut state = self.state.lock().unwrap();
+
+            if !state.pending || state.generation != generation {
+                return;
+            }
+
+            state.pending = false;
+            state.timeout = None;
+        }
+
+        if let Err(err) = crate::restart_source(source) {
+            warn!("Failed to restart the source: {}", err);
+            self.schedule(source);
+        }
+    }
+
+    /// Restart `source` right away, e.g. on a new URI, cancelling any
+    /// scheduled reconnection and starting the backoff over. The restart
+    /// runs on the main context, where reconnections are scheduled, so
+    /// that it cannot interleave with them, and is waited for.
+    pub fn restart(self: &Arc<Self>, source: &gst::Element) -> Result<(), anyhow::Error> {
+        let (sender, receiver) = std::sync::mpsc::channel();
+        let reconnector = self.clone();
+        let source = source.clone();
+
+        glib::MainContext::default().invoke(move || {
+            let _ = sender.send(reconnector.restart_now(&source));
+        });
+
+        receiver
+            .recv()
+            .map_err(|_| anyhow::anyhow!("Main loop not running, cannot restart the source"))?
+    }
+
+    fn restart_now(&self, source: &gst::Element) -> Result<(), anyhow::Error> {
+        let timeout = {
+            let mut state = self.state.lock().unwrap();
+            state.attempts = 0;
+            state.pending = false;
+            state.exhausted = false;
+            state.generation += 1;
+            state.timeout.take()
+        };
+
+        if let Some(timeout) = timeout {
+            glib::source_remove(timeout);
+        }
+
+        crate::restart_source(source)
+    }
+}
@@ -0,0 +1,224 @@
+//! Recording of fallback events.
+//!
+//! The program output is encoded continuously into a ring buffer holding the
+//! last `pre_roll` of it, which is otherwise blocked. When the live video
+//! goes missing, the ring buffer is unblocked into a new file starting with
+//! its first keyframe, and the recording goes on until `post_roll` after the
+//! live video came back, so each file shows what led to the event and how it
+//! ended without recording around the clock.
+
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use gst::prelude::*;
+
+use crate::output::Encoding;
+use crate::status::Status;
+
+/// How soon to start recording again while the previous recording is still
+/// being finalized
+const FINALIZE_RETRY: Duration = Duration::from_millis(200);
+
+#[derive(Default)]
+struct State {
+    /// Probe blocking the ring buffer while not recording
+    block: Option<gst::PadProbeId>,
+    /// Muxer and file sink of the ongoing recording
+    recording: Option<gst::Bin>,
+    /// Drop data until the first keyframe of the recording
+    wait_keyframe: bool,
+    /// When the live video came back during the recording
+    live_since: Option<Instant>,
+    /// The output shows live video
+    live: bool,
+}
+
+pub struct EventRecorder {
+    dir: PathBuf,
+    post_roll: Duration,
+    pipe: gst::Pipeline,
+    ring: gst::Element,
+    state: Mutex<State>,
+}
+
+impl EventRecorder {
+    /// Encode the output of `tee` in `pipe` into the ring buffer
+    pub fn new(
+        pipe: &gst::Pipeline,
+        tee: &gst::Element,
+        encoding: &Encoding,
+        dir: &Path,
+        pre_roll: Duration,
+        post_roll: Duration,
+    ) -> Result<Arc<Self>, anyhow::Error> {
+        std::fs::create_dir_all(dir)?;
+
+        let encoder = crate::hints::parse_bin(&format!(
+            "queue ! {} ! h264parse",
+            encoding.video_chain()
+        ))?;
+        let ring = crate::hints::make_element("queue", Some("recording_ring"))?;
+        ring.set_property("max-size-buffers", &0u32)?;
+        ring.set_property("max-size-bytes", &0u32)?;
+        ring.set_property("max-size-time", &(pre_roll.as_nanos() as u64))?;
+        ring.set_property_from_str("leaky", "downstream");
+
+        pipe.add_many(&[encoder.upcast_ref(), &ring])?;
+        gst::Element::link_many(&[tee, encoder.upcast_ref(), &ring])?;
+
+        let recorder = Arc::new(Self {
+            dir: dir.to_path_buf(),
+            post_roll,
+            pipe: pipe.clone(),
+            ring,
+            state: Mutex::new(State::default()),
+        });
+
+        let src = recorder.ring.get_static_pad("src").unwrap();
+        recorder.state.lock().unwrap().block = Some(block(&src));
+
+        let recorder_clone = recorder.clone();
+        src.add_probe(gst::PadProbeType::BUFFER, move |_, info| {
+            let mut state = recorder_clone.state.lock().unwrap();
+
+            if let Some(gst::PadProbeData::Buffer(ref buffer)) = info.data {
+                if state.wait_keyframe {
+                    if buffer.get_flags().contains(gst::BufferFlags::DELTA_UNIT) {
+                        return gst::PadProbeReturn::Drop;
+                    }
+                    state.wait_keyframe = false;
+                }
+            }
+
+            gst::PadProbeReturn::Ok
+        });
+
+        Ok(recorder)
+    }
+
+    fn start_recording(&self) -> Result<(), anyhow::Error> {
+        let timestamp = humantime::format_rfc3339_seconds(std::time::SystemTime::now());
+        let path = self.dir.join(format!("{}.mkv", timestamp));
+
+        let bin = gst::Bin::new(None);
+        let mux = crate::hints::make_element("matroskamux", None)?;
+        let sink = crate::hints::make_element("filesink", None)?;
+        sink.set_property("location", &path.to_string_lossy().as_ref())?;
+        bin.add_many(&[&mux, &sink])?;
+        mux.link(&sink)?;
+
+        let mux_pad = mux
+            .get_request_pad("video_%u")
+            .ok_or_else(|| anyhow::anyhow!("Failed to request a video pad from matroskamux"))?;
+        bin.add_pad(&gst::GhostPad::new(Some("sink"), &mux_pad)?)?;
+
+        self.pipe.add(&bin)?;
+        if let Err(err) = self
+            .ring
+            .link(&bin)
+            .map_err(anyhow::Error::from)
+            .and_then(|_| bin.sync_state_with_parent().map_err(anyhow::Error::from))
+        {
+            let _ = bin.set_state(gst::State::Null);
+            let _ = self.pipe.remove(&bin);
+            return Err(err);
+        }
+
+        info!("Recording fallback event to {}", path.display());
+
+        let mut state = self.state.lock().unwrap();
+        state.recording = Some(bin);
+        state.wait_keyframe = true;
+        state.live_since = None;
+
+        if let Some(block) = state.block.take() {
+            self.ring.get_static_pad("src").unwrap().remove_probe(block);
+        }
+
+        Ok(())
+    }
+
+    /// Block the ring buffer again, then finalize the file and dispose of
+    /// its muxer
+    fn stop_recording(&self) {
+        let mut state = self.state.lock().unwrap();
+        let bin = Mutex::new(state.recording.take());
+        let pipe = self.pipe.clone();
+        let src = self.ring.get_static_pad("src").unwrap();
+
+        state.block = src.add_probe(gst::PadProbeType::BLOCK_DOWNSTREAM, move |pad, _| {
+            let bin = match bin.lock().unwrap().take() {
+                Some(bin) => bin,
+                None => return gst::PadProbeReturn::Ok,
+            };
+
+            if let Some(sink) = bin.get_static_pad("sink") {
+                let _ = pad.unlink(&sink);
+                sink.send_event(gst::event::Eos::new());
+            }
+
+            let pipe = pipe.clone();
+            glib::idle_add(move || {
+                let _ = bin.set_state(gst::State::Null);
+                let _ = pipe.remove(&bin);
+                glib::Continue(false)
+            });
+
+            info!("Recording finished");
+
+            gst::PadProbeReturn::Ok
+        });
+    }
+
+    /// Start or stop recording as per what the output shows
+    fn check(self: &Arc<Self>) {
+        let mut state = self.state.lock().unwrap();
+
+        match (state.recording.is_some(), state.live) {
+            (false, false) => {
+                drop(state);
+
+                /* The previous recording is still being finalized */
+                if self.ring.get_static_pad("src").unwrap().is_linked() {
+                    let recorder = self.clone();
+                    crate::call_after(FINALIZE_RETRY, move || recorder.check());
+                } else if let Err(err) = self.start_recording() {
+                    error!("Failed to start recording: {:?}", err);
+                }
+            }
+            (true, true) => {
+                let live_since = *state.live_since.get_or_insert_with(|| {
+                    /* Checked again once the post-roll is over */
+                    let recorder = self.clone();
+                    crate::call_after(self.post_roll, move || recorder.check());
+                    Instant::now()
+                });
+                if live_since.elapsed() >= self.post_roll {
+                    drop(state);
+                    self.stop_recording();
+                }
+            }
+            (true, false) => state.live_since = None,
+            (false, true) => (),
+        }
+    }
+
+    /// Record whenever the live video goes missing once it was there
+    pub fn start(self: &Arc<Self>, status: &Arc<Status>) {
+        let recorder = self.clone();
+
+        /* The output starts on the slate, the first switch being to live */
+        status.connect_live_changed(move |live| {
+            recorder.state.lock().unwrap().live = live;
+            recorder.check();
+        });
+    }
+}
+
+fn block(pad: &gst::Pad) -> gst::PadProbeId {
+    pad.add_probe(gst::PadProbeType::BLOCK_DOWNSTREAM, |_, _| {
+        gst::PadProbeReturn::Ok
+    })
+    .unwrap()
+}
@@ -0,0 +1,99 @@
+//! Hysteresis before switching back from the slate to the live video.
+//!
+//! An unstable source coming back for a few seconds at a time would make the
+//! output flap between live and slate. Once the live video has been missing
+//! for long enough for the slate to show, its buffers are dropped again until
+//! they have been flowing without such an interruption for the return delay.
+//! The startup delay applies in the same way to the very first live video,
+//! so that the output starts on the slate until the source proved stable.
+
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use gst::prelude::*;
+
+#[derive(Default)]
+struct State {
+    last_buffer: Option<Instant>,
+    /// Start of the current streak of buffers
+    streak_since: Option<Instant>,
+    returned: bool,
+    /// The live video was returned to at least once
+    started: bool,
+}
+
+pub struct ReturnDelay {
+    delay: Duration,
+    startup_delay: Duration,
+    /// How long the live video is missing before the slate shows, never
+    /// after the first buffer if unset
+    down_after: Option<Duration>,
+    state: Mutex<State>,
+}
+
+impl ReturnDelay {
+    pub fn new(
+        delay: Duration,
+        startup_delay: Duration,
+        down_after: Option<Duration>,
+    ) -> Arc<Self> {
+        Arc::new(Self {
+            delay,
+            startup_delay,
+            down_after,
+            state: Mutex::new(State::default()),
+        })
+    }
+
+    /// Returns whether the buffer may pass
+    fn handle_buffer(&self) -> bool {
+        let now = Instant::now();
+        let mut state = self.state.lock().unwrap();
+
+        let went_down = match state.last_buffer {
+            Some(last_buffer) => self
+                .down_after
+                .map_or(false, |down_after| now - last_buffer > down_after),
+            None => true,
+        };
+
+        if went_down {
+            if state.returned {
+                info!("Live video went missing, waiting for it to be stable before returning");
+            }
+            state.streak_since = Some(now);
+            state.returned = false;
+        }
+
+        state.last_buffer = Some(now);
+
+        let delay = if state.started {
+            self.delay
+        } else {
+            self.startup_delay
+        };
+        let stable = state
+            .streak_since
+            .map_or(false, |since| now - since >= delay);
+
+        if stable && !state.returned {
+            info!("Live video stable for {:?}, returning to it", delay);
+            state.returned = true;
+            state.started = true;
+        }
+
+        stable
+    }
+
+    /// Drop the buffers flowing through `pad` until they are stable
+    pub fn install(self: &Arc<Self>, pad: &gst::Pad) {
+        let return_delay = self.clone();
+        pad.add_probe(gst::PadProbeType::BUFFER, move |_, _| {
+            if return_delay.handle_buffer() {
+                gst::PadProbeReturn::Ok
+            } else {
+                gst::PadProbeReturn::Drop
+            }
+        });
+    }
+}
@@ -0,0 +1,87 @@
+//! RTSP input options.
+//!
+//! playbin picks `rtspsrc` for `rtsp://` URIs, the lower transports and
+//! timeouts are configured on it once it is created. IP cameras behind NAT
+//! or firewalls often only work over TCP.
+
+use std::time::Duration;
+
+use gst::prelude::*;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RtspProtocol {
+    Tcp,
+    Udp,
+    UdpMcast,
+}
+
+impl RtspProtocol {
+    fn nick(self) -> &'static str {
+        match self {
+            RtspProtocol::Tcp => "tcp",
+            RtspProtocol::Udp => "udp",
+            RtspProtocol::UdpMcast => "udp-mcast",
+        }
+    }
+}
+
+impl std::str::FromStr for RtspProtocol {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "tcp" => Ok(RtspProtocol::Tcp),
+            "udp" => Ok(RtspProtocol::Udp),
+            "udp-mcast" => Ok(RtspProtocol::UdpMcast),
+            _ => Err(anyhow::anyhow!("Unknown RTSP protocol {}", s)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct RtspOptions {
+    /// Lower transports to try, in rtspsrc's order
+    pub protocols: Vec<RtspProtocol>,
+    /// Time without data over UDP before trying TCP
+    pub timeout: Option<Duration>,
+    /// Timeout of TCP connections and requests
+    pub tcp_timeout: Option<Duration>,
+    pub latency: Option<Duration>,
+}
+
+impl RtspOptions {
+    /// Apply the options to `source` if it is an RTSP source
+    pub fn configure(&self, source: &gst::Element) -> Result<(), anyhow::Error> {
+        let is_rtsp = source
+            .get_factory()
+            .map_or(false, |factory| factory.get_name() == "rtspsrc");
+
+        if !is_rtsp {
+            return Ok(());
+        }
+
+        if !self.protocols.is_empty() {
+            let protocols = self
+                .protocols
+                .iter()
+                .map(|protocol| protocol.nick())
+                .collect::<Vec<_>>()
+                .join("+");
+            source.set_property_from_str("protocols", &protocols);
+        }
+
+        if let Some(timeout) = self.timeout {
+            source.set_property("timeout", &(timeout.as_micros() as u64))?;
+        }
+
+        if let Some(tcp_timeout) = self.tcp_timeout {
+            source.set_property("tcp-timeout", &(tcp_timeout.as_micros() as u64))?;
+        }
+
+        if let Some(latency) = self.latency {
+            source.set_property("latency", &(latency.as_millis() as u32))?;
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,151 @@
+//! Scripting hooks, letting advanced users customize the fallback policy
+//! with a Rhai script instead of forking the crate.
+//!
+//! The script may define any of these functions:
+//!
+//! * `on_fallback(reason)`: a fallback trigger fired, return `false` to
+//!   veto it and keep the live video on air
+//! * `on_switch(live)`: the output switched between live and slate
+//!
+//! and may call:
+//!
+//! * `force_slate(on)`: hold the slate on air, or release it
+//! * `set_slate_text(text)`: change the slate message
+//! * `run(command)`: run a shell command, returning its exit code
+//! * `http_post(url, body)`: POST `body`, returning the HTTP status
+//! * `log(message)`
+
+use std::path::Path;
+use std::process::Command;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use anyhow::anyhow;
+use rhai::{Engine, Scope, AST};
+
+use crate::gate::LiveGate;
+use crate::slate::Slate;
+use crate::status::Status;
+
+const FORCED: &str = "forced by script";
+
+pub struct ScriptHooks {
+    engine: Engine,
+    ast: AST,
+    scope: Mutex<Scope<'static>>,
+}
+
+fn run_command(command: &str) -> i64 {
+    match Command::new("sh").arg("-c").arg(command).status() {
+        Ok(status) => status.code().unwrap_or(-1) as i64,
+        Err(err) => {
+            warn!("Script failed to run {:?}: {}", command, err);
+            -1
+        }
+    }
+}
+
+fn http_post(url: &str, body: &str) -> i64 {
+    match ureq::post(url)
+        .timeout(Duration::from_secs(5))
+        .send_string(body)
+    {
+        Ok(response) => response.status() as i64,
+        Err(ureq::Error::Status(status, _)) => status as i64,
+        Err(err) => {
+            warn!("Script failed to POST to {}: {}", url, err);
+            -1
+        }
+    }
+}
+
+impl ScriptHooks {
+    pub fn load(
+        path: &Path,
+        gate: &Arc<LiveGate>,
+        slate: &Arc<Slate>,
+    ) -> Result<Arc<Self>, anyhow::Error> {
+        let mut engine = Engine::new();
+
+        let gate_clone = gate.clone();
+        engine.register_fn("force_slate", move |on: bool| {
+            if on {
+                gate_clone.force_close(FORCED);
+            } else {
+                gate_clone.open(FORCED);
+            }
+        });
+
+        let slate = slate.clone();
+        engine.register_fn("set_slate_text", move |text: &str| slate.set_text(text));
+        engine.register_fn("run", run_command);
+        engine.register_fn("http_post", http_post);
+        engine.register_fn("log", |message: &str| info!("Script: {}", message));
+
+        let ast = engine
+            .compile_file(path.to_path_buf())
+            .map_err(|err| anyhow!("Failed to load script {}: {}", path.display(), err))?;
+
+        let hooks = Arc::new(Self {
+            engine,
+            ast,
+            scope: Mutex::new(Scope::new()),
+        });
+
+        /* Run the top level statements, e.g. initializing state */
+        {
+            let mut scope = hooks.scope.lock().unwrap();
+            hooks
+                .engine
+                .run_ast_with_scope(&mut scope, &hooks.ast)
+                .map_err(|err| anyhow!("Script failed: {}", err))?;
+        }
+
+        if hooks.defines("on_fallback") {
+            let hooks_clone = hooks.clone();
+            gate.set_veto(Box::new(move |reason| {
+                hooks_clone
+                    .call("on_fallback", (reason.to_string(),))
+                    .unwrap_or(true)
+            }));
+        }
+
+        Ok(hooks)
+    }
+
+    fn defines(&self, name: &str) -> bool {
+        self.ast.iter_functions().any(|f| f.name == name)
+    }
+
+    /// Call `name` if defined, logging failures
+    fn call<T: Clone + Send + Sync + 'static>(
+        &self,
+        name: &str,
+        args: impl rhai::FuncArgs,
+    ) -> Option<T> {
+        if !self.defines(name) {
+            return None;
+        }
+
+        let mut scope = self.scope.lock().unwrap();
+        match self.engine.call_fn(&mut scope, &self.ast, name, args) {
+            Ok(ret) => Some(ret),
+            Err(err) => {
+                warn!("Script {} failed: {}", name, err);
+                None
+            }
+        }
+    }
+
+    /// Call `on_switch` whenever `status` reports a switch
+    pub fn start(self: &Arc<Self>, status: &Arc<Status>) {
+        if !self.defines("on_switch") {
+            return;
+        }
+
+        let hooks = self.clone();
+        status.connect_live_changed(move |live| {
+            let _: Option<()> = hooks.call("on_switch", (live,));
+        });
+    }
+}
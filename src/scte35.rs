@@ -0,0 +1,86 @@
+//! SCTE-35 markers around the slate periods of MPEG-TS outputs.
+//!
+//! Entering the slate sends a splice out to every `mpegtsmux`, leaving it
+//! sends the matching splice in, so that downstream ad insertion and
+//! monitoring systems know the slate is not program material. The splice
+//! sections are built with libgstmpegts, which has no Rust bindings, and
+//! `mpegtsmux` writes them to the SCTE-35 PID at the running time they
+//! carry.
+
+use std::os::raw::{c_int, c_void};
+use std::sync::Arc;
+
+use gst::prelude::*;
+
+use crate::status::Status;
+
+#[link(name = "gstmpegts-1.0")]
+extern "C" {
+    fn gst_mpegts_initialize();
+    fn gst_mpegts_scte_splice_out_new(
+        event_id: u32,
+        splice_time: u64,
+        duration: u64,
+    ) -> *mut c_void;
+    fn gst_mpegts_scte_splice_in_new(event_id: u32, splice_time: u64) -> *mut c_void;
+    fn gst_mpegts_section_from_scte_sit(sit: *mut c_void, pid: u16) -> *mut c_void;
+    fn gst_mpegts_section_send_event(section: *mut c_void, element: *mut c_void) -> c_int;
+}
+
+/// Send a splice out of the network if `out`, back in otherwise, for
+/// `event_id` at the current running time of `mux`
+fn splice(mux: &gst::Element, pid: u16, event_id: u32, out: bool) {
+    let running_time = match mux.get_clock() {
+        Some(clock) => clock.get_time() - mux.get_base_time(),
+        None => return,
+    };
+    let splice_time = match running_time.nseconds() {
+        Some(splice_time) => splice_time,
+        None => return,
+    };
+
+    /* The section takes ownership of the table, sending it takes ownership
+     * of the section */
+    let sent = unsafe {
+        let sit = if out {
+            gst_mpegts_scte_splice_out_new(event_id, splice_time, 0)
+        } else {
+            gst_mpegts_scte_splice_in_new(event_id, splice_time)
+        };
+        let section = gst_mpegts_section_from_scte_sit(sit, pid);
+        gst_mpegts_section_send_event(section, mux.as_ptr() as *mut c_void) != 0
+    };
+
+    if !sent {
+        warn!("Failed to send SCTE-35 splice to {}", mux.get_name());
+    }
+}
+
+/// Mark the slate periods on the `muxes` SCTE-35 PID `pid`, as per what
+/// `status` shows on air
+pub fn start(muxes: Vec<gst::Element>, pid: u16, status: &Arc<Status>) {
+    unsafe { gst_mpegts_initialize() };
+
+    /* The output starts on the slate, but there is no program to splice
+     * back into until the source first went live */
+    let mut event_id = 0;
+
+    status.connect_live_changed(move |live| {
+        let slate = !live;
+
+        if slate {
+            event_id += 1;
+        } else if event_id == 0 {
+            return;
+        }
+
+        info!(
+            "Sending SCTE-35 splice {} for event {}",
+            if slate { "out" } else { "in" },
+            event_id
+        );
+        for mux in &muxes {
+            splice(mux, pid, event_id, slate);
+        }
+    });
+}
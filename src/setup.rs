@@ -0,0 +1,246 @@
+//! The interactive `setup` command, asking a few questions, checking the
+//! environment for the required plugins and hardware, and writing a config
+//! file with the answers, to be passed with `--config`.
+
+use std::ffi::OsString;
+use std::io::{BufRead, Write};
+use std::path::Path;
+
+use clap::Parser;
+use serde_json::Value;
+
+use crate::cli::{self, Args};
+use crate::config;
+
+const REQUIRED: &[&str] = &[
+    "playbin3",
+    "interpipesrc",
+    "interpipesink",
+    "compositor",
+    "videotestsrc",
+    "identity",
+    "queue",
+    "capsfilter",
+    "audioconvert",
+    "audioresample",
+    "audiomixer",
+    "audiotestsrc",
+    "level",
+    "fakesink",
+    "uridecodebin",
+    "videoconvert",
+    "imagefreeze",
+];
+
+fn has_element(factory: &str) -> bool {
+    gst::ElementFactory::find(factory).is_some()
+}
+
+fn ask(question: &str, default: &str) -> Result<String, anyhow::Error> {
+    if default.is_empty() {
+        print!("{}: ", question);
+    } else {
+        print!("{} [{}]: ", question, default);
+    }
+    std::io::stdout().flush()?;
+
+    let mut answer = String::new();
+    if std::io::stdin().lock().read_line(&mut answer)? == 0 {
+        anyhow::bail!("Setup aborted");
+    }
+
+    let answer = answer.trim();
+    Ok(if answer.is_empty() {
+        default.to_string()
+    } else {
+        answer.to_string()
+    })
+}
+
+fn ask_yes_no(question: &str, default: bool) -> Result<bool, anyhow::Error> {
+    loop {
+        let answer = ask(question, if default { "y" } else { "n" })?;
+        match answer.to_lowercase().as_str() {
+            "y" | "yes" => return Ok(true),
+            "n" | "no" => return Ok(false),
+            _ => println!("Please answer y or n"),
+        }
+    }
+}
+
+/// Ask until `parse` accepts the answer, an empty answer being None unless
+/// there is a default
+fn ask_parsed<T>(
+    question: &str,
+    default: &str,
+    parse: impl Fn(&str) -> Result<T, String>,
+) -> Result<Option<T>, anyhow::Error> {
+    loop {
+        let answer = ask(question, default)?;
+        if answer.is_empty() {
+            return Ok(None);
+        }
+
+        match parse(&answer) {
+            Ok(value) => return Ok(Some(value)),
+            Err(err) => println!("{}", err),
+        }
+    }
+}
+
+/// Parse a URI, or the path of an existing file as its file:// URI
+fn parse_media(s: &str) -> Result<String, String> {
+    let path = Path::new(s);
+    if path.is_file() {
+        let path = path.canonicalize().map_err(|err| err.to_string())?;
+        return glib::filename_to_uri(&path, None)
+            .map(|uri| uri.to_string())
+            .map_err(|err| err.to_string());
+    }
+
+    cli::parse_uri(s).map_err(|_| format!("{} is neither a file nor a URI", s))
+}
+
+/// Write `options` to `path`, as TOML or YAML depending on its extension
+fn write_config(
+    path: &Path,
+    options: &serde_json::Map<String, Value>,
+) -> Result<(), anyhow::Error> {
+    let contents = match path.extension().and_then(|ext| ext.to_str()) {
+        Some("toml") => toml::to_string(options)?,
+        Some("yaml") | Some("yml") => serde_yaml::to_string(options)?,
+        _ => anyhow::bail!(
+            "Unknown config file format {}, expected .toml, .yaml or .yml",
+            path.display()
+        ),
+    };
+
+    std::fs::write(
+        path,
+        format!("# Generated by rtmp-slate-fallback setup\n{}", contents),
+    )?;
+
+    Ok(())
+}
+
+pub fn run(output: &Path) -> Result<(), anyhow::Error> {
+    println!("Checking required plugins...");
+
+    let missing = REQUIRED
+        .iter()
+        .filter(|factory| !has_element(factory))
+        .collect::<Vec<_>>();
+
+    for factory in &missing {
+        println!("  missing {}", factory);
+    }
+
+    if !missing.is_empty() {
+        anyhow::bail!("Install the missing plugins and run setup again");
+    }
+
+    println!("  all required plugins found");
+
+    let mut options = serde_json::Map::new();
+
+    let mut inputs = vec![];
+    while inputs.is_empty() {
+        if let Some(uri) = ask_parsed(
+            "Live input URI (e.g. rtmp://host/app/key)",
+            "",
+            cli::parse_uri,
+        )? {
+            inputs.push(Value::from(uri));
+        }
+    }
+    while let Some(uri) = ask_parsed("Backup input URI, empty for none", "", cli::parse_uri)? {
+        inputs.push(Value::from(uri));
+    }
+    options.insert("live-rtmp-uri".into(), Value::Array(inputs));
+
+    if let Some(uri) = ask_parsed(
+        "Slate image or video file, empty for a test pattern",
+        "",
+        parse_media,
+    )? {
+        options.insert("slate-uri".into(), Value::from(uri));
+    }
+
+    let discard_after = ask_parsed(
+        "How long to freeze the last frame before showing the slate",
+        "2s",
+        |s| cli::parse_duration(s).map(|_| s.to_string()),
+    )?;
+    if let Some(discard_after) = discard_after {
+        options.insert("discard-after".into(), Value::from(discard_after));
+    }
+
+    let outputs = ask_parsed(
+        "Output URI to push to (e.g. rtmp://host/app/key), empty to preview",
+        "",
+        cli::parse_uri,
+    )?;
+
+    let preview_sinks = [("xv", "xvimagesink"), ("wayland", "waylandsink")]
+        .iter()
+        .filter(|(_, factory)| has_element(factory))
+        .map(|(name, _)| *name)
+        .collect::<Vec<_>>();
+
+    if let Some(uri) = outputs {
+        options.insert("output-uri".into(), Value::Array(vec![Value::from(uri)]));
+    } else if preview_sinks.is_empty() || !ask_yes_no("Display a preview window?", false)? {
+        options.insert("headless".into(), Value::Bool(true));
+    } else {
+        let preview_sink = loop {
+            let sink = ask(
+                &format!("Preview sink ({})", preview_sinks.join(", ")),
+                "auto",
+            )?;
+            if sink == "auto" || preview_sinks.contains(&sink.as_str()) {
+                break sink;
+            }
+            println!("Please answer auto or one of {}", preview_sinks.join(", "));
+        };
+        options.insert("preview-sink".into(), Value::from(preview_sink));
+    }
+
+    if has_element("nvv4l2decoder") && has_element("nvvidconv") {
+        if ask_yes_no("NVIDIA Jetson decoder found, use it?", true)? {
+            options.insert("hw-decoder".into(), Value::from("nvv4l2"));
+        }
+    } else if has_element("v4l2h264dec")
+        && ask_yes_no("V4L2 hardware decoder found, use it?", true)?
+    {
+        options.insert("hw-decoder".into(), Value::from("v4l2"));
+    }
+
+    if let Some(port) = ask_parsed(
+        "Port to serve health checks on, empty to disable",
+        "",
+        |s| s.parse::<u16>().map_err(|_| format!("{} is not a port", s)),
+    )? {
+        options.insert("health-port".into(), Value::from(port));
+    }
+
+    write_config(output, &options)?;
+
+    /* Make sure the config file loads as it would when going live */
+    let args = config::expand_args(vec![
+        OsString::from("rtmp-slate-fallback"),
+        OsString::from("--config"),
+        output.as_os_str().to_os_string(),
+    ])?;
+    if let Err(err) = Args::try_parse_from(args) {
+        anyhow::bail!("Invalid config file {}: {}", output.display(), err);
+    }
+
+    println!(
+        "Wrote {}, run `{} --config {}` to go live",
+        output.display(),
+        std::env::current_exe()?.display(),
+        output.display()
+    );
+
+    Ok(())
+}
@@ -0,0 +1,121 @@
+//! Silence on the live audio as a fallback trigger.
+//!
+//! Based on the RMS level reported by the `level` element of the RTMP
+//! pipeline. Once the level stays below the threshold for the timeout, the
+//! slate audio replaces the live audio, and optionally the slate replaces
+//! the live video too. The live audio only returns after it stayed a few dB
+//! above the threshold for the recovery time, so that quiet passages around
+//! the threshold do not flap between the two.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use crate::gate::LiveGate;
+
+/// How far above the threshold the level has to be to count as recovered
+const HYSTERESIS_DB: f64 = 3.0;
+
+#[derive(Default)]
+struct State {
+    quiet_since: Option<Instant>,
+    loud_since: Option<Instant>,
+}
+
+pub struct SilenceDetector {
+    threshold_db: f64,
+    timeout: Duration,
+    recovery: Duration,
+    /// When set, the live video is dropped as well while silent
+    gate: Option<Arc<LiveGate>>,
+    silent: AtomicBool,
+    state: Mutex<State>,
+}
+
+impl SilenceDetector {
+    pub fn new(
+        threshold_db: f64,
+        timeout: Duration,
+        recovery: Duration,
+        gate: Option<Arc<LiveGate>>,
+    ) -> Arc<Self> {
+        Arc::new(Self {
+            threshold_db,
+            timeout,
+            recovery,
+            gate,
+            silent: AtomicBool::new(false),
+            state: Mutex::new(State::default()),
+        })
+    }
+
+    /// Whether the live audio is currently considered silent
+    pub fn is_silent(&self) -> bool {
+        self.silent.load(Ordering::Relaxed)
+    }
+
+    fn set_silent(&self, silent: bool, rms: f64) {
+        self.silent.store(silent, Ordering::Relaxed);
+
+        if silent {
+            crate::logging::event(
+                crate::logging::Level::Warn,
+                "audio-silence",
+                "Live audio silent, switching to the slate audio",
+                serde_json::json!({ "rms_db": rms, "threshold_db": self.threshold_db }),
+            );
+            if let Some(ref gate) = self.gate {
+                gate.close("live audio silent");
+            }
+        } else {
+            crate::logging::event(
+                crate::logging::Level::Info,
+                "audio-silence-ended",
+                "Live audio back",
+                serde_json::json!({ "rms_db": rms }),
+            );
+            if let Some(ref gate) = self.gate {
+                gate.open("live audio silent");
+            }
+        }
+    }
+
+    /// Handle a message posted by a `level` element, other messages are
+    /// ignored
+    pub fn handle_message(&self, msg: &gst::Message) {
+        let s = match msg.get_structure() {
+            Some(s) if s.get_name() == "level" => s,
+            _ => return,
+        };
+
+        let rms = match crate::loudness::max_of(s, "rms") {
+            Some(rms) => rms,
+            None => return,
+        };
+
+        let now = Instant::now();
+        let mut state = self.state.lock().unwrap();
+
+        if rms < self.threshold_db {
+            state.loud_since = None;
+            let quiet_since = *state.quiet_since.get_or_insert(now);
+
+            if !self.is_silent() && now - quiet_since >= self.timeout {
+                self.set_silent(true, rms);
+            }
+        } else {
+            state.quiet_since = None;
+
+            if rms < self.threshold_db + HYSTERESIS_DB {
+                state.loud_since = None;
+                return;
+            }
+
+            let loud_since = *state.loud_since.get_or_insert(now);
+
+            if self.is_silent() && now - loud_since >= self.recovery {
+                self.set_silent(false, rms);
+            }
+        }
+    }
+}
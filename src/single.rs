@@ -0,0 +1,303 @@
+//! Ingest within the output pipeline, without any bridge.
+//!
+//! uridecodebin3 and the live video and audio branches live in a bin of
+//! the output pipeline, so that latency is negotiated across the whole
+//! chain. Restarting the source only cycles uridecodebin3, whose new pads
+//! get linked to the branches again. Pad probes on the way out of the bin
+//! keep the teardown from reaching the mixers: once uridecodebin3 removes
+//! its pads, the bin's pads are blocked until the segment of the new stream,
+//! what is left of the old one being dropped. EOS and flushes are dropped as
+//! well, and every new stream is shifted to the current running time, as its
+//! timestamps start over like with `stream-sync` `restart-ts`.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+
+use gst::prelude::*;
+
+use crate::hints;
+use crate::Args;
+
+type MessageHandler = Box<dyn FnMut(&gst::Message) + Send>;
+
+/// Fail on the options that need the source in its own pipeline
+pub fn check(args: &Args) -> Result<(), anyhow::Error> {
+    if args.live_rtmp_uri.len() > 1 {
+        anyhow::bail!("Backup inputs are not supported with --single-pipeline");
+    }
+
+    if args.audio_meters {
+        anyhow::bail!("--audio-meters is not supported with --single-pipeline");
+    }
+
+    if args.jitter_buffer.is_some() {
+        anyhow::bail!("--jitter-buffer is not supported with --single-pipeline");
+    }
+
+    if args.subtitle_file.is_some() {
+        anyhow::bail!("--subtitle-file is not supported with --single-pipeline");
+    }
+
+    if args.source_latency.is_some() {
+        anyhow::bail!("--source-latency is not supported with --single-pipeline, use --latency");
+    }
+
+    Ok(())
+}
+
+/// End of the live branch named `name`, as a gst-launch description
+pub fn sink(name: &str) -> String {
+    format!("queue name={}", name)
+}
+
+/// Start of the output branch fed by the live branch named `name`
+pub fn source(name: &str) -> Result<gst::Element, anyhow::Error> {
+    hints::make_element("identity", Some(&format!("{}_src", name)))
+}
+
+/// Names of the bin's pads, fed by the video and audio branches
+const PADS: [&str; 2] = ["video", "audio"];
+
+pub struct SingleSource {
+    bin: gst::Bin,
+    decodebin: gst::Element,
+    handler: Mutex<Option<MessageHandler>>,
+    /// The current stream ended, reset once a new one starts
+    ended: AtomicBool,
+    /// Whether each of `PADS` is blocked until a new stream starts
+    blocked: [AtomicBool; 2],
+}
+
+/// Type of the stream of a uridecodebin3 pad, by its name if the stream is
+/// not known yet
+fn stream_type(pad: &gst::Pad) -> Option<gst::StreamType> {
+    let stream_type = match pad.get_stream() {
+        Some(stream) => stream.get_stream_type(),
+        None if pad.get_name().starts_with("video") => gst::StreamType::VIDEO,
+        None if pad.get_name().starts_with("audio") => gst::StreamType::AUDIO,
+        None => return None,
+    };
+
+    [gst::StreamType::VIDEO, gst::StreamType::AUDIO]
+        .iter()
+        .copied()
+        .find(|&candidate| stream_type.contains(candidate))
+}
+
+impl SingleSource {
+    /// Source decoding into the `video` and `audio` live branches
+    pub fn new(video: &gst::Bin, audio: &gst::Bin) -> Result<Arc<Self>, anyhow::Error> {
+        let bin = gst::Bin::new(Some("rtmp_source"));
+        let decodebin = hints::make_element("uridecodebin3", Some("decodebin"))?;
+        bin.add_many(&[&decodebin, video.upcast_ref(), audio.upcast_ref()])?;
+
+        for (&name, branch) in PADS.iter().zip(&[video, audio]) {
+            let target = branch.get_static_pad("src").unwrap();
+            let pad = gst::GhostPad::new(Some(name), &target)?;
+            bin.add_pad(&pad)?;
+        }
+
+        let video_sink = video.get_static_pad("sink").unwrap();
+        let audio_sink = audio.get_static_pad("sink").unwrap();
+        decodebin.connect_pad_added(move |_, pad| {
+            let sink = match stream_type(pad) {
+                Some(gst::StreamType::VIDEO) => &video_sink,
+                Some(gst::StreamType::AUDIO) => &audio_sink,
+                _ => return,
+            };
+
+            /* The stream selected last replaces the one played so far */
+            if let Some(peer) = sink.get_peer() {
+                let _ = peer.unlink(sink);
+            }
+
+            if let Err(err) = pad.link(sink) {
+                warn!("Failed to link {}: {:?}", pad.get_name(), err);
+            }
+        });
+
+        let source = Arc::new(Self {
+            bin,
+            decodebin,
+            handler: Mutex::new(None),
+            ended: AtomicBool::new(false),
+            blocked: [AtomicBool::new(false), AtomicBool::new(false)],
+        });
+
+        /* Pads are removed as uridecodebin3 is torn down, on restarts */
+        let weak = Arc::downgrade(&source);
+        source.decodebin.connect_pad_removed(move |_, _| {
+            if let Some(source) = weak.upgrade() {
+                source.block();
+            }
+        });
+
+        Ok(source)
+    }
+
+    /// uridecodebin3, restarted on its own when the source fails
+    pub fn decodebin(&self) -> &gst::Element {
+        &self.decodebin
+    }
+
+    /// Call `f` with the messages posted from within the source, in place
+    /// of a bus watch of its own
+    pub fn set_message_handler<F: FnMut(&gst::Message) + Send + 'static>(&self, f: F) {
+        *self.handler.lock().unwrap() = Some(Box::new(f));
+    }
+
+    /// Pass `msg` to the message handler if posted from within the source,
+    /// returns whether it was
+    pub fn handle(&self, msg: &gst::Message) -> bool {
+        let posted = msg
+            .get_src()
+            .map_or(false, |src| src.has_as_ancestor(&self.bin));
+
+        if posted {
+            if let Some(ref mut handler) = *self.handler.lock().unwrap() {
+                handler(msg);
+            }
+        }
+
+        posted
+    }
+
+    /// Feed the live video into `video` and the live audio into `audio` in
+    /// `pipe`, they must be added already
+    pub fn link(
+        self: &Arc<Self>,
+        pipe: &gst::Pipeline,
+        video: &gst::Element,
+        audio: &gst::Element,
+    ) -> Result<(), anyhow::Error> {
+        pipe.add(&self.bin)?;
+
+        for (&name, element) in PADS.iter().zip(&[video, audio]) {
+            let pad = self.bin.get_static_pad(name).unwrap();
+            pad.link(&element.get_static_pad("sink").unwrap())?;
+            self.install(&pad);
+        }
+
+        Ok(())
+    }
+
+    fn install(self: &Arc<Self>, pad: &gst::Pad) {
+        let source = self.clone();
+        let resync = AtomicBool::new(true);
+        pad.add_probe(
+            gst::PadProbeType::BUFFER
+                | gst::PadProbeType::EVENT_DOWNSTREAM
+                | gst::PadProbeType::EVENT_FLUSH,
+            move |pad, info| match info.data {
+                Some(gst::PadProbeData::Buffer(ref buffer)) => {
+                    /* The sticky events are sent again with the new offset
+                     * before this very buffer */
+                    if resync.swap(false, Ordering::SeqCst) {
+                        shift_to_now(pad, buffer);
+                    }
+
+                    gst::PadProbeReturn::Ok
+                }
+                Some(gst::PadProbeData::Event(ref event)) => match event.get_type() {
+                    gst::EventType::StreamStart => {
+                        resync.store(true, Ordering::SeqCst);
+                        source.ended.store(false, Ordering::SeqCst);
+                        gst::PadProbeReturn::Ok
+                    }
+                    gst::EventType::Eos => {
+                        source.end();
+                        gst::PadProbeReturn::Drop
+                    }
+                    gst::EventType::FlushStart | gst::EventType::FlushStop => {
+                        gst::PadProbeReturn::Drop
+                    }
+                    _ => gst::PadProbeReturn::Ok,
+                },
+                _ => gst::PadProbeReturn::Ok,
+            },
+        );
+    }
+
+    /// Block the bin's pads until a new stream starts, dropping what is
+    /// left of the old one instead of letting it reach the mixers
+    fn block(self: &Arc<Self>) {
+        for (index, name) in PADS.iter().enumerate() {
+            if self.blocked[index].swap(true, Ordering::SeqCst) {
+                continue;
+            }
+
+            let pad = self.bin.get_static_pad(name).unwrap();
+            let source = self.clone();
+            let started = AtomicBool::new(false);
+
+            /* Dropped items do not block, so that the new stream can come
+             * through and release the block */
+            pad.add_probe(gst::PadProbeType::BLOCK_DOWNSTREAM, move |pad, info| {
+                let event_type = match info.data {
+                    Some(gst::PadProbeData::Event(ref event)) => Some(event.get_type()),
+                    _ => None,
+                };
+
+                match event_type {
+                    Some(gst::EventType::StreamStart) => {
+                        started.store(true, Ordering::SeqCst);
+                        gst::PadProbeReturn::Pass
+                    }
+                    Some(gst::EventType::Segment) if started.load(Ordering::SeqCst) => {
+                        debug!("New stream on {}, unblocking", pad.get_name());
+                        source.blocked[index].store(false, Ordering::SeqCst);
+                        gst::PadProbeReturn::Remove
+                    }
+                    _ if started.load(Ordering::SeqCst) => gst::PadProbeReturn::Pass,
+                    _ => gst::PadProbeReturn::Drop,
+                }
+            });
+        }
+    }
+
+    /// Hand the end of the stream to the message handler from the main
+    /// loop, as the EOS message never makes it out of the output pipeline
+    fn end(self: &Arc<Self>) {
+        if self.ended.swap(true, Ordering::SeqCst) {
+            return;
+        }
+
+        let source = self.clone();
+        glib::idle_add(move || {
+            if let Some(ref mut handler) = *source.handler.lock().unwrap() {
+                handler(&gst::message::Eos::new());
+            }
+            glib::Continue(false)
+        });
+    }
+}
+
+/// Offset `pad` so that `buffer` would have been at the current running
+/// time
+fn shift_to_now(pad: &gst::Pad, buffer: &gst::BufferRef) {
+    let element = match pad.get_parent_element() {
+        Some(element) => element,
+        None => return,
+    };
+    let now = match element.get_clock() {
+        Some(clock) => clock.get_time() - element.get_base_time(),
+        None => return,
+    };
+    let running_time = match pad.get_sticky_event(gst::EventType::Segment, 0) {
+        Some(event) => match event.view() {
+            gst::EventView::Segment(segment) => segment
+                .get_segment()
+                .downcast_ref::<gst::ClockTime>()
+                .map(|segment| segment.to_running_time(buffer.get_pts())),
+            _ => None,
+        },
+        None => None,
+    };
+
+    if let (Some(now), Some(running_time)) = (
+        now.nseconds(),
+        running_time.and_then(|running_time| running_time.nseconds()),
+    ) {
+        pad.set_offset(now as i64 - running_time as i64);
+    }
+}
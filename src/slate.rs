@@ -0,0 +1,287 @@
+//! Slate messages and media per failure reason.
+//!
+//! The slate shows a different message depending on why the live video is
+//! missing: the source has not been live yet, it failed, or it ended.
+//! Messages are templates, re-rendered every second so that placeholders
+//! such as the downtime stay current. The slate video can differ per reason
+//! too, the player being switched over when the reason changes.
+
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+
+use gst::prelude::*;
+use once_cell::sync::OnceCell;
+
+use crate::slate_player::SlateVideo;
+use crate::status::{self, Status};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SlateReason {
+    /// No live video since startup
+    Starting,
+    /// The source errored out or a fallback trigger fired
+    Error,
+    /// The source reached EOS
+    Eos,
+}
+
+/// Slate image or video URIs of the reasons that have their own
+#[derive(Debug, Clone, Default)]
+pub struct SlateAssets {
+    pub starting: Option<String>,
+    pub error: Option<String>,
+    pub eos: Option<String>,
+}
+
+impl SlateAssets {
+    pub fn is_empty(&self) -> bool {
+        self.starting.is_none() && self.error.is_none() && self.eos.is_none()
+    }
+
+    pub fn get(&self, reason: SlateReason) -> Option<&String> {
+        match reason {
+            SlateReason::Starting => self.starting.as_ref(),
+            SlateReason::Error => self.error.as_ref(),
+            SlateReason::Eos => self.eos.as_ref(),
+        }
+    }
+}
+
+/// Slate video played per reason
+struct Assets {
+    video: Arc<SlateVideo>,
+    uris: SlateAssets,
+    /// For the reasons without their own, None for the test pattern
+    default: Mutex<Option<String>>,
+}
+
+struct State {
+    reason: SlateReason,
+    /// Set through `set_text`, until the reason changes
+    text_override: Option<String>,
+    /// Since when the slate is on air
+    down_since: Option<Instant>,
+    /// Last rendered message
+    text: String,
+}
+
+pub struct Slate {
+    overlay: Option<gst::Element>,
+    starting: String,
+    error: String,
+    eos: String,
+    status: Arc<Status>,
+    state: Mutex<State>,
+    assets: OnceCell<Assets>,
+}
+
+/// Local wall clock time as HH:MM:SS
+fn local_time() -> String {
+    unsafe {
+        let now = libc::time(std::ptr::null_mut());
+        let mut tm: libc::tm = std::mem::zeroed();
+        libc::localtime_r(&now, &mut tm);
+        format!("{:02}:{:02}:{:02}", tm.tm_hour, tm.tm_min, tm.tm_sec)
+    }
+}
+
+impl Slate {
+    /// Messages fall back to `default`, without any message the slate is
+    /// left as is
+    pub fn new(
+        starting: Option<String>,
+        error: Option<String>,
+        eos: Option<String>,
+        default: Option<String>,
+        status: &Arc<Status>,
+    ) -> Result<Arc<Self>, anyhow::Error> {
+        let overlay = if starting.is_some() || error.is_some() || eos.is_some() || default.is_some()
+        {
+            let overlay = crate::hints::make_element("textoverlay", Some("slate_text"))?;
+            overlay.set_property_from_str("valignment", "center");
+            overlay.set_property_from_str("halignment", "center");
+            overlay.set_property("font-desc", &"Sans 36")?;
+            Some(overlay)
+        } else {
+            None
+        };
+
+        let default = default.unwrap_or_default();
+        let slate = Arc::new(Self {
+            overlay,
+            starting: starting.unwrap_or_else(|| default.clone()),
+            error: error.unwrap_or_else(|| default.clone()),
+            eos: eos.unwrap_or(default),
+            status: status.clone(),
+            state: Mutex::new(State {
+                reason: SlateReason::Starting,
+                text_override: None,
+                down_since: None,
+                text: String::new(),
+            }),
+            assets: OnceCell::new(),
+        });
+        slate.render();
+
+        Ok(slate)
+    }
+
+    /// Element rendering the message, to link into the slate branch
+    pub fn overlay(&self) -> Option<&gst::Element> {
+        self.overlay.as_ref()
+    }
+
+    /// Replace the placeholders of `template`
+    fn expand(&self, template: &str, state: &State) -> String {
+        let downtime = state
+            .down_since
+            .map(|since| since.elapsed())
+            .unwrap_or_default();
+
+        template
+            .replace("{downtime}", &status::format_duration(downtime))
+            .replace("{time}", &local_time())
+            .replace("{uri}", &self.status.uri())
+    }
+
+    /// Show the current message, if it changed
+    fn render(&self) {
+        let overlay = match self.overlay {
+            Some(ref overlay) => overlay,
+            None => return,
+        };
+
+        let mut state = self.state.lock().unwrap();
+        let template = match state.text_override {
+            Some(ref text) => text,
+            None => match state.reason {
+                SlateReason::Starting => &self.starting,
+                SlateReason::Error => &self.error,
+                SlateReason::Eos => &self.eos,
+            },
+        };
+        let text = self.expand(template, &state);
+
+        if text != state.text {
+            overlay.set_property("text", &text).unwrap();
+            state.text = text;
+        }
+    }
+
+    pub fn set_reason(self: &Arc<Self>, reason: SlateReason) {
+        {
+            let mut state = self.state.lock().unwrap();
+            if state.reason == reason {
+                return;
+            }
+            state.reason = reason;
+            state.text_override = None;
+        }
+
+        self.render();
+
+        /* Switching starts a pipeline, which is not to be done from the
+         * streaming threads */
+        if self.assets.get().is_some() {
+            let slate = self.clone();
+            glib::idle_add(move || {
+                if let Err(err) = slate.switch_asset() {
+                    warn!("Failed to switch the slate video: {}", err);
+                }
+                glib::Continue(false)
+            });
+        }
+    }
+
+    /// Play the slate video of each reason of `uris` on `video`, and
+    /// `default` for the others
+    pub fn set_assets(&self, video: &Arc<SlateVideo>, uris: SlateAssets, default: Option<String>) {
+        let _ = self.assets.set(Assets {
+            video: video.clone(),
+            uris,
+            default: Mutex::new(default),
+        });
+    }
+
+    /// Play `uri` for the reasons without a slate video of their own, the
+    /// previous one is kept if switching over fails
+    pub fn set_default_asset(&self, uri: &str) -> Result<(), anyhow::Error> {
+        let assets = self
+            .assets
+            .get()
+            .ok_or_else(|| anyhow::anyhow!("The slate video can not be replaced"))?;
+        let previous = assets.default.lock().unwrap().replace(uri.to_string());
+
+        self.switch_asset().map_err(|err| {
+            *assets.default.lock().unwrap() = previous;
+            err
+        })
+    }
+
+    /// Play the slate video of the current reason, if not already
+    fn switch_asset(&self) -> Result<(), anyhow::Error> {
+        let assets = match self.assets.get() {
+            Some(assets) => assets,
+            None => return Ok(()),
+        };
+        let reason = self.state.lock().unwrap().reason;
+        let uri = assets
+            .uris
+            .get(reason)
+            .cloned()
+            .or_else(|| assets.default.lock().unwrap().clone());
+
+        if uri == assets.video.uri() {
+            return Ok(());
+        }
+
+        info!(
+            "Switching the slate video to {}",
+            uri.as_deref().unwrap_or("the test pattern")
+        );
+        assets.video.switch(uri.as_deref())
+    }
+
+    /// Override the message until the reason changes
+    pub fn set_text(&self, text: &str) {
+        if self.overlay.is_none() {
+            warn!("No slate message configured, not showing {:?}", text);
+            return;
+        }
+
+        self.state.lock().unwrap().text_override = Some(text.to_string());
+        self.render();
+    }
+
+    /// Once live video flows through `pad`, anything that takes it away is
+    /// an error until told otherwise
+    pub fn watch_live(self: &Arc<Self>, pad: &gst::Pad) {
+        let slate = self.clone();
+        pad.add_probe(gst::PadProbeType::BUFFER, move |_, _| {
+            slate.set_reason(SlateReason::Error);
+            gst::PadProbeReturn::Ok
+        });
+    }
+
+    /// Track the downtime and refresh the message every second
+    pub fn start(self: &Arc<Self>) {
+        if self.overlay.is_none() {
+            return;
+        }
+
+        let slate = self.clone();
+        glib::timeout_add_seconds(1, move || {
+            {
+                let mut state = slate.state.lock().unwrap();
+                if slate.status.shows_live() {
+                    state.down_since = None;
+                } else {
+                    state.down_since.get_or_insert_with(Instant::now);
+                }
+            }
+
+            slate.render();
+            glib::Continue(true)
+        });
+    }
+}
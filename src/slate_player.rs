@@ -0,0 +1,321 @@
+//! Pipeline playing slate media from a URI in a loop.
+//!
+//! The media crosses over to the mixer pipeline on interpipes, like the live
+//! input, so that the mixer never sees it end. Looping is gapless, playbin3
+//! is handed the same URI again whenever it is about to finish.
+//!
+//! Still images are not played this way, they are decoded once and frozen
+//! in the mixer pipeline itself, unless the slate video is to be replaced
+//! while running. The replacement plays in a pipeline of its own, feeding an
+//! interpipesink of its own, and the mixer only listens to it once its first
+//! frame arrived, so that the slate never goes black in between.
+
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use gst::prelude::*;
+
+use crate::hints;
+
+/// Name the slate audio interpipesink listens on
+pub const AUDIO: &str = "slate_audio";
+
+/// Name the slate video interpipesink listens on
+pub const VIDEO: &str = "slate_video";
+
+const IMAGE_EXTENSIONS: &[&str] = &["png", "jpg", "jpeg", "bmp", "webp"];
+
+/// Whether `uri` points to a still image, guessed from its extension
+pub fn is_image(uri: &str) -> bool {
+    let path = uri.split(|c| c == '?' || c == '#').next().unwrap_or(uri);
+
+    path.rsplit('.')
+        .next()
+        .map_or(false, |ext| IMAGE_EXTENSIONS.contains(&ext.to_lowercase().as_str()))
+}
+
+/// Source of a still image `uri` repeated as live video
+pub fn build_image_source(uri: &str) -> Result<gst::Element, anyhow::Error> {
+    let bin = hints::parse_bin("uridecodebin name=dec ! videoconvert ! imagefreeze is-live=true")?;
+    bin.get_by_name("dec").unwrap().set_property("uri", &uri)?;
+
+    Ok(bin.upcast())
+}
+
+fn build_sink(name: &str) -> Result<gst::Element, anyhow::Error> {
+    let sink = hints::make_element("interpipesink", Some(name))?;
+    sink.set_property("drop", &false)?;
+    sink.set_property("sync", &true)?;
+    Ok(sink)
+}
+
+/// How long an asset may take to preroll when validating it
+const VALIDATE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Play `uri` in a loop, feeding its audio and / or video to the slate
+/// interpipes
+pub fn build(uri: &str, audio: bool, video: bool) -> Result<gst::Pipeline, anyhow::Error> {
+    let name = match (audio, video) {
+        (true, true) => "slate_player",
+        (true, false) => "slate_audio_player",
+        _ => "slate_video_player",
+    };
+
+    build_named(name, uri, audio, if video { Some(VIDEO) } else { None })
+}
+
+fn build_named(
+    name: &str,
+    uri: &str,
+    audio: bool,
+    video: Option<&str>,
+) -> Result<gst::Pipeline, anyhow::Error> {
+    let playbin = hints::make_element("playbin3", Some(name))?;
+
+    let asink = if audio {
+        hints::parse_bin(&format!(
+            "audioconvert ! audioresample ! interpipesink drop=false sync=true name={}",
+            AUDIO
+        ))?
+        .upcast::<gst::Element>()
+    } else {
+        let sink = hints::make_element("fakesink", None)?;
+        sink.set_property("sync", &true)?;
+        sink
+    };
+
+    let vsink = if let Some(video) = video {
+        build_sink(video)?
+    } else {
+        let sink = hints::make_element("fakesink", None)?;
+        sink.set_property("sync", &true)?;
+        sink
+    };
+
+    playbin.set_property("uri", &uri)?;
+    playbin.set_property("audio-sink", &asink)?;
+    playbin.set_property("video-sink", &vsink)?;
+
+    let uri = uri.to_string();
+    playbin.connect("about-to-finish", false, move |values| {
+        let playbin = values[0].get::<gst::Element>().unwrap().unwrap();
+        playbin.set_property("uri", &uri).unwrap();
+        None
+    })?;
+
+    let pipe = playbin.downcast::<gst::Pipeline>().unwrap();
+    watch_bus(&pipe)?;
+
+    Ok(pipe)
+}
+
+/// Repeat the still image `uri` as live video into the interpipesink `video`
+fn build_image(name: &str, uri: &str, video: &str) -> Result<gst::Pipeline, anyhow::Error> {
+    let pipe = gst::Pipeline::new(Some(name));
+    let source = build_image_source(uri)?;
+    let sink = build_sink(video)?;
+
+    pipe.add_many(&[&source, &sink])?;
+    source.link(&sink)?;
+    watch_bus(&pipe)?;
+
+    Ok(pipe)
+}
+
+/// Test pattern as live video into the interpipesink `video`
+fn build_test_pattern(name: &str, video: &str) -> Result<gst::Pipeline, anyhow::Error> {
+    let pipe = gst::Pipeline::new(Some(name));
+    let source = hints::parse_bin("videotestsrc is-live=true ! video/x-raw,width=800,height=448")?;
+    let sink = build_sink(video)?;
+
+    pipe.add_many(&[source.upcast_ref(), &sink])?;
+    source.link(&sink)?;
+    watch_bus(&pipe)?;
+
+    Ok(pipe)
+}
+
+fn watch_bus(pipe: &gst::Pipeline) -> Result<(), anyhow::Error> {
+    let bus = pipe.get_bus().unwrap();
+    bus.add_watch(move |_, msg| {
+        match msg.view() {
+            gst::MessageView::Error(err) => {
+                hints::log_error("Slate playback error", &err);
+            }
+            gst::MessageView::Latency(..) => {
+                if let Some(pipe) = msg
+                    .get_src()
+                    .and_then(|src| src.downcast::<gst::Pipeline>().ok())
+                {
+                    let _ = pipe.recalculate_latency();
+                }
+            }
+            _ => (),
+        }
+        glib::Continue(true)
+    })?;
+
+    Ok(())
+}
+
+/// Check that `uri` can be played as the slate: it prerolls within
+/// `VALIDATE_TIMEOUT` and has a video stream
+pub fn validate(uri: &str) -> Result<(), anyhow::Error> {
+    let playbin = hints::make_element("playbin", None)?;
+    playbin.set_property("uri", &uri)?;
+    playbin.set_property("video-sink", &hints::make_element("fakesink", None)?)?;
+    playbin.set_property("audio-sink", &hints::make_element("fakesink", None)?)?;
+
+    let pipe = playbin.downcast::<gst::Pipeline>().unwrap();
+    let bus = pipe.get_bus().unwrap();
+    let result = pipe
+        .set_state(gst::State::Paused)
+        .map_err(anyhow::Error::from)
+        .and_then(|_| {
+            let msg = bus
+                .timed_pop_filtered(
+                    gst::ClockTime::from_nseconds(VALIDATE_TIMEOUT.as_nanos() as u64),
+                    &[gst::MessageType::AsyncDone, gst::MessageType::Error],
+                )
+                .ok_or_else(|| anyhow::anyhow!("{} did not preroll in time", uri))?;
+
+            if let gst::MessageView::Error(err) = msg.view() {
+                anyhow::bail!("{} cannot be played: {}", uri, err.get_error());
+            }
+
+            let n_video = pipe.get_property("n-video")?.get_some::<i32>().unwrap_or(0);
+            if n_video == 0 {
+                anyhow::bail!("{} has no video", uri);
+            }
+
+            Ok(())
+        });
+    let _ = pipe.set_state(gst::State::Null);
+
+    result
+}
+
+/// Player of the slate video `uri`, a test pattern without any, feeding the
+/// interpipesink `video`
+fn build_video(name: &str, uri: Option<&str>, video: &str) -> Result<gst::Pipeline, anyhow::Error> {
+    match uri {
+        Some(uri) if is_image(uri) => build_image(name, uri, video),
+        Some(uri) => build_named(name, uri, false, Some(video)),
+        None => build_test_pattern(name, video),
+    }
+}
+
+fn stop(pipe: &gst::Pipeline) {
+    let _ = pipe.set_state(gst::State::Null);
+    if let Some(bus) = pipe.get_bus() {
+        let _ = bus.remove_watch();
+    }
+}
+
+/// Slate video that can be replaced while running
+pub struct SlateVideo {
+    /// Interpipesrc feeding the mixer
+    src: gst::Element,
+    /// The first player, started along with the other pipelines
+    first: gst::Pipeline,
+    /// Players still running, by the order they were started in
+    players: Mutex<Vec<(usize, gst::Pipeline)>>,
+    /// Number of players started
+    started: AtomicUsize,
+    /// URI of the latest player
+    uri: Mutex<Option<String>>,
+}
+
+impl SlateVideo {
+    /// Play `uri`, a test pattern without any
+    pub fn new(uri: Option<&str>) -> Result<Arc<Self>, anyhow::Error> {
+        let first = build_video("slate_video_player", uri, VIDEO)?;
+
+        let src = hints::make_element("interpipesrc", None)?;
+        src.set_property("listen-to", &VIDEO)?;
+        src.set_property("format", &gst::Format::Time)?;
+        src.set_property("is-live", &true)?;
+        src.set_property_from_str("stream-sync", &"restart-ts");
+
+        Ok(Arc::new(Self {
+            src,
+            first: first.clone(),
+            players: Mutex::new(vec![(0, first)]),
+            started: AtomicUsize::new(1),
+            uri: Mutex::new(uri.map(str::to_string)),
+        }))
+    }
+
+    /// Source of the slate video for the mixer
+    pub fn src(&self) -> gst::Element {
+        self.src.clone()
+    }
+
+    /// Pipeline of the first player, started along with the others
+    pub fn pipeline(&self) -> gst::Pipeline {
+        self.first.clone()
+    }
+
+    /// URI of the latest player, None for the test pattern
+    pub fn uri(&self) -> Option<String> {
+        self.uri.lock().unwrap().clone()
+    }
+
+    /// Stop the players started before `player`, once it is on air
+    fn retire(&self, player: usize) {
+        let mut players = self.players.lock().unwrap();
+        for (_, pipe) in players.iter().filter(|(index, _)| *index < player) {
+            stop(pipe);
+        }
+        players.retain(|(index, _)| *index >= player);
+    }
+
+    /// Play `uri` instead, the mixer switches over once its first frame
+    /// arrived and the previous players are then stopped
+    pub fn switch(self: &Arc<Self>, uri: Option<&str>) -> Result<(), anyhow::Error> {
+        let player = self.started.fetch_add(1, Ordering::SeqCst);
+        let video = format!("{}_{}", VIDEO, player);
+        let pipe = build_video(&format!("slate_video_player_{}", player), uri, &video)?;
+
+        if let Some(clock) = self.first.get_clock() {
+            pipe.use_clock(Some(&clock));
+        }
+
+        let slate_video = self.clone();
+        let sink = pipe.get_by_name(&video).unwrap();
+        sink.get_static_pad("sink")
+            .unwrap()
+            .add_probe(gst::PadProbeType::BUFFER, move |_, _| {
+                /* A player started later takes over from this one once on
+                 * air */
+                if slate_video.started.load(Ordering::SeqCst) == player + 1 {
+                    let _ = slate_video.src.set_property("listen-to", &video);
+
+                    /* Pipelines cannot be stopped from their streaming
+                     * threads */
+                    let slate_video = slate_video.clone();
+                    glib::idle_add(move || {
+                        slate_video.retire(player);
+                        glib::Continue(false)
+                    });
+                }
+
+                gst::PadProbeReturn::Remove
+            });
+
+        self.players.lock().unwrap().push((player, pipe.clone()));
+        *self.uri.lock().unwrap() = uri.map(str::to_string);
+
+        if let Err(err) = hints::set_state(&pipe, gst::State::Playing) {
+            stop(&pipe);
+            self.players
+                .lock()
+                .unwrap()
+                .retain(|(index, _)| *index != player);
+            return Err(err);
+        }
+
+        Ok(())
+    }
+}
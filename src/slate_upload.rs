@@ -0,0 +1,118 @@
+//! Slate assets uploaded through the control API.
+//!
+//! An upload is written next to the current asset under a temporary name,
+//! checked to play, and renamed to an asset of its own. The previous asset is
+//! only removed once the slate switched over to the new one, so that a broken
+//! upload never replaces a working slate. The asset stays in the directory,
+//! and is played instead of `--slate-uri` after a restart.
+
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::slate_player;
+
+/// Name of the assets in the directory, followed by the time of their
+/// upload and their extension
+const STEM: &str = "slate";
+
+pub struct SlateUploads {
+    dir: PathBuf,
+    /// Held while storing an upload, one at a time
+    storing: Mutex<()>,
+}
+
+fn to_uri(path: &Path) -> Result<String, anyhow::Error> {
+    Ok(glib::filename_to_uri(path, None)?.to_string())
+}
+
+impl SlateUploads {
+    pub fn new(dir: &Path) -> Result<Self, anyhow::Error> {
+        std::fs::create_dir_all(dir)?;
+
+        Ok(Self {
+            dir: dir.to_path_buf(),
+            storing: Mutex::new(()),
+        })
+    }
+
+    /// Assets in the directory, whatever their extension
+    fn assets(&self) -> Vec<PathBuf> {
+        std::fs::read_dir(&self.dir)
+            .map(|entries| {
+                entries
+                    .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+                    .filter(|path| {
+                        path.file_stem()
+                            .and_then(|stem| stem.to_str())
+                            .map_or(false, |stem| {
+                                stem == STEM || stem.starts_with(&format!("{}-", STEM))
+                            })
+                    })
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    /// URI of the asset uploaded last, if any
+    pub fn current(&self) -> Option<String> {
+        self.assets()
+            .into_iter()
+            .max_by_key(|path| {
+                std::fs::metadata(path)
+                    .and_then(|metadata| metadata.modified())
+                    .unwrap_or(UNIX_EPOCH)
+            })
+            .and_then(|path| to_uri(&path).ok())
+    }
+
+    /// Check that `data` plays as the slate and make it the current asset,
+    /// `extension` tells its format, e.g. `png` or `mp4`. `activate` switches
+    /// the slate over to the URI of the new asset, which is removed again if
+    /// that fails, and the previous one otherwise. Blocks while the upload
+    /// prerolls, and while another one is being stored.
+    pub fn store<F>(&self, data: &[u8], extension: &str, activate: F) -> Result<(), anyhow::Error>
+    where
+        F: FnOnce(&str) -> Result<(), anyhow::Error>,
+    {
+        let extension = extension.to_lowercase();
+        if extension.is_empty() || !extension.chars().all(|c| c.is_ascii_alphanumeric()) {
+            anyhow::bail!("Invalid asset extension {:?}", extension);
+        }
+
+        let _storing = self.storing.lock().unwrap();
+
+        let tmp_path = self.dir.join(format!(".upload.{}", extension));
+        std::fs::write(&tmp_path, data)?;
+
+        if let Err(err) = slate_player::validate(&to_uri(&tmp_path)?) {
+            let _ = std::fs::remove_file(&tmp_path);
+            return Err(err);
+        }
+
+        let uploaded = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_millis();
+        let path = self
+            .dir
+            .join(format!("{}-{}.{}", STEM, uploaded, extension));
+        if let Err(err) = std::fs::rename(&tmp_path, &path) {
+            let _ = std::fs::remove_file(&tmp_path);
+            return Err(err.into());
+        }
+
+        if let Err(err) = to_uri(&path).and_then(|uri| activate(&uri)) {
+            let _ = std::fs::remove_file(&path);
+            return Err(err);
+        }
+
+        for old in self.assets().into_iter().filter(|old| *old != path) {
+            let _ = std::fs::remove_file(old);
+        }
+
+        info!("Stored new slate asset {}", path.display());
+
+        Ok(())
+    }
+}
@@ -0,0 +1,95 @@
+//! Snapshots of the program output, for dashboards to show what is on air.
+//!
+//! A branch off the output tee keeps the last frame in an `appsink`, and
+//! the frame is only encoded when a snapshot is asked for, so that the
+//! program does not pay for snapshots nobody looks at.
+
+use gst::prelude::*;
+
+use crate::hints;
+
+/// How long encoding a snapshot may take
+const CONVERT_TIMEOUT_MS: u64 = 1000;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ImageFormat {
+    Jpeg,
+    Png,
+}
+
+impl std::str::FromStr for ImageFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "jpeg" | "jpg" => Ok(ImageFormat::Jpeg),
+            "png" => Ok(ImageFormat::Png),
+            _ => Err(anyhow::anyhow!("Unknown image format {}", s)),
+        }
+    }
+}
+
+impl ImageFormat {
+    pub fn mime_type(self) -> &'static str {
+        match self {
+            ImageFormat::Jpeg => "image/jpeg",
+            ImageFormat::Png => "image/png",
+        }
+    }
+}
+
+pub struct Snapshot {
+    bin: gst::Bin,
+    sink: gst::Element,
+}
+
+impl Snapshot {
+    pub fn new() -> Result<Self, anyhow::Error> {
+        let bin = hints::parse_bin(
+            "queue leaky=downstream max-size-buffers=1 ! \
+             appsink name=snapshot sync=false max-buffers=1 drop=true",
+        )?;
+        let sink = bin.get_by_name("snapshot").unwrap();
+
+        Ok(Self { bin, sink })
+    }
+
+    /// Keep the last frame from `video_tee` in `pipe`
+    pub fn link(
+        &self,
+        pipe: &gst::Pipeline,
+        video_tee: &gst::Element,
+    ) -> Result<(), anyhow::Error> {
+        pipe.add(&self.bin)?;
+        video_tee.link(&self.bin)?;
+
+        Ok(())
+    }
+
+    /// The last frame of the output encoded as `format`, unset before the
+    /// first one
+    pub fn take(&self, format: ImageFormat) -> Result<Option<Vec<u8>>, anyhow::Error> {
+        let sample = match self
+            .sink
+            .get_property("last-sample")?
+            .get::<gst::Sample>()?
+        {
+            Some(sample) => sample,
+            None => return Ok(None),
+        };
+
+        let image = gst_video::convert_sample(
+            &sample,
+            &gst::Caps::new_simple(format.mime_type(), &[]),
+            gst::ClockTime::from_mseconds(CONVERT_TIMEOUT_MS),
+        )?;
+        let buffer = image
+            .get_buffer()
+            .ok_or_else(|| anyhow::anyhow!("Snapshot without data"))?;
+        let map = buffer
+            .map_readable()
+            .map_err(|_| anyhow::anyhow!("Failed to map snapshot"))?;
+
+        Ok(Some(map.as_slice().to_vec()))
+    }
+}
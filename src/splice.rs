@@ -0,0 +1,562 @@
+//! Compressed-domain splicing for `--passthrough-uri`.
+//!
+//! The live H.264 is tapped in front of the decoder and output as is, never
+//! re-encoded. Next to it, the program is encoded with the resolution,
+//! framerate and profile of the live stream, and output instead while the
+//! slate is on air. Cuts between the two only happen at an IDR frame of the
+//! stream cut to: the stream on air goes on until then, and a keyframe is
+//! requested from the program encoder when cutting to it. The SPS and PPS of
+//! the stream cut to are sent in front of that IDR frame unless it carries
+//! them already, and its timestamps are shifted to continue the output.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+
+use gst::prelude::*;
+
+use crate::cli::Args;
+use crate::hints;
+use crate::output::Encoding;
+use crate::status::Status;
+
+/// What the splicer works on, parameter sets in front of the slices of each
+/// access unit
+const AU_CAPS: &str = "video/x-h264,stream-format=byte-stream,alignment=au";
+
+const NAL_IDR: u8 = 5;
+const NAL_SPS: u8 = 7;
+const NAL_PPS: u8 = 8;
+const NAL_AUD: u8 = 9;
+
+/// Assumed until the output had a buffer with a duration
+const DEFAULT_DURATION_NS: u64 = 40_000_000;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Feed {
+    Live,
+    Program,
+}
+
+impl Feed {
+    fn index(self) -> usize {
+        match self {
+            Feed::Live => 0,
+            Feed::Program => 1,
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            Feed::Live => "live",
+            Feed::Program => "program",
+        }
+    }
+}
+
+#[derive(Default)]
+struct FeedState {
+    /// SPS and PPS seen last, with their start codes
+    parameter_sets: Vec<u8>,
+    caps: Option<gst::Caps>,
+    /// To tell when the stream restarted with new timestamps
+    last_dts: gst::ClockTime,
+}
+
+struct State {
+    feeds: [FeedState; 2],
+    /// Feed to cut to at its next IDR frame, or to stay on
+    target: Feed,
+    /// Feed output, unset before the first cut and once it restarted
+    on_air: Option<Feed>,
+    /// Shift of the timestamps of the feed on air, in nanoseconds
+    offset: i64,
+    last_dts: gst::ClockTime,
+    last_duration: gst::ClockTime,
+    caps: Option<gst::Caps>,
+}
+
+pub struct Splicer {
+    bin: gst::Bin,
+    /// Where the live H.264 tapped in front of the decoder goes
+    live_src: gst::Element,
+    /// Where the spliced H.264 goes
+    output: gst::Element,
+    program_sink: gst::Element,
+    /// Raw video format and H.264 profile the program is encoded with
+    raw_caps: gst::Element,
+    profile_caps: gst::Element,
+    live_caps: Mutex<Option<gst::Caps>>,
+    warned_codec: AtomicBool,
+    state: Mutex<State>,
+}
+
+/// Fail on the options splicing does not support
+pub fn check(args: &Args) -> Result<(), anyhow::Error> {
+    if args.live_rtmp_uri.len() > 1 {
+        anyhow::bail!("--passthrough-uri is not supported with backup inputs");
+    }
+
+    if args.time_shift.is_some() {
+        anyhow::bail!("--passthrough-uri is not supported with --time-shift");
+    }
+
+    Ok(())
+}
+
+/// Annex B NAL units of `data`, each with its start code
+fn nal_units(data: &[u8]) -> Vec<&[u8]> {
+    let mut starts = vec![];
+    let mut i = 0;
+
+    while i + 3 <= data.len() {
+        if data[i..i + 3] == [0, 0, 1] {
+            starts.push(if i > 0 && data[i - 1] == 0 { i - 1 } else { i });
+            i += 3;
+        } else {
+            i += 1;
+        }
+    }
+
+    starts
+        .iter()
+        .enumerate()
+        .map(|(n, start)| &data[*start..starts.get(n + 1).copied().unwrap_or(data.len())])
+        .collect()
+}
+
+fn nal_type(unit: &[u8]) -> Option<u8> {
+    let header = unit.windows(3).position(|bytes| bytes == [0, 0, 1])? + 3;
+
+    unit.get(header).map(|byte| byte & 0x1f)
+}
+
+/// `units` with `parameter_sets` inserted after the access unit delimiter,
+/// if any
+fn with_parameter_sets(units: &[&[u8]], parameter_sets: &[u8]) -> Vec<u8> {
+    let delimiters = units
+        .iter()
+        .take_while(|unit| nal_type(unit) == Some(NAL_AUD))
+        .count();
+    let mut data = units[..delimiters].concat();
+
+    data.extend_from_slice(parameter_sets);
+    data.extend(units[delimiters..].concat());
+
+    data
+}
+
+fn shift(ts: gst::ClockTime, offset: i64) -> gst::ClockTime {
+    match ts.nseconds() {
+        Some(ts) => gst::ClockTime::from_nseconds((ts as i64 + offset).max(0) as u64),
+        None => gst::CLOCK_TIME_NONE,
+    }
+}
+
+fn pull_sample(sink: &gst::Element) -> Option<gst::Sample> {
+    sink.emit("pull-sample", &[])
+        .ok()
+        .flatten()
+        .and_then(|value| value.get::<gst::Sample>().ok().flatten())
+}
+
+/// `buffer` with its timestamps in running time of the segment on `pad`
+fn to_running_time(pad: &gst::Pad, buffer: &gst::Buffer) -> Option<gst::Buffer> {
+    let event = pad.get_sticky_event(gst::EventType::Segment, 0)?;
+    let segment = match event.view() {
+        gst::EventView::Segment(segment) => segment
+            .get_segment()
+            .downcast_ref::<gst::ClockTime>()?
+            .clone(),
+        _ => return None,
+    };
+
+    let mut buffer = buffer.copy();
+    {
+        let buffer = buffer.get_mut().unwrap();
+        buffer.set_pts(segment.to_running_time(buffer.get_pts()));
+        buffer.set_dts(segment.to_running_time(buffer.get_dts()));
+    }
+
+    Some(buffer)
+}
+
+impl Splicer {
+    /// The program encoder, as per `encoding`, and the splicer, in a bin
+    /// taking the raw program video and outputting the spliced H.264
+    pub fn new(encoding: &Encoding) -> Result<Arc<Self>, anyhow::Error> {
+        let bin = hints::parse_bin(&format!(
+            "queue leaky=downstream ! videorate ! videoscale ! capsfilter name=splice_raw \
+             ! {chain} ! capsfilter name=splice_profile ! h264parse config-interval=-1 \
+             ! capsfilter caps=\"{caps}\" \
+             ! appsink name=splice_program emit-signals=true sync=false \
+             appsrc name=splice_live format=time is-live=true ! h264parse config-interval=-1 \
+             ! capsfilter caps=\"{caps}\" \
+             ! appsink name=splice_live_sink emit-signals=true sync=false \
+             appsrc name=splice_out format=time is-live=true ! h264parse ! queue",
+            chain = encoding.video_chain(),
+            caps = AU_CAPS
+        ))?;
+        bin.set_property("name", &"splice")?;
+
+        let splicer = Arc::new(Self {
+            live_src: bin.get_by_name("splice_live").unwrap(),
+            output: bin.get_by_name("splice_out").unwrap(),
+            program_sink: bin.get_by_name("splice_program").unwrap(),
+            raw_caps: bin.get_by_name("splice_raw").unwrap(),
+            profile_caps: bin.get_by_name("splice_profile").unwrap(),
+            bin,
+            live_caps: Mutex::new(None),
+            warned_codec: AtomicBool::new(false),
+            state: Mutex::new(State {
+                feeds: Default::default(),
+                target: Feed::Program,
+                on_air: None,
+                offset: 0,
+                last_dts: gst::CLOCK_TIME_NONE,
+                last_duration: gst::CLOCK_TIME_NONE,
+                caps: None,
+            }),
+        });
+
+        for (feed, name) in &[
+            (Feed::Live, "splice_live_sink"),
+            (Feed::Program, "splice_program"),
+        ] {
+            let feed = *feed;
+            let splicer_weak = Arc::downgrade(&splicer);
+            splicer
+                .bin
+                .get_by_name(name)
+                .unwrap()
+                .connect("new-sample", false, move |values| {
+                    let sink = values[0].get::<gst::Element>().unwrap().unwrap();
+
+                    if let (Some(splicer), Some(sample)) =
+                        (splicer_weak.upgrade(), pull_sample(&sink))
+                    {
+                        splicer.handle(feed, &sample);
+                    }
+
+                    Some(gst::FlowReturn::Ok.to_value())
+                })?;
+        }
+
+        Ok(splicer)
+    }
+
+    /// Bin with a `sink` pad for the raw program video and a `src` pad
+    /// for the spliced H.264
+    pub fn bin(&self) -> &gst::Bin {
+        &self.bin
+    }
+
+    /// Tap the live H.264 on the sink `pad` of the video decoder
+    pub fn install_live(self: &Arc<Self>, pad: &gst::Pad) {
+        let splicer = Arc::downgrade(self);
+
+        pad.add_probe(gst::PadProbeType::BUFFER, move |pad, info| {
+            if let (Some(splicer), Some(gst::PadProbeData::Buffer(buffer))) =
+                (splicer.upgrade(), &info.data)
+            {
+                splicer.push_live(pad, buffer);
+            }
+
+            gst::PadProbeReturn::Ok
+        });
+    }
+
+    fn push_live(&self, pad: &gst::Pad, buffer: &gst::Buffer) {
+        let caps = match pad.get_current_caps() {
+            Some(caps) => caps,
+            None => return,
+        };
+
+        let is_h264 = caps
+            .get_structure(0)
+            .map_or(false, |s| s.get_name() == "video/x-h264");
+        if !is_h264 {
+            if !self.warned_codec.swap(true, Ordering::SeqCst) {
+                warn!(
+                    "The live video is not H.264 but {}, the passthrough output re-encodes it",
+                    caps
+                );
+            }
+            return;
+        }
+
+        let buffer = match to_running_time(pad, buffer) {
+            Some(buffer) => buffer,
+            None => return,
+        };
+
+        let mut live_caps = self.live_caps.lock().unwrap();
+        if live_caps.as_ref() != Some(&caps) {
+            let _ = self.live_src.set_property("caps", &caps);
+            *live_caps = Some(caps);
+        }
+
+        let _ = self.live_src.emit("push-buffer", &[&buffer]);
+    }
+
+    /// Encode the program like the live stream with `caps`, for the
+    /// decoders downstream to see the same format across cuts
+    fn match_live(&self, caps: &gst::CapsRef) {
+        let s = match caps.get_structure(0) {
+            Some(s) => s,
+            None => return,
+        };
+        let (width, height) = match (s.get_some::<i32>("width"), s.get_some::<i32>("height")) {
+            (Ok(width), Ok(height)) => (width, height),
+            _ => return,
+        };
+
+        let mut raw =
+            gst::Caps::new_simple("video/x-raw", &[("width", &width), ("height", &height)]);
+        {
+            let structure = raw.get_mut().unwrap().get_mut_structure(0).unwrap();
+
+            /* 0/1 for variable framerates */
+            if let Ok(framerate) = s.get_some::<gst::Fraction>("framerate") {
+                if *framerate.numer() > 0 {
+                    structure.set("framerate", &framerate);
+                }
+            }
+
+            if let Ok(par) = s.get_some::<gst::Fraction>("pixel-aspect-ratio") {
+                structure.set("pixel-aspect-ratio", &par);
+            }
+        }
+
+        let mut h264 = gst::Caps::new_simple("video/x-h264", &[]);
+        if let Ok(Some(profile)) = s.get::<&str>("profile") {
+            h264.get_mut()
+                .unwrap()
+                .get_mut_structure(0)
+                .unwrap()
+                .set("profile", &profile);
+        }
+
+        info!("Encoding the program for splicing as {} {}", raw, h264);
+
+        let _ = self.raw_caps.set_property("caps", &raw);
+        let _ = self.profile_caps.set_property("caps", &h264);
+    }
+
+    /// Output `sample` of `feed` if on air, or cut to it at its IDR frames
+    fn handle(&self, feed: Feed, sample: &gst::Sample) {
+        let (buffer, caps) = match (sample.get_buffer(), sample.get_caps()) {
+            (Some(buffer), Some(caps)) => (buffer, caps),
+            _ => return,
+        };
+        let map = match buffer.map_readable() {
+            Ok(map) => map,
+            Err(_) => return,
+        };
+        let units = nal_units(map.as_slice());
+        let types = units
+            .iter()
+            .filter_map(|unit| nal_type(unit))
+            .collect::<Vec<_>>();
+        let dts = if buffer.get_dts().is_some() {
+            buffer.get_dts()
+        } else {
+            buffer.get_pts()
+        };
+
+        let mut state = self.state.lock().unwrap();
+
+        let feed_state = &mut state.feeds[feed.index()];
+        if types.contains(&NAL_SPS) {
+            feed_state.parameter_sets = units
+                .iter()
+                .filter(|unit| matches!(nal_type(unit), Some(NAL_SPS) | Some(NAL_PPS)))
+                .flat_map(|unit| unit.iter().copied())
+                .collect();
+        }
+
+        /* A restarted stream starts over from an IDR frame, with timestamps
+         * unrelated to the previous ones */
+        let restarted = dts.is_some() && feed_state.last_dts.is_some() && dts < feed_state.last_dts;
+        feed_state.last_dts = dts;
+
+        let caps_changed = feed_state.caps.as_deref() != Some(caps);
+        if caps_changed {
+            feed_state.caps = Some(caps.to_owned());
+
+            if feed == Feed::Live {
+                self.match_live(caps);
+            }
+        }
+
+        if (restarted || caps_changed) && state.on_air == Some(feed) {
+            info!(
+                "The {} stream restarted, splicing it again at its next IDR frame",
+                feed.name()
+            );
+            state.on_air = None;
+        }
+
+        let cut = types.contains(&NAL_IDR) && state.target == feed && state.on_air != Some(feed);
+        if !cut && state.on_air != Some(feed) {
+            return;
+        }
+
+        if cut {
+            let ts = match dts.nseconds() {
+                Some(ts) => ts as i64,
+                None => return,
+            };
+            /* The program is timestamped in running time of the output
+             * already, the live stream of its own pipeline */
+            let aligned = match feed {
+                Feed::Live => self.running_time().map_or(0, |now| now as i64 - ts),
+                Feed::Program => 0,
+            };
+            /* Never going back in time over the cut */
+            let continuous = state.last_dts.nseconds().map(|last_dts| {
+                (last_dts
+                    + state
+                        .last_duration
+                        .nseconds()
+                        .unwrap_or(DEFAULT_DURATION_NS)) as i64
+                    - ts
+            });
+
+            state.offset = continuous.map_or(aligned, |continuous| continuous.max(aligned));
+            state.on_air = Some(feed);
+
+            info!("Spliced to the {} stream at an IDR frame", feed.name());
+        }
+
+        let mut out = if cut && !types.contains(&NAL_SPS) {
+            let data = with_parameter_sets(&units, &state.feeds[feed.index()].parameter_sets);
+            let mut out = gst::Buffer::from_mut_slice(data);
+            {
+                let out = out.get_mut().unwrap();
+                out.set_flags(buffer.get_flags());
+                out.set_duration(buffer.get_duration());
+            }
+            out
+        } else {
+            buffer.copy()
+        };
+        {
+            let out = out.get_mut().unwrap();
+            out.set_pts(shift(buffer.get_pts(), state.offset));
+            out.set_dts(shift(buffer.get_dts(), state.offset));
+            if cut {
+                out.set_flags(gst::BufferFlags::DISCONT);
+            }
+        }
+
+        state.last_dts = shift(dts, state.offset);
+        if buffer.get_duration().is_some() {
+            state.last_duration = buffer.get_duration();
+        }
+
+        /* Pushed with the lock held, so that the last access unit of the
+         * previous feed never comes after the cut */
+        if state.caps.as_deref() != Some(caps) {
+            let caps = caps.to_owned();
+            let _ = self.output.set_property("caps", &caps);
+            state.caps = Some(caps);
+        }
+
+        let _ = self.output.emit("push-buffer", &[&out]);
+    }
+
+    /// Current running time of the output
+    fn running_time(&self) -> Option<u64> {
+        let clock = self.output.get_clock()?;
+
+        (clock.get_time() - self.output.get_base_time()).nseconds()
+    }
+
+    /// Cut to the live stream or to the program at their next IDR frame,
+    /// requesting one from the program encoder
+    fn select(&self, live: bool) {
+        let feed = if live { Feed::Live } else { Feed::Program };
+
+        let mut state = self.state.lock().unwrap();
+        if state.target == feed {
+            return;
+        }
+        state.target = feed;
+        let on_air = state.on_air;
+        drop(state);
+
+        info!(
+            "Splicing to the {} stream at its next IDR frame",
+            feed.name()
+        );
+
+        if feed == Feed::Program && on_air != Some(feed) {
+            self.program_sink.send_event(
+                gst_video::UpstreamForceKeyUnitEvent::builder()
+                    .all_headers(true)
+                    .build(),
+            );
+        }
+    }
+
+    /// Splice along with what `status` shows on air
+    pub fn start(self: &Arc<Self>, status: &Arc<Status>) {
+        let splicer = self.clone();
+        status.connect_live_changed(move |live| splicer.select(live));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const AUD: &[u8] = &[0, 0, 0, 1, 0x09, 0xf0];
+    const SPS: &[u8] = &[0, 0, 0, 1, 0x67, 0x64, 0x00, 0x1f];
+    const PPS: &[u8] = &[0, 0, 0, 1, 0x68, 0xee, 0x3c, 0x80];
+    const IDR: &[u8] = &[0, 0, 1, 0x65, 0x88, 0x84, 0x00];
+
+    #[test]
+    fn splits_nal_units() {
+        let data = [AUD, SPS, PPS, IDR].concat();
+        let units = nal_units(&data);
+
+        assert_eq!(units, vec![AUD, SPS, PPS, IDR]);
+        assert_eq!(
+            units
+                .iter()
+                .filter_map(|unit| nal_type(unit))
+                .collect::<Vec<_>>(),
+            vec![NAL_AUD, NAL_SPS, NAL_PPS, NAL_IDR]
+        );
+    }
+
+    #[test]
+    fn inserts_parameter_sets_after_delimiter() {
+        let parameter_sets = [SPS, PPS].concat();
+
+        assert_eq!(
+            with_parameter_sets(&[AUD, IDR], &parameter_sets),
+            [AUD, SPS, PPS, IDR].concat()
+        );
+        assert_eq!(
+            with_parameter_sets(&[IDR], &parameter_sets),
+            [SPS, PPS, IDR].concat()
+        );
+    }
+
+    #[test]
+    fn shifts_timestamps() {
+        assert_eq!(
+            shift(gst::ClockTime::from_mseconds(40), 20_000_000),
+            gst::ClockTime::from_mseconds(60)
+        );
+        assert_eq!(
+            shift(gst::ClockTime::from_mseconds(40), -80_000_000),
+            gst::ClockTime::from_nseconds(0)
+        );
+        assert_eq!(
+            shift(gst::CLOCK_TIME_NONE, 20_000_000),
+            gst::CLOCK_TIME_NONE
+        );
+    }
+}
@@ -0,0 +1,79 @@
+//! SRT input options.
+//!
+//! playbin picks `srtsrc` for `srt://` URIs on its own, the connection mode,
+//! encryption and latency are configured on it once it is created.
+
+use std::time::Duration;
+
+use gst::prelude::*;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SrtMode {
+    Caller,
+    Listener,
+    Rendezvous,
+}
+
+impl SrtMode {
+    fn nick(self) -> &'static str {
+        match self {
+            SrtMode::Caller => "caller",
+            SrtMode::Listener => "listener",
+            SrtMode::Rendezvous => "rendezvous",
+        }
+    }
+}
+
+impl std::str::FromStr for SrtMode {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "caller" => Ok(SrtMode::Caller),
+            "listener" => Ok(SrtMode::Listener),
+            "rendezvous" => Ok(SrtMode::Rendezvous),
+            _ => Err(anyhow::anyhow!("Unknown SRT mode {}", s)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct SrtOptions {
+    pub mode: Option<SrtMode>,
+    pub passphrase: Option<String>,
+    /// AES key length in bits
+    pub key_length: Option<u32>,
+    pub latency: Option<Duration>,
+}
+
+impl SrtOptions {
+    /// Apply the options to `source` if it is an SRT source, options that
+    /// are not set keep the values from the URI or the defaults
+    pub fn configure(&self, source: &gst::Element) -> Result<(), anyhow::Error> {
+        let is_srt = source
+            .get_factory()
+            .map_or(false, |factory| factory.get_name().starts_with("srt"));
+
+        if !is_srt {
+            return Ok(());
+        }
+
+        if let Some(mode) = self.mode {
+            source.set_property_from_str("mode", mode.nick());
+        }
+
+        if let Some(ref passphrase) = self.passphrase {
+            source.set_property("passphrase", passphrase)?;
+        }
+
+        if let Some(key_length) = self.key_length {
+            source.set_property_from_str("pbkeylen", &(key_length / 8).to_string());
+        }
+
+        if let Some(latency) = self.latency {
+            source.set_property("latency", &(latency.as_millis() as i32))?;
+        }
+
+        Ok(())
+    }
+}
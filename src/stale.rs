@@ -0,0 +1,95 @@
+//! Detection of buffers whose timestamps stopped advancing.
+//!
+//! Some broken encoders keep sending data with a frozen timestamp. The
+//! buffers keep the source looking healthy, while the compositor drops them
+//! all as late and starves silently. We error out the source pipeline
+//! instead, which gets it restarted.
+
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use gst::prelude::*;
+
+struct State {
+    last_pts: gst::ClockTime,
+    last_advance: Instant,
+    errored: bool,
+}
+
+pub struct StaleTimestampDetector {
+    timeout: Duration,
+    state: Mutex<State>,
+}
+
+impl StaleTimestampDetector {
+    pub fn new(timeout: Duration) -> Arc<Self> {
+        Arc::new(Self {
+            timeout,
+            state: Mutex::new(State {
+                last_pts: gst::CLOCK_TIME_NONE,
+                last_advance: Instant::now(),
+                errored: false,
+            }),
+        })
+    }
+
+    fn reset(&self) {
+        let mut state = self.state.lock().unwrap();
+        state.last_pts = gst::CLOCK_TIME_NONE;
+        state.last_advance = Instant::now();
+        state.errored = false;
+    }
+
+    /// Returns whether the timestamps are now considered stale
+    fn handle_buffer(&self, buffer: &gst::BufferRef) -> bool {
+        let mut state = self.state.lock().unwrap();
+        let pts = buffer.get_pts();
+
+        if pts.is_none() {
+            return false;
+        }
+
+        if state.last_pts.is_none() || pts > state.last_pts {
+            state.last_pts = pts;
+            state.last_advance = Instant::now();
+            return false;
+        }
+
+        if !state.errored && state.last_advance.elapsed() > self.timeout {
+            state.errored = true;
+            return true;
+        }
+
+        false
+    }
+
+    /// Watch the timestamps of the buffers flowing through `pad`, state is
+    /// reset on every new segment as timestamps restart along with the source
+    pub fn install(self: &Arc<Self>, pad: &gst::Pad) {
+        let detector = self.clone();
+        pad.add_probe(
+            gst::PadProbeType::BUFFER | gst::PadProbeType::EVENT_DOWNSTREAM,
+            move |pad, info| {
+                match info.data {
+                    Some(gst::PadProbeData::Buffer(ref buffer)) => {
+                        if detector.handle_buffer(buffer) {
+                            let element = pad.get_parent_element().unwrap();
+                            gst::gst_element_error!(
+                                element,
+                                gst::StreamError::Failed,
+                                ["Buffer timestamps stopped advancing at {}", buffer.get_pts()]
+                            );
+                        }
+                    }
+                    Some(gst::PadProbeData::Event(ref event))
+                        if event.get_type() == gst::EventType::Segment =>
+                    {
+                        detector.reset();
+                    }
+                    _ => (),
+                }
+                gst::PadProbeReturn::Ok
+            },
+        );
+    }
+}
@@ -0,0 +1,285 @@
+//! Single status line summarizing what is on air, updated in place.
+
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use gst::prelude::*;
+
+use crate::bitrate::BitrateMonitor;
+use crate::logging::{self, Level};
+
+/// Live buffers older than this mean the compositor repeats the last one
+const FROZEN_AFTER: Duration = Duration::from_secs(1);
+
+/// How often what is on air is checked, to track the time on slate, log
+/// changes and notify switches, often enough for fades to start in time
+const TRACK_INTERVAL_MS: u32 = 40;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum OnAir {
+    Connecting,
+    Buffering(i32),
+    Live,
+    /// The compositor repeats the last live buffer
+    Frozen,
+    Slate,
+}
+
+impl OnAir {
+    pub fn label(self) -> String {
+        match self {
+            OnAir::Connecting => "CONNECTING".to_string(),
+            OnAir::Buffering(percent) => format!("BUFFERING {:3}%", percent),
+            OnAir::Live => "LIVE".to_string(),
+            OnAir::Frozen => "FROZEN".to_string(),
+            OnAir::Slate => "SLATE".to_string(),
+        }
+    }
+
+    /// Name in structured log events
+    fn name(self) -> &'static str {
+        match self {
+            OnAir::Connecting => "connecting",
+            OnAir::Buffering(_) => "buffering",
+            OnAir::Live => "live",
+            OnAir::Frozen => "frozen",
+            OnAir::Slate => "slate",
+        }
+    }
+
+    /// ANSI color code
+    fn color(self) -> u8 {
+        match self {
+            OnAir::Live => 32,
+            OnAir::Connecting | OnAir::Buffering(_) | OnAir::Frozen => 33,
+            OnAir::Slate => 31,
+        }
+    }
+}
+
+#[derive(Default)]
+struct State {
+    buffering: Option<i32>,
+    last_live: Option<Instant>,
+    slate_since: Option<Instant>,
+}
+
+type LiveHandler = Box<dyn FnMut(bool) + Send>;
+
+pub struct Status {
+    uri: Mutex<String>,
+    discard_after: Option<Duration>,
+    state: Mutex<State>,
+    live_handlers: Mutex<Vec<LiveHandler>>,
+}
+
+/// Format `duration` as HH:MM:SS
+pub fn format_duration(duration: Duration) -> String {
+    let secs = duration.as_secs();
+    format!("{:02}:{:02}:{:02}", secs / 3600, secs / 60 % 60, secs % 60)
+}
+
+impl Status {
+    pub fn new(uri: &str, discard_after: Option<Duration>) -> Arc<Self> {
+        Arc::new(Self {
+            uri: Mutex::new(uri.to_string()),
+            discard_after,
+            state: Mutex::new(State::default()),
+            live_handlers: Mutex::new(vec![]),
+        })
+    }
+
+    /// URI of the live source
+    pub fn uri(&self) -> String {
+        self.uri.lock().unwrap().clone()
+    }
+
+    pub fn set_uri(&self, uri: &str) {
+        *self.uri.lock().unwrap() = uri.to_string();
+    }
+
+    /// Buffering level of the source, while it is buffering
+    pub fn buffering(&self) -> Option<i32> {
+        self.state.lock().unwrap().buffering
+    }
+
+    pub fn set_buffering(&self, percent: i32) {
+        let mut state = self.state.lock().unwrap();
+        let buffering = if percent < 100 { Some(percent) } else { None };
+
+        match (state.buffering, buffering) {
+            (None, Some(_)) => logging::event(
+                Level::Info,
+                "buffering-started",
+                "Source buffering",
+                serde_json::json!({ "percent": percent }),
+            ),
+            (Some(_), None) => logging::event(
+                Level::Info,
+                "buffering-finished",
+                "Source done buffering",
+                serde_json::json!({}),
+            ),
+            (Some(_), Some(_)) => logging::event(
+                Level::Debug,
+                "buffering",
+                "Source buffering",
+                serde_json::json!({ "percent": percent }),
+            ),
+            (None, None) => (),
+        }
+
+        state.buffering = buffering;
+    }
+
+    /// Track the live buffers reaching the compositor through `pad`
+    pub fn watch_live(self: &Arc<Self>, pad: &gst::Pad) {
+        let status = self.clone();
+        pad.add_probe(gst::PadProbeType::BUFFER, move |_, _| {
+            status.state.lock().unwrap().last_live = Some(Instant::now());
+            gst::PadProbeReturn::Ok
+        });
+    }
+
+    /// Whether the compositor outputs live video, possibly repeating the
+    /// last frame, rather than the slate
+    pub fn shows_live(&self) -> bool {
+        match self.state.lock().unwrap().last_live {
+            Some(last_live) => self
+                .discard_after
+                .map_or(true, |discard_after| last_live.elapsed() <= discard_after),
+            None => false,
+        }
+    }
+
+    pub fn on_air(&self) -> OnAir {
+        let state = self.state.lock().unwrap();
+
+        let since_live = match state.last_live {
+            Some(last_live) => last_live.elapsed(),
+            None => {
+                return match state.buffering {
+                    Some(percent) => OnAir::Buffering(percent),
+                    None => OnAir::Connecting,
+                }
+            }
+        };
+
+        if since_live < FROZEN_AFTER {
+            OnAir::Live
+        } else if self
+            .discard_after
+            .map_or(false, |discard_after| since_live > discard_after)
+        {
+            OnAir::Slate
+        } else if let Some(percent) = state.buffering {
+            OnAir::Buffering(percent)
+        } else {
+            OnAir::Frozen
+        }
+    }
+
+    /// Time spent on the slate since the last switch to it
+    pub fn time_on_slate(&self) -> Option<Duration> {
+        self.state
+            .lock()
+            .unwrap()
+            .slate_since
+            .map(|since| since.elapsed())
+    }
+
+    fn update(&self) -> OnAir {
+        let on_air = self.on_air();
+        let mut state = self.state.lock().unwrap();
+
+        match on_air {
+            OnAir::Live => state.slate_since = None,
+            OnAir::Slate | OnAir::Connecting => {
+                state.slate_since.get_or_insert_with(Instant::now);
+            }
+            _ => (),
+        }
+
+        on_air
+    }
+
+    fn line(&self, bitrate: &BitrateMonitor, color: bool) -> String {
+        let on_air = self.on_air();
+        let label = if color {
+            format!("\x1b[1;{}m{:<14}\x1b[0m", on_air.color(), on_air.label())
+        } else {
+            format!("{:<14}", on_air.label())
+        };
+
+        let mut line = format!(
+            "{} {} {:>8.1} kbps",
+            label,
+            self.uri.lock().unwrap(),
+            bitrate.bitrate() as f64 / 1000.0
+        );
+
+        if let Some(time_on_slate) = self.time_on_slate() {
+            line.push_str(&format!(" on slate for {}", format_duration(time_on_slate)));
+        }
+
+        line
+    }
+
+    /// Call `f` from the main loop with whether the compositor outputs live
+    /// video whenever that changes, the output starting on the slate. All
+    /// handlers see the same switches, however short.
+    pub fn connect_live_changed<F: FnMut(bool) + Send + 'static>(&self, f: F) {
+        self.live_handlers.lock().unwrap().push(Box::new(f));
+    }
+
+    /// Track the time on slate, whether the status line is printed or not,
+    /// log an event whenever what is on air changes, switches to the slate
+    /// as warnings, and notify the switches between live and slate
+    pub fn log_changes(self: &Arc<Self>) {
+        let status = self.clone();
+        let mut previous = OnAir::Connecting;
+        let mut live = false;
+        glib::timeout_add(TRACK_INTERVAL_MS, move || {
+            let shows_live = status.shows_live();
+
+            if shows_live != live {
+                live = shows_live;
+                for handler in status.live_handlers.lock().unwrap().iter_mut() {
+                    handler(live);
+                }
+            }
+
+            let on_air = status.update();
+
+            if on_air.name() != previous.name() {
+                logging::event(
+                    if on_air == OnAir::Slate {
+                        Level::Warn
+                    } else {
+                        Level::Info
+                    },
+                    "on-air-changed",
+                    &format!("Now {}", on_air.name()),
+                    serde_json::json!({
+                        "from": previous.name(),
+                        "to": on_air.name(),
+                        "uri": status.uri(),
+                    }),
+                );
+                previous = on_air;
+            }
+
+            glib::Continue(true)
+        });
+    }
+
+    /// Refresh the status line every second
+    pub fn start(self: &Arc<Self>, bitrate: &Arc<BitrateMonitor>, color: bool) {
+        let status = self.clone();
+        let bitrate = bitrate.clone();
+        glib::timeout_add_seconds(1, move || {
+            crate::logging::status_line(&status.line(&bitrate, color));
+            glib::Continue(true)
+        });
+    }
+}
@@ -0,0 +1,44 @@
+//! Timestamping of the live video and audio entering the output pipeline.
+//!
+//! `interpipesrc` either passes the timestamps of the RTMP pipeline through,
+//! restarts them from its own running time, or compensates for the base time
+//! difference between the two pipelines. Unless they are passed through,
+//! QoS events from the preview sink carry timestamps the RTMP pipeline knows
+//! nothing about, and interpipe does not translate them, so the sink must
+//! not send any.
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum StreamSync {
+    PassthroughTs,
+    RestartTs,
+    CompensateTs,
+}
+
+impl std::str::FromStr for StreamSync {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "passthrough-ts" => Ok(StreamSync::PassthroughTs),
+            "restart-ts" => Ok(StreamSync::RestartTs),
+            "compensate-ts" => Ok(StreamSync::CompensateTs),
+            _ => Err(anyhow::anyhow!("Unknown stream-sync mode {}", s)),
+        }
+    }
+}
+
+impl StreamSync {
+    /// Nick of the `stream-sync` property value
+    pub fn nick(self) -> &'static str {
+        match self {
+            StreamSync::PassthroughTs => "passthrough-ts",
+            StreamSync::RestartTs => "restart-ts",
+            StreamSync::CompensateTs => "compensate-ts",
+        }
+    }
+
+    /// Whether sinks downstream must have QoS disabled
+    pub fn breaks_qos(self) -> bool {
+        self != StreamSync::PassthroughTs
+    }
+}
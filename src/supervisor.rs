@@ -0,0 +1,280 @@
+//! The `supervise` command, running one child process per channel.
+//!
+//! Each line of the channels file holds a channel name followed by the
+//! command line arguments of that channel, quoted as in a POSIX shell:
+//!
+//! ```text
+//! # name   arguments
+//! news     --live-rtmp-uri rtmp://ingest/news --headless --health-port 8081
+//! sports   --live-rtmp-uri rtmp://ingest/sports --headless --slate-error-text 'Back soon'
+//! ```
+//!
+//! Children crashing are restarted, while those failing for reasons a restart
+//! does not fix, such as invalid arguments, are left stopped.
+
+use std::path::Path;
+use std::process::{Child, Command};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use anyhow::{anyhow, bail, Context as _};
+
+use crate::exit_code::ExitCode;
+
+/// How often children are checked on
+const POLL_INTERVAL_MS: u32 = 200;
+
+/// How often the aggregate status is logged
+const STATUS_INTERVAL_SECS: u32 = 60;
+
+struct Channel {
+    name: String,
+    args: Vec<String>,
+    child: Option<Child>,
+    restarts: u32,
+    restart_at: Option<Instant>,
+    stopped: bool,
+}
+
+/// Split `line` into words, honouring single and double quotes and
+/// backslash escapes
+fn split_words(line: &str) -> Result<Vec<String>, anyhow::Error> {
+    let mut words = vec![];
+    let mut word: Option<String> = None;
+    let mut quote = None;
+    let mut chars = line.chars();
+
+    while let Some(c) = chars.next() {
+        match (quote, c) {
+            (Some(q), c) if c == q => quote = None,
+            (Some('"'), '\\') | (None, '\\') => {
+                let escaped = chars.next().ok_or_else(|| anyhow!("Trailing backslash"))?;
+                word.get_or_insert_with(String::new).push(escaped);
+            }
+            (Some(_), c) => word.get_or_insert_with(String::new).push(c),
+            (None, '\'') | (None, '"') => {
+                quote = Some(c);
+                word.get_or_insert_with(String::new);
+            }
+            (None, c) if c.is_whitespace() => words.extend(word.take()),
+            (None, c) => word.get_or_insert_with(String::new).push(c),
+        }
+    }
+
+    if quote.is_some() {
+        bail!("Unterminated quote");
+    }
+
+    words.extend(word);
+
+    Ok(words)
+}
+
+fn parse_channels(path: &Path) -> Result<Vec<Channel>, anyhow::Error> {
+    let contents = std::fs::read_to_string(path)
+        .with_context(|| format!("Failed to read channels from {}", path.display()))?;
+    let mut channels = vec![];
+
+    for (lineno, line) in contents.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let mut words = split_words(line)
+            .with_context(|| format!("{}:{}", path.display(), lineno + 1))?
+            .into_iter();
+        let name = words.next().unwrap();
+
+        if channels.iter().any(|channel: &Channel| channel.name == name) {
+            bail!("{}:{}: duplicate channel {}", path.display(), lineno + 1, name);
+        }
+
+        channels.push(Channel {
+            name,
+            args: words.collect(),
+            child: None,
+            restarts: 0,
+            restart_at: Some(Instant::now()),
+            stopped: false,
+        });
+    }
+
+    if channels.is_empty() {
+        bail!("No channels in {}", path.display());
+    }
+
+    Ok(channels)
+}
+
+impl Channel {
+    fn spawn(&mut self) {
+        let program = match std::env::current_exe() {
+            Ok(program) => program,
+            Err(err) => {
+                error!("Failed to locate our own executable: {}", err);
+                self.stopped = true;
+                return;
+            }
+        };
+
+        match Command::new(program).args(&self.args).spawn() {
+            Ok(child) => {
+                info!("Started channel {} with PID {}", self.name, child.id());
+                self.child = Some(child);
+            }
+            Err(err) => {
+                error!("Failed to start channel {}: {}", self.name, err);
+                self.stopped = true;
+            }
+        }
+    }
+
+    /// Reap the child if it exited, and decide whether to restart it
+    fn check(&mut self, restart_delay: Duration) {
+        let status = match self.child.as_mut().map(Child::try_wait) {
+            Some(Ok(Some(status))) => status,
+            Some(Ok(None)) | None => return,
+            Some(Err(err)) => {
+                error!("Failed to check on channel {}: {}", self.name, err);
+                return;
+            }
+        };
+
+        self.child = None;
+
+        match status.code() {
+            Some(code) if code == ExitCode::Success.code() => {
+                info!("Channel {} finished", self.name);
+                self.stopped = true;
+            }
+            Some(code)
+                if code == ExitCode::Config.code() || code == ExitCode::MissingPlugin.code() =>
+            {
+                error!(
+                    "Channel {} failed with exit code {}, restarting would not help",
+                    self.name, code
+                );
+                self.stopped = true;
+            }
+            _ => {
+                warn!(
+                    "Channel {} exited with {}, restarting in {:?}",
+                    self.name, status, restart_delay
+                );
+                self.restarts += 1;
+                self.restart_at = Some(Instant::now() + restart_delay);
+            }
+        }
+    }
+
+    fn poll(&mut self, restart_delay: Duration) {
+        self.check(restart_delay);
+
+        if self.child.is_none() && !self.stopped {
+            if let Some(restart_at) = self.restart_at {
+                if Instant::now() >= restart_at {
+                    self.restart_at = None;
+                    self.spawn();
+                }
+            }
+        }
+    }
+
+    fn terminate(&self) {
+        if let Some(ref child) = self.child {
+            unsafe {
+                libc::kill(child.id() as libc::pid_t, libc::SIGTERM);
+            }
+        }
+    }
+
+    fn wait(&mut self) {
+        if let Some(mut child) = self.child.take() {
+            let _ = child.wait();
+        }
+    }
+}
+
+fn log_status(channels: &[Channel]) {
+    let running = channels
+        .iter()
+        .filter(|channel| channel.child.is_some())
+        .count();
+
+    crate::logging::event(
+        crate::logging::Level::Info,
+        "channels",
+        &format!("{}/{} channels running", running, channels.len()),
+        serde_json::json!({
+            "channels": channels
+                .iter()
+                .map(|channel| serde_json::json!({
+                    "name": channel.name,
+                    "running": channel.child.is_some(),
+                    "stopped": channel.stopped,
+                    "restarts": channel.restarts,
+                }))
+                .collect::<Vec<_>>(),
+        }),
+    );
+}
+
+/// Supervise the channels listed in `path` until terminated or all of them
+/// stopped for good
+pub fn run(path: &Path, restart_delay: Duration) -> Result<ExitCode, anyhow::Error> {
+    let channels = Arc::new(Mutex::new(parse_channels(path)?));
+    let main_loop = glib::MainLoop::new(None, false);
+    let exit_code = Arc::new(Mutex::new(ExitCode::Success));
+
+    for signal in &[libc::SIGINT, libc::SIGTERM] {
+        let signal = *signal;
+        let main_loop = main_loop.clone();
+        let exit_code = exit_code.clone();
+        glib::unix_signal_add(signal, move || {
+            info!("Received termination signal, stopping channels");
+            *exit_code.lock().unwrap() = ExitCode::Signal(signal);
+            main_loop.quit();
+            glib::Continue(false)
+        });
+    }
+
+    let channels_clone = channels.clone();
+    let main_loop_clone = main_loop.clone();
+    glib::timeout_add(POLL_INTERVAL_MS, move || {
+        let mut channels = channels_clone.lock().unwrap();
+
+        for channel in channels.iter_mut() {
+            channel.poll(restart_delay);
+        }
+
+        if channels.iter().all(|channel| channel.stopped) {
+            info!("All channels stopped");
+            main_loop_clone.quit();
+            return glib::Continue(false);
+        }
+
+        glib::Continue(true)
+    });
+
+    let channels_clone = channels.clone();
+    glib::timeout_add_seconds(STATUS_INTERVAL_SECS, move || {
+        log_status(&channels_clone.lock().unwrap());
+        glib::Continue(true)
+    });
+
+    main_loop.run();
+
+    /* Let the channels drain in parallel */
+    let mut channels = channels.lock().unwrap();
+    for channel in channels.iter() {
+        channel.terminate();
+    }
+    for channel in channels.iter_mut() {
+        channel.wait();
+    }
+
+    let exit_code = *exit_code.lock().unwrap();
+
+    Ok(exit_code)
+}
@@ -0,0 +1,66 @@
+//! Switching between the live video and the slate.
+//!
+//! By default the compositor does the switching, as the live pad is layered
+//! over the slate and its last buffer is only repeated for
+//! `--discard-after`. Alternatively `fallbackswitch` from gst-plugins-rs
+//! switches to the slate as soon as the live video times out, on a frame
+//! boundary, and the compositor only renders its output.
+
+use std::time::Duration;
+
+use gst::prelude::*;
+
+use crate::gap::{self, HoldMode};
+use crate::hints;
+
+/// Timeout that never expires, as without `--discard-after` the last live
+/// frame is repeated forever
+const NEVER: Duration = Duration::from_secs(100 * 365 * 24 * 3600);
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SwitchMode {
+    Compositor,
+    FallbackSwitch,
+}
+
+impl std::str::FromStr for SwitchMode {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "compositor" => Ok(SwitchMode::Compositor),
+            "fallbackswitch" => Ok(SwitchMode::FallbackSwitch),
+            _ => Err(anyhow::anyhow!("Unknown switch mode {}", s)),
+        }
+    }
+}
+
+/// A `fallbackswitch` timing out the live video after `discard_after`, or
+/// right away in gap mode
+pub fn build_fallbackswitch(
+    hold: HoldMode,
+    discard_after: Option<Duration>,
+) -> Result<gst::Element, anyhow::Error> {
+    let switch = hints::make_element("fallbackswitch", Some("switch"))?;
+
+    let timeout = match hold {
+        HoldMode::Repeat => discard_after.unwrap_or(NEVER),
+        HoldMode::Gap => gap::STALL_AFTER,
+    };
+    switch.set_property("timeout", &(timeout.as_nanos() as u64))?;
+
+    Ok(switch)
+}
+
+/// Give the pad `element` links to on `switch` the `priority`, lower is
+/// preferred
+pub fn set_priority(element: &gst::Element, priority: u32) -> Result<(), anyhow::Error> {
+    let pad = element
+        .get_static_pad("src")
+        .and_then(|pad| pad.get_peer())
+        .ok_or_else(|| anyhow::anyhow!("{} is not linked", element.get_name()))?;
+
+    pad.set_property("priority", &priority)?;
+
+    Ok(())
+}
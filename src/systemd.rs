@@ -0,0 +1,80 @@
+//! Minimal implementation of the systemd notification protocol, see
+//! sd_notify(3). All functions are no-ops when not running under systemd.
+
+use std::os::unix::net::UnixDatagram;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+
+static READY: AtomicBool = AtomicBool::new(false);
+
+fn notify(state: &str) -> Result<(), anyhow::Error> {
+    let path = match std::env::var("NOTIFY_SOCKET") {
+        Ok(path) => path,
+        Err(_) => return Ok(()),
+    };
+
+    let socket = UnixDatagram::unbound()?;
+
+    if let Some(name) = path.strip_prefix('@') {
+        use std::os::linux::net::SocketAddrExt;
+        let addr = std::os::unix::net::SocketAddr::from_abstract_name(name)?;
+        socket.send_to_addr(state.as_bytes(), &addr)?;
+    } else {
+        socket.send_to(state.as_bytes(), &path)?;
+    }
+
+    Ok(())
+}
+
+/// Signal readiness to the service manager, only the first call has an effect
+pub fn notify_ready() {
+    if READY.swap(true, Ordering::SeqCst) {
+        return;
+    }
+
+    if let Err(err) = notify("READY=1") {
+        warn!("Failed to notify systemd of readiness: {}", err);
+    }
+}
+
+pub fn notify_stopping() {
+    if let Err(err) = notify("STOPPING=1") {
+        warn!("Failed to notify systemd of shutdown: {}", err);
+    }
+}
+
+/// The watchdog timeout configured with WatchdogSec=, if any
+fn watchdog_timeout() -> Option<Duration> {
+    if let Ok(pid) = std::env::var("WATCHDOG_PID") {
+        if pid.parse::<u32>().ok() != Some(std::process::id()) {
+            return None;
+        }
+    }
+
+    std::env::var("WATCHDOG_USEC")
+        .ok()
+        .and_then(|usec| usec.parse::<u64>().ok())
+        .filter(|usec| *usec > 0)
+        .map(Duration::from_micros)
+}
+
+/// Ping the watchdog from the default main context at half the configured
+/// timeout, a stuck main loop will thus get the service restarted.
+pub fn start_watchdog() {
+    let timeout = match watchdog_timeout() {
+        Some(timeout) => timeout,
+        None => return,
+    };
+
+    /* A timeout below 2ms would round down to a busy loop */
+    let interval = ((timeout / 2).as_millis() as u32).max(1);
+
+    info!("Pinging systemd watchdog every {}ms", interval);
+
+    glib::timeout_add(interval, || {
+        if let Err(err) = notify("WATCHDOG=1") {
+            warn!("Failed to ping systemd watchdog: {}", err);
+        }
+        glib::Continue(true)
+    });
+}
@@ -0,0 +1,197 @@
+//! Tally output, telling studio tally lights and under monitor displays
+//! whether the program shows the live input or the slate.
+//!
+//! Changes are sent over HTTP, TSL UMD 3.1 or 5.0 over UDP, or a GPIO pin.
+//! TSL and GPIO state is also refreshed periodically, so that displays that
+//! restarted catch up. Changes can also be sent as commands to a production
+//! switcher, for facilities where the actual switch happens upstream.
+//! Outputs are driven from a dedicated thread so that slow receivers never
+//! block the main loop.
+
+use std::io::Write;
+use std::net::{TcpStream, ToSocketAddrs, UdpSocket};
+use std::sync::mpsc;
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::anyhow;
+
+use crate::status::Status;
+
+/// How often TSL and GPIO state is sent even without changes
+const REFRESH_INTERVAL: Duration = Duration::from_secs(5);
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TslVersion {
+    V3_1,
+    V5_0,
+}
+
+impl std::str::FromStr for TslVersion {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "3.1" => Ok(TslVersion::V3_1),
+            "5.0" => Ok(TslVersion::V5_0),
+            _ => Err(anyhow!("Unknown TSL UMD version {}", s)),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum TallyOutput {
+    /// POST `{"tally": "live"}` or `{"tally": "slate"}` on changes
+    Http(String),
+    /// Tally 1 / red on the display at `index` while live
+    Tsl {
+        addr: String,
+        version: TslVersion,
+        index: u16,
+    },
+    /// sysfs GPIO pin, high while live
+    Gpio(u32),
+    /// Line-based TCP commands as understood by e.g. RossTalk, with `{uri}`
+    /// replaced by the live URI
+    Switcher {
+        addr: String,
+        on_live: Option<String>,
+        on_slate: Option<String>,
+        uri: String,
+    },
+}
+
+/// TSL UMD 3.1 message, tally 1 and full brightness while live
+fn tsl31_message(index: u16, live: bool) -> Vec<u8> {
+    let mut message = vec![0x80 + (index as u8 & 0x7f), 0x30 | live as u8];
+    let text = if live { "LIVE" } else { "SLATE" };
+    message.extend(format!("{:<16}", text).bytes());
+    message
+}
+
+/// TSL UMD 5.0 packet with a single display message, red right-hand and
+/// text tally and full brightness while live
+fn tsl50_message(index: u16, live: bool) -> Vec<u8> {
+    let text = if live { "LIVE" } else { "SLATE" };
+    let tally = if live { 0b01_01 } else { 0 };
+    let control: u16 = 0b11 << 6 | tally;
+
+    let mut dmsg = vec![];
+    dmsg.extend(&index.to_le_bytes());
+    dmsg.extend(&control.to_le_bytes());
+    dmsg.extend(&(text.len() as u16).to_le_bytes());
+    dmsg.extend(text.bytes());
+
+    /* Byte count excludes itself, then version, flags and screen */
+    let mut message = vec![];
+    message.extend(&((dmsg.len() + 4) as u16).to_le_bytes());
+    message.extend(&[0, 0]);
+    message.extend(&0u16.to_le_bytes());
+    message.extend(dmsg);
+    message
+}
+
+fn send_command(addr: &str, command: &str) -> Result<(), anyhow::Error> {
+    let addr = addr
+        .to_socket_addrs()?
+        .next()
+        .ok_or_else(|| anyhow!("Could not resolve {}", addr))?;
+    let mut stream = TcpStream::connect_timeout(&addr, Duration::from_secs(5))?;
+    stream.set_write_timeout(Some(Duration::from_secs(5)))?;
+    stream.write_all(format!("{}\r\n", command).as_bytes())?;
+    Ok(())
+}
+
+fn set_gpio(pin: u32, live: bool) -> Result<(), anyhow::Error> {
+    let dir = format!("/sys/class/gpio/gpio{}", pin);
+
+    if !std::path::Path::new(&dir).exists() {
+        std::fs::write("/sys/class/gpio/export", pin.to_string())?;
+        std::fs::write(format!("{}/direction", dir), "out")?;
+    }
+
+    std::fs::write(format!("{}/value", dir), if live { "1" } else { "0" })?;
+
+    Ok(())
+}
+
+impl TallyOutput {
+    fn emit(&self, socket: &UdpSocket, live: bool, refresh: bool) -> Result<(), anyhow::Error> {
+        match self {
+            TallyOutput::Http(_) | TallyOutput::Switcher { .. } if refresh => Ok(()),
+            TallyOutput::Http(url) => {
+                let body = serde_json::json!({
+                    "tally": if live { "live" } else { "slate" },
+                });
+                ureq::post(url)
+                    .timeout(Duration::from_secs(5))
+                    .set("Content-Type", "application/json")
+                    .send_string(&body.to_string())?;
+                Ok(())
+            }
+            TallyOutput::Tsl {
+                addr,
+                version,
+                index,
+            } => {
+                let message = match version {
+                    TslVersion::V3_1 => tsl31_message(*index, live),
+                    TslVersion::V5_0 => tsl50_message(*index, live),
+                };
+                socket.send_to(&message, addr.as_str())?;
+                Ok(())
+            }
+            TallyOutput::Gpio(pin) => set_gpio(*pin, live),
+            TallyOutput::Switcher {
+                addr,
+                on_live,
+                on_slate,
+                uri,
+            } => match if live { on_live } else { on_slate } {
+                Some(command) => send_command(addr, &command.replace("{uri}", uri)),
+                None => Ok(()),
+            },
+        }
+    }
+}
+
+/// Drive `outputs` from what `status` reports to be on air
+pub fn start(outputs: Vec<TallyOutput>, status: &Arc<Status>) -> Result<(), anyhow::Error> {
+    if outputs.is_empty() {
+        return Ok(());
+    }
+
+    let socket = UdpSocket::bind("0.0.0.0:0")?;
+    let (sender, receiver) = mpsc::channel::<bool>();
+
+    /* Only the initial state of tally lights matters, switchers must not
+     * be triggered before anything happened */
+    std::thread::spawn(move || {
+        let mut live = false;
+        let mut refresh = true;
+
+        loop {
+            for output in &outputs {
+                if let Err(err) = output.emit(&socket, live, refresh) {
+                    warn!("Failed to send tally to {:?}: {}", output, err);
+                }
+            }
+
+            match receiver.recv_timeout(REFRESH_INTERVAL) {
+                Ok(new_live) => {
+                    live = new_live;
+                    refresh = false;
+                }
+                Err(mpsc::RecvTimeoutError::Timeout) => refresh = true,
+                Err(mpsc::RecvTimeoutError::Disconnected) => break,
+            }
+        }
+    });
+
+    status.connect_live_changed(move |live| {
+        info!("Tally {}", if live { "live" } else { "slate" });
+        let _ = sender.send(live);
+    });
+
+    Ok(())
+}
@@ -0,0 +1,128 @@
+//! Periodic thumbnails of the program output, for monitoring walls.
+//!
+//! Every interval, the last frame of the output is taken from the snapshot
+//! tap and encoded as JPEG from a dedicated thread. Thumbnails are written
+//! to a directory, as a timestamped file pruned as per the retention along
+//! with `latest.jpg` which is replaced atomically, or PUT to a URL such as
+//! an S3-compatible bucket.
+
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+
+use crate::snapshot::{ImageFormat, Snapshot};
+
+/// Name of the most recent thumbnail in the directory
+const LATEST: &str = "latest.jpg";
+
+#[derive(Debug, Clone)]
+pub enum ThumbnailTarget {
+    Dir(PathBuf),
+    /// With `{timestamp}` replaced by the time of the thumbnail
+    Url(String),
+}
+
+#[derive(Debug, Clone)]
+pub struct ThumbnailOptions {
+    pub target: ThumbnailTarget,
+    pub interval: Duration,
+    /// Thumbnails older than this are deleted from the directory
+    pub max_age: Option<Duration>,
+    /// Only the most recent thumbnails are kept in the directory
+    pub max_files: Option<usize>,
+}
+
+/// Current time for file names and URLs, without colons as they are not
+/// allowed in file names everywhere
+fn timestamp() -> String {
+    humantime::format_rfc3339_seconds(SystemTime::now())
+        .to_string()
+        .replace(':', "-")
+}
+
+impl ThumbnailOptions {
+    fn write(&self, dir: &Path, image: &[u8]) -> Result<(), anyhow::Error> {
+        std::fs::write(dir.join(format!("{}.jpg", timestamp())), image)?;
+
+        /* Readers must never see a partially written latest thumbnail */
+        let tmp = dir.join(format!(".{}", LATEST));
+        std::fs::write(&tmp, image)?;
+        std::fs::rename(&tmp, dir.join(LATEST))?;
+
+        self.prune(dir)
+    }
+
+    /// Delete the thumbnails past the retention
+    fn prune(&self, dir: &Path) -> Result<(), anyhow::Error> {
+        let mut files = vec![];
+
+        for entry in std::fs::read_dir(dir)? {
+            let path = entry?.path();
+
+            if path.extension().map_or(false, |ext| ext == "jpg")
+                && path.file_name().map_or(false, |name| name != LATEST)
+            {
+                files.push((path.clone(), path.metadata()?.modified()?));
+            }
+        }
+
+        files.sort_by_key(|(_, modified)| *modified);
+        let count = files.len();
+
+        for (index, (path, modified)) in files.iter().enumerate() {
+            let too_old = self.max_age.map_or(false, |max_age| {
+                modified.elapsed().map_or(false, |age| age > max_age)
+            });
+            let too_many = self
+                .max_files
+                .map_or(false, |max_files| count - index > max_files);
+
+            if too_old || too_many {
+                std::fs::remove_file(path)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn upload(&self, url: &str, image: &[u8]) -> Result<(), anyhow::Error> {
+        ureq::put(&url.replace("{timestamp}", &timestamp()))
+            .timeout(self.interval.max(Duration::from_secs(1)))
+            .set("Content-Type", ImageFormat::Jpeg.mime_type())
+            .send_bytes(image)?;
+
+        Ok(())
+    }
+
+    fn save(&self, image: &[u8]) -> Result<(), anyhow::Error> {
+        match self.target {
+            ThumbnailTarget::Dir(ref dir) => self.write(dir, image),
+            ThumbnailTarget::Url(ref url) => self.upload(url, image),
+        }
+    }
+}
+
+/// Save thumbnails from `snapshot` as per `options`, from a dedicated
+/// thread
+pub fn start(options: ThumbnailOptions, snapshot: &Arc<Snapshot>) -> Result<(), anyhow::Error> {
+    if let ThumbnailTarget::Dir(ref dir) = options.target {
+        std::fs::create_dir_all(dir)?;
+    }
+
+    let snapshot = snapshot.clone();
+    std::thread::spawn(move || loop {
+        std::thread::sleep(options.interval);
+
+        match snapshot.take(ImageFormat::Jpeg) {
+            Ok(Some(image)) => {
+                if let Err(err) = options.save(&image) {
+                    warn!("Failed to save thumbnail: {}", err);
+                }
+            }
+            Ok(None) => (),
+            Err(err) => warn!("Failed to take thumbnail: {}", err),
+        }
+    });
+
+    Ok(())
+}
@@ -0,0 +1,158 @@
+//! Time-shift buffer on the live input.
+//!
+//! The live input is delayed by the time-shift on its way into the
+//! compositor, the queue in front of it holding that much of the input. When
+//! the source drops, what the queue holds plays out before the slate shows,
+//! and the input following a reconnection comes after a jump over the gap.
+//!
+//! In catch-up mode, the queue instead stops playing out once the input
+//! dropped, and after a reconnection resumes from just before the drop, the
+//! output falling behind by the length of the outage. That extra delay is
+//! capped at the time-shift, longer outages being jumped over as without
+//! catch-up.
+
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use gst::prelude::*;
+
+use crate::logging::{self, Level};
+
+/// No input for this long is a drop
+const DROP_AFTER: Duration = Duration::from_secs(1);
+
+/// How often the input is checked for having dropped
+const CHECK_INTERVAL_MS: u32 = 100;
+
+struct State {
+    last_input: Option<Instant>,
+    /// Since when the queue is held, with the probe holding it
+    held: Option<(Instant, gst::PadProbeId)>,
+    /// Delay added by catching up, on top of the time-shift
+    extra: Duration,
+}
+
+pub struct TimeShift {
+    src_pad: gst::Pad,
+    delay: Duration,
+    state: Mutex<State>,
+}
+
+fn as_ms(duration: Duration) -> u64 {
+    duration.as_millis() as u64
+}
+
+impl TimeShift {
+    /// Delay the live input flowing through `queue` by `delay`, catching up
+    /// after drops when `catch_up` is set
+    pub fn install(
+        queue: &gst::Element,
+        delay: Duration,
+        catch_up: bool,
+    ) -> Result<(), anyhow::Error> {
+        let src_pad = queue.get_static_pad("src").unwrap();
+
+        /* Room for the catch-up delay on top of the time-shift */
+        queue.set_property("max-size-buffers", &0u32)?;
+        queue.set_property("max-size-bytes", &0u32)?;
+        queue.set_property("max-size-time", &(2 * delay.as_nanos() as u64))?;
+        queue.set_property_from_str("leaky", "downstream");
+        src_pad.set_offset(delay.as_nanos() as i64);
+
+        if !catch_up {
+            return Ok(());
+        }
+
+        let time_shift = Arc::new(Self {
+            src_pad,
+            delay,
+            state: Mutex::new(State {
+                last_input: None,
+                held: None,
+                extra: Duration::from_secs(0),
+            }),
+        });
+
+        let input = time_shift.clone();
+        queue
+            .get_static_pad("sink")
+            .unwrap()
+            .add_probe(gst::PadProbeType::BUFFER, move |_, _| {
+                input.handle_input();
+                gst::PadProbeReturn::Ok
+            });
+
+        glib::timeout_add(CHECK_INTERVAL_MS, move || {
+            time_shift.check();
+            glib::Continue(true)
+        });
+
+        Ok(())
+    }
+
+    /// Release the queue if it is held, delaying what it holds by how long
+    /// it was held so that it plays on from just before the drop
+    fn handle_input(&self) {
+        let mut state = self.state.lock().unwrap();
+        state.last_input = Some(Instant::now());
+
+        let (since, probe) = match state.held.take() {
+            Some(held) => held,
+            None => return,
+        };
+        let outage = since.elapsed();
+
+        if state.extra + outage <= self.delay {
+            state.extra += outage;
+            self.src_pad
+                .set_offset((self.delay + state.extra).as_nanos() as i64);
+            logging::event(
+                Level::Info,
+                "time-shift-caught-up",
+                "Live input back, resuming from before the drop",
+                serde_json::json!({
+                    "outage_ms": as_ms(outage),
+                    "delay_ms": as_ms(self.delay + state.extra),
+                }),
+            );
+        } else {
+            logging::event(
+                Level::Info,
+                "time-shift-skipped",
+                "Live input back, outage too long to catch up",
+                serde_json::json!({
+                    "outage_ms": as_ms(outage),
+                    "delay_ms": as_ms(self.delay + state.extra),
+                }),
+            );
+        }
+
+        self.src_pad.remove_probe(probe);
+    }
+
+    /// Hold the queue once the input dropped, unless catching up already
+    /// added as much delay as allowed
+    fn check(&self) {
+        let mut state = self.state.lock().unwrap();
+
+        if state.held.is_some() || state.extra >= self.delay {
+            return;
+        }
+
+        match state.last_input {
+            Some(last_input) if last_input.elapsed() >= DROP_AFTER => (),
+            _ => return,
+        }
+
+        /* Blocking probes keep the pad blocked until removed */
+        if let Some(probe) = self
+            .src_pad
+            .add_probe(gst::PadProbeType::BLOCK_DOWNSTREAM, |_, _| {
+                gst::PadProbeReturn::Ok
+            })
+        {
+            info!("Live input dropped, holding the time-shift buffer to catch up");
+            state.held = Some((Instant::now(), probe));
+        }
+    }
+}
@@ -0,0 +1,103 @@
+//! Selection of the live audio track.
+//!
+//! Inputs such as multi-language MPEG-TS carry several audio tracks, of
+//! which playbin3 and uridecodebin3 only play the first one by default.
+//! Once the streams of the input are known, the audio track chosen by index
+//! or language is selected instead, along with the first stream of every
+//! other type as they would be by default.
+
+use gst::prelude::*;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum AudioTrack {
+    /// Index among the audio tracks, from 0
+    Index(usize),
+    /// ISO 639 language code, as in the tags of the track
+    Language(String),
+}
+
+impl std::str::FromStr for AudioTrack {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if let Ok(index) = s.parse() {
+            return Ok(AudioTrack::Index(index));
+        }
+
+        if s.is_empty() || !s.chars().all(|c| c.is_ascii_alphabetic()) {
+            anyhow::bail!("{} is neither a track index nor a language code", s);
+        }
+
+        Ok(AudioTrack::Language(s.to_lowercase()))
+    }
+}
+
+fn language(stream: &gst::Stream) -> Option<String> {
+    let tags = stream.get_tags()?;
+    let language = tags.get::<gst::tags::LanguageCode>()?;
+
+    language.get().map(|language| language.to_lowercase())
+}
+
+impl AudioTrack {
+    fn matches(&self, index: usize, stream: &gst::Stream) -> bool {
+        match self {
+            AudioTrack::Index(wanted) => index == *wanted,
+            AudioTrack::Language(wanted) => language(stream).as_deref() == Some(wanted.as_str()),
+        }
+    }
+
+    /// Select the streams of `collection` posted by `source`
+    pub fn select(&self, source: &gst::Element, collection: &gst::StreamCollection) {
+        let streams = collection.iter().collect::<Vec<_>>();
+        let audio = streams
+            .iter()
+            .filter(|stream| stream.get_stream_type().contains(gst::StreamType::AUDIO))
+            .collect::<Vec<_>>();
+
+        if audio.len() < 2 {
+            return;
+        }
+
+        let chosen = match audio
+            .iter()
+            .enumerate()
+            .find(|(index, stream)| self.matches(*index, stream))
+        {
+            Some((_, stream)) => stream,
+            None => {
+                warn!(
+                    "No audio track {:?} among the {} of the input, keeping the first one",
+                    self,
+                    audio.len()
+                );
+                return;
+            }
+        };
+
+        let mut selected = vec![];
+        for stream_type in &[gst::StreamType::VIDEO, gst::StreamType::TEXT] {
+            if let Some(stream) = streams
+                .iter()
+                .find(|stream| stream.get_stream_type().contains(*stream_type))
+            {
+                selected.extend(stream.get_stream_id());
+            }
+        }
+        selected.extend(chosen.get_stream_id());
+
+        info!(
+            "Selecting audio track {} ({}) out of {}",
+            chosen
+                .get_stream_id()
+                .map_or_else(String::new, |id| id.to_string()),
+            language(chosen).unwrap_or_else(|| "no language".to_string()),
+            audio.len()
+        );
+
+        let ids = selected.iter().map(|id| id.as_str()).collect::<Vec<_>>();
+        if !source.send_event(gst::event::SelectStreams::new(&ids)) {
+            warn!("Failed to select audio track {:?}", self);
+        }
+    }
+}
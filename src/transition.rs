@@ -0,0 +1,69 @@
+//! Crossfades between the live video and the slate.
+//!
+//! The alpha of the live compositor pad is driven by a controller, so that
+//! it ramps on the pipeline clock frame by frame rather than from the main
+//! loop. On every switch, the ramp starts from the current alpha at the
+//! current running time of the mixer pipeline.
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use gst::prelude::*;
+use gst_controller::prelude::*;
+
+use crate::status::Status;
+
+pub struct Transition {
+    pipe: gst::Pipeline,
+    duration: gst::ClockTime,
+    control: gst_controller::InterpolationControlSource,
+}
+
+impl Transition {
+    /// Fade the alpha of `pad` of the compositor in `pipe` over `duration`,
+    /// starting out on the slate
+    pub fn new(
+        pipe: &gst::Pipeline,
+        pad: &gst::Pad,
+        duration: Duration,
+    ) -> Result<Arc<Self>, anyhow::Error> {
+        let control = gst_controller::InterpolationControlSource::new();
+        control.set_property_mode(gst_controller::InterpolationMode::Linear);
+        control.set(gst::ClockTime::from_seconds(0), 0.0);
+
+        let binding = gst_controller::DirectControlBinding::new_absolute(pad, "alpha", &control);
+        pad.add_control_binding(&binding)?;
+
+        Ok(Arc::new(Self {
+            pipe: pipe.clone(),
+            duration: gst::ClockTime::from_nseconds(duration.as_nanos() as u64),
+            control,
+        }))
+    }
+
+    fn running_time(&self) -> Option<gst::ClockTime> {
+        let clock = self.pipe.get_clock()?;
+        Some(clock.get_time() - self.pipe.get_base_time())
+    }
+
+    /// Ramp the alpha to `alpha` from now on
+    fn ramp(&self, alpha: f64) {
+        let now = match self.running_time() {
+            Some(now) if now.is_some() => now,
+            _ => return,
+        };
+        let current = self.control.get_value(now).unwrap_or(1.0 - alpha);
+
+        self.control.unset_all();
+        self.control.set(now, current);
+        self.control.set(now + self.duration, alpha);
+    }
+
+    /// Fade on every switch between live and slate
+    pub fn start(self: &Arc<Self>, status: &Arc<Status>) {
+        let transition = self.clone();
+        status.connect_live_changed(move |live| {
+            transition.ramp(if live { 1.0 } else { 0.0 });
+        });
+    }
+}
@@ -0,0 +1,83 @@
+//! Webhook notifications, so that operators can be paged when the program
+//! goes to the slate for too long or the source is given up on.
+//!
+//! Every event is POSTed as a JSON object to all webhook URLs, from a
+//! dedicated thread so that slow receivers never block the main loop.
+
+use std::sync::mpsc;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use crate::status::Status;
+
+pub struct Webhook {
+    sender: Mutex<mpsc::Sender<serde_json::Value>>,
+}
+
+impl Webhook {
+    pub fn new(urls: Vec<String>) -> Arc<Self> {
+        let (sender, receiver) = mpsc::channel::<serde_json::Value>();
+
+        std::thread::spawn(move || {
+            for body in receiver {
+                for url in &urls {
+                    if let Err(err) = ureq::post(url)
+                        .timeout(Duration::from_secs(5))
+                        .set("Content-Type", "application/json")
+                        .send_string(&body.to_string())
+                    {
+                        warn!("Failed to send webhook to {}: {}", url, err);
+                    }
+                }
+            }
+        });
+
+        Arc::new(Self {
+            sender: Mutex::new(sender),
+        })
+    }
+
+    /// Send `event`, `fields` must be a JSON object
+    pub fn notify(&self, event: &str, fields: serde_json::Value) {
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs_f64())
+            .unwrap_or(0.0);
+        let mut body = serde_json::json!({
+            "event": event,
+            "timestamp": timestamp,
+        });
+
+        if let (Some(body), serde_json::Value::Object(fields)) = (body.as_object_mut(), fields) {
+            body.extend(fields);
+        }
+
+        let _ = self.sender.lock().unwrap().send(body);
+    }
+
+    /// Notify switches to the slate and back to live once the program went
+    /// live for the first time
+    pub fn start(self: &Arc<Self>, status: &Arc<Status>) {
+        let webhook = self.clone();
+        let status_clone = status.clone();
+        let mut slate_since: Option<Instant> = None;
+        status.connect_live_changed(move |live| {
+            let status = &status_clone;
+
+            if live {
+                if let Some(since) = slate_since.take() {
+                    webhook.notify(
+                        "live",
+                        serde_json::json!({
+                            "uri": status.uri(),
+                            "slate_seconds": since.elapsed().as_secs_f64(),
+                        }),
+                    );
+                }
+            } else {
+                slate_since = Some(Instant::now());
+                webhook.notify("slate", serde_json::json!({ "uri": status.uri() }));
+            }
+        });
+    }
+}
@@ -0,0 +1,245 @@
+//! End to end fallback behaviour: a test source streams MPEG-TS over UDP to
+//! the relay, and frames are classified on an appsink at its output, red
+//! for live and blue for the slate.
+//!
+//! Skipped when GStreamer or any of the elements involved is unavailable.
+
+use std::net::UdpSocket;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use gst::prelude::*;
+use rtmp_slate_fallback::{ExitCode, FallbackPipeline};
+
+const REQUIRED: &[&str] = &[
+    "appsink",
+    "audiomixer",
+    "audiotestsrc",
+    "avdec_h264",
+    "compositor",
+    "h264parse",
+    "identity",
+    "interpipesink",
+    "interpipesrc",
+    "mpegtsmux",
+    "playbin3",
+    "tsdemux",
+    "udpsink",
+    "udpsrc",
+    "videoconvert",
+    "videotestsrc",
+    "watchdog",
+    "x264enc",
+];
+
+const DISCARD_AFTER: Duration = Duration::from_secs(1);
+/// Slack for the compositor timeout, on top of `DISCARD_AFTER`
+const SLACK: Duration = Duration::from_secs(2);
+
+fn gstreamer_available() -> bool {
+    if let Err(err) = gst::init() {
+        eprintln!("Skipping, GStreamer is not available: {}", err);
+        return false;
+    }
+
+    let missing: Vec<_> = REQUIRED
+        .iter()
+        .filter(|name| gst::ElementFactory::find(name).is_none())
+        .collect();
+
+    if !missing.is_empty() {
+        eprintln!("Skipping, missing elements {:?}", missing);
+        return false;
+    }
+
+    true
+}
+
+fn free_udp_port() -> u16 {
+    UdpSocket::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap()
+        .port()
+}
+
+/// Live red frames streamed to a local UDP port, failing with an error after
+/// a number of frames like a crashing encoder would
+struct TestSource {
+    pipe: gst::Pipeline,
+}
+
+impl TestSource {
+    fn new(port: u16, error_after: Option<u32>) -> Self {
+        let pipe = gst::parse_launch(&format!(
+            "videotestsrc is-live=true pattern=solid-color foreground-color=0xffff0000 \
+             ! video/x-raw,width=320,height=240,framerate=30/1 ! identity error-after={} \
+             ! videoconvert ! x264enc tune=zerolatency key-int-max=15 ! mpegtsmux \
+             ! udpsink host=127.0.0.1 port={}",
+            error_after.map_or(-1, |frames| frames as i32),
+            port
+        ))
+        .unwrap()
+        .downcast::<gst::Pipeline>()
+        .unwrap();
+
+        Self { pipe }
+    }
+
+    fn play(&self) {
+        self.pipe.set_state(gst::State::Playing).unwrap();
+    }
+
+    /// Wait for the simulated failure, and stop sending
+    fn wait_for_error(&self, timeout: Duration) -> bool {
+        let bus = self.pipe.get_bus().unwrap();
+        let failed = bus
+            .timed_pop_filtered(
+                gst::ClockTime::from_mseconds(timeout.as_millis() as u64),
+                &[gst::MessageType::Error],
+            )
+            .is_some();
+
+        self.stop();
+
+        failed
+    }
+
+    fn stop(&self) {
+        self.pipe.set_state(gst::State::Null).unwrap();
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Kind {
+    Live,
+    Slate,
+}
+
+/// Whether the centre pixel of an RGBx `sample` is red or blue
+fn classify(sample: &gst::Sample) -> Option<Kind> {
+    let s = sample.get_caps()?.get_structure(0)?;
+    let width = s.get_some::<i32>("width").ok()? as usize;
+    let height = s.get_some::<i32>("height").ok()? as usize;
+    let map = sample.get_buffer()?.map_readable().ok()?;
+    let offset = (height / 2 * width + width / 2) * 4;
+    let pixel = map.get(offset..offset + 3)?;
+
+    match (pixel[0], pixel[2]) {
+        (r, b) if r > 150 && b < 100 => Some(Kind::Live),
+        (r, b) if b > 150 && r < 100 => Some(Kind::Slate),
+        _ => None,
+    }
+}
+
+/// Output sink recording when live and slate frames come out
+fn recording_sink(frames: &Arc<Mutex<Vec<(Instant, Kind)>>>) -> gst::Element {
+    let bin = gst::parse_bin_from_description(
+        "videoconvert ! video/x-raw,format=RGBx ! appsink name=sink sync=true",
+        true,
+    )
+    .unwrap();
+    let appsink = bin.get_by_name("sink").unwrap();
+    appsink.set_property("emit-signals", &true).unwrap();
+
+    let frames = frames.clone();
+    appsink
+        .connect("new-sample", false, move |values| {
+            let sink = values[0].get::<gst::Element>().unwrap().unwrap();
+            let sample = sink
+                .emit("pull-sample", &[])
+                .unwrap()
+                .unwrap()
+                .get::<gst::Sample>()
+                .unwrap()
+                .unwrap();
+
+            if let Some(kind) = classify(&sample) {
+                frames.lock().unwrap().push((Instant::now(), kind));
+            }
+
+            Some(gst::FlowReturn::Ok.to_value())
+        })
+        .unwrap();
+
+    bin.upcast()
+}
+
+fn blue_slate() -> gst::Element {
+    let source = gst::ElementFactory::make("videotestsrc", None).unwrap();
+    source.set_property("is-live", &true).unwrap();
+    source.set_property_from_str("pattern", "solid-color");
+    source
+        .set_property("foreground-color", &0xff00_00ffu32)
+        .unwrap();
+    source
+}
+
+#[test]
+fn slate_replaces_failed_source_until_it_recovers() {
+    if !gstreamer_available() {
+        return;
+    }
+
+    let port = free_udp_port();
+    let frames = Arc::new(Mutex::new(vec![]));
+
+    /* Live for 3 seconds, down for 4, then live until the end */
+    let feeder = thread::spawn(move || {
+        let source = TestSource::new(port, Some(90));
+        source.play();
+        assert!(source.wait_for_error(Duration::from_secs(10)));
+
+        thread::sleep(Duration::from_secs(4));
+
+        let source = TestSource::new(port, None);
+        source.play();
+        thread::sleep(Duration::from_secs(8));
+        source.stop();
+    });
+
+    let audio_sink = gst::ElementFactory::make("fakesink", None).unwrap();
+    audio_sink.set_property("sync", &true).unwrap();
+
+    let exit_code = FallbackPipeline::builder(&format!("udp://127.0.0.1:{}", port))
+        .unwrap()
+        .slate_source(blue_slate())
+        .output_sink(recording_sink(&frames))
+        .audio_output_sink(audio_sink)
+        .discard_after(DISCARD_AFTER)
+        .stall_timeout(Duration::from_secs(2))
+        .duration(Duration::from_secs(14))
+        .build()
+        .run()
+        .unwrap();
+
+    feeder.join().unwrap();
+
+    assert_eq!(exit_code, ExitCode::Success);
+
+    let frames = frames.lock().unwrap();
+    let first_live = frames
+        .iter()
+        .position(|(_, kind)| *kind == Kind::Live)
+        .expect("no live frames");
+    let first_slate = frames[first_live..]
+        .iter()
+        .position(|(_, kind)| *kind == Kind::Slate)
+        .map(|index| first_live + index)
+        .expect("no slate frames after the source failed");
+    let (last_live_at, _) = frames[first_slate - 1];
+    let (first_slate_at, _) = frames[first_slate];
+
+    assert!(
+        first_slate_at - last_live_at <= DISCARD_AFTER + SLACK,
+        "slate shown {:?} after the last live frame",
+        first_slate_at - last_live_at
+    );
+    assert!(
+        frames[first_slate..]
+            .iter()
+            .any(|(_, kind)| *kind == Kind::Live),
+        "live frames did not resume"
+    );
+}